@@ -0,0 +1,192 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::jobs::JobId;
+
+/// A file produced by a crawl job, in the artifacts GET request result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Artifact {
+    pub(crate) name: String,
+    /// Size in bytes.
+    pub(crate) size: u64,
+    pub(crate) modified: DateTime<Utc>,
+}
+
+/// Files produced by crawl jobs, e.g. their summary or an export of the URLs found, kept on disk so
+/// that they can be downloaded. Job ids start over with the server, so every run of the server
+/// keeps its artifacts in a directory of its own, `<dir>/<started at>/<job id>/<name>`.
+/// Nothing is kept if there is no directory.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Artifacts(Option<Arc<PathBuf>>);
+
+impl Artifacts {
+    /// Keep the artifacts of this run of the server under `dir`, if any.
+    pub(crate) fn new(dir: Option<&Path>, started_at: DateTime<Utc>) -> Self {
+        Self(dir.map(|dir| Arc::new(dir.join(started_at.format("%Y%m%dT%H%M%SZ").to_string()))))
+    }
+
+    /// Write the artifact `name` of job `id`. It is written next to its final path first, so that
+    /// it is never served half-written.
+    pub(crate) async fn write(&self, id: JobId, name: &str, contents: &[u8]) -> io::Result<()> {
+        let path = match self.path(id, name) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let partial = path.with_file_name(format!(".{}.partial", name));
+
+        tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+        tokio::fs::write(&partial, contents).await?;
+        tokio::fs::rename(&partial, &path).await
+    }
+
+    /// The artifacts of job `id`, by name, none if it did not produce any yet.
+    pub(crate) async fn list(&self, id: JobId) -> io::Result<Vec<Artifact>> {
+        let dir = match &self.0 {
+            Some(dir) => dir.join(id.to_string()),
+            None => return Ok(Vec::new()),
+        };
+        let mut entries = match tokio::fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut artifacts = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let name = match entry.file_name().into_string() {
+                Ok(name) if is_valid_name(&name) => name,
+                _ => continue,
+            };
+            let metadata = entry.metadata().await?;
+            if metadata.is_file() {
+                artifacts.push(Artifact {
+                    name,
+                    size: metadata.len(),
+                    modified: metadata.modified()?.into(),
+                });
+            }
+        }
+        artifacts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(artifacts)
+    }
+
+    /// Where the artifact `name` of job `id` is kept, `None` if nothing is kept or if `name` is
+    /// not a valid artifact name, e.g. `..`.
+    pub(crate) fn path(&self, id: JobId, name: &str) -> Option<PathBuf> {
+        let dir = self.0.as_ref()?;
+        is_valid_name(name).then(|| dir.join(id.to_string()).join(name))
+    }
+}
+
+/// Whether `name` can be the name of an artifact: a plain file name, not a hidden or partial one.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+/// The part of a file a `Range` header asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ByteRange {
+    /// No range, or one that is ignored: malformed, or made of several ranges.
+    Whole,
+    /// From `start` to `end`, both included.
+    Partial { start: u64, end: u64 },
+    /// A range starting past the end of the file.
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// The range `header` asks of a file of `len` bytes.
+    pub(crate) fn parse(header: Option<&str>, len: u64) -> Self {
+        let spec = match header.and_then(|header| header.trim().strip_prefix("bytes=")) {
+            Some(spec) if !spec.contains(',') => spec,
+            _ => return ByteRange::Whole,
+        };
+        let (start, end) = match spec.split_once('-') {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => return ByteRange::Whole,
+        };
+
+        let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+            // The last `end` bytes.
+            (Err(_), Ok(suffix)) if start.is_empty() => {
+                if suffix == 0 || len == 0 {
+                    return ByteRange::Unsatisfiable;
+                }
+                (len.saturating_sub(suffix), len - 1)
+            }
+            (Ok(start), Err(_)) if end.is_empty() => (start, u64::MAX),
+            (Ok(start), Ok(end)) if start <= end => (start, end),
+            _ => return ByteRange::Whole,
+        };
+        if start >= len {
+            return ByteRange::Unsatisfiable;
+        }
+
+        ByteRange::Partial {
+            start,
+            end: end.min(len - 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::{Artifacts, ByteRange};
+
+    #[test]
+    fn test_byte_range() {
+        let parse = |header: &str| ByteRange::parse(Some(header), 100);
+        let partial = |start, end| ByteRange::Partial { start, end };
+
+        assert_eq!(ByteRange::parse(None, 100), ByteRange::Whole);
+        assert_eq!(parse("bytes=0-9"), partial(0, 9));
+        assert_eq!(parse("bytes=90-"), partial(90, 99));
+        assert_eq!(parse("bytes=-10"), partial(90, 99));
+        assert_eq!(parse("bytes=-200"), partial(0, 99));
+        assert_eq!(parse("bytes=50-500"), partial(50, 99));
+        assert_eq!(parse("bytes=100-"), ByteRange::Unsatisfiable);
+        assert_eq!(parse("bytes=-0"), ByteRange::Unsatisfiable);
+        assert_eq!(parse("bytes=9-0"), ByteRange::Whole);
+        assert_eq!(parse("bytes=0-1,5-6"), ByteRange::Whole);
+        assert_eq!(parse("items=0-9"), ByteRange::Whole);
+        assert_eq!(parse("bytes=a-b"), ByteRange::Whole);
+        assert_eq!(
+            ByteRange::parse(Some("bytes=-1"), 0),
+            ByteRange::Unsatisfiable
+        );
+    }
+
+    #[tokio::test]
+    async fn test_artifacts() {
+        let dir = std::env::temp_dir().join(format!("crawler-artifacts-{}", std::process::id()));
+        let artifacts = Artifacts::new(Some(&dir), Utc::now());
+
+        artifacts
+            .write(1, "urls.csv", b"url,count\n")
+            .await
+            .unwrap();
+        artifacts.write(1, "summary.json", b"{}").await.unwrap();
+
+        let listed = artifacts.list(1).await.unwrap();
+        let names: Vec<_> = listed.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["summary.json", "urls.csv"]);
+        assert_eq!(listed[1].size, 10);
+        assert!(artifacts.list(2).await.unwrap().is_empty());
+
+        assert!(artifacts.path(1, "urls.csv").unwrap().is_file());
+        assert_eq!(artifacts.path(1, ".."), None);
+        assert_eq!(artifacts.path(1, ".urls.csv.partial"), None);
+        assert_eq!(Artifacts::default().path(1, "urls.csv"), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use url::Url;
+
+/// A control-plane action worth keeping track of.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+// The names are the ones in the log, e.g. `crawl_started`.
+pub(crate) enum AuditAction {
+    CrawlStarted { domain: Url },
+    CrawlCancelled { domain: Url },
+    CrawlPaused { domain: Url },
+    CrawlResumed { domain: Url },
+    ScheduleCreated { domain: Url },
+    ScheduleDeleted { domain: Url },
+    DataPurged { domain: Url },
+}
+
+impl AuditAction {
+    /// The domain the action applies to.
+    pub(crate) fn domain(&self) -> &Url {
+        match self {
+            AuditAction::CrawlStarted { domain }
+            | AuditAction::CrawlCancelled { domain }
+            | AuditAction::CrawlPaused { domain }
+            | AuditAction::CrawlResumed { domain }
+            | AuditAction::ScheduleCreated { domain }
+            | AuditAction::ScheduleDeleted { domain }
+            | AuditAction::DataPurged { domain } => domain,
+        }
+    }
+}
+
+/// One record of the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AuditEntry {
+    pub(crate) timestamp: DateTime<Utc>,
+    /// Who requested the action, see `server::filters::with_actor`.
+    pub(crate) actor: Option<String>,
+    #[serde(flatten)]
+    pub(crate) action: AuditAction,
+}
+
+/// Append-only, thread-safe log of control-plane actions.
+/// Every entry is also emitted as a structured `tracing` event with the `audit` target.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct AuditLog(Arc<RwLock<Vec<AuditEntry>>>);
+
+impl AuditLog {
+    /// Append an entry for `action`, requested by `actor`.
+    pub(crate) fn record(&self, actor: Option<String>, action: AuditAction) {
+        let entry = AuditEntry {
+            timestamp: Utc::now(),
+            actor,
+            action,
+        };
+        info!(
+            target: "audit",
+            actor = ?entry.actor,
+            action = ?entry.action,
+            "Audit"
+        );
+
+        self.0.write().unwrap().push(entry);
+    }
+
+    /// All entries, oldest first, optionally restricted to the ones for `domain`.
+    pub(crate) fn entries(&self, domain: Option<&Url>) -> Vec<AuditEntry> {
+        self.0
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|entry| domain.is_none_or(|domain| entry.action.domain() == domain))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{AuditAction, AuditLog};
+
+    #[test]
+    fn test_entries() {
+        let log = AuditLog::default();
+        let foo = Url::parse("https://foo.com").unwrap();
+        let bar = Url::parse("https://bar.com").unwrap();
+
+        log.record(
+            Some("abcd".to_string()),
+            AuditAction::CrawlStarted {
+                domain: foo.clone(),
+            },
+        );
+        log.record(None, AuditAction::CrawlStarted { domain: bar });
+
+        assert_eq!(log.entries(None).len(), 2);
+
+        let entries = log.entries(Some(&foo));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].actor.as_deref(), Some("abcd"));
+        assert_eq!(entries[0].action, AuditAction::CrawlStarted { domain: foo });
+    }
+}
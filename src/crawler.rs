@@ -1,183 +1,3576 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
-use futures::{stream::SelectAll, StreamExt};
-use robotstxt::DefaultMatcher;
-use tokio::sync::{broadcast, mpsc};
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use chrono::{DateTime, Utc};
+use futures::{
+    stream::{self, BoxStream, SelectAll},
+    StreamExt,
+};
+use scraper::Selector;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc, watch, OwnedSemaphorePermit, Semaphore};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, trace};
 
-use crate::{db::Db, downloader::Downloader, task::Task};
-use url::Url;
+use crate::{
+    db::{Db, FetchStatus},
+    downloader::{DownloadError, Downloader, DownloaderConfig, Validators},
+    egress,
+    frontier::{Frontier, FrontierOrder, FrontierScorer, Scheduled},
+    histogram::Histogram,
+    normalize::NormalizeConfig,
+    overrides::DomainOverrides,
+    parser::{parse_selectors, AssetKind},
+    patterns::UrlPatterns,
+    robots::{self, RobotsDirectives, RobotsMode},
+    seen_filter::{SeenFilter, SeenUrls},
+    sink::{self, Sink, SinkConfig},
+    sitemap,
+    slow_start::{Ramp, SlowStart},
+    summary::{CrawlOutcome, CrawlSummary},
+    task::{extract_links, page_status, Links, Task, TaskEvent},
+    throttle::{Throttle, MAX_INTERVAL},
+};
+use url::{Host, Url};
+
+/// Receive ends of the channels the crawler's tasks send found URLs and failures on, and the seeds.
+type UrlStreams = SelectAll<BoxStream<'static, TaskEvent>>;
+
+/// Whether a crawl starts from the URLs stored for the domain by previous crawls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarmStart {
+    /// Only spider from the domain root.
+    #[default]
+    Off,
+    /// Fetch all stored URLs again, in addition to spidering from the domain root.
+    Append,
+    /// Fetch all stored URLs again, instead of spidering from the domain root.
+    Replace,
+}
+
+/// Per-crawl options, supplied in the POST /domains body.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrawlerConfig {
+    /// Only fetch `robots.txt` and the seed page, and report which links would be followed.
+    pub(crate) dry_run: bool,
+    /// Only fetch `robots.txt`, the sitemaps and the seed page, and estimate the size of the
+    /// domain to recommend limits for a full crawl.
+    pub(crate) probe: bool,
+    /// Seed the frontier with the URLs stored by previous crawls of the domain.
+    pub(crate) warm_start: WarmStart,
+    /// Rules used to canonicalize found URLs.
+    pub(crate) normalize: NormalizeConfig,
+    /// Also crawl the other hosts of the registrable domain, e.g. `blog.example.com` when crawling
+    /// `www.example.com`, each with its own `robots.txt`.
+    pub(crate) include_subdomains: bool,
+    /// Path prefixes the crawl is restricted to, e.g. `["/blog/"]`, each of them being a seed of
+    /// the crawl instead of the domain root. Matched on whole segments, so that `/blog` is
+    /// `/blog` and the paths under `/blog/`, not `/blogger`. The whole domain if empty.
+    pub(crate) scope: Vec<String>,
+    /// Patterns of the URLs the crawl is restricted to, e.g. `["/docs/*"]`, matched against
+    /// their path and query. Every URL in scope if empty. See `UrlPatterns` for the syntax.
+    pub(crate) include: Vec<String>,
+    /// Patterns of the URLs that are not fetched, e.g. `["/wp-admin/*"]`.
+    pub(crate) exclude: Vec<String>,
+    /// Store the URLs rejected by `include` and `exclude` as found, with an `excluded` status,
+    /// instead of dropping them.
+    pub(crate) record_excluded: bool,
+    /// Maximum number of pages to fetch under a path prefix, e.g. `{"/forum/": 1000}`, matched on
+    /// whole segments as `scope` is.
+    pub(crate) prefix_limits: BTreeMap<String, usize>,
+    /// HTTP client settings.
+    pub(crate) downloader: DownloaderConfig,
+    /// Thresholds that make the crawl fail instead of completing.
+    pub(crate) fail_on: FailOn,
+    /// Maximum number of pages downloaded at once by this crawl. Unbounded if not set.
+    pub(crate) max_concurrency: Option<usize>,
+    /// Start with few downloads at once, and ramp up to `max_concurrency` while the host keeps up.
+    pub(crate) slow_start: Option<SlowStart>,
+    /// Minimum delay between the start of two requests of the crawl, in milliseconds.
+    pub(crate) delay_ms: Option<u64>,
+    /// Maximum number of requests per second of the crawl. The slower of this and `delay_ms` wins,
+    /// as well as `Crawl-delay` and `Request-rate` from `robots.txt` if they are slower still.
+    pub(crate) max_rps: Option<f64>,
+    /// Maximum number of pages fetched by the crawl. Unbounded if not set.
+    pub(crate) max_pages: Option<usize>,
+    /// The order found URLs are fetched in, and so the ones that fit in `max_pages`, when
+    /// downloads are limited by `max_concurrency`.
+    pub(crate) frontier_order: FrontierOrder,
+    /// Ranks the found URLs in place of `frontier_order`. Not part of the crawl options of a
+    /// request, but set from code with `CrawlerConfig::frontier_scorer`.
+    #[serde(skip)]
+    pub(crate) frontier_scorer: Option<FrontierScorer>,
+    /// Decide which URLs to fetch with a probabilistic set instead of the database, for very
+    /// large domains. Their visits are still all stored, in the background.
+    pub(crate) seen_filter: Option<SeenFilter>,
+    /// Maximum number of links followed from the seed. Unbounded if not set.
+    pub(crate) max_depth: Option<usize>,
+    /// Maximum length of the URLs fetched, in bytes, e.g. `2048` to leave out URLs that keep
+    /// growing, as calendars or faceted search pages make them. Unbounded if not set.
+    pub(crate) max_url_length: Option<usize>,
+    /// Number of events, e.g. found URLs, a task can send before waiting for the crawler to
+    /// process them. 1024 if not set.
+    pub(crate) channel_capacity: Option<usize>,
+    /// Maximum number of links followed from a single page, the first ones in document order.
+    /// Unbounded if not set.
+    pub(crate) max_links_per_page: Option<usize>,
+    /// Do not seed the crawl with the pages listed in `/sitemap.xml` and the sitemaps of `robots.txt`.
+    pub(crate) ignore_sitemaps: bool,
+    /// CSS selectors of page sections whose links are ignored, e.g. `["nav", ".footer"]`.
+    pub(crate) exclude_selectors: Vec<String>,
+    /// Follow the links with `rel="nofollow"`, and the ones of pages with a `nofollow` robots meta
+    /// tag.
+    pub(crate) ignore_nofollow: bool,
+    /// Record the title and hash of pages with a `noindex` robots meta tag like any other's.
+    pub(crate) ignore_noindex: bool,
+    /// Record the resources fetched pages refer to besides their links, e.g. images, scripts or
+    /// stylesheets, as assets of the domain. They are not fetched.
+    pub(crate) extract_assets: bool,
+    /// Index the text of fetched HTML pages, to search them with `Db::search`. Pages with a
+    /// `noindex` robots meta tag are not indexed, unless `ignore_noindex` is set.
+    pub(crate) index_text: bool,
+    /// Do not follow the links of pages whose content is identical to a page already fetched by
+    /// the crawl under another URL.
+    pub(crate) skip_duplicates: bool,
+    /// Download every page in full, instead of sending the `ETag` and `Last-Modified` of its last
+    /// fetch for the server to answer `304 Not Modified` if it did not change.
+    pub(crate) ignore_validators: bool,
+    /// Media types of the pages whose body is downloaded and parsed, e.g. `["text/html",
+    /// "text/*"]`. The others, e.g. PDFs or images, are only recorded with their status and
+    /// content type, their body being skipped as soon as their headers are received. HTML and
+    /// XHTML if empty, every type with `["*/*"]`.
+    pub(crate) content_types: Vec<String>,
+    /// Relaxed rules for crawling local development servers.
+    pub(crate) dev: DevMode,
+    /// Free-form labels of the crawl, e.g. `{"team": "seo", "ticket": "WEB-123"}`.
+    pub(crate) tags: BTreeMap<String, String>,
+    /// How `robots.txt` is treated. It may only be ignored on `owned_domains`.
+    pub(crate) robots_mode: RobotsMode,
+    /// Where the events of the crawl are emitted as they happen, e.g. an HTTP endpoint. Sinks
+    /// writing on the machine the crawl runs on are only allowed with `allow_local_sinks`.
+    pub(crate) sinks: Vec<SinkConfig>,
+    /// Sent a POST request with the final status of the job of the crawl once it is done, whatever
+    /// its outcome, by the server only. The request goes through the egress rules and the proxy of
+    /// the crawl, and transient failures are retried as set in `downloader.retry`.
+    pub(crate) callback_url: Option<Url>,
+    /// Allow the `file` and `stdout` sinks, as the command line does. Not part of the crawl options
+    /// of a request.
+    #[serde(skip)]
+    pub(crate) allow_local_sinks: bool,
+    /// Domains the crawl may ignore `robots.txt` of, with their subdomains. Not part of the crawl
+    /// options of a request, but of the settings of the server.
+    #[serde(skip)]
+    pub(crate) owned_domains: Vec<String>,
+    /// Politeness settings enforced on the crawls of some domains, with their subdomains,
+    /// whatever the other options say. Not part of the crawl options of a request, but of the
+    /// settings of the server.
+    #[serde(skip)]
+    pub(crate) domain_overrides: BTreeMap<String, DomainOverrides>,
+}
+
+impl CrawlerConfig {
+    /// Parse `options`, e.g. the body of POST /domains, the crawl options it does not give being
+    /// taken from `self`. Only top-level options are merged: a `normalize` in `options` replaces
+    /// the whole one of `self`.
+    pub fn parse_options<T: DeserializeOwned>(
+        &self,
+        options: serde_json::Value,
+    ) -> serde_json::Result<T> {
+        let mut merged = serde_json::to_value(self)?;
+        match (&mut merged, options) {
+            (serde_json::Value::Object(merged), serde_json::Value::Object(options)) => {
+                merged.extend(options)
+            }
+            _ => return Err(serde::de::Error::custom("Options must be a JSON object")),
+        }
+
+        serde_json::from_value(merged)
+    }
+}
+
+/// Builder-style setters, for configuring a crawl from code,
+/// e.g. `CrawlerConfig::default().max_pages(100).tag("team", "seo")`.
+impl CrawlerConfig {
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn probe(mut self, probe: bool) -> Self {
+        self.probe = probe;
+        self
+    }
+
+    pub fn warm_start(mut self, warm_start: WarmStart) -> Self {
+        self.warm_start = warm_start;
+        self
+    }
+
+    pub fn normalize(mut self, normalize: NormalizeConfig) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    pub fn include_subdomains(mut self, include_subdomains: bool) -> Self {
+        self.include_subdomains = include_subdomains;
+        self
+    }
+
+    /// Restrict the crawl to the paths under `prefix`, in addition to the other scope prefixes.
+    pub fn scope(mut self, prefix: impl Into<String>) -> Self {
+        self.scope.push(prefix.into());
+        self
+    }
+
+    /// Only fetch the URLs matching `pattern`, or one of the other include patterns.
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Do not fetch the URLs matching `pattern`.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    pub fn record_excluded(mut self, record_excluded: bool) -> Self {
+        self.record_excluded = record_excluded;
+        self
+    }
+
+    /// Fetch at most `limit` pages under the path `prefix`.
+    pub fn prefix_limit(mut self, prefix: impl Into<String>, limit: usize) -> Self {
+        self.prefix_limits.insert(prefix.into(), limit);
+        self
+    }
+
+    pub fn downloader(mut self, downloader: DownloaderConfig) -> Self {
+        self.downloader = downloader;
+        self
+    }
+
+    pub fn fail_on(mut self, fail_on: FailOn) -> Self {
+        self.fail_on = fail_on;
+        self
+    }
+
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    pub fn slow_start(mut self, slow_start: SlowStart) -> Self {
+        self.slow_start = Some(slow_start);
+        self
+    }
+
+    pub fn delay_ms(mut self, delay_ms: u64) -> Self {
+        self.delay_ms = Some(delay_ms);
+        self
+    }
+
+    pub fn max_rps(mut self, max_rps: f64) -> Self {
+        self.max_rps = Some(max_rps);
+        self
+    }
+
+    pub fn max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
+
+    pub fn seen_filter(mut self, seen_filter: SeenFilter) -> Self {
+        self.seen_filter = Some(seen_filter);
+        self
+    }
+
+    pub fn frontier_order(mut self, frontier_order: FrontierOrder) -> Self {
+        self.frontier_order = frontier_order;
+        self
+    }
+
+    /// Fetch the found URLs with the lowest `score` first, given each URL and the number of links
+    /// followed from the seed to it, instead of in one of the `FrontierOrder`s. URLs with the
+    /// same score are fetched in the order they were found.
+    pub fn frontier_scorer(
+        mut self,
+        score: impl Fn(&Url, usize) -> i64 + Send + Sync + 'static,
+    ) -> Self {
+        self.frontier_scorer = Some(FrontierScorer::new(score));
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    pub fn max_url_length(mut self, max_url_length: usize) -> Self {
+        self.max_url_length = Some(max_url_length);
+        self
+    }
+
+    pub fn channel_capacity(mut self, channel_capacity: usize) -> Self {
+        self.channel_capacity = Some(channel_capacity);
+        self
+    }
+
+    pub fn max_links_per_page(mut self, max_links_per_page: usize) -> Self {
+        self.max_links_per_page = Some(max_links_per_page);
+        self
+    }
+
+    pub fn ignore_sitemaps(mut self, ignore_sitemaps: bool) -> Self {
+        self.ignore_sitemaps = ignore_sitemaps;
+        self
+    }
+
+    /// Ignore the links inside elements matching the CSS `selector`.
+    pub fn exclude_selector(mut self, selector: impl Into<String>) -> Self {
+        self.exclude_selectors.push(selector.into());
+        self
+    }
+
+    pub fn ignore_nofollow(mut self, ignore_nofollow: bool) -> Self {
+        self.ignore_nofollow = ignore_nofollow;
+        self
+    }
+
+    pub fn ignore_noindex(mut self, ignore_noindex: bool) -> Self {
+        self.ignore_noindex = ignore_noindex;
+        self
+    }
+
+    pub fn extract_assets(mut self, extract_assets: bool) -> Self {
+        self.extract_assets = extract_assets;
+        self
+    }
+
+    pub fn index_text(mut self, index_text: bool) -> Self {
+        self.index_text = index_text;
+        self
+    }
+
+    pub fn skip_duplicates(mut self, skip_duplicates: bool) -> Self {
+        self.skip_duplicates = skip_duplicates;
+        self
+    }
+
+    pub fn ignore_validators(mut self, ignore_validators: bool) -> Self {
+        self.ignore_validators = ignore_validators;
+        self
+    }
+
+    /// Download and parse the pages of `media_type` too, e.g. `text/plain` or `image/*`.
+    pub fn content_type(mut self, media_type: impl Into<String>) -> Self {
+        self.content_types.push(media_type.into());
+        self
+    }
+
+    pub fn dev(mut self, dev: DevMode) -> Self {
+        self.dev = dev;
+        self
+    }
+
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn robots_mode(mut self, robots_mode: RobotsMode) -> Self {
+        self.robots_mode = robots_mode;
+        self
+    }
+
+    /// Emit the events of the crawl to the sink `sink`.
+    pub fn sink(mut self, sink: SinkConfig) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn callback_url(mut self, callback_url: Url) -> Self {
+        self.callback_url = Some(callback_url);
+        self
+    }
+
+    pub fn allow_local_sinks(mut self, allow_local_sinks: bool) -> Self {
+        self.allow_local_sinks = allow_local_sinks;
+        self
+    }
+
+    /// Allow ignoring the `robots.txt` of `domain` and its subdomains.
+    pub fn owned_domain(mut self, domain: impl Into<String>) -> Self {
+        self.owned_domains.push(domain.into());
+        self
+    }
+
+    /// Enforce `overrides` when crawling `domain` or one of its subdomains.
+    pub fn domain_override(
+        mut self,
+        domain: impl Into<String>,
+        overrides: DomainOverrides,
+    ) -> Self {
+        self.domain_overrides.insert(domain.into(), overrides);
+        self
+    }
+}
+
+/// Relaxed rules for crawling a dev server such as `http://localhost:3000`.
+/// Only allowed when the crawled domain is a local host.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DevMode {
+    /// Do not fetch nor enforce `robots.txt`.
+    pub ignore_robots: bool,
+    /// Also follow links to these ports of the same host, e.g. an API server next to the front-end.
+    pub ports: Vec<u16>,
+}
+
+impl DevMode {
+    fn is_enabled(&self) -> bool {
+        self.ignore_robots || !self.ports.is_empty()
+    }
+}
+
+/// Whether the hosts of `a` and `b` only differ by a leading `www.`.
+fn is_www_variant(a: &Url, b: &Url) -> bool {
+    match (a.host_str(), b.host_str()) {
+        (Some(a), Some(b)) => {
+            a.strip_prefix("www.") == Some(b) || b.strip_prefix("www.") == Some(a)
+        }
+        _ => false,
+    }
+}
+
+/// Whether `path` is under `prefix` on whole segments: `/forum` covers `/forum` and `/forum/1`,
+/// but not `/forum-archive`.
+fn has_path_prefix(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The registrable domain of the host of `url`, from the public suffix list, e.g. `example.co.uk`
+/// for `blog.example.co.uk`. `None` for IP addresses and public suffixes.
+fn registrable_domain(url: &Url) -> Option<&str> {
+    match url.host()? {
+        Host::Domain(host) => psl::domain_str(host),
+        Host::Ipv4(_) | Host::Ipv6(_) => None,
+    }
+}
+
+/// Whether `url` points to the local machine.
+fn is_local(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Domain(domain)) => domain == "localhost" || domain.ends_with(".localhost"),
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    }
+}
+
+/// Thresholds that make a crawl end as failed, e.g. `{"error_rate": 0.1, "status": [500]}`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FailOn {
+    /// Maximum fraction of fetched pages that can fail to download, between 0 and 1.
+    pub error_rate: Option<f64>,
+    /// Fail if any page responds with one of these statuses.
+    pub status: Vec<u16>,
+}
+
+impl FailOn {
+    fn validate(&self) -> anyhow::Result<()> {
+        if self
+            .error_rate
+            .is_some_and(|rate| !(0.0..=1.0).contains(&rate))
+        {
+            anyhow::bail!("The error_rate of fail_on must be between 0 and 1");
+        }
+
+        Ok(())
+    }
+
+    /// Returns why the crawl failed, if `stats` cross one of the thresholds.
+    fn check(&self, stats: &CrawlStats) -> Option<String> {
+        for status in &self.status {
+            if let Some(count) = stats.errors.get(&format!("http_{}", status)) {
+                return Some(format!("{} pages responded with status {}", count, status));
+            }
+        }
+
+        if let Some(max_rate) = self.error_rate {
+            let errors: usize = stats.errors.values().sum();
+            let rate = errors as f64 / stats.fetched.max(1) as f64;
+            if rate > max_rate {
+                return Some(format!(
+                    "Error rate {:.3} is above {:.3} ({} of {} pages)",
+                    rate, max_rate, errors, stats.fetched
+                ));
+            }
+        }
+
+        None
+    }
+}
+
+/// Counters gathered while crawling.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CrawlStats {
+    /// Number of URLs scheduled for fetching.
+    pub fetched: usize,
+    /// Number of URLs scheduled for fetching under each prefix in `prefix_limits`.
+    pub prefix_counts: BTreeMap<String, usize>,
+    /// Number of found links of each class.
+    pub links: BTreeMap<LinkClass, usize>,
+    /// Number of failed downloads for each `DownloadError::kind`.
+    pub errors: BTreeMap<String, usize>,
+    /// Start of the body of pages that responded with an error status, if
+    /// `downloader.error_snippet_bytes` is set.
+    pub error_pages: Vec<ErrorPage>,
+    /// Number of found URLs session identifiers were stripped from.
+    pub session_ids_stripped: usize,
+    /// Number of URLs scheduled for fetching although `robots.txt` disallows them, with
+    /// `robots_mode` `record_only`.
+    pub robots_disallowed: usize,
+    /// Number of fetched pages whose content is identical to a page fetched before by the crawl.
+    pub duplicate_pages: usize,
+    /// Number of fetched pages with a `noindex` robots meta tag, unless `ignore_noindex` is set.
+    pub noindex_pages: usize,
+    /// Number of pages that did not change since their last fetch, as the server answered
+    /// `304 Not Modified`. Neither their status nor their links are recorded again.
+    pub unchanged_pages: usize,
+    /// Number of pages whose body was not downloaded, as their content type is not one of
+    /// `content_types`.
+    pub skipped_bodies: usize,
+    /// Number of fetched pages with each `ContentIssue::kind`, e.g. `content_length_mismatch`.
+    pub content_issues: BTreeMap<String, usize>,
+    /// Number of references to assets of each kind found on fetched pages, with `extract_assets`.
+    pub assets: BTreeMap<AssetKind, usize>,
+    /// Number of fetched pages whose text was indexed, with `index_text`.
+    pub indexed_pages: usize,
+    /// Highest number of downloads at once reached with `slow_start`.
+    pub peak_concurrency: Option<usize>,
+    /// Number of found URLs that were not fetched, for each reason. Links of the sections left
+    /// out by `exclude_selectors` are counted, although they are not found.
+    pub excluded: BTreeMap<SkipReason, usize>,
+    /// The first URLs rejected by each of the limits of the crawl, see `SkipReason::is_guard`, to
+    /// tell whether the limits are too tight.
+    pub guard_examples: BTreeMap<SkipReason, Vec<Url>>,
+    /// Some URLs were not fetched because a limit was reached.
+    pub truncated: bool,
+    /// Time enforced between the start of two requests, from the crawl options and `robots.txt`.
+    pub request_interval_ms: Option<u64>,
+    /// The domain the seed redirects to, and that was crawled instead.
+    pub redirected_to: Option<Url>,
+    /// Sitemaps listed in `robots.txt`.
+    pub sitemaps: Vec<String>,
+    /// Number of page URLs found in sitemaps.
+    pub sitemap_urls: usize,
+    /// Time between an URL being scheduled and its download starting, including the wait for a
+    /// `max_concurrency` slot.
+    pub queue_wait: Histogram,
+    /// Number of times `downloader.circuit_breaker` tripped. The URLs are fetched once the
+    /// breaker closes.
+    pub circuit_breaker_trips: usize,
+    /// Number of downloads retried after a transient failure, as set in `downloader.retry`.
+    pub retries: usize,
+    /// Id of the crawl session, recorded in the lineage of the URLs it finds. `None` if it could
+    /// not be started.
+    pub session: Option<u64>,
+}
+
+/// Number of events a `CrawlProgress` subscriber can lag behind before missing some.
+const PROGRESS_EVENTS_CAPACITY: usize = 1024;
+
+/// Default `CrawlerConfig::channel_capacity`.
+const DEFAULT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Default `CrawlerConfig::content_types`.
+const DEFAULT_CONTENT_TYPES: &[&str] = &["text/html", "application/xhtml+xml"];
+
+/// Number of URLs kept in `CrawlStats::guard_examples` for each limit.
+const GUARD_EXAMPLES: usize = 5;
+
+/// Live counters of a running crawl, shared with whoever wants to report on it.
+/// Every change is also published as a `CrawlEvent` to the subscribers.
+#[derive(Debug)]
+pub struct CrawlProgress {
+    found: AtomicUsize,
+    fetched: AtomicUsize,
+    errors: AtomicUsize,
+    rejected: Mutex<BTreeMap<SkipReason, usize>>,
+    events: broadcast::Sender<CrawlEvent>,
+}
+
+impl Default for CrawlProgress {
+    fn default() -> Self {
+        Self {
+            found: AtomicUsize::default(),
+            fetched: AtomicUsize::default(),
+            errors: AtomicUsize::default(),
+            rejected: Mutex::default(),
+            events: broadcast::channel(PROGRESS_EVENTS_CAPACITY).0,
+        }
+    }
+}
+
+impl CrawlProgress {
+    /// The current value of the counters.
+    pub fn counters(&self) -> ProgressCounters {
+        ProgressCounters {
+            found: self.found.load(Ordering::Relaxed),
+            fetched: self.fetched.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Number of URLs rejected by each of the limits of the crawl so far.
+    pub fn rejected(&self) -> BTreeMap<SkipReason, usize> {
+        self.rejected.lock().unwrap().clone()
+    }
+
+    /// Receive the events of the crawl from now on, up to the `Finished` one.
+    pub fn subscribe(&self) -> broadcast::Receiver<CrawlEvent> {
+        self.events.subscribe()
+    }
+
+    pub(crate) fn found(&self, url: &Url) {
+        self.found.fetch_add(1, Ordering::Relaxed);
+        self.publish(|| CrawlEventKind::Found { url: url.clone() });
+    }
+
+    fn fetched(&self, url: &Url) {
+        self.fetched.fetch_add(1, Ordering::Relaxed);
+        self.publish(|| CrawlEventKind::Fetched { url: url.clone() });
+    }
+
+    fn error(&self, url: &Url, kind: &str) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        self.publish(|| CrawlEventKind::Error {
+            url: url.clone(),
+            kind: kind.to_string(),
+        });
+    }
+
+    fn rejected_by(&self, reason: SkipReason) {
+        *self.rejected.lock().unwrap().entry(reason).or_default() += 1;
+    }
+
+    fn paused(&self, paused: bool) {
+        self.publish(|| {
+            if paused {
+                CrawlEventKind::Paused
+            } else {
+                CrawlEventKind::Resumed
+            }
+        });
+    }
+
+    pub(crate) fn finished(&self, outcome: &CrawlOutcome) {
+        self.publish(|| CrawlEventKind::Finished {
+            outcome: outcome.clone(),
+        });
+    }
+
+    /// Send the event `kind` builds, only built if anyone listens.
+    fn publish(&self, kind: impl FnOnce() -> CrawlEventKind) {
+        if self.events.receiver_count() == 0 {
+            return;
+        }
+
+        let _ = self.events.send(CrawlEvent {
+            kind: kind(),
+            counters: self.counters(),
+        });
+    }
+}
+
+/// Pauses and resumes a crawl, shared with whoever controls it. While paused, the crawl spawns no
+/// new download: the ones in flight finish, and the URLs to fetch are kept until it resumes.
+#[derive(Debug, Clone)]
+pub struct CrawlControl(Arc<(watch::Sender<bool>, watch::Receiver<bool>)>);
+
+impl Default for CrawlControl {
+    fn default() -> Self {
+        // The receiver is kept so that sending never fails for lack of one.
+        Self(Arc::new(watch::channel(false)))
+    }
+}
+
+impl CrawlControl {
+    pub fn pause(&self) {
+        let _ = self.0 .0.send(true);
+    }
+
+    pub fn resume(&self) {
+        let _ = self.0 .0.send(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.0 .1.borrow()
+    }
+
+    fn subscribe(&self) -> watch::Receiver<bool> {
+        self.0 .1.clone()
+    }
+}
+
+/// Something that happened during a crawl, along with the counters right after it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrawlEvent {
+    #[serde(flatten)]
+    pub kind: CrawlEventKind,
+    #[serde(flatten)]
+    pub counters: ProgressCounters,
+}
+
+/// What a `CrawlEvent` is about.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum CrawlEventKind {
+    /// A URL was found, before being checked against the scope and the limits of the crawl.
+    Found {
+        url: Url,
+    },
+    /// A URL was scheduled for fetching.
+    Fetched {
+        url: Url,
+    },
+    /// A page failed to download, `kind` being the one counted in `CrawlStats::errors`.
+    Error {
+        url: Url,
+        kind: String,
+    },
+    /// The crawl stopped spawning downloads, see `CrawlControl`.
+    Paused,
+    Resumed,
+    /// The crawl ended, no event follows.
+    Finished {
+        outcome: CrawlOutcome,
+    },
+}
+
+/// A snapshot of `CrawlProgress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProgressCounters {
+    /// Number of URLs found so far, including the seeds and the ones that are not fetched.
+    pub found: usize,
+    /// Number of URLs scheduled for fetching so far.
+    pub fetched: usize,
+    /// Number of pages that failed to download so far.
+    pub errors: usize,
+}
+
+/// Where a found link points to, relative to the crawled domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkClass {
+    /// The host of the crawled domain.
+    Internal,
+    /// Another host under the same domain, e.g. `blog.example.com` or `example.com` when crawling
+    /// `www.example.com`.
+    Subdomain,
+    /// Any other host.
+    ThirdParty,
+}
+
+/// A page that responded with an error status, and what it said.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorPage {
+    pub url: Url,
+    pub status: u16,
+    pub snippet: String,
+}
+
+/// Why an URL is not visited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    OffDomain,
+    /// Under none of the scope prefixes of the crawl.
+    OutOfScope,
+    Robots,
+    /// Linked to from a section matching one of the `exclude_selectors`.
+    ExcludedSelector,
+    /// A link with `rel="nofollow"`, or on a page with a `nofollow` robots meta tag, unless
+    /// `ignore_nofollow` is set.
+    Nofollow,
+    /// Not matching the `include` patterns, or matching one of the `exclude` ones.
+    ExcludedPattern,
+    /// Linked to from a page whose content was already fetched under another URL, with
+    /// `skip_duplicates`.
+    DuplicateContent,
+    AlreadyVisited,
+    PrefixLimit,
+    MaxPages,
+    MaxDepth,
+    /// Longer than `max_url_length`.
+    UrlTooLong,
+    /// Found on a page after its first `max_links_per_page` links.
+    PageLinkLimit,
+    DbError,
+}
+
+impl SkipReason {
+    /// The reasons that are limits of the crawl rather than its scope.
+    pub const GUARDS: [SkipReason; 5] = [
+        SkipReason::UrlTooLong,
+        SkipReason::MaxDepth,
+        SkipReason::PrefixLimit,
+        SkipReason::PageLinkLimit,
+        SkipReason::MaxPages,
+    ];
+
+    /// Whether the URL was rejected by one of the limits of the crawl, which are counted in the
+    /// metrics and have examples kept in the summary.
+    pub fn is_guard(self) -> bool {
+        Self::GUARDS.contains(&self)
+    }
+
+    /// The name of the reason, as serialized, e.g. `url_too_long`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SkipReason::OffDomain => "off_domain",
+            SkipReason::OutOfScope => "out_of_scope",
+            SkipReason::Robots => "robots",
+            SkipReason::ExcludedSelector => "excluded_selector",
+            SkipReason::Nofollow => "nofollow",
+            SkipReason::ExcludedPattern => "excluded_pattern",
+            SkipReason::DuplicateContent => "duplicate_content",
+            SkipReason::AlreadyVisited => "already_visited",
+            SkipReason::PrefixLimit => "prefix_limit",
+            SkipReason::MaxPages => "max_pages",
+            SkipReason::MaxDepth => "max_depth",
+            SkipReason::UrlTooLong => "url_too_long",
+            SkipReason::PageLinkLimit => "page_link_limit",
+            SkipReason::DbError => "db_error",
+        }
+    }
+}
 
 /// Whether an URL should further visited or not.
 #[derive(Debug, PartialEq, Eq)]
 enum ProcessResult {
     ShouldVisit,
-    ShouldNotVisit,
+    ShouldNotVisit(SkipReason),
+}
+
+/// An URL that was found but not visited.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SkippedUrl {
+    pub url: Url,
+    pub reason: SkipReason,
+}
+
+/// What a dry run found on the seed page: the links that would be followed and the ones
+/// that would be skipped, also counted for each reason.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CrawlReport {
+    pub followed: Vec<Url>,
+    pub skipped: Vec<SkippedUrl>,
+    pub excluded: BTreeMap<SkipReason, usize>,
+}
+
+/// What a probe found out about the size of a domain, see `Crawler::probe`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProbeReport {
+    /// Sitemaps listed in `robots.txt`.
+    pub sitemaps: Vec<String>,
+    /// Number of page URLs in scope listed in the sitemaps.
+    pub sitemap_urls: usize,
+    /// Number of links of the seed page a crawl would follow.
+    pub seed_links: usize,
+    /// Time enforced between the start of two requests, from the crawl options and `robots.txt`.
+    pub request_interval_ms: Option<u64>,
+    /// Rough number of pages of the domain: the pages listed in the sitemaps if there are any,
+    /// otherwise the pages two links away from the seed if every page had as many links.
+    pub estimated_pages: usize,
+    pub recommended: ProbeRecommendation,
+}
+
+/// Limits recommended by a probe for a full crawl of the domain.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeRecommendation {
+    /// The estimated number of pages, with a quarter more for the pages found along the way.
+    pub max_pages: usize,
+    /// One more than the deepest path of the URLs found, the number of path segments standing in
+    /// for the number of links followed.
+    pub max_depth: usize,
+    /// Time a crawl of `max_pages` would take at least, given `request_interval_ms`.
+    pub estimated_duration_ms: Option<u64>,
+}
+
+impl ProbeReport {
+    fn new(
+        sitemaps: Vec<String>,
+        sitemap_urls: &[Url],
+        seed: &CrawlReport,
+        request_interval_ms: Option<u64>,
+    ) -> Self {
+        let seed_links = seed.followed.len();
+        let estimated_pages = if sitemap_urls.is_empty() {
+            1 + seed_links + seed_links * seed_links
+        } else {
+            sitemap_urls.len().max(1 + seed_links)
+        };
+        let max_pages = estimated_pages + estimated_pages / 4;
+        let deepest = sitemap_urls
+            .iter()
+            .chain(&seed.followed)
+            .map(|url| {
+                url.path_segments()
+                    .map_or(0, |segments| segments.filter(|s| !s.is_empty()).count())
+            })
+            .max()
+            .unwrap_or(0);
+
+        Self {
+            sitemaps,
+            sitemap_urls: sitemap_urls.len(),
+            seed_links,
+            request_interval_ms,
+            estimated_pages,
+            recommended: ProbeRecommendation {
+                max_pages,
+                max_depth: deepest.max(1) + 1,
+                estimated_duration_ms: request_interval_ms
+                    .map(|interval| interval * max_pages as u64),
+            },
+        }
+    }
+}
+
+/// What fetching a single URL found, see `Crawler::check`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UrlCheck {
+    pub url: Url,
+    /// Why the URL was not fetched, if it was not.
+    pub skipped: Option<SkipReason>,
+    /// Where the page was downloaded from, after following redirects.
+    pub final_url: Option<Url>,
+    pub status: Option<u16>,
+    pub content_type: Option<String>,
+    /// Charset the body was decoded from, e.g. `windows-1252`.
+    pub encoding: Option<String>,
+    /// Size of the body, in bytes.
+    pub bytes: Option<usize>,
+    /// The body was neither downloaded nor parsed, as the content type is not one of
+    /// `content_types`.
+    pub body_skipped: bool,
+    /// Time spent downloading, retries included.
+    pub duration_ms: Option<u64>,
+    /// Why the download failed, `error_kind` being the one counted in `CrawlStats::errors`.
+    pub error: Option<String>,
+    pub error_kind: Option<String>,
+    /// The links found on the page that a crawl would follow or skip.
+    pub links: Option<CrawlReport>,
 }
 
 /// A crawler that only works for the given domain.
 /// It tries to respect `robots.txt` if one exists.
 #[derive(Debug)]
-pub(crate) struct Crawler {
+pub struct Crawler {
     domain: Url,
+    config: CrawlerConfig,
     downloader: Downloader,
     robots_txt: String,
+    stats: CrawlStats,
+    progress: Arc<CrawlProgress>,
+    control: CrawlControl,
+    // Shared by all tasks of the crawl to honour `max_concurrency`.
+    permits: Option<Arc<Semaphore>>,
+    // Adjusts `permits` with `slow_start`.
+    ramp: Option<Ramp>,
+    // Shared by all tasks of the crawl to honour `delay_ms`, `max_rps` and `robots.txt`.
+    throttle: Option<Arc<Throttle>>,
+    // Parsed `exclude_selectors`.
+    exclude: Arc<Vec<Selector>>,
+    // Parsed `include` and `exclude` patterns.
+    patterns: UrlPatterns,
+    // Hashes of the content of the pages fetched by the crawl.
+    content_hashes: HashSet<String>,
+    // Fetched pages whose content was fetched before under another URL, whose links are not
+    // followed with `skip_duplicates`.
+    duplicates: HashSet<Url>,
+    // `robots.txt` of the other hosts of the domain, with `include_subdomains`.
+    host_robots: HashMap<String, String>,
+    // Emit the events of the crawl, taken when it starts.
+    sinks: Vec<Box<dyn Sink>>,
+    // URLs found by the running crawl, with `seen_filter`.
+    seen: Option<SeenUrls>,
 }
 
-impl Crawler {
-    /// Create a new crawler for the given `domain`.
-    pub(crate) fn new(domain: Url) -> anyhow::Result<Self> {
-        let downloader = Downloader::new()?;
+impl Crawler {
+    /// Create a new crawler for the given `domain`.
+    pub fn new(domain: Url, mut config: CrawlerConfig) -> anyhow::Result<Self> {
+        if config.dev.is_enabled() && !is_local(&domain) {
+            anyhow::bail!("Dev mode is only allowed for local hosts");
+        }
+        if config.robots_mode == RobotsMode::Ignore
+            && !egress::matches_any(&config.owned_domains, domain.host_str().unwrap_or_default())
+        {
+            anyhow::bail!("robots.txt may only be ignored on owned domains");
+        }
+        // The overrides of a subdomain apply after, and so win over, the ones of its parents.
+        let mut overrides: Vec<_> = config
+            .domain_overrides
+            .iter()
+            .filter(|(pattern, _)| {
+                egress::matches_any(
+                    std::slice::from_ref(pattern),
+                    domain.host_str().unwrap_or_default(),
+                )
+            })
+            .map(|(pattern, overrides)| (pattern.trim_start_matches('.').len(), overrides.clone()))
+            .collect();
+        overrides.sort_by_key(|(len, _)| *len);
+        for (_, overrides) in overrides {
+            info!(
+                "Enforcing the politeness overrides of {}: {:?}",
+                domain, overrides
+            );
+            overrides.apply(&mut config);
+        }
+
+        let content_types = match config.content_types.is_empty() {
+            true => DEFAULT_CONTENT_TYPES
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            false => config.content_types.clone(),
+        };
+        if let Some(content_type) = content_types.iter().find(|t| !t.contains('/')) {
+            anyhow::bail!(
+                "Content type {:?} is not a media type, e.g. text/html",
+                content_type
+            );
+        }
+        if config.downloader.max_connections_per_host == Some(0) {
+            anyhow::bail!("The maximum connections per host must be above 0");
+        }
+        if let Some(circuit_breaker) = &config.downloader.circuit_breaker {
+            circuit_breaker.validate()?;
+        }
+        if let Some(retry) = &config.downloader.retry {
+            retry.validate()?;
+        }
+        if let Some(slow_start) = &config.slow_start {
+            slow_start.validate()?;
+        }
+        config.fail_on.validate()?;
+        // The crawled domain may be a local dev server, with `allow_private_domain`.
+        let downloader = Downloader::new(&config.downloader)?
+            .with_crawled_host(domain.host_str().unwrap_or_default())
+            .with_cookies_for(&domain)
+            .with_credentials_for(&domain)
+            .with_content_types(&content_types);
+        let exclude = Arc::new(parse_selectors(&config.exclude_selectors)?);
+        let patterns = UrlPatterns::new(&config.include, &config.exclude)?;
+        if let Some(prefix) = config.scope.iter().find(|prefix| !prefix.starts_with('/')) {
+            anyhow::bail!("Scope prefix {:?} is not an absolute path", prefix);
+        }
+        if config
+            .delay_ms
+            .is_some_and(|delay_ms| u128::from(delay_ms) > MAX_INTERVAL.as_millis())
+        {
+            anyhow::bail!(
+                "The delay between requests must be at most {} ms",
+                MAX_INTERVAL.as_millis()
+            );
+        }
+        if let Some(max_rps) = config.max_rps {
+            if !max_rps.is_finite() || max_rps * MAX_INTERVAL.as_secs_f64() < 1.0 {
+                anyhow::bail!(
+                    "The maximum requests per second must be at least one per {} seconds",
+                    MAX_INTERVAL.as_secs()
+                );
+            }
+        }
+        if config.channel_capacity == Some(0) {
+            anyhow::bail!("The channel capacity of the tasks must be above 0");
+        }
+        if let Some(seen_filter) = &config.seen_filter {
+            seen_filter.validate()?;
+        }
+        if !config.allow_local_sinks && config.sinks.iter().any(SinkConfig::is_local) {
+            anyhow::bail!("File and stdout sinks are only allowed from the command line");
+        }
+        let sinks = config
+            .sinks
+            .iter()
+            .map(|sink| sink.open(&downloader))
+            .collect::<anyhow::Result<_>>()?;
+        let ramp = config
+            .slow_start
+            .clone()
+            .map(|slow_start| Ramp::new(slow_start, config.max_concurrency));
+        let permits = match &ramp {
+            Some(ramp) => Some(ramp.permits()),
+            None => config
+                .max_concurrency
+                .map(|max| Arc::new(Semaphore::new(max.max(1)))),
+        };
+        let stats = CrawlStats {
+            peak_concurrency: ramp.as_ref().map(Ramp::peak),
+            ..CrawlStats::default()
+        };
+
+        Ok(Self {
+            domain,
+            config,
+            downloader,
+            robots_txt: String::from(""),
+            stats,
+            progress: Arc::default(),
+            control: CrawlControl::default(),
+            permits,
+            ramp,
+            throttle: None,
+            exclude,
+            patterns,
+            content_hashes: HashSet::new(),
+            duplicates: HashSet::new(),
+            host_robots: HashMap::new(),
+            sinks,
+            seen: None,
+        })
+    }
+
+    /// A reference to the crawler's domain.
+    pub fn domain(&self) -> &Url {
+        &self.domain
+    }
+
+    /// Live counters of the crawl, updated while it runs.
+    pub fn progress(&self) -> Arc<CrawlProgress> {
+        Arc::clone(&self.progress)
+    }
+
+    /// The client the crawl sends its requests with.
+    pub(crate) fn client(&self) -> &Downloader {
+        &self.downloader
+    }
+
+    /// Pause and resume the crawl, also before it starts.
+    pub fn control(&self) -> CrawlControl {
+        self.control.clone()
+    }
+
+    /// Emit the events of the crawl to `sink` too, along with the `sinks` of its options.
+    pub fn add_sink(&mut self, sink: impl Sink + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    /// The options this crawler was created with.
+    pub fn config(&self) -> &CrawlerConfig {
+        &self.config
+    }
+
+    /// Follow the redirects of the seed. When the domain redirects to its `www.` variant or the
+    /// other way around, e.g. `example.com` to `www.example.com`, crawl the host it redirects to
+    /// instead, so that its links are in scope.
+    async fn resolve_seed(&mut self) {
+        let resolved = match self.downloader.resolve(&self.domain).await {
+            Ok(resolved) => resolved,
+            Err(_) => return,
+        };
+
+        if is_www_variant(&self.domain, &resolved) {
+            let canonical = resolved.join("/").unwrap();
+            info!(
+                "{} redirects to {}, crawling it instead",
+                self.domain, canonical
+            );
+            self.stats.redirected_to = Some(canonical.clone());
+            self.downloader.rebind_to(&canonical);
+            self.domain = canonical;
+        }
+    }
+
+    /// Whether `robots.txt` is neither fetched nor enforced, by the dev mode or `robots_mode`.
+    fn ignores_robots(&self) -> bool {
+        self.config.dev.ignore_robots || self.config.robots_mode == RobotsMode::Ignore
+    }
+
+    /// Try to download the `robots.txt` if it exists.
+    async fn fetch_robots_txt(&mut self) {
+        if self.ignores_robots() {
+            return;
+        }
+
+        let robots_url = self.domain.join("robots.txt").unwrap();
+        if let Ok(page) = self.downloader.download(&robots_url).await {
+            self.robots_txt = page;
+        }
+    }
+
+    /// Download the `robots.txt` of the host of `url` if it is another host of the domain that is
+    /// crawled with `include_subdomains`, and it was not downloaded yet.
+    async fn fetch_host_robots_txt(&mut self, url: &Url) {
+        let host = match url.host_str() {
+            Some(host) => host,
+            None => return,
+        };
+        if self.ignores_robots()
+            || Some(host) == self.domain.host_str()
+            || self.host_robots.contains_key(host)
+            || !self.in_host_scope(url)
+        {
+            return;
+        }
+
+        let robots_url = url.join("/robots.txt").unwrap();
+        let robots_txt = self
+            .downloader
+            .download(&robots_url)
+            .await
+            .unwrap_or_default();
+        self.host_robots.insert(host.to_string(), robots_txt);
+    }
+
+    /// The `robots.txt` applying to `url`, empty if there is none or it was not downloaded.
+    fn robots_txt_for(&self, url: &Url) -> &str {
+        match url.host_str() {
+            Some(host) if Some(host) != self.domain.host_str() => self
+                .host_robots
+                .get(host)
+                .map(String::as_str)
+                .unwrap_or_default(),
+            _ => &self.robots_txt,
+        }
+    }
+
+    /// Space out requests as asked by the crawl options and `robots.txt`, whichever is slower.
+    fn start_throttle(&mut self) {
+        let directives = RobotsDirectives::parse(&self.robots_txt);
+        let interval = Throttle::interval(self.config.delay_ms, self.config.max_rps)
+            .max(directives.interval());
+
+        if let Some(interval) = interval {
+            info!("Waiting {:?} between requests", interval);
+        }
+        self.stats.request_interval_ms = interval.map(|interval| interval.as_millis() as u64);
+        self.stats.sitemaps = directives.sitemaps;
+        self.throttle = interval.map(|interval| Arc::new(Throttle::new(interval)));
+    }
+
+    /// Fetch `robots.txt` and the seed page, and report which of the links found on it would be
+    /// followed or skipped, and why. Nothing is written to the database and nothing is recursed into.
+    pub async fn dry_run(&mut self) -> anyhow::Result<CrawlReport> {
+        self.resolve_seed().await;
+        self.fetch_robots_txt().await;
+
+        let seed = self.seeds().swap_remove(0);
+        let page = self.downloader.download(&seed).await?;
+
+        let mut links = extract_links(
+            &self.domain,
+            &self.config.normalize,
+            &self.exclude,
+            false,
+            false,
+            &page,
+        );
+        links.ignore_hints(self.config.ignore_nofollow, self.config.ignore_noindex);
+        links.cap(self.config.max_links_per_page);
+
+        Ok(self.report(seed, links))
+    }
+
+    /// Fetch `robots.txt`, the sitemaps and the seed page, and estimate how big the domain is from
+    /// the number of pages the sitemaps list and the number of links of the seed, to recommend
+    /// `max_pages` and `max_depth` before committing to a full crawl. Nothing is written to the
+    /// database and nothing is recursed into.
+    pub async fn probe(&mut self) -> anyhow::Result<ProbeReport> {
+        let seed = self.dry_run().await?;
+        self.start_throttle();
+
+        let mut sitemap_urls: Vec<_> =
+            sitemap::discover(&self.downloader, &self.domain, &self.stats.sitemaps)
+                .await
+                .iter()
+                .map(|url| self.config.normalize.normalize(url))
+                .filter(|url| self.check_scope(url).is_ok())
+                .collect();
+        sitemap_urls.sort();
+        sitemap_urls.dedup();
+
+        Ok(ProbeReport::new(
+            self.stats.sitemaps.clone(),
+            &sitemap_urls,
+            &seed,
+            self.stats.request_interval_ms,
+        ))
+    }
+
+    /// Report which of the `links` found on `page` would be followed or skipped, and why.
+    fn report(&self, page: Url, links: Links) -> CrawlReport {
+        let mut report = CrawlReport::default();
+        let mut seen = HashSet::new();
+        seen.insert(page);
+
+        for url in links.urls {
+            let result = if seen.insert(url.clone()) {
+                self.check_length(&url)
+                    .and_then(|()| self.check_depth(1))
+                    .and_then(|()| self.check_scope(&url))
+            } else {
+                Err(SkipReason::AlreadyVisited)
+            };
+
+            match result {
+                Ok(()) => report.followed.push(url),
+                Err(reason) => report.skipped.push(SkippedUrl { url, reason }),
+            }
+        }
+        report
+            .skipped
+            .extend(links.excluded.into_iter().map(|url| SkippedUrl {
+                url,
+                reason: SkipReason::ExcludedSelector,
+            }));
+        report
+            .skipped
+            .extend(links.nofollow.into_iter().map(|url| SkippedUrl {
+                url,
+                reason: SkipReason::Nofollow,
+            }));
+        report
+            .skipped
+            .extend(links.over_limit.into_iter().map(|url| SkippedUrl {
+                url,
+                reason: SkipReason::PageLinkLimit,
+            }));
+        for skipped in &report.skipped {
+            *report.excluded.entry(skipped.reason).or_default() += 1;
+        }
+
+        report
+    }
+
+    /// Fetch the single page `url` right away, if it is in scope and allowed by `robots.txt`,
+    /// and record it in `db` as a crawl would. Nothing is recursed into.
+    pub async fn check(&mut self, url: &Url, db: &Db) -> UrlCheck {
+        self.fetch_robots_txt().await;
+
+        let url = self.config.normalize.normalize(url);
+        self.fetch_host_robots_txt(&url).await;
+        let mut check = UrlCheck {
+            url: url.clone(),
+            skipped: None,
+            final_url: None,
+            status: None,
+            content_type: None,
+            encoding: None,
+            bytes: None,
+            body_skipped: false,
+            duration_ms: None,
+            error: None,
+            error_kind: None,
+            links: None,
+        };
+        if let Err(reason) = self.check_scope(&url) {
+            check.skipped = Some(reason);
+            return check;
+        }
+        if let Err(e) = db.visit(Cow::Borrowed(&url)) {
+            error!("Could not record {}: {}", url, e);
+        }
+
+        let started = Instant::now();
+        let result = self.downloader.fetch_page(&url).await;
+        let elapsed = started.elapsed();
+        check.duration_ms = Some(elapsed.as_millis() as u64);
+        match result {
+            Ok(page) if page.skipped => {
+                self.record_status(db, &url, page_status(&page, &Links::default(), elapsed));
+                check.final_url = Some(page.url);
+                check.status = Some(page.status);
+                check.content_type = page.content_type;
+                check.body_skipped = true;
+            }
+            Ok(page) => {
+                let mut links = extract_links(
+                    &self.domain,
+                    &self.config.normalize,
+                    &self.exclude,
+                    self.config.extract_assets,
+                    self.config.index_text && page.is_html(),
+                    &page.body,
+                );
+                links.ignore_hints(self.config.ignore_nofollow, self.config.ignore_noindex);
+                self.record_status(db, &url, page_status(&page, &links, elapsed));
+                self.record_assets(db, std::mem::take(&mut links.assets));
+                if let Some(text) = links.indexable_text() {
+                    self.record_text(db, &url, &text);
+                }
+                check.links = Some(self.report(url, links));
+                check.final_url = Some(page.url);
+                check.status = Some(page.status);
+                check.content_type = page.content_type;
+                check.encoding = page.encoding.map(str::to_string);
+                check.bytes = Some(page.body.len());
+            }
+            Err(e) => {
+                self.record_status(db, &url, FetchStatus::failed(e.status(), e.kind()));
+                check.status = e.status();
+                check.error_kind = Some(e.kind());
+                check.error = Some(e.to_string());
+            }
+        }
+
+        check
+    }
+
+    /// Start crawling the domain associated with this crawler and populate the `db` with found URLs.
+    /// Cancelling `cancel` aborts all in-flight downloads and stops the crawl.
+    /// Returns the summary of the crawl.
+    pub async fn crawl(&mut self, db: Db, cancel: CancellationToken) -> CrawlSummary {
+        // Subscribed before the crawl starts, for the sinks to get every event of it.
+        let sinks = std::mem::take(&mut self.sinks);
+        let forwarder = (!sinks.is_empty())
+            .then(|| tokio::spawn(sink::forward(self.progress.subscribe(), sinks)));
+
+        let summary = self.run(db, cancel).await;
+        if let Some(forwarder) = forwarder {
+            if let Err(e) = forwarder.await {
+                error!("Could not emit the events of the crawl: {}", e);
+            }
+        }
+
+        summary
+    }
+
+    async fn run(&mut self, db: Db, cancel: CancellationToken) -> CrawlSummary {
+        let started_at = Utc::now();
+        let requested = self.domain.clone();
+
+        tokio::select! {
+            _ = async {
+                self.resolve_seed().await;
+                self.fetch_robots_txt().await;
+            } => {}
+            _ = cancel.cancelled() => {
+                info!("Shutting down");
+                return self.summary(started_at, CrawlOutcome::Cancelled);
+            }
+        }
+        self.start_throttle();
+
+        // Kept in memory until the crawl ends, whatever the budget: the requested domain holds the
+        // redirect to the crawled one.
+        let _pins: Vec<_> = [&requested, &self.domain]
+            .iter()
+            .filter_map(|domain| db.pin(domain).ok())
+            .collect();
+        let canonical = self.stats.redirected_to.as_ref();
+        if let Err(e) = db.set_alias(&requested, canonical) {
+            error!("Could not record the redirect of {}: {}", requested, e);
+        }
+        if !self.ignores_robots() {
+            if let Err(e) = db.set_robots_txt(&self.domain, &self.robots_txt) {
+                error!("Could not store robots.txt of {}: {}", self.domain, e);
+            }
+        }
+        match db.start_session(&self.domain, self.config.robots_mode) {
+            Ok(session) => self.stats.session = Some(session),
+            Err(e) => error!("Could not start a session for {}: {}", self.domain, e),
+        }
+        // Every session finds the seeds, even if a warm start schedules them without processing them.
+        let seeds = self.seeds();
+        if let Some(session) = self.stats.session {
+            for seed in &seeds {
+                if let Err(e) = db.see(seed, session) {
+                    error!("Could not record the lineage of {}: {}", seed, e);
+                }
+            }
+        }
+
+        // Give each async task a `Sender`. When all tasks end, the senders are dropped,
+        // and the crawler has finished work.
+        let mut urls: UrlStreams = SelectAll::new();
+        let mut found = Vec::new();
+
+        let (shutdown_complete_tx, mut shutdown_complete_rx) = broadcast::channel(1);
+
+        // Previously stored URLs are already known to the database, so `process_url` would skip
+        // them. When warm starting, they are scheduled directly to have them all fetched again.
+        let mut stored = if self.config.warm_start == WarmStart::Off {
+            Vec::new()
+        } else {
+            db.unique_urls_for_domain(&self.domain).unwrap_or_default()
+        };
+        stored.retain(|url| self.in_path_scope(url) && self.patterns.allows(url));
+
+        // Seed the crawler with the initial domain URL, or the scope prefixes.
+        if self.config.warm_start != WarmStart::Replace {
+            for seed in seeds {
+                if !stored.contains(&seed) {
+                    found.push(TaskEvent::Found(seed, 0, None));
+                }
+            }
+        }
+
+        // Pages listed in the sitemaps are seeds as well.
+        if self.config.warm_start != WarmStart::Replace && !self.config.ignore_sitemaps {
+            let sitemap_urls = tokio::select! {
+                found = sitemap::discover(&self.downloader, &self.domain, &self.stats.sitemaps) => found,
+                _ = cancel.cancelled() => {
+                    info!("Shutting down");
+                    return self.summary(started_at, CrawlOutcome::Cancelled);
+                }
+            };
+            info!("Found {} URLs in sitemaps", sitemap_urls.len());
+            self.stats.sitemap_urls = sitemap_urls.len();
+            for url in sitemap_urls {
+                found.push(TaskEvent::Found(
+                    self.config.normalize.normalize(&url),
+                    0,
+                    None,
+                ));
+            }
+        }
+        urls.push(stream::iter(found).boxed());
+
+        // URLs to fetch, spawned as soon as a download is allowed unless the crawl is paused, and
+        // checked against the budgets then.
+        let mut frontier = Frontier::new(
+            self.config.frontier_order,
+            self.config.frontier_scorer.clone(),
+        );
+        if !stored.is_empty() {
+            info!("Warm start with {} stored URLs", stored.len());
+        }
+        self.seen = self
+            .config
+            .seen_filter
+            .as_ref()
+            .map(|seen_filter| SeenUrls::new(seen_filter, db.clone()));
+        for url in stored {
+            // Not to be fetched again when found.
+            if let Some(seen) = &mut self.seen {
+                seen.insert(&url);
+            }
+            frontier.push(url, 0);
+        }
+
+        // Process incoming URLs as long as there are still spawned async tasks that are sending data.
+        let mut pause = self.control.subscribe();
+        let mut paused = *pause.borrow();
+        let mut cancelled = false;
+        loop {
+            // Without a limit, every URL is spawned right away, their permits being taken below
+            // otherwise.
+            if !paused && self.permits.is_none() {
+                while let Some(scheduled) = frontier.pop() {
+                    self.schedule(
+                        scheduled,
+                        None,
+                        &db,
+                        &mut urls,
+                        &cancel,
+                        shutdown_complete_tx.clone(),
+                    );
+                }
+            }
+
+            tokio::select! {
+                biased;
+
+                _ = cancel.cancelled() => {
+                    info!("Shutting down");
+                    cancelled = true;
+                    break;
+                }
+                // The sender lives in `self.control`, so this never fails.
+                Ok(()) = pause.changed() => {
+                    if *pause.borrow() != paused {
+                        paused = !paused;
+                        info!("{} the crawl of {}", if paused { "Pausing" } else { "Resuming" }, self.domain);
+                        self.progress.paused(paused);
+                    }
+                }
+                // Once paused with no task left, wait to be resumed instead of ending the crawl,
+                // and wait for a permit while URLs are left in the frontier.
+                event = urls.next(), if !urls.is_empty() || (!paused && frontier.is_empty()) => {
+                    match event {
+                        Some(TaskEvent::Found(_, _, Some(from))) if self.duplicates.contains(&from) => {
+                            *self.stats.excluded.entry(SkipReason::DuplicateContent).or_default() += 1;
+                        }
+                        Some(TaskEvent::Found(url, depth, from)) => {
+                            self.progress.found(&url);
+                            if self.config.include_subdomains {
+                                self.fetch_host_robots_txt(&url).await;
+                            }
+                            // Further spawn a task for each URL we are supposed to visit.
+                            match self.process_url(&url, depth, from.as_ref(), &db) {
+                                ProcessResult::ShouldVisit => frontier.push(url, depth),
+                                ProcessResult::ShouldNotVisit(reason) => self.record_skip(reason, &url),
+                            }
+                        }
+                        Some(TaskEvent::Started(queue_wait)) => {
+                            self.stats.queue_wait.record(queue_wait);
+                        }
+                        Some(TaskEvent::SessionIdsStripped(count)) => {
+                            self.stats.session_ids_stripped += count;
+                        }
+                        Some(TaskEvent::Excluded(count)) => {
+                            *self.stats.excluded.entry(SkipReason::ExcludedSelector).or_default() += count;
+                        }
+                        Some(TaskEvent::Nofollow(count)) => {
+                            *self.stats.excluded.entry(SkipReason::Nofollow).or_default() += count;
+                        }
+                        Some(TaskEvent::OverLinkLimit(urls)) => {
+                            self.stats.truncated = true;
+                            for url in urls {
+                                self.record_skip(SkipReason::PageLinkLimit, &url);
+                            }
+                        }
+                        Some(TaskEvent::Assets(assets)) => self.record_assets(&db, assets),
+                        Some(TaskEvent::Text(url, text)) => self.record_text(&db, &url, &text),
+                        Some(TaskEvent::Fetched(url, status)) => {
+                            self.record_download(false, status.duration_ms.map(Duration::from_millis));
+                            self.stats.noindex_pages += status.noindex as usize;
+                            self.stats.skipped_bodies += status.body_skipped as usize;
+                            for issue in &status.issues {
+                                *self.stats.content_issues.entry(issue.kind().to_string()).or_default() += 1;
+                            }
+                            self.record_duplicate(&url, &status);
+                            self.record_status(&db, &url, status);
+                        }
+                        Some(TaskEvent::Unchanged(latency)) => {
+                            self.record_download(false, Some(latency));
+                            self.stats.unchanged_pages += 1;
+                        }
+                        Some(TaskEvent::Failed(url, e)) => {
+                            // Error statuses other than these are answers of a host that keeps up.
+                            let failed = e.status().is_none_or(|status| status == 429 || status >= 500);
+                            self.record_download(failed, None);
+                            self.record_status(&db, &url, FetchStatus::failed(e.status(), e.kind()));
+                            self.record_error(url, e);
+                        }
+                        // The last task ended while paused, the frontier is fetched once resumed, or
+                        // the frontier is left to fetch.
+                        None if paused || !frontier.is_empty() => {}
+                        None => break,
+                    }
+                }
+                // The best URL of the frontier, once a download is allowed. After the events ready, for
+                // the URLs found by a task to be ordered together.
+                Some(permit) = acquire(self.permits.clone()), if !paused && !frontier.is_empty() => {
+                    if let Some(scheduled) = frontier.pop() {
+                        self.schedule(scheduled, Some(permit), &db, &mut urls, &cancel, shutdown_complete_tx.clone());
+                    }
+                }
+            }
+        }
+
+        drop(shutdown_complete_tx);
+        // Tasks waiting for room in their channel once the crawl is cancelled give up.
+        drop(urls);
+
+        let _ = shutdown_complete_rx.recv().await;
+        if let Some(seen) = self.seen.take() {
+            seen.finish().await;
+        }
+        self.stats.circuit_breaker_trips = self.downloader.circuit_breaker_trips();
+        self.stats.retries = self.downloader.retries();
+
+        if let Err(e) = db.set_truncated(&self.domain, self.stats.truncated) {
+            error!("Could not record truncation of {}: {}", self.domain, e);
+        }
+
+        let outcome = if cancelled {
+            CrawlOutcome::Cancelled
+        } else if let Some(reason) = self.config.fail_on.check(&self.stats) {
+            CrawlOutcome::Failed { reason }
+        } else {
+            CrawlOutcome::Completed
+        };
+
+        self.summary(started_at, outcome)
+    }
+
+    fn summary(&self, started_at: DateTime<Utc>, outcome: CrawlOutcome) -> CrawlSummary {
+        self.progress.finished(&outcome);
+
+        CrawlSummary::new(
+            self.domain.clone(),
+            self.config.clone(),
+            started_at,
+            outcome,
+            self.stats.clone(),
+        )
+    }
+
+    /// Record how the fetch of `url` went in this session, and whether the page changed since its
+    /// last fetch by an earlier one.
+    fn record_status(&self, db: &Db, url: &Url, mut status: FetchStatus) {
+        status.session = self.stats.session;
+        match db.status(url) {
+            // Fetched twice by this session: it changed if either fetch found it different.
+            Ok(Some(previous))
+                if previous.session.is_some() && previous.session == status.session =>
+            {
+                status.changed = previous.changed || status.differs_from(&previous)
+            }
+            Ok(Some(previous)) => status.changed = status.differs_from(&previous),
+            Ok(None) => {}
+            Err(e) => error!("Could not read the last status of {}: {}", url, e),
+        }
+        if let Err(e) = db.set_status(url, &status) {
+            error!("Could not record the status of {}: {}", url, e);
+        }
+    }
+
+    /// Count `url` as not fetched for `reason`, keeping it as an example if it hit a limit.
+    fn record_skip(&mut self, reason: SkipReason, url: &Url) {
+        *self.stats.excluded.entry(reason).or_default() += 1;
+        if reason.is_guard() {
+            self.progress.rejected_by(reason);
+            let examples = self.stats.guard_examples.entry(reason).or_default();
+            if examples.len() < GUARD_EXAMPLES {
+                examples.push(url.clone());
+            }
+        }
+    }
+
+    /// Record the `assets` a fetched page refers to.
+    fn record_assets(&mut self, db: &Db, assets: Vec<(AssetKind, Url)>) {
+        for (kind, url) in assets {
+            *self.stats.assets.entry(kind).or_default() += 1;
+            if let Err(e) = db.visit_asset(&self.domain, &url, kind) {
+                error!("Could not record asset {}: {}", url, e);
+            }
+        }
+    }
+
+    fn record_text(&mut self, db: &Db, url: &Url, text: &str) {
+        self.stats.indexed_pages += 1;
+        if let Err(e) = db.index_text(url, text) {
+            error!("Could not index the text of {}: {}", url, e);
+        }
+    }
+
+    /// The validators of the last fetch of `url`, to only download it again if it changed. None
+    /// with `ignore_validators`.
+    fn validators(&self, db: &Db, url: &Url) -> Validators {
+        if self.config.ignore_validators {
+            return Validators::default();
+        }
+
+        match db.status(url) {
+            Ok(status) => status.map_or_else(Validators::default, |status| Validators {
+                etag: status.etag,
+                last_modified: status.last_modified,
+            }),
+            Err(e) => {
+                error!("Could not read the last status of {}: {}", url, e);
+                Validators::default()
+            }
+        }
+    }
+
+    /// Let the slow start, if any, know that a download finished, taking `latency` if it
+    /// succeeded.
+    fn record_download(&mut self, failed: bool, latency: Option<Duration>) {
+        if let Some(ramp) = &mut self.ramp {
+            ramp.record(failed, latency);
+            self.stats.peak_concurrency = Some(ramp.peak());
+        }
+    }
+
+    /// Count the fetched `url` as a duplicate if a page with the same content was fetched before,
+    /// and remember it to skip its links if `skip_duplicates` is set.
+    fn record_duplicate(&mut self, url: &Url, status: &FetchStatus) {
+        let hash = match &status.content_hash {
+            Some(hash) => hash,
+            None => return,
+        };
+        if self.content_hashes.insert(hash.clone()) {
+            return;
+        }
+
+        self.stats.duplicate_pages += 1;
+        if self.config.skip_duplicates {
+            self.duplicates.insert(url.clone());
+        }
+    }
+
+    fn record_error(&mut self, url: Url, e: DownloadError) {
+        let kind = e.kind();
+        self.progress.error(&url, &kind);
+        *self.stats.errors.entry(kind).or_default() += 1;
+
+        if let DownloadError::Status {
+            status,
+            snippet: Some(snippet),
+        } = e
+        {
+            self.stats.error_pages.push(ErrorPage {
+                url,
+                status: status.as_u16(),
+                snippet,
+            });
+        }
+    }
+
+    /// Spawn a task for the `scheduled` URL holding the download `permit`, if it fits in the
+    /// budgets of the crawl.
+    fn schedule(
+        &mut self,
+        scheduled: Scheduled,
+        permit: Option<OwnedSemaphorePermit>,
+        db: &Db,
+        urls: &mut UrlStreams,
+        cancel: &CancellationToken,
+        shutdown_complete: broadcast::Sender<()>,
+    ) {
+        match self.admit(&scheduled.url) {
+            Ok(()) => {
+                let validators = self.validators(db, &scheduled.url);
+                self.spawn_task(
+                    scheduled,
+                    permit,
+                    validators,
+                    urls,
+                    cancel,
+                    shutdown_complete,
+                );
+            }
+            Err(reason) => self.record_skip(reason, &scheduled.url),
+        }
+    }
+
+    /// Spawn a download + parse task for the `scheduled` URL and register the stream of URLs it
+    /// finds.
+    fn spawn_task(
+        &self,
+        scheduled: Scheduled,
+        permit: Option<OwnedSemaphorePermit>,
+        validators: Validators,
+        urls: &mut UrlStreams,
+        cancel: &CancellationToken,
+        shutdown_complete: broadcast::Sender<()>,
+    ) {
+        // Send the Sender to the task, register the receiver stream. Bounded, for a page with many
+        // links to wait for the crawler instead of piling them up.
+        let (tx, rx) = mpsc::channel(
+            self.config
+                .channel_capacity
+                .unwrap_or(DEFAULT_CHANNEL_CAPACITY),
+        );
+        urls.push(ReceiverStream::new(rx).boxed());
+
+        let mut task = Task {
+            downloader: self.downloader.clone(),
+            domain: self.domain.clone(),
+            normalize: self.config.normalize.clone(),
+            exclude: Arc::clone(&self.exclude),
+            ignore_nofollow: self.config.ignore_nofollow,
+            ignore_noindex: self.config.ignore_noindex,
+            extract_assets: self.config.extract_assets,
+            index_text: self.config.index_text,
+            max_links: self.config.max_links_per_page,
+            url: scheduled.url,
+            validators,
+            depth: scheduled.depth,
+            enqueued_at: scheduled.enqueued_at,
+            _permit: permit,
+            throttle: self.throttle.clone(),
+            tx,
+            cancel: cancel.clone(),
+            _shutdown_complete: shutdown_complete,
+        };
+
+        tokio::spawn(async move { task.run().await });
+    }
+
+    /// Classifies an `http(s)` URL relative to the crawled domain. Other schemes have no class.
+    fn classify(&self, url: &Url) -> Option<LinkClass> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return None;
+        }
+        let host = url.host_str()?;
+        let domain = self.domain.host_str()?;
+
+        if host == domain {
+            return Some(LinkClass::Internal);
+        }
+
+        let site = registrable_domain(&self.domain);
+        let class = if site.is_some() && registrable_domain(url) == site {
+            LinkClass::Subdomain
+        } else {
+            LinkClass::ThirdParty
+        };
+
+        Some(class)
+    }
+
+    /// Whether more pages can be fetched without going over `max_pages`.
+    fn has_page_budget(&self) -> bool {
+        self.config
+            .max_pages
+            .is_none_or(|max_pages| self.stats.fetched < max_pages)
+    }
+
+    /// Checks whether an URL found `depth` links away from the seed may be followed.
+    fn check_depth(&self, depth: usize) -> Result<(), SkipReason> {
+        match self.config.max_depth {
+            Some(max_depth) if depth > max_depth => {
+                trace!("Max depth reached");
+                Err(SkipReason::MaxDepth)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks whether the URL is short enough to be followed.
+    fn check_length(&self, url: &Url) -> Result<(), SkipReason> {
+        match self.config.max_url_length {
+            Some(max) if url.as_str().len() > max => {
+                trace!("URL too long");
+                Err(SkipReason::UrlTooLong)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The URLs the crawl starts from: the domain root, or each of the scope prefixes.
+    fn seeds(&self) -> Vec<Url> {
+        if self.config.scope.is_empty() {
+            return vec![self.config.normalize.normalize(&self.domain)];
+        }
+
+        self.config
+            .scope
+            .iter()
+            .filter_map(|prefix| self.domain.join(prefix).ok())
+            .map(|seed| self.config.normalize.normalize(&seed))
+            .collect()
+    }
+
+    /// Whether the path of the URL is under one of the scope prefixes, if any.
+    fn in_path_scope(&self, url: &Url) -> bool {
+        self.config.scope.is_empty()
+            || self
+                .config
+                .scope
+                .iter()
+                .any(|prefix| has_path_prefix(url.path(), prefix))
+    }
+
+    /// Whether the host of `url` is the crawled one, or another one of the same registrable
+    /// domain with `include_subdomains`.
+    fn in_host_scope(&self, url: &Url) -> bool {
+        url.host_str() == self.domain.host_str()
+            || (self.config.include_subdomains
+                && registrable_domain(url).is_some()
+                && registrable_domain(url) == registrable_domain(&self.domain))
+    }
+
+    /// Checks whether the URL is in the crawler's scope and allowed by `robots.txt`.
+    fn check_scope(&self, url: &Url) -> Result<(), SkipReason> {
+        // Restrict to current domain. Default ports are `None`, so `http` and `https` links to
+        // the same host are in scope, while other ports are different sites, unless listed in
+        // the dev mode ports.
+        let in_scope_port = url.port() == self.domain.port()
+            || url
+                .port_or_known_default()
+                .is_some_and(|port| self.config.dev.ports.contains(&port));
+        if !self.in_host_scope(url) || !in_scope_port {
+            trace!("Different domain");
+            return Err(SkipReason::OffDomain);
+        }
+        if !self.in_path_scope(url) {
+            trace!("Out of the scope prefixes");
+            return Err(SkipReason::OutOfScope);
+        }
+
+        // Respect robots.txt, unless only recording what it disallows.
+        if !self.ignores_robots() {
+            let decision = robots::check(self.robots_txt_for(url), url);
+            trace!("Robots decision: {:?}", decision);
+            if !decision.allowed && self.config.robots_mode != RobotsMode::RecordOnly {
+                trace!("Not allowed by robots");
+                return Err(SkipReason::Robots);
+            }
+        }
+
+        // Checked last: an URL rejected by the patterns passed every other check, which matters
+        // for the seeds, fetched whatever the patterns for their links to be found.
+        if !self.patterns.allows(url) {
+            trace!("Rejected by the include and exclude patterns");
+            return Err(SkipReason::ExcludedPattern);
+        }
+
+        Ok(())
+    }
+
+    /// Processes the URL by registering it to the database and checking wether it should be
+    /// visited or it was already visited by a previous crawler/from a diferent path.
+    /// `from` is the page the URL was found on, if any.
+    fn process_url(
+        &mut self,
+        url: &Url,
+        depth: usize,
+        from: Option<&Url>,
+        db: &Db,
+    ) -> ProcessResult {
+        info!("Processing url {}", url);
+
+        if let Some(class) = self.classify(url) {
+            *self.stats.links.entry(class).or_default() += 1;
+            if class == LinkClass::ThirdParty {
+                if let Err(e) =
+                    db.visit_third_party(&self.domain, url.host_str().unwrap_or_default())
+                {
+                    error!("Could not record third-party link {}: {}", url, e);
+                }
+            }
+        }
+
+        // Checked before registering the visit: the same URL may still be found later through a
+        // shorter path, and must not be skipped as already visited then.
+        let excluded = match self
+            .check_length(url)
+            .and_then(|()| self.check_depth(depth))
+            .and_then(|()| self.check_scope(url))
+        {
+            Ok(()) => false,
+            Err(SkipReason::ExcludedPattern) if from.is_none() && self.seeds().contains(url) => {
+                false
+            }
+            // Registered as found, but not fetched.
+            Err(SkipReason::ExcludedPattern) if self.config.record_excluded => true,
+            Err(reason) => return ProcessResult::ShouldNotVisit(reason),
+        };
+
+        // Register visit to database, checking at once whether it is new: another crawler of the
+        // domain could be registering it too.
+        let is_first_visit = match &mut self.seen {
+            Some(seen) => seen.visit(url),
+            None => match db.record_visit(url) {
+                Ok(o) => o,
+                Err(e) => {
+                    error!("Skipping {}, DB Error: {}", url, e);
+                    return ProcessResult::ShouldNotVisit(SkipReason::DbError);
+                }
+            },
+        };
+
+        if let Some(from) = from {
+            if let Err(e) = db.link(url, from) {
+                error!("Could not record the link from {} to {}: {}", from, url, e);
+            }
+        }
+        if let Some(session) = self.stats.session {
+            if let Err(e) = db.see(url, session) {
+                error!("Could not record the lineage of {}: {}", url, e);
+            }
+        }
+
+        if excluded {
+            if is_first_visit {
+                self.record_status(db, url, FetchStatus::failed(None, "excluded"));
+            }
+            return ProcessResult::ShouldNotVisit(SkipReason::ExcludedPattern);
+        }
+
+        // Do not visit a second time
+        if !is_first_visit {
+            return ProcessResult::ShouldNotVisit(SkipReason::AlreadyVisited);
+        }
+
+        ProcessResult::ShouldVisit
+    }
+
+    /// Check that `url`, leaving the frontier, fits in the budgets of the crawl, and count it as
+    /// fetched if so.
+    fn admit(&mut self, url: &Url) -> Result<(), SkipReason> {
+        // Keep a single section of the site from using up the whole crawl.
+        let prefixes: Vec<&String> = self
+            .config
+            .prefix_limits
+            .keys()
+            .filter(|prefix| has_path_prefix(url.path(), prefix))
+            .collect();
+        for prefix in &prefixes {
+            let count = self.stats.prefix_counts.get(*prefix).copied().unwrap_or(0);
+            if count >= self.config.prefix_limits[*prefix] {
+                trace!("Prefix limit reached for {}", prefix);
+                self.stats.truncated = true;
+                return Err(SkipReason::PrefixLimit);
+            }
+        }
+
+        if !self.has_page_budget() {
+            trace!("Page budget reached");
+            self.stats.truncated = true;
+            return Err(SkipReason::MaxPages);
+        }
+
+        for prefix in prefixes {
+            *self.stats.prefix_counts.entry(prefix.clone()).or_default() += 1;
+        }
+
+        if self.config.robots_mode == RobotsMode::RecordOnly
+            && !robots::check(self.robots_txt_for(url), url).allowed
+        {
+            self.stats.robots_disallowed += 1;
+        }
+
+        self.stats.fetched += 1;
+        self.progress.fetched(url);
+        Ok(())
+    }
+}
+
+/// A permit of `permits`, waited for, or never if there is no limit.
+async fn acquire(permits: Option<Arc<Semaphore>>) -> Option<OwnedSemaphorePermit> {
+    match permits {
+        Some(permits) => permits.acquire_owned().await.ok(),
+        None => futures::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use mockito::mock;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        db::{Db, FetchStatus},
+        downloader::{CircuitBreakerConfig, Credentials, ProxyConfig, RetryConfig},
+        frontier::FrontierOrder,
+        overrides::DomainOverrides,
+        robots::RobotsMode,
+        seen_filter::SeenFilter,
+        sink::SinkConfig,
+        slow_start::SlowStart,
+        summary::CrawlOutcome,
+    };
+
+    use super::{
+        is_www_variant, AssetKind, CrawlEvent, CrawlEventKind, CrawlReport, CrawlStats, Crawler,
+        CrawlerConfig, DevMode, FailOn, LinkClass, ProbeRecommendation, ProbeReport, SkipReason,
+        WarmStart,
+    };
+    use crate::tests::compare_sorted;
+
+    /// The options of a crawl of the mock server, which is on a loopback address.
+    fn local_config() -> CrawlerConfig {
+        let mut config = CrawlerConfig::default();
+        config.downloader.egress.allow_private_domain = true;
+        config
+    }
+
+    #[tokio::test]
+    async fn crawl_domain() {
+        let _m = mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <title>Home</title>
+                <a href="/foo">foo</a>
+                <a href="/bar">bar</a>
+            "#,
+            )
+            .create();
+
+        let _m = mock("GET", "/foo")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("body")
+            .create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let mut crawler = Crawler::new(domain.clone(), local_config()).unwrap();
+
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.outcome, CrawlOutcome::Completed);
+        assert_eq!(summary.stats.fetched, 3);
+        // `/bar` is not mocked.
+        assert_eq!(summary.stats.errors["http_501"], 1);
+        assert_eq!(summary.stats.queue_wait.count, 3);
+        let mut statuses = db.statuses_for_domain(&domain).unwrap();
+        statuses.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let statuses: Vec<_> = statuses
+            .into_iter()
+            .map(|(url, status)| (url.path().to_string(), status))
+            .collect();
+        assert_eq!(statuses[1].0, "/bar");
+        assert_eq!(
+            statuses[1].1,
+            FetchStatus {
+                session: Some(1),
+                ..FetchStatus::failed(Some(501), "http_501")
+            }
+        );
+        let (path, root) = &statuses[0];
+        assert_eq!(path, "/");
+        assert_eq!(root.status, Some(200));
+        assert_eq!(root.title.as_deref(), Some("Home"));
+        assert_eq!(root.content_type.as_deref(), Some("text/html"));
+        assert!(root.bytes.unwrap() > 0);
+        assert!(root.duration_ms.is_some());
+        assert_eq!(statuses[2].1.title, None);
+        assert_eq!(
+            db.referrers(&domain.join("/bar").unwrap()).unwrap(),
+            vec![domain.clone()]
+        );
+
+        let expected = vec![
+            domain.clone(),
+            domain.join("/foo").unwrap(),
+            domain.join("/bar").unwrap(),
+        ];
+        let unique_urls = db.unique_urls_for_domain(&domain).unwrap();
+
+        compare_sorted(unique_urls, expected);
+    }
+
+    #[tokio::test]
+    async fn crawl_lineage() {
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        // Warm started, as the second crawl would not fetch the stored root again otherwise.
+        let crawl = |links: &'static str| {
+            let m = mock("GET", "/")
+                .with_status(200)
+                .with_header("content-type", "text/html")
+                .with_body(links)
+                .create();
+            let mut crawler = Crawler::new(
+                domain.clone(),
+                local_config()
+                    .ignore_sitemaps(true)
+                    .warm_start(WarmStart::Append),
+            )
+            .unwrap();
+            let db = db.clone();
+            async move {
+                let summary = crawler.crawl(db, CancellationToken::new()).await;
+                drop(m);
+                summary
+            }
+        };
+
+        let summary = crawl(r#"<a href="/gone">gone</a><a href="/kept">kept</a>"#).await;
+        assert_eq!(summary.stats.session, Some(1));
+        let summary = crawl(r#"<a href="/kept">kept</a><a href="/new">new</a>"#).await;
+        assert_eq!(summary.stats.session, Some(2));
+
+        let lineage: Vec<_> = db
+            .lineage_for_domain(&domain)
+            .unwrap()
+            .into_iter()
+            .map(|(url, lineage)| {
+                (
+                    url.path().to_string(),
+                    lineage.first_seen,
+                    lineage.last_seen,
+                )
+            })
+            .collect();
+        compare_sorted(
+            lineage,
+            vec![
+                ("/".to_string(), 1, 2),
+                ("/gone".to_string(), 1, 1),
+                ("/kept".to_string(), 1, 2),
+                ("/new".to_string(), 2, 2),
+            ],
+        );
+        assert_eq!(db.sessions(&domain).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn crawl_seen_filter() {
+        let _m = mock("GET", "/seen/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <a href="/seen/1">1</a>
+                <a href="/seen/2">2</a>
+                <a href="/seen/1">1 again</a>
+            "#,
+            )
+            .create();
+        let _m = mock("GET", "/seen/1")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/seen/">back</a>"#)
+            .expect(1)
+            .create();
+        let _m = mock("GET", "/seen/2")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("body")
+            .create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config()
+            .scope("/seen/")
+            .ignore_sitemaps(true)
+            .seen_filter(SeenFilter {
+                expected_urls: 100,
+                ..SeenFilter::default()
+            });
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.stats.fetched, 3);
+        assert_eq!(summary.stats.excluded[&SkipReason::AlreadyVisited], 2);
+        // Every visit reached the database once the crawl ended.
+        compare_sorted(
+            db.url_counts_for_domain(&domain)
+                .unwrap()
+                .into_iter()
+                .map(|(url, count)| (url.path().to_string(), count))
+                .collect(),
+            vec![
+                ("/seen/".to_string(), 2),
+                ("/seen/1".to_string(), 2),
+                ("/seen/2".to_string(), 1),
+            ],
+        );
+
+        let config = local_config().seen_filter(SeenFilter {
+            expected_urls: 0,
+            ..SeenFilter::default()
+        });
+        assert!(Crawler::new(domain, config).is_err());
+    }
+
+    #[tokio::test]
+    async fn crawl_frontier_order() {
+        let _m = mock("GET", "/frontier/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <a href="/frontier/archive/2019/post">post</a>
+                <a href="/frontier/about">about</a>
+            "#,
+            )
+            .create();
+        let _m = mock("GET", "/frontier/archive/2019/post")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("post")
+            .create();
+        let _m = mock("GET", "/frontier/about")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("about")
+            .create();
+
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let deepest = local_config().frontier_scorer(|url, _| {
+            -(url.path_segments().map_or(0, |segments| segments.count()) as i64)
+        });
+        for (config, fetched) in [
+            (
+                local_config().frontier_order(FrontierOrder::Discovery),
+                "/frontier/archive/2019/post",
+            ),
+            (
+                local_config().frontier_order(FrontierOrder::ShortestPath),
+                "/frontier/about",
+            ),
+            (
+                deepest.frontier_order(FrontierOrder::ShortestPath),
+                "/frontier/archive/2019/post",
+            ),
+        ] {
+            let db = Db::default();
+            let config = config
+                .scope("/frontier/")
+                .ignore_sitemaps(true)
+                .max_concurrency(1)
+                .max_pages(2);
+            let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+            let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+            assert_eq!(summary.stats.fetched, 2);
+            assert_eq!(summary.stats.excluded[&SkipReason::MaxPages], 1);
+            assert!(summary.stats.truncated);
+            compare_sorted(
+                db.statuses_for_domain(&domain)
+                    .unwrap()
+                    .into_iter()
+                    .map(|(url, _)| url.path().to_string())
+                    .collect(),
+                vec!["/frontier/".to_string(), fetched.to_string()],
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn crawl_scope() {
+        let _m = mock("GET", "/scoped/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <a href="/scoped/1">1</a>
+                <a href="/unscoped/1">1</a>
+            "#,
+            )
+            .create();
+        let _m = mock("GET", "/scoped/1")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("body")
+            .create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config().scope("/scoped/").ignore_sitemaps(true);
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.stats.fetched, 2);
+        assert_eq!(summary.stats.excluded[&SkipReason::OutOfScope], 1);
+        compare_sorted(
+            db.unique_urls_for_domain(&domain).unwrap(),
+            vec![
+                domain.join("/scoped/").unwrap(),
+                domain.join("/scoped/1").unwrap(),
+            ],
+        );
+
+        assert!(Crawler::new(domain, local_config().scope("scoped/")).is_err());
+    }
+
+    #[tokio::test]
+    async fn crawl_skip_duplicates() {
+        // Every request is mocked, so that no connection is dropped by an unmocked 501.
+        let _m = mock(
+            "GET",
+            mockito::Matcher::Regex("^/(robots.txt)?$".to_string()),
+        )
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .create();
+        let _m = mock("GET", "/dup/c")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("c")
+            .create();
+        let _m = mock("GET", "/dup/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/dup/a">a</a><a href="/dup/b">b</a>"#)
+            .create();
+        let _m = mock("GET", mockito::Matcher::Regex("^/dup/[ab]$".to_string()))
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/dup/c">c</a>"#)
+            .expect(2)
+            .create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config()
+            .scope("/dup/")
+            .skip_duplicates(true)
+            .ignore_sitemaps(true);
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        // `/dup/c` is only found on the first of the duplicates.
+        assert_eq!(summary.stats.fetched, 4);
+        assert_eq!(summary.stats.duplicate_pages, 1);
+        assert_eq!(summary.stats.excluded[&SkipReason::DuplicateContent], 1);
+        assert!(!summary
+            .stats
+            .excluded
+            .contains_key(&SkipReason::AlreadyVisited));
+        let duplicates = db.duplicates_for_domain(&domain).unwrap();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(
+            duplicates[0].1,
+            vec![
+                domain.join("/dup/a").unwrap(),
+                domain.join("/dup/b").unwrap()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn crawl_index_text() {
+        let _m = mock("GET", "/robots.txt").with_status(404).create();
+        let _m = mock("GET", "/indexed/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"<p>Welcome to the zebra page</p><a href="/indexed/hidden">hidden</a>
+                <a href="/indexed/notes.txt">notes</a>"#,
+            )
+            .create();
+        let _m = mock("GET", "/indexed/hidden")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<meta name="robots" content="noindex"><p>Zebra secrets</p>"#)
+            .create();
+        let _m = mock("GET", "/indexed/notes.txt")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("Zebra notes")
+            .create();
+
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config().scope("/indexed/").ignore_sitemaps(true);
+        let db = Db::default();
+        let mut crawler = Crawler::new(domain.clone(), config.clone()).unwrap();
+        crawler.crawl(db.clone(), CancellationToken::new()).await;
+        assert!(db.search(&domain, "zebra", 10).unwrap().is_empty());
+
+        let db = Db::default();
+        let mut crawler = Crawler::new(domain.clone(), config.index_text(true)).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        // Only the HTML pages that may be indexed are.
+        assert_eq!(summary.stats.fetched, 3);
+        assert_eq!(summary.stats.indexed_pages, 1);
+        let hits = db.search(&domain, "zebra", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].url.path(), "/indexed/");
+        assert_eq!(hits[0].snippet, "Welcome to the zebra page hidden notes");
+    }
+
+    #[tokio::test]
+    async fn crawl_robots_hints() {
+        let _m = mock(
+            "GET",
+            mockito::Matcher::Regex("^/(robots.txt|hint/ad|hint/b)?$".to_string()),
+        )
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .create();
+        let _m = mock("GET", "/hint/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/hint/a">a</a><a href="/hint/ad" rel="nofollow">ad</a>"#)
+            .create();
+        let _m = mock("GET", "/hint/a")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"<title>A</title><meta name="robots" content="noindex, nofollow">
+                <a href="/hint/b">b</a>"#,
+            )
+            .create();
+
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config().scope("/hint/").ignore_sitemaps(true);
+        let db = Db::default();
+        let mut crawler = Crawler::new(domain.clone(), config.clone()).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.stats.fetched, 2);
+        assert_eq!(summary.stats.excluded[&SkipReason::Nofollow], 2);
+        assert_eq!(summary.stats.noindex_pages, 1);
+        let statuses = db.statuses_for_domain(&domain).unwrap();
+        let (_, status) = statuses
+            .iter()
+            .find(|(url, _)| url.path() == "/hint/a")
+            .unwrap();
+        assert!(status.noindex);
+        assert_eq!(status.title, None);
+        assert_eq!(status.content_hash, None);
+
+        let db = Db::default();
+        let config = config.ignore_nofollow(true).ignore_noindex(true);
+        let mut crawler = Crawler::new(domain, config).unwrap();
+        let summary = crawler.crawl(db, CancellationToken::new()).await;
+
+        assert_eq!(summary.stats.fetched, 4);
+        assert_eq!(summary.stats.noindex_pages, 0);
+        assert!(!summary.stats.excluded.contains_key(&SkipReason::Nofollow));
+    }
+
+    #[tokio::test]
+    async fn crawl_assets() {
+        let _m = mock(
+            "GET",
+            mockito::Matcher::Regex("^/(robots.txt|asset/b)?$".to_string()),
+        )
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .create();
+        let _m = mock("GET", "/asset/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"<link rel="stylesheet" href="/asset/main.css"><img src="/asset/logo.png">
+                <img src="data:image/gif;base64,R0lGOD"><a href="/asset/b">b</a>"#,
+            )
+            .create();
+
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config().scope("/asset/").ignore_sitemaps(true);
+        let db = Db::default();
+        let mut crawler = Crawler::new(domain.clone(), config.clone()).unwrap();
+        crawler.crawl(db.clone(), CancellationToken::new()).await;
+        assert!(db.assets_for_domain(&domain).unwrap().is_empty());
+
+        let db = Db::default();
+        let mut crawler = Crawler::new(domain.clone(), config.extract_assets(true)).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        // Assets are recorded, but not fetched.
+        assert_eq!(summary.stats.fetched, 2);
+        assert_eq!(summary.stats.assets[&AssetKind::Image], 1);
+        assert_eq!(summary.stats.assets[&AssetKind::Stylesheet], 1);
+        let mut assets: Vec<_> = db
+            .assets_for_domain(&domain)
+            .unwrap()
+            .into_iter()
+            .map(|(url, asset)| (url.path().to_string(), asset.kind))
+            .collect();
+        assets.sort();
+        assert_eq!(
+            assets,
+            [
+                ("/asset/logo.png".to_string(), AssetKind::Image),
+                ("/asset/main.css".to_string(), AssetKind::Stylesheet)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn crawl_base_href() {
+        let _m = mock(
+            "GET",
+            mockito::Matcher::Regex("^/(robots.txt|base/sub/a|base/b)?$".to_string()),
+        )
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .create();
+        let _m = mock("GET", "/base/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<base href="/base/sub/"><a href="a">a</a><a href="/base/b">b</a>"#)
+            .create();
+
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config().scope("/base/").ignore_sitemaps(true);
+        let db = Db::default();
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.stats.fetched, 3);
+        let mut paths: Vec<_> = db
+            .statuses_for_domain(&domain)
+            .unwrap()
+            .into_iter()
+            .map(|(url, _)| url.path().to_string())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, ["/base/", "/base/b", "/base/sub/a"]);
+    }
+
+    #[tokio::test]
+    async fn crawl_patterns() {
+        let _m = mock(
+            "GET",
+            mockito::Matcher::Regex("^/(robots.txt)?$".to_string()),
+        )
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .create();
+        let _m = mock("GET", "/pat/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"<a href="/pat/docs/a">a</a>
+                <a href="/pat/docs/drafts/b">b</a>
+                <a href="/pat/blog">blog</a>"#,
+            )
+            .create();
+        let _m = mock("GET", "/pat/docs/a")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .expect(1)
+            .create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        // The seed is fetched although it is not included.
+        let config = local_config()
+            .scope("/pat/")
+            .include("/pat/docs/*")
+            .exclude("*/drafts/*")
+            .record_excluded(true)
+            .ignore_sitemaps(true);
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.stats.fetched, 2);
+        assert_eq!(summary.stats.excluded[&SkipReason::ExcludedPattern], 2);
+        let statuses = db.statuses_for_domain(&domain).unwrap();
+        let excluded: Vec<_> = statuses
+            .iter()
+            .filter(|(_, status)| status.error.as_deref() == Some("excluded"))
+            .map(|(url, _)| url.path())
+            .collect();
+        compare_sorted(excluded, vec!["/pat/blog", "/pat/docs/drafts/b"]);
+        assert_eq!(db.unique_urls_for_domain(&domain).unwrap().len(), 4);
+
+        assert!(Crawler::new(domain, local_config().include("re:(")).is_err());
+    }
+
+    #[tokio::test]
+    async fn crawl_robots_modes() {
+        let _m = mock("GET", "/robots.txt")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("User-agent: *\nDisallow: /stage/secret\n")
+            .create();
+        let _m = mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .create();
+        let _m = mock("GET", "/stage/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/stage/secret">secret</a>"#)
+            .create();
+        let _m = mock("GET", "/stage/secret")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("secret")
+            .create();
+
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config().scope("/stage/").ignore_sitemaps(true);
+        let crawl = |config: CrawlerConfig| {
+            let domain = domain.clone();
+            async move {
+                let db = Db::default();
+                let mut crawler = Crawler::new(domain, config).unwrap();
+                (
+                    crawler.crawl(db.clone(), CancellationToken::new()).await,
+                    db,
+                )
+            }
+        };
+
+        let (summary, _) = crawl(config.clone()).await;
+        assert_eq!(summary.stats.fetched, 1);
+        assert_eq!(summary.stats.excluded[&SkipReason::Robots], 1);
+
+        let (summary, _) = crawl(config.clone().robots_mode(RobotsMode::RecordOnly)).await;
+        assert_eq!(summary.stats.fetched, 2);
+        assert_eq!(summary.stats.robots_disallowed, 1);
+
+        let owned = config
+            .clone()
+            .robots_mode(RobotsMode::Ignore)
+            .owned_domain(domain.host_str().unwrap());
+        let (summary, db) = crawl(owned).await;
+        assert_eq!(summary.stats.fetched, 2);
+        assert_eq!(summary.stats.robots_disallowed, 0);
+        assert_eq!(db.robots_txt(&domain).unwrap(), None);
+        assert_eq!(
+            db.robots_modes(&domain).unwrap(),
+            vec![Some(RobotsMode::Ignore)]
+        );
+
+        assert!(Crawler::new(domain, config.robots_mode(RobotsMode::Ignore)).is_err());
+    }
+
+    #[tokio::test]
+    async fn crawl_slow_start() {
+        let _m = mock(
+            "GET",
+            mockito::Matcher::Regex("^/(robots.txt|slow/[a-d])?$".to_string()),
+        )
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .create();
+        let _m = mock("GET", "/slow/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"<a href="/slow/a">a</a><a href="/slow/b">b</a>
+                <a href="/slow/c">c</a><a href="/slow/d">d</a>"#,
+            )
+            .create();
+
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config()
+            .scope("/slow/")
+            .ignore_sitemaps(true)
+            .max_concurrency(2)
+            .slow_start(SlowStart {
+                initial: 1,
+                window: 1,
+                ..SlowStart::default()
+            });
+        let mut crawler = Crawler::new(domain, config).unwrap();
+        assert_eq!(crawler.permits.as_ref().unwrap().available_permits(), 1);
+
+        let summary = crawler.crawl(Db::default(), CancellationToken::new()).await;
+
+        assert_eq!(summary.outcome, CrawlOutcome::Completed);
+        assert_eq!(summary.stats.fetched, 5);
+        assert_eq!(summary.stats.peak_concurrency, Some(2));
+        assert_eq!(crawler.permits.unwrap().available_permits(), 2);
+    }
+
+    #[tokio::test]
+    async fn crawl_max_concurrency() {
+        let _m = mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <a href="/foo">foo</a>
+                <a href="/bar">bar</a>
+            "#,
+            )
+            .create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = CrawlerConfig {
+            max_concurrency: Some(1),
+            ..local_config()
+        };
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.outcome, CrawlOutcome::Completed);
+        assert_eq!(summary.stats.queue_wait.count, 3);
+        // Every task gave its permit back.
+        assert_eq!(crawler.permits.unwrap().available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn crawl_channel_capacity() {
+        let links: String = (0..50)
+            .map(|i| format!(r#"<a href="/backpressure/{}">{}</a>"#, i, i))
+            .collect();
+        let _m = mock("GET", "/backpressure/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(links)
+            .create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        // Each task waits for the crawler after every event.
+        let config = local_config()
+            .scope("/backpressure/")
+            .ignore_sitemaps(true)
+            .max_depth(1)
+            .max_concurrency(2)
+            .channel_capacity(1);
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.outcome, CrawlOutcome::Completed);
+        assert_eq!(summary.stats.fetched, 51);
+        assert_eq!(db.unique_urls_for_domain(&domain).unwrap().len(), 51);
+
+        let config = local_config().channel_capacity(0);
+        assert!(Crawler::new(domain, config).is_err());
+    }
+
+    #[tokio::test]
+    async fn crawl_cancelled() {
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let mut crawler = Crawler::new(domain.clone(), local_config()).unwrap();
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let summary = crawler.crawl(db.clone(), cancel).await;
+
+        assert_eq!(summary.outcome, CrawlOutcome::Cancelled);
+        assert!(db.unique_urls_for_domain(&domain).is_err());
+    }
+
+    #[tokio::test]
+    async fn crawl_paused() {
+        let _m = mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/paused">paused</a>"#)
+            .create();
+        let _m = mock("GET", "/paused").with_status(200).create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let mut crawler =
+            Crawler::new(domain.clone(), local_config().ignore_sitemaps(true)).unwrap();
+        let control = crawler.control();
+        let mut events = crawler.progress().subscribe();
+        control.pause();
+
+        let crawl = tokio::spawn({
+            let db = db.clone();
+            async move { crawler.crawl(db, CancellationToken::new()).await }
+        });
+
+        // The seed is found, but not downloaded while the crawl is paused, so its link is not found.
+        while !matches!(
+            events.recv().await.unwrap().kind,
+            CrawlEventKind::Found { .. }
+        ) {}
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        while let Ok(event) = events.try_recv() {
+            assert!(!matches!(event.kind, CrawlEventKind::Found { .. }));
+        }
+
+        control.resume();
+        let summary = crawl.await.unwrap();
+        assert_eq!(summary.outcome, CrawlOutcome::Completed);
+        compare_sorted(
+            db.unique_urls_for_domain(&domain).unwrap(),
+            vec![domain.clone(), domain.join("/paused").unwrap()],
+        );
+    }
+
+    #[tokio::test]
+    async fn probe() {
+        let _m = mock("GET", "/robots.txt")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("User-agent: *\nCrawl-delay: 0.5\nSitemap: /probe.xml\n")
+            .create();
+        let _m = mock("GET", "/probe.xml")
+            .with_status(200)
+            .with_header("content-type", "application/xml")
+            .with_body(
+                r#"<urlset>
+                <url><loc>/probe/</loc></url>
+                <url><loc>/probe/a</loc></url>
+                <url><loc>/probe/b/c</loc></url>
+                <url><loc>/other</loc></url>
+            </urlset>"#,
+            )
+            .create();
+        let _m = mock("GET", "/probe/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/probe/a">a</a><a href="/other">other</a>"#)
+            .create();
+
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config().scope("/probe/").probe(true);
+        let mut crawler = Crawler::new(domain, config).unwrap();
+
+        let report = crawler.probe().await.unwrap();
+
+        assert_eq!(report.sitemaps, vec!["/probe.xml".to_string()]);
+        assert_eq!(report.sitemap_urls, 3);
+        assert_eq!(report.seed_links, 1);
+        assert_eq!(report.request_interval_ms, Some(500));
+        assert_eq!(report.estimated_pages, 3);
+        assert_eq!(
+            report.recommended,
+            ProbeRecommendation {
+                max_pages: 3,
+                max_depth: 4,
+                estimated_duration_ms: Some(1500),
+            }
+        );
+    }
+
+    #[test]
+    fn probe_without_sitemaps() {
+        let domain = url::Url::parse("https://example.com").unwrap();
+        let seed = CrawlReport {
+            followed: (0..10)
+                .map(|i| domain.join(&format!("/{}", i)).unwrap())
+                .collect(),
+            ..CrawlReport::default()
+        };
+
+        let report = ProbeReport::new(Vec::new(), &[], &seed, None);
+
+        assert_eq!(report.estimated_pages, 111);
+        assert_eq!(
+            report.recommended,
+            ProbeRecommendation {
+                max_pages: 138,
+                max_depth: 2,
+                estimated_duration_ms: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_run() {
+        let _m = mock("GET", "/robots.txt")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("User-agent: *\nDisallow: /private\n")
+            .create();
+
+        let _m = mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <a href="/foo">foo</a>
+                <a href="/foo#top">foo again</a>
+                <a href="/private/page">private</a>
+                <a href="https://example.com/">elsewhere</a>
+                <nav><a href="/menu">menu</a></nav>
+            "#,
+            )
+            .create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = CrawlerConfig {
+            dry_run: true,
+            ..local_config()
+        }
+        .exclude_selector("nav");
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+
+        let report = crawler.dry_run().await.unwrap();
+
+        assert_eq!(report.followed, vec![domain.join("/foo").unwrap()]);
+        let skipped: Vec<_> = report
+            .skipped
+            .iter()
+            .map(|skipped| (skipped.url.as_str(), skipped.reason))
+            .collect();
+        let private = domain.join("/private/page").unwrap();
+        let foo = domain.join("/foo").unwrap();
+        let menu = domain.join("/menu").unwrap();
+        assert_eq!(
+            skipped,
+            vec![
+                (foo.as_str(), SkipReason::AlreadyVisited),
+                (private.as_str(), SkipReason::Robots),
+                ("https://example.com/", SkipReason::OffDomain),
+                (menu.as_str(), SkipReason::ExcludedSelector),
+            ]
+        );
+        assert_eq!(report.excluded.len(), 4);
+        assert_eq!(report.excluded[&SkipReason::Robots], 1);
+
+        assert!(db.unique_urls_for_domain(&domain).is_err());
+    }
+
+    #[tokio::test]
+    async fn check_url() {
+        let _m = mock("GET", "/robots.txt")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("User-agent: *\nDisallow: /private\n")
+            .create();
+        let _m = mock("GET", "/page")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/foo">foo</a><a href="/private/a">private</a>"#)
+            .create();
+        let _m = mock("GET", "/gone").with_status(404).create();
+        let private = mock("GET", "/private/page").expect(0).create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let mut crawler = Crawler::new(domain.clone(), local_config()).unwrap();
 
-        Ok(Self {
-            domain,
-            downloader,
-            robots_txt: String::from(""),
-        })
-    }
+        let check = crawler.check(&domain.join("/page").unwrap(), &db).await;
+        assert_eq!(check.status, Some(200));
+        assert_eq!(check.content_type.as_deref(), Some("text/html"));
+        assert_eq!(check.final_url, Some(domain.join("/page").unwrap()));
+        let links = check.links.unwrap();
+        assert_eq!(links.followed, vec![domain.join("/foo").unwrap()]);
+        assert_eq!(links.excluded[&SkipReason::Robots], 1);
+        // Only the checked URL is recorded, not its links.
+        assert_eq!(
+            db.unique_urls_for_domain(&domain).unwrap(),
+            vec![domain.join("/page").unwrap()]
+        );
 
-    /// A reference to the crawler's domain.
-    pub(crate) fn domain(&self) -> &Url {
-        &self.domain
+        let check = crawler.check(&domain.join("/gone").unwrap(), &db).await;
+        assert_eq!(check.status, Some(404));
+        assert!(check.error.is_some());
+        assert!(check.links.is_none());
+
+        let check = crawler
+            .check(&domain.join("/private/page").unwrap(), &db)
+            .await;
+        assert_eq!(check.skipped, Some(SkipReason::Robots));
+        let check = crawler
+            .check(&url::Url::parse("https://example.com/").unwrap(), &db)
+            .await;
+        assert_eq!(check.skipped, Some(SkipReason::OffDomain));
+        private.assert();
     }
 
-    /// Start crawling the domain associated with this crawler and populate the `db` with found URLs.
-    pub(crate) async fn crawl(&mut self, db: Db, shutdown: broadcast::Sender<()>) {
-        // Try to download the `robots.txt` if it exists.
-        let robots_url = self.domain.join("robots.txt").unwrap();
-        let page = self.downloader.download(&robots_url).await.ok();
-        if let Some(page) = page {
-            self.robots_txt = page;
-        }
+    #[tokio::test]
+    async fn crawl_warm_start() {
+        let m = mock("GET", "/old")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/new">new</a>"#)
+            .create();
 
-        // Give each async task a `Sender`. When all tasks end, the senders are dropped,
-        // and the crawler has finished work.
-        let mut urls = SelectAll::new();
-        let (tx, rx) = mpsc::unbounded_channel();
+        let _m = mock("GET", "/new")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("body")
+            .create();
 
-        // Seed the crawler with the initial domain URL.
-        tx.send(self.domain.clone()).unwrap();
-        drop(tx);
-        let rx = UnboundedReceiverStream::new(rx);
-        urls.push(rx);
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        db.visit(Cow::Owned(domain.join("/old").unwrap())).unwrap();
 
-        let (shutdown_complete_tx, mut shutdown_complete_rx) = broadcast::channel(1);
+        let config = CrawlerConfig {
+            warm_start: WarmStart::Replace,
+            ..local_config()
+        };
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        crawler.crawl(db.clone(), CancellationToken::new()).await;
 
-        let mut shutdown_receiver = shutdown.subscribe();
+        m.assert();
+        let expected = vec![domain.join("/old").unwrap(), domain.join("/new").unwrap()];
+        let unique_urls = db.unique_urls_for_domain(&domain).unwrap();
 
-        // Process incoming URLs as long as there are still spawned async tasks that are sending data.
-        loop {
-            tokio::select! {
-                url = urls.next() => {
-                    if let Some(url) = url {
-                        // Further spawn a task for each URL we are supposed to visit.
-                        if self.process_url(&url, &db) == ProcessResult::ShouldVisit {
-                            // Send the Sender to the task, register the receiver stream.
-                            let (tx, rx) = mpsc::unbounded_channel();
-                            let rx = UnboundedReceiverStream::new(rx);
-                            urls.push(rx);
-
-                            let shutdown_complete = shutdown_complete_tx.clone();
-
-                            // Create a download + parse task
-                            let mut task = Task {
-                                downloader: self.downloader.clone(),
-                                domain: self.domain.clone(),
-                                url,
-                                tx,
-                                notify_shutdown: shutdown.subscribe(),
-                                _shutdown_complete: shutdown_complete
-                            };
-
-                            tokio::spawn(async move {
-                                task.run().await
-                            });
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                _ = shutdown_receiver.recv() => {
-                    info!("Shutting down");
-                    break;
+        compare_sorted(unique_urls, expected);
+    }
+
+    #[tokio::test]
+    async fn crawl_sinks() {
+        let _m = mock(
+            "GET",
+            mockito::Matcher::Regex("^/(robots.txt|sink/)?$".to_string()),
+        )
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .create();
+
+        let path =
+            std::env::temp_dir().join(format!("crawler-events-{}.ndjson", std::process::id()));
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config()
+            .scope("/sink/")
+            .ignore_sitemaps(true)
+            .sink(SinkConfig::File { path: path.clone() });
+        assert!(Crawler::new(domain.clone(), config.clone()).is_err());
+
+        let mut crawler = Crawler::new(domain.clone(), config.allow_local_sinks(true)).unwrap();
+        crawler.crawl(Db::default(), CancellationToken::new()).await;
+
+        let events: Vec<_> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<CrawlEvent>(line).unwrap().kind)
+            .collect();
+        let seed = domain.join("/sink/").unwrap();
+        assert_eq!(
+            events,
+            [
+                CrawlEventKind::Found { url: seed.clone() },
+                CrawlEventKind::Fetched { url: seed },
+                CrawlEventKind::Finished {
+                    outcome: CrawlOutcome::Completed
                 }
-            }
-        }
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
 
-        drop(shutdown_complete_tx);
+    #[tokio::test]
+    async fn crawl_unchanged() {
+        let _m = mock("GET", "/robots.txt")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .create();
+        let unchanged = mock("GET", "/cond/page")
+            .match_header("if-none-match", "\"v1\"")
+            .with_status(304)
+            .expect(1)
+            .create();
+        let _m = mock("GET", "/cond/page")
+            .match_header("if-none-match", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_header("etag", "\"v2\"")
+            .with_body("<title>New</title>")
+            .create();
 
-        let _ = shutdown_complete_rx.recv().await;
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let page = domain.join("/cond/page").unwrap();
+        db.visit(Cow::Borrowed(&page)).unwrap();
+        let stored = FetchStatus {
+            title: Some("Old".to_string()),
+            etag: Some("\"v1\"".to_string()),
+            ..FetchStatus::ok(200)
+        };
+        db.set_status(&page, &stored).unwrap();
+
+        let config = CrawlerConfig {
+            warm_start: WarmStart::Replace,
+            ..local_config()
+        };
+        let mut crawler = Crawler::new(domain.clone(), config.clone()).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        unchanged.assert();
+        assert_eq!(summary.stats.unchanged_pages, 1);
+        assert_eq!(db.status(&page).unwrap(), Some(stored));
+
+        let mut crawler = Crawler::new(domain.clone(), config.ignore_validators(true)).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.stats.unchanged_pages, 0);
+        let status = db.status(&page).unwrap().unwrap();
+        assert_eq!(status.title.as_deref(), Some("New"));
+        assert_eq!(status.etag.as_deref(), Some("\"v2\""));
     }
 
-    /// Processes the URL by registering it to the database and checking wether it should be
-    /// visited or it was already visited by a previous crawler/from a diferent path.
-    fn process_url(&mut self, url: &Url, db: &Db) -> ProcessResult {
-        info!("Processing url {}", url);
+    #[tokio::test]
+    async fn crawl_content_types() {
+        let _m = mock("GET", "/robots.txt")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .create();
+        let _m = mock("GET", "/types/")
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"<a href="/types/report.pdf">Report</a><a href="/types/notes.txt">Notes</a>"#,
+            )
+            .create();
+        let _m = mock("GET", "/types/report.pdf")
+            .with_header("content-type", "application/pdf")
+            .with_body(r#"<a href="/types/hidden">"#)
+            .create();
+        let _m = mock("GET", "/types/notes.txt")
+            .with_header("content-type", "text/plain; charset=utf-8")
+            .with_body(r#"<a href="/types/plain">"#)
+            .create();
+        let _m = mock("GET", "/types/plain")
+            .with_header("content-type", "text/html")
+            .create();
 
-        // Restrict to current domain.
-        if url.domain() != self.domain.domain() {
-            trace!("Different domain");
-            return ProcessResult::ShouldNotVisit;
-        }
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config().scope("/types/").ignore_sitemaps(true);
+        let db = Db::default();
+        let mut crawler = Crawler::new(domain.clone(), config.clone()).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
 
-        // Respect robots.txt
-        let mut matcher = DefaultMatcher::default();
-        if !matcher.allowed_by_robots(&self.robots_txt, vec!["*"], &url.as_str()) {
-            trace!("Not allowed by robots");
-            return ProcessResult::ShouldNotVisit;
-        }
+        assert_eq!(summary.stats.skipped_bodies, 2);
+        let pdf = db
+            .status(&domain.join("/types/report.pdf").unwrap())
+            .unwrap()
+            .unwrap();
+        assert!(pdf.body_skipped);
+        assert_eq!(pdf.content_type.as_deref(), Some("application/pdf"));
+        assert_eq!((pdf.bytes, pdf.content_hash), (None, None));
+        let plain = domain.join("/types/plain").unwrap();
+        assert_eq!(db.status(&plain).unwrap(), None);
 
-        let is_first_visit = match db.is_first_visit(&url) {
-            Ok(o) => o,
-            Err(e) => {
-                error!("Skipping {}, DB Error: {}", url, e);
-                return ProcessResult::ShouldNotVisit;
-            }
+        let db = Db::default();
+        let config = config.content_type("text/html").content_type("text/*");
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+        assert_eq!(summary.stats.skipped_bodies, 1);
+        assert!(db.status(&plain).unwrap().is_some());
+
+        let config = local_config().content_type("pdf");
+        assert!(Crawler::new(domain, config).is_err());
+    }
+
+    #[tokio::test]
+    async fn crawl_diff() {
+        let _m = mock("GET", "/robots.txt")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .create();
+        let page = |path: &str, body: &str| {
+            mock("GET", path)
+                .with_status(200)
+                .with_header("content-type", "text/html")
+                .with_body(body)
+                .create()
         };
 
-        // Register visit to database
-        match db.visit(Cow::Borrowed(url)) {
-            Ok(_) => {}
-            Err(e) => {
-                error!("Skipping {}, DB Error: {}", url, e);
-                return ProcessResult::ShouldNotVisit;
-            }
-        }
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let url = |path: &str| domain.join(path).unwrap();
+        let config = local_config().scope("/diff/").ignore_sitemaps(true);
 
-        // Do not visit a second time
-        if is_first_visit {
-            ProcessResult::ShouldVisit
-        } else {
-            ProcessResult::ShouldNotVisit
-        }
+        let first = [
+            page(
+                "/diff/",
+                r#"<a href="/diff/a">a</a><a href="/diff/b">b</a>"#,
+            ),
+            page("/diff/a", "<title>A</title>"),
+            page("/diff/b", "<title>B</title>"),
+        ];
+        let mut crawler = Crawler::new(domain.clone(), config.clone()).unwrap();
+        crawler.crawl(db.clone(), CancellationToken::new()).await;
+        drop(first);
+
+        let diff = db.diff_for_domain(&domain).unwrap().unwrap();
+        assert_eq!(diff.session, 1);
+        assert_eq!(diff.new, [url("/diff/"), url("/diff/a"), url("/diff/b")]);
+        assert!(diff.changed.is_empty());
+
+        // The page linking to `b` now links to `c` instead, and `a` changed.
+        let _second = [
+            page(
+                "/diff/",
+                r#"<a href="/diff/a">a</a><a href="/diff/c">c</a>"#,
+            ),
+            page("/diff/a", "<title>A, updated</title>"),
+            page("/diff/b", "<title>B</title>"),
+            page("/diff/c", "<title>C</title>"),
+        ];
+        let mut crawler =
+            Crawler::new(domain.clone(), config.warm_start(WarmStart::Append)).unwrap();
+        crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        let diff = db.diff_for_domain(&domain).unwrap().unwrap();
+        assert_eq!(diff.session, 2);
+        assert_eq!(diff.new, [url("/diff/c")]);
+        assert_eq!(diff.removed, [url("/diff/b")]);
+        assert_eq!(diff.changed, [url("/diff/"), url("/diff/a")]);
+        let status = db.status(&url("/diff/b")).unwrap().unwrap();
+        assert_eq!(status.session, Some(2));
+        assert!(!status.changed);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use mockito::mock;
-    use tokio::sync::broadcast;
+    #[tokio::test]
+    async fn crawl_www_redirect_credentials() {
+        // Requests through an HTTP proxy have the whole URL as their path.
+        let _apex = mock("GET", "http://auth.example.com/")
+            .with_status(301)
+            .with_header("location", "http://www.auth.example.com/")
+            .create();
+        let m = mock("GET", "http://www.auth.example.com/")
+            .match_header("authorization", "Bearer secret")
+            .match_header("cookie", "session=abc")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<p>Members only</p>")
+            .expect(1)
+            .create();
 
-    use crate::db::Db;
+        let domain = url::Url::parse("http://auth.example.com").unwrap();
+        let mut config = local_config();
+        config.downloader.proxy = Some(ProxyConfig {
+            url: url::Url::parse(&mockito::server_url()).unwrap(),
+            no_proxy: Vec::new(),
+        });
+        config.downloader.auth = Some(Credentials::Bearer {
+            token: "secret".to_string(),
+        });
+        config.downloader.cookies = vec!["session=abc".to_string()];
+        let mut crawler = Crawler::new(domain, config).unwrap();
+        let summary = crawler.crawl(Db::default(), CancellationToken::new()).await;
 
-    use super::Crawler;
-    use crate::tests::compare_sorted;
+        assert_eq!(
+            summary.stats.redirected_to,
+            Some(url::Url::parse("http://www.auth.example.com/").unwrap())
+        );
+        m.assert();
+    }
 
     #[tokio::test]
-    async fn crawl_domain() {
+    async fn crawl_prefix_limits() {
         let _m = mock("GET", "/")
             .with_status(200)
             .with_header("content-type", "text/html")
             .with_body(
                 r#"
-                <a href="/foo">foo</a>
-                <a href="/bar">bar</a>
+                <a href="/forum/1">1</a>
+                <a href="/forum/2">2</a>
+                <a href="/forum/3">3</a>
+                <a href="/forum-archive/1">archive 1</a>
+                <a href="/forum-archive/2">archive 2</a>
+                <a href="/about">about</a>
             "#,
             )
             .create();
 
-        let _m = mock("GET", "/foo")
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let mut config = local_config();
+        // Without a trailing slash, the prefix still does not cover `/forum-archive`.
+        config.prefix_limits.insert("/forum".to_string(), 2);
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        let stats = summary.stats;
+        assert_eq!(stats.fetched, 6);
+        assert_eq!(stats.prefix_counts["/forum"], 2);
+        assert!(stats.truncated);
+    }
+
+    #[tokio::test]
+    async fn crawl_max_pages() {
+        let _m = mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(
+                r#"
+                <a href="/1">1</a>
+                <a href="/2">2</a>
+                <a href="/3">3</a>
+            "#,
+            )
+            .create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = CrawlerConfig {
+            max_pages: Some(2),
+            ..local_config()
+        };
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.stats.fetched, 2);
+        assert!(summary.stats.truncated);
+        assert!(db.is_truncated(&domain).unwrap());
+
+        let progress = crawler.progress().counters();
+        assert_eq!(progress.fetched, 2);
+        assert!(progress.found >= 4);
+    }
+
+    #[tokio::test]
+    async fn crawl_max_depth() {
+        let _m = mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/depth/1">1</a>"#)
+            .create();
+
+        let _m = mock("GET", "/depth/1")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/depth/2">2</a>"#)
+            .create();
+
+        let m = mock("GET", "/depth/2").expect(0).create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = CrawlerConfig {
+            max_depth: Some(1),
+            ..local_config()
+        };
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        m.assert();
+        assert_eq!(summary.stats.fetched, 2);
+        assert_eq!(summary.stats.excluded[&SkipReason::MaxDepth], 1);
+        let expected = vec![domain.clone(), domain.join("/depth/1").unwrap()];
+        let unique_urls = db.unique_urls_for_domain(&domain).unwrap();
+
+        compare_sorted(unique_urls, expected);
+    }
+
+    #[tokio::test]
+    async fn crawl_guards() {
+        let _m = mock(
+            "GET",
+            mockito::Matcher::Regex("^/(robots.txt|guard/a|guard/b)?$".to_string()),
+        )
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .create();
+        let long = format!("/guard/{}", "x".repeat(100));
+        let _m = mock("GET", "/guard/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(format!(
+                r#"<a href="/guard/a">a</a><a href="{}">long</a><a href="/guard/b">b</a>
+                <a href="/guard/c">c</a><a href="/guard/d">d</a>"#,
+                long
+            ))
+            .create();
+        let m = mock(
+            "GET",
+            mockito::Matcher::Regex("^/guard/(c|d|x+)$".to_string()),
+        )
+        .expect(0)
+        .create();
+
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = local_config()
+            .scope("/guard/")
+            .ignore_sitemaps(true)
+            .max_url_length(100)
+            .max_links_per_page(3);
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        let progress = crawler.progress();
+        let summary = crawler.crawl(Db::default(), CancellationToken::new()).await;
+
+        m.assert();
+        assert_eq!(summary.stats.fetched, 3);
+        assert!(summary.stats.truncated);
+        assert_eq!(summary.stats.excluded[&SkipReason::UrlTooLong], 1);
+        assert_eq!(summary.stats.excluded[&SkipReason::PageLinkLimit], 2);
+        assert_eq!(
+            summary.stats.guard_examples[&SkipReason::UrlTooLong],
+            [domain.join(&long).unwrap()]
+        );
+        assert_eq!(
+            summary.stats.guard_examples[&SkipReason::PageLinkLimit],
+            [
+                domain.join("/guard/c").unwrap(),
+                domain.join("/guard/d").unwrap()
+            ]
+        );
+        assert_eq!(progress.rejected()[&SkipReason::PageLinkLimit], 2);
+        for reason in SkipReason::GUARDS {
+            assert_eq!(serde_json::to_value(reason).unwrap(), reason.as_str());
+        }
+    }
+
+    #[tokio::test]
+    async fn crawl_sitemap() {
+        let _m = mock("GET", "/robots.txt")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("Sitemap: /pages.xml\n")
+            .create();
+
+        let _m = mock("GET", "/pages.xml")
+            .with_status(200)
+            .with_header("content-type", "application/xml")
+            .with_body("<urlset><url><loc>/orphan</loc></url></urlset>")
+            .create();
+
+        let m = mock("GET", "/orphan")
             .with_status(200)
             .with_header("content-type", "text/plain")
             .with_body("body")
@@ -185,18 +3578,366 @@ mod tests {
 
         let db = Db::default();
         let domain = url::Url::parse(&mockito::server_url()).unwrap();
-        let mut crawler = Crawler::new(domain.clone()).unwrap();
+        let mut crawler = Crawler::new(domain.clone(), local_config()).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
 
-        let (tx, _rx) = broadcast::channel(1);
-        crawler.crawl(db.clone(), tx).await;
+        m.assert();
+        assert_eq!(summary.stats.sitemap_urls, 1);
+        assert_eq!(
+            db.url_count_for_domain(&domain.join("/orphan").unwrap())
+                .unwrap(),
+            1
+        );
+    }
 
-        let expected = vec![
+    #[tokio::test]
+    async fn crawl_robots_directives() {
+        let _m = mock("GET", "/robots.txt")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("User-agent: *\nCrawl-delay: 0.05\nSitemap: /sitemap.xml\n")
+            .create();
+
+        let db = Db::default();
+        let domain = url::Url::parse(&mockito::server_url()).unwrap();
+        let config = CrawlerConfig {
+            delay_ms: Some(10),
+            ..local_config()
+        };
+        let mut crawler = Crawler::new(domain.clone(), config).unwrap();
+        let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+
+        assert_eq!(summary.stats.request_interval_ms, Some(50));
+        assert_eq!(summary.stats.sitemaps, vec!["/sitemap.xml".to_string()]);
+        assert_eq!(
+            db.robots_txt(&domain).unwrap().unwrap(),
+            "User-agent: *\nCrawl-delay: 0.05\nSitemap: /sitemap.xml\n"
+        );
+    }
+
+    #[test]
+    fn parse_options() {
+        let defaults = CrawlerConfig::default().max_pages(100).delay_ms(250);
+
+        let config: CrawlerConfig = defaults
+            .parse_options(serde_json::json!({"max_pages": 10, "max_depth": 2}))
+            .unwrap();
+        assert_eq!(config.max_pages, Some(10));
+        assert_eq!(config.max_depth, Some(2));
+        assert_eq!(config.delay_ms, Some(250));
+
+        assert!(defaults
+            .parse_options::<CrawlerConfig>(serde_json::json!([1]))
+            .is_err());
+        assert!(defaults
+            .parse_options::<CrawlerConfig>(serde_json::json!({"max_pages": "all"}))
+            .is_err());
+    }
+
+    #[test]
+    fn politeness_limits() {
+        let domain = url::Url::parse("https://example.com").unwrap();
+        let new = |config| Crawler::new(domain.clone(), config);
+
+        assert!(new(CrawlerConfig::default().delay_ms(60 * 60 * 1000)).is_ok());
+        assert!(new(CrawlerConfig::default().delay_ms(u64::MAX)).is_err());
+        assert!(new(CrawlerConfig::default().max_rps(0.5)).is_ok());
+        assert!(new(CrawlerConfig::default().max_rps(0.0)).is_err());
+        assert!(new(CrawlerConfig::default().max_rps(1e-300)).is_err());
+        assert!(new(CrawlerConfig::default().max_rps(f64::INFINITY)).is_err());
+
+        let mut config = CrawlerConfig::default();
+        config.downloader.max_connections_per_host = Some(0);
+        assert!(new(config).is_err());
+        let mut config = CrawlerConfig::default();
+        config.downloader.circuit_breaker = Some(CircuitBreakerConfig {
+            cooldown_ms: u64::MAX,
+            ..CircuitBreakerConfig::default()
+        });
+        assert!(new(config).is_err());
+        let retry = |max_attempts, base_delay_ms, max_delay_ms| {
+            let mut config = CrawlerConfig::default();
+            config.downloader.retry = Some(RetryConfig {
+                max_attempts,
+                base_delay_ms,
+                max_delay_ms,
+            });
+            new(config)
+        };
+        assert!(retry(10, 1000, 60 * 1000).is_ok());
+        assert!(retry(1_000_000_000, 500, 10_000).is_err());
+        assert!(retry(3, 500, u64::MAX).is_err());
+        assert!(retry(3, 20_000, 10_000).is_err());
+        let timeouts = |connect_timeout_ms, read_timeout_ms| {
+            let mut config = CrawlerConfig::default();
+            config.downloader.connect_timeout_ms = connect_timeout_ms;
+            config.downloader.read_timeout_ms = read_timeout_ms;
+            new(config)
+        };
+        assert!(timeouts(Some(1), Some(5 * 60 * 1000)).is_ok());
+        assert!(timeouts(Some(0), None).is_err());
+        assert!(timeouts(None, Some(0)).is_err());
+        assert!(timeouts(Some(u64::MAX), None).is_err());
+        assert!(timeouts(None, Some(5 * 60 * 1000 + 1)).is_err());
+        let config = CrawlerConfig::default().slow_start(SlowStart {
+            max_error_rate: f64::NAN,
+            ..SlowStart::default()
+        });
+        assert!(new(config).is_err());
+        let config = CrawlerConfig {
+            fail_on: FailOn {
+                error_rate: Some(1.5),
+                ..FailOn::default()
+            },
+            ..CrawlerConfig::default()
+        };
+        assert!(new(config).is_err());
+    }
+
+    #[test]
+    fn domain_overrides() {
+        let gentle = |delay_ms| DomainOverrides {
+            delay_ms: Some(delay_ms),
+            ..DomainOverrides::default()
+        };
+        let config = CrawlerConfig::default()
+            .delay_ms(100)
+            .domain_override("example.com", gentle(1000))
+            .domain_override("shop.example.com", gentle(5000));
+        let delay = |domain: &str| {
+            Crawler::new(url::Url::parse(domain).unwrap(), config.clone())
+                .unwrap()
+                .config()
+                .delay_ms
+        };
+
+        assert_eq!(delay("https://example.com"), Some(1000));
+        assert_eq!(delay("https://www.example.com"), Some(1000));
+        assert_eq!(delay("https://shop.example.com"), Some(5000));
+        assert_eq!(delay("https://notexample.com"), Some(100));
+    }
+
+    #[test]
+    fn config_builder() {
+        let config = CrawlerConfig::default()
+            .max_pages(100)
+            .prefix_limit("/forum/", 10)
+            .exclude_selector("nav")
+            .tag("team", "seo");
+
+        assert_eq!(config.max_pages, Some(100));
+        assert_eq!(config.prefix_limits["/forum/"], 10);
+        assert_eq!(config.exclude_selectors, vec!["nav".to_string()]);
+        assert_eq!(config.tags["team"], "seo");
+        assert_eq!(config.max_depth, None);
+    }
+
+    #[test]
+    fn www_variant() {
+        let url = |url: &str| url::Url::parse(url).unwrap();
+
+        assert!(is_www_variant(
+            &url("https://example.com"),
+            &url("https://www.example.com/")
+        ));
+        assert!(is_www_variant(
+            &url("http://www.example.com"),
+            &url("https://example.com/home")
+        ));
+        assert!(!is_www_variant(
+            &url("https://example.com"),
+            &url("https://example.com/")
+        ));
+        assert!(!is_www_variant(
+            &url("https://example.com"),
+            &url("https://blog.example.com/")
+        ));
+    }
+
+    #[test]
+    fn classify() {
+        let crawler = Crawler::new(
+            url::Url::parse("https://www.example.com").unwrap(),
+            CrawlerConfig::default(),
+        )
+        .unwrap();
+        let classify = |url: &str| crawler.classify(&url::Url::parse(url).unwrap());
+
+        assert_eq!(
+            classify("http://www.example.com/foo"),
+            Some(LinkClass::Internal)
+        );
+        assert_eq!(
+            classify("https://example.com/foo"),
+            Some(LinkClass::Subdomain)
+        );
+        assert_eq!(
+            classify("https://blog.example.com/"),
+            Some(LinkClass::Subdomain)
+        );
+        assert_eq!(
+            classify("https://notexample.com/"),
+            Some(LinkClass::ThirdParty)
+        );
+        assert_eq!(classify("mailto:foo@example.com"), None);
+
+        // Sites are told apart with the public suffix list.
+        let crawler = Crawler::new(
+            url::Url::parse("https://shop.example.co.uk").unwrap(),
+            CrawlerConfig::default(),
+        )
+        .unwrap();
+        let classify = |url: &str| crawler.classify(&url::Url::parse(url).unwrap());
+        assert_eq!(
+            classify("https://blog.example.co.uk/"),
+            Some(LinkClass::Subdomain)
+        );
+        assert_eq!(
+            classify("https://other.co.uk/"),
+            Some(LinkClass::ThirdParty)
+        );
+    }
+
+    #[test]
+    fn scope_ports() {
+        let domain = url::Url::parse("https://example.com:8080").unwrap();
+        let crawler = Crawler::new(domain.clone(), CrawlerConfig::default()).unwrap();
+
+        assert_eq!(crawler.check_scope(&domain.join("/foo").unwrap()), Ok(()));
+        assert_eq!(
+            crawler.check_scope(&url::Url::parse("https://example.com/foo").unwrap()),
+            Err(SkipReason::OffDomain)
+        );
+
+        let domain = url::Url::parse("https://example.com").unwrap();
+        let crawler = Crawler::new(domain, CrawlerConfig::default()).unwrap();
+
+        assert_eq!(
+            crawler.check_scope(&url::Url::parse("http://example.com/foo").unwrap()),
+            Ok(())
+        );
+        assert_eq!(
+            crawler.check_scope(&url::Url::parse("https://example.com:8443/foo").unwrap()),
+            Err(SkipReason::OffDomain)
+        );
+    }
+
+    #[test]
+    fn scope_prefixes() {
+        let domain = url::Url::parse("https://example.com").unwrap();
+        let config = CrawlerConfig::default().scope("/blog").scope("/docs/");
+        let crawler = Crawler::new(domain.clone(), config).unwrap();
+        let check = |path| crawler.check_scope(&domain.join(path).unwrap());
+
+        assert_eq!(check("/blog"), Ok(()));
+        assert_eq!(check("/blog/post"), Ok(()));
+        assert_eq!(check("/docs/"), Ok(()));
+        assert_eq!(check("/docs/api"), Ok(()));
+        assert_eq!(check("/blogger"), Err(SkipReason::OutOfScope));
+        assert_eq!(check("/docs"), Err(SkipReason::OutOfScope));
+    }
+
+    #[test]
+    fn scope_subdomains() {
+        let domain = url::Url::parse("https://www.example.com").unwrap();
+        let parse = |url: &str| url::Url::parse(url).unwrap();
+
+        let crawler = Crawler::new(domain.clone(), CrawlerConfig::default()).unwrap();
+        assert_eq!(
+            crawler.check_scope(&parse("https://blog.example.com/")),
+            Err(SkipReason::OffDomain)
+        );
+
+        let config = CrawlerConfig::default().include_subdomains(true);
+        let mut crawler = Crawler::new(domain, config).unwrap();
+        crawler.robots_txt = "User-agent: *\nDisallow: /private\n".to_string();
+        crawler.host_robots.insert(
+            "blog.example.com".to_string(),
+            "User-agent: *\nDisallow: /drafts\n".to_string(),
+        );
+
+        // Each host has its own `robots.txt`.
+        assert_eq!(
+            crawler.check_scope(&parse("https://blog.example.com/private")),
+            Ok(())
+        );
+        assert_eq!(
+            crawler.check_scope(&parse("https://blog.example.com/drafts/1")),
+            Err(SkipReason::Robots)
+        );
+        assert_eq!(
+            crawler.check_scope(&parse("https://www.example.com/private")),
+            Err(SkipReason::Robots)
+        );
+        assert_eq!(crawler.check_scope(&parse("https://example.com/")), Ok(()));
+        assert_eq!(
+            crawler.check_scope(&parse("https://example.org/")),
+            Err(SkipReason::OffDomain)
+        );
+    }
+
+    #[test]
+    fn dev_mode() {
+        let dev = DevMode {
+            ignore_robots: true,
+            ports: vec![8080],
+        };
+        let config = CrawlerConfig {
+            dev: dev.clone(),
+            ..CrawlerConfig::default()
+        };
+        assert!(Crawler::new(url::Url::parse("https://example.com").unwrap(), config).is_err());
+
+        let domain = url::Url::parse("http://localhost:3000").unwrap();
+        let mut crawler = Crawler::new(
             domain.clone(),
-            domain.join("/foo").unwrap(),
-            domain.join("/bar").unwrap(),
-        ];
-        let unique_urls = db.unique_urls_for_domain(&domain).unwrap();
+            CrawlerConfig {
+                dev,
+                ..CrawlerConfig::default()
+            },
+        )
+        .unwrap();
+        crawler.robots_txt = "User-agent: *\nDisallow: /\n".to_string();
 
-        compare_sorted(unique_urls, expected);
+        assert_eq!(crawler.check_scope(&domain.join("/foo").unwrap()), Ok(()));
+        assert_eq!(
+            crawler.check_scope(&url::Url::parse("http://localhost:8080/api").unwrap()),
+            Ok(())
+        );
+        assert_eq!(
+            crawler.check_scope(&url::Url::parse("http://localhost:8081/api").unwrap()),
+            Err(SkipReason::OffDomain)
+        );
+        assert_eq!(
+            crawler.check_scope(&url::Url::parse("http://127.0.0.1:8080/api").unwrap()),
+            Err(SkipReason::OffDomain)
+        );
+    }
+
+    #[test]
+    fn fail_on() {
+        let mut stats = CrawlStats {
+            fetched: 10,
+            ..CrawlStats::default()
+        };
+        stats.errors.insert("http_404".to_string(), 1);
+
+        let fail_on = FailOn {
+            error_rate: Some(0.1),
+            status: vec![500],
+        };
+        assert_eq!(fail_on.check(&stats), None);
+
+        stats.errors.insert("timeout".to_string(), 1);
+        assert!(fail_on.check(&stats).is_some());
+
+        let fail_on = FailOn {
+            error_rate: None,
+            status: vec![404],
+        };
+        assert_eq!(
+            fail_on.check(&stats).as_deref(),
+            Some("1 pages responded with status 404")
+        );
     }
 }
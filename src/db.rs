@@ -1,106 +1,1658 @@
+mod dump;
+mod overflow;
+mod persistent;
+
 use std::{
     borrow::Cow,
-    collections::HashMap,
-    sync::{Arc, RwLock},
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
 };
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tracing::info;
 use url::{Position, Url};
 
-type UniqueUrlsMap = HashMap<String, usize>;
-type DomainsMap = HashMap<String, UniqueUrlsMap>;
+use crate::{
+    diagnostics::ContentIssue,
+    graph::{Edge, LinkGraph},
+    normalize::normalize_escapes,
+    parser::AssetKind,
+    robots::RobotsMode,
+    search::{self, SearchHit},
+};
+
+pub use dump::{DumpError, DumpSummary};
+pub use overflow::OverflowStorage;
+pub use persistent::SledStorage;
+
+/// An URL of a domain with its number of occurences and how its last fetch went, if it was fetched.
+pub type UrlDetails = (Url, usize, Option<FetchStatus>);
+
+/// The order `Db::url_counts_page` lists URLs in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlSort {
+    /// By path and query.
+    #[default]
+    Url,
+    /// The most occurences first, then by path and query.
+    Count,
+}
+
+/// Which URLs of a domain `Db::url_counts_page` lists, and in which order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UrlQuery {
+    /// Only the URLs whose path starts with this, e.g. `/blog/`.
+    pub prefix: Option<String>,
+    /// Only the URLs with at least this number of occurences.
+    pub min_count: usize,
+    pub sort: UrlSort,
+}
+
+impl UrlQuery {
+    fn matches(&self, path: &str, count: usize) -> bool {
+        count >= self.min_count
+            && self
+                .prefix
+                .as_deref()
+                .is_none_or(|prefix| path.starts_with(prefix))
+    }
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum DbError {
+    #[error("URL does not contain domain")]
+    DoesNotContainDomain,
+    #[error("URL cannot be a base")]
+    CannotBeABase,
+    #[error("Domain does not exist")]
+    DomainDoesNotExist,
+    #[error("Storage error: {0}")]
+    Storage(String),
+    /// A stored value that cannot be read, unlike a `Storage` error of a backend that is down.
+    #[error("Corrupted data: {0}")]
+    Corrupted(String),
+}
+
+/// Key under which an URL is stored: its domain, and its path and query, with its escapes
+/// normalized as by `NormalizeConfig::normalize_escapes`, whatever the crawl options.
+/// The scheme, userinfo and fragment are not part of the key, unless the fragment is a `#/route`
+/// of a single-page app, as kept by `NormalizeConfig::fragment_routes`.
+#[derive(Debug, PartialEq, Eq)]
+struct UrlKey<'a> {
+    domain: Cow<'a, str>,
+    path: Cow<'a, str>,
+}
+
+impl<'a> UrlKey<'a> {
+    fn new(url: &'a Url) -> Result<Self, DbError> {
+        let end = if url
+            .fragment()
+            .is_some_and(|fragment| fragment.starts_with('/'))
+        {
+            Position::AfterFragment
+        } else {
+            Position::AfterQuery
+        };
+
+        Ok(Self {
+            domain: parse_domain(url)?,
+            path: normalize_escapes(&url[Position::BeforePath..end]),
+        })
+    }
+}
+
+/// What is stored about a domain, besides its URLs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DomainMeta {
+    /// The last crawl of the domain stopped before visiting every URL it found.
+    truncated: bool,
+    /// The domain redirects to this one, e.g. `example.com` to `www.example.com`, where its data
+    /// is stored.
+    alias: Option<Url>,
+    /// `robots.txt` as fetched by the last crawl, empty if the domain has none.
+    robots_txt: Option<String>,
+    /// Start time of each crawl session of the domain, see `Db::start_session`.
+    #[serde(default)]
+    sessions: Vec<DateTime<Utc>>,
+    /// How each crawl session treated `robots.txt`, like `sessions`. Sessions started before it
+    /// was recorded have none.
+    #[serde(default)]
+    robots_modes: Vec<Option<RobotsMode>>,
+}
+
+/// The crawl sessions of its domain that found an URL. Sessions are numbered from 1 for each
+/// domain, see `Db::start_session`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lineage {
+    /// The session that found the URL first.
+    pub first_seen: u64,
+    /// The last session that found the URL.
+    pub last_seen: u64,
+}
+
+impl Lineage {
+    fn new(session: u64) -> Self {
+        Self {
+            first_seen: session,
+            last_seen: session,
+        }
+    }
+
+    /// Record that `session` found the URL too.
+    fn see(&mut self, session: u64) {
+        self.first_seen = self.first_seen.min(session);
+        self.last_seen = self.last_seen.max(session);
+    }
+}
+
+/// Maximum number of pages kept as linking to an URL, see `Storage::link`.
+pub const MAX_REFERRERS: usize = 10;
+
+/// How the last fetch of an URL went, with what was found about the page if it was downloaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FetchStatus {
+    /// Status of the response, `None` if none was received.
+    pub status: Option<u16>,
+    /// Kind of error the fetch failed with, e.g. `http_404` or `timeout`, `None` if it succeeded.
+    pub error: Option<String>,
+    /// `<title>` of the page, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// Size of the body, once decoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<usize>,
+    /// Time taken to download the page, retries included.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// SHA-256 of the body, in hex. Pages served under several URLs share it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// The page asked not to be indexed with `<meta name="robots" content="noindex">`, so neither
+    /// its title nor its hash were recorded.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub noindex: bool,
+    /// The body was neither downloaded nor parsed, as the content type of the page is not one the
+    /// crawl was set to download.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub body_skipped: bool,
+    /// What is wrong with the body, e.g. it is shorter than its `Content-Length`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub issues: Vec<ContentIssue>,
+    /// `ETag` of the response, sent back in `If-None-Match` when the URL is fetched again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// `Last-Modified` of the response, sent back in `If-Modified-Since` when the URL is fetched
+    /// again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// The crawl session of the domain that made the fetch, see `Db::start_session`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<u64>,
+    /// The fetch found another status or content than the previous fetch of the URL, by an earlier
+    /// session.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub changed: bool,
+}
+
+impl FetchStatus {
+    pub fn ok(status: u16) -> Self {
+        Self {
+            status: Some(status),
+            error: None,
+            title: None,
+            content_type: None,
+            bytes: None,
+            duration_ms: None,
+            content_hash: None,
+            noindex: false,
+            body_skipped: false,
+            issues: Vec::new(),
+            etag: None,
+            last_modified: None,
+            session: None,
+            changed: false,
+        }
+    }
+
+    pub fn failed(status: Option<u16>, error: impl Into<String>) -> Self {
+        Self {
+            status,
+            error: Some(error.into()),
+            title: None,
+            content_type: None,
+            bytes: None,
+            duration_ms: None,
+            content_hash: None,
+            noindex: false,
+            body_skipped: false,
+            issues: Vec::new(),
+            etag: None,
+            last_modified: None,
+            session: None,
+            changed: false,
+        }
+    }
+
+    /// Whether the fetch found the page different from the `previous` fetch: another status, error
+    /// or content.
+    pub fn differs_from(&self, previous: &FetchStatus) -> bool {
+        self.status != previous.status
+            || self.error != previous.error
+            || self.content_hash != previous.content_hash
+    }
+
+    /// Whether a link to the URL is broken: it responded with a `4xx` or `5xx` status, or could
+    /// not be reached at all. Blocked or oversized pages are not broken links.
+    pub fn is_broken(&self) -> bool {
+        match (self.status, self.error.as_deref()) {
+            (Some(status), _) => status >= 400,
+            (None, Some(error)) => matches!(error, "timeout" | "connect" | "request"),
+            (None, None) => false,
+        }
+    }
+}
+
+/// A resource referred to by the pages of a domain, e.g. an image, see `Storage::visit_asset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Asset {
+    /// The kind the resource was first found as.
+    pub kind: AssetKind,
+    /// Number of times the pages of the domain refer to it.
+    pub count: usize,
+}
+
+/// Add `from` to the `referrers` of an URL, unless it is already there or there are
+/// `MAX_REFERRERS` of them.
+fn add_referrer(referrers: &mut Vec<String>, from: &str) {
+    if referrers.len() < MAX_REFERRERS && !referrers.iter().any(|referrer| referrer == from) {
+        referrers.push(from.to_string());
+    }
+}
+
+/// Health of a `Storage` backend.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageHealth {
+    /// The backend fails, and writes are kept in memory until it recovers.
+    pub degraded: bool,
+    /// Number of writes waiting for the backend to recover.
+    pub buffered: usize,
+}
+
+/// How much a `Db` holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DbTotals {
+    /// Number of domains stored, including the ones only redirecting to another.
+    pub domains: usize,
+    /// Number of unique URLs over all domains.
+    pub urls: usize,
+}
+
+/// Where the `Db` keeps its data. Domains are given as computed by `parse_domain`, and URLs as
+/// their path and query. A domain exists once one of its URLs is visited or its meta is set.
+pub trait Storage: Debug + Send + Sync {
+    /// Increase the number of occurences of `path` for `domain`.
+    fn visit(&self, domain: &str, path: &str) -> Result<(), DbError> {
+        self.add_visits(domain, path, 1)
+    }
+    /// Increase the number of occurences of `path` for `domain` by `count` at once, e.g. when
+    /// loading a dump.
+    fn add_visits(&self, domain: &str, path: &str, count: usize) -> Result<(), DbError>;
+    /// Like `visit`, returning whether it was the first occurence of `path`. Backends should
+    /// override it to check and increase the count at once: concurrent crawls of `path` could
+    /// otherwise both see it as new.
+    fn record_visit(&self, domain: &str, path: &str) -> Result<bool, DbError> {
+        let first = self.count(domain, path)?.unwrap_or(0) == 0;
+        self.visit(domain, path)?;
+
+        Ok(first)
+    }
+    /// The number of occurences of `path` for `domain`, `None` if the domain does not exist.
+    fn count(&self, domain: &str, path: &str) -> Result<Option<usize>, DbError>;
+    /// Like `count` for each of the domains and paths of `keys`. Backends able to read them all at
+    /// once should override it.
+    fn counts(&self, keys: &[(&str, &str)]) -> Result<Vec<Option<usize>>, DbError> {
+        keys.iter()
+            .map(|(domain, path)| self.count(domain, path))
+            .collect()
+    }
+    /// All the paths stored for `domain`, with their number of occurences.
+    fn paths(&self, domain: &str) -> Result<Vec<(String, usize)>, DbError>;
+    /// Like `paths`, ordered by path: at most `limit` of them after the first `offset` ones, with
+    /// the number of paths of `domain`. Sorts all the paths unless overridden.
+    fn paths_page(
+        &self,
+        domain: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<(String, usize)>, usize), DbError> {
+        let mut paths = self.paths(domain)?;
+        paths.sort_unstable();
+        let total = paths.len();
+
+        Ok((paths.into_iter().skip(offset).take(limit).collect(), total))
+    }
+    /// The meta of `domain`, `None` if the domain does not exist.
+    fn meta(&self, domain: &str) -> Result<Option<DomainMeta>, DbError>;
+    fn set_meta(&self, domain: &str, meta: DomainMeta) -> Result<(), DbError>;
+    /// Remove everything stored for `domain`, returning whether it existed.
+    fn remove_domain(&self, domain: &str) -> Result<bool, DbError>;
+    /// Increase the number of links found from `domain` to the third-party `host`.
+    fn visit_third_party(&self, domain: &str, host: &str) -> Result<(), DbError> {
+        self.add_third_party(domain, host, 1)
+    }
+    /// Increase the number of links found from `domain` to the third-party `host` by `count`.
+    fn add_third_party(&self, domain: &str, host: &str, count: usize) -> Result<(), DbError>;
+    /// The third-party hosts `domain` links to, with the number of links to each.
+    fn third_party(&self, domain: &str) -> Result<Vec<(String, usize)>, DbError>;
+    /// All the domains that exist.
+    fn domains(&self) -> Result<Vec<String>, DbError>;
+    /// Record that the crawl session `session` of `domain` found `path`. Lineage is not kept
+    /// unless overridden.
+    fn see(&self, _domain: &str, _path: &str, _session: u64) -> Result<(), DbError> {
+        Ok(())
+    }
+    /// The lineage of the paths of `domain` found by a session.
+    fn lineage(&self, _domain: &str) -> Result<Vec<(String, Lineage)>, DbError> {
+        Ok(Vec::new())
+    }
+    /// Replace the status of the last fetch of `path`. Statuses are not kept unless overridden.
+    fn set_status(&self, _domain: &str, _path: &str, _status: &FetchStatus) -> Result<(), DbError> {
+        Ok(())
+    }
+    /// The status of the last fetch of the paths of `domain` that were fetched.
+    fn statuses(&self, _domain: &str) -> Result<Vec<(String, FetchStatus)>, DbError> {
+        Ok(Vec::new())
+    }
+    /// The status of the last fetch of `path`, `None` if it was not fetched. Goes through every
+    /// status of the domain unless overridden.
+    fn status(&self, domain: &str, path: &str) -> Result<Option<FetchStatus>, DbError> {
+        Ok(self
+            .statuses(domain)?
+            .into_iter()
+            .find_map(|(stored, status)| (stored == path).then_some(status)))
+    }
+    /// Record that the page `from`, an absolute URL, links to `path`. At most `MAX_REFERRERS`
+    /// pages are kept for each path. Links are not kept unless overridden.
+    fn link(&self, _domain: &str, _path: &str, _from: &str) -> Result<(), DbError> {
+        Ok(())
+    }
+    /// The pages found linking to `path`, in the order they were found.
+    fn referrers(&self, _domain: &str, _path: &str) -> Result<Vec<String>, DbError> {
+        Ok(Vec::new())
+    }
+    /// Record that the page `from` links to the page `to`, both paths of `domain`. Unlike
+    /// referrers, every link is kept. Links are not kept unless overridden.
+    fn add_edge(&self, _domain: &str, _from: &str, _to: &str) -> Result<(), DbError> {
+        Ok(())
+    }
+    /// The links between the pages of `domain`, as `(from, to)` paths, each given once.
+    fn edges(&self, _domain: &str) -> Result<Vec<(String, String)>, DbError> {
+        Ok(Vec::new())
+    }
+    /// Increase the number of times the pages of `domain` refer to the asset `url`, an absolute
+    /// URL, of `kind` if it is new.
+    fn visit_asset(&self, domain: &str, url: &str, kind: AssetKind) -> Result<(), DbError> {
+        self.add_asset(domain, url, kind, 1)
+    }
+    /// Like `visit_asset`, by `count` at once. Assets are not kept unless overridden.
+    fn add_asset(
+        &self,
+        _domain: &str,
+        _url: &str,
+        _kind: AssetKind,
+        _count: usize,
+    ) -> Result<(), DbError> {
+        Ok(())
+    }
+    /// The assets the pages of `domain` refer to.
+    fn assets(&self, _domain: &str) -> Result<Vec<(String, Asset)>, DbError> {
+        Ok(Vec::new())
+    }
+    /// Replace the indexed text of the page `path`. Texts are not kept unless overridden, and no
+    /// page can be searched then.
+    fn set_text(&self, _domain: &str, _path: &str, _text: &str) -> Result<(), DbError> {
+        Ok(())
+    }
+    /// The indexed text of the page `path`, `None` if it has none.
+    fn text(&self, _domain: &str, _path: &str) -> Result<Option<String>, DbError> {
+        Ok(None)
+    }
+    /// Set the number of occurences of `term` in the text of the page `path`, the page being
+    /// removed from the pages containing the term if it is 0.
+    fn set_posting(
+        &self,
+        _domain: &str,
+        _term: &str,
+        _path: &str,
+        _count: usize,
+    ) -> Result<(), DbError> {
+        Ok(())
+    }
+    /// The pages of `domain` whose text contains `term`, with its number of occurences in each.
+    fn postings(&self, _domain: &str, _term: &str) -> Result<Vec<(String, usize)>, DbError> {
+        Ok(Vec::new())
+    }
+    /// The number of paths stored over all domains. Goes through every domain unless overridden.
+    fn path_count(&self) -> Result<usize, DbError> {
+        self.domains()?
+            .iter()
+            .map(|domain| Ok(self.paths(domain)?.len()))
+            .sum()
+    }
+    /// Healthy unless told otherwise, as only `OverflowStorage` can degrade.
+    fn health(&self) -> StorageHealth {
+        StorageHealth::default()
+    }
+    /// How much of its memory budget the storage uses, `None` if it is not kept in memory.
+    fn memory_usage(&self) -> Option<MemoryUsage> {
+        None
+    }
+    /// Keep `domain` and its subdomains from being evicted while it is crawled, until as many
+    /// `unpin` calls. Only storages evicting domains need to override it.
+    fn pin(&self, _domain: &str) {}
+    fn unpin(&self, _domain: &str) {}
+}
+
+/// How much of its budget a `Db` kept in memory uses, see `Db::with_memory_budget`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryUsage {
+    /// Number of URLs kept, over all domains.
+    pub urls: usize,
+    /// Most URLs kept before domains are evicted, unbounded if `None`.
+    pub max_urls: Option<usize>,
+    /// Number of domains evicted to stay within the budget since the start.
+    pub evicted_domains: usize,
+}
+
+/// Data stored in memory for a domain.
+#[derive(Debug, Default)]
+struct DomainEntry {
+    urls: HashMap<String, usize>,
+    meta: DomainMeta,
+    /// Number of links found to each third-party host.
+    third_party: HashMap<String, usize>,
+    lineage: HashMap<String, Lineage>,
+    statuses: HashMap<String, FetchStatus>,
+    referrers: HashMap<String, Vec<String>>,
+    /// Links between the pages of the domain, from one path to another.
+    edges: HashSet<(String, String)>,
+    assets: HashMap<String, Asset>,
+    texts: HashMap<String, String>,
+    /// The pages containing each term, with its number of occurences in each.
+    postings: HashMap<String, HashMap<String, usize>>,
+    /// Set once the domain was removed from the storage: it is then written to and read from a
+    /// new entry instead.
+    removed: bool,
+}
+
+/// A domain of a `MemoryStorage`, locked on its own so that the crawls of different domains do
+/// not wait for each other.
+#[derive(Debug, Default)]
+struct DomainShard {
+    entry: RwLock<DomainEntry>,
+    /// `MemoryBudget::clock` when an URL of the domain was last visited.
+    last_visit: AtomicU64,
+}
+
+/// Bounds the number of URLs a `MemoryStorage` keeps.
+#[derive(Debug, Default)]
+struct MemoryBudget {
+    max_urls: Option<usize>,
+    /// Number of URLs stored over all domains.
+    urls: AtomicUsize,
+    /// Ticks on each visit, to tell which domain was visited the least recently.
+    clock: AtomicU64,
+    evicted_domains: AtomicUsize,
+}
+
+/// In-memory storage, lost on restart. For each domain, it stores a `HashMap` of unique URLs and
+/// the number of occurences. Beyond the budget, the domains visited the least recently are
+/// evicted, with all their data.
+/// The map of domains is only locked for writing to add or remove one, each domain having its own
+/// lock. It is never waited for while the lock of a domain is held.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryStorage {
+    domains: RwLock<HashMap<String, Arc<DomainShard>>>,
+    budget: MemoryBudget,
+    /// Domains being crawled, with the number of crawls of each.
+    pinned: Mutex<HashMap<String, usize>>,
+}
+
+impl MemoryStorage {
+    /// A storage keeping at most `max_urls` URLs, unless a single domain has more.
+    pub(crate) fn with_budget(max_urls: usize) -> Self {
+        Self {
+            domains: RwLock::default(),
+            budget: MemoryBudget {
+                max_urls: Some(max_urls),
+                ..MemoryBudget::default()
+            },
+            pinned: Mutex::default(),
+        }
+    }
+
+    /// Read the entry of `domain` with `f`, `None` if the domain does not exist.
+    fn read<T>(&self, domain: &str, f: impl FnOnce(&DomainEntry) -> T) -> Option<T> {
+        let shard = self.domains.read().unwrap().get(domain).cloned()?;
+        let entry = shard.entry.read().unwrap();
+
+        if entry.removed {
+            None
+        } else {
+            Some(f(&entry))
+        }
+    }
+
+    /// Update the entry of `domain` with `f`, creating the domain if it does not exist.
+    fn write<T>(&self, domain: &str, f: impl FnOnce(&mut DomainEntry) -> T) -> T {
+        loop {
+            let shard = self.shard(domain);
+            let mut entry = shard.entry.write().unwrap();
+            // Removed since it was looked up.
+            if !entry.removed {
+                return f(&mut entry);
+            }
+        }
+    }
+
+    /// The shard of `domain`, created if it does not exist.
+    fn shard(&self, domain: &str) -> Arc<DomainShard> {
+        if let Some(shard) = self.domains.read().unwrap().get(domain) {
+            return Arc::clone(shard);
+        }
+
+        Arc::clone(
+            self.domains
+                .write()
+                .unwrap()
+                .entry(domain.to_string())
+                .or_default(),
+        )
+    }
+
+    /// Remove the shard of `domain` from `domains`, returning its number of URLs.
+    fn remove_shard(
+        &self,
+        domains: &mut HashMap<String, Arc<DomainShard>>,
+        domain: &str,
+    ) -> Option<usize> {
+        let shard = domains.remove(domain)?;
+        let mut entry = shard.entry.write().unwrap();
+        entry.removed = true;
+        self.budget
+            .urls
+            .fetch_sub(entry.urls.len(), Ordering::Relaxed);
+
+        Some(entry.urls.len())
+    }
+
+    /// Add `count` occurences of `path` for `domain`, returning whether the path is new.
+    fn add_path(&self, domain: &str, path: &str, count: usize) -> bool {
+        let first = loop {
+            let shard = self.shard(domain);
+            let mut entry = shard.entry.write().unwrap();
+            if entry.removed {
+                continue;
+            }
+
+            let tick = self.budget.clock.fetch_add(1, Ordering::Relaxed) + 1;
+            shard.last_visit.store(tick, Ordering::Relaxed);
+            let stored = entry.urls.entry(path.to_string()).or_insert(0);
+            let first = *stored == 0;
+            *stored = stored.saturating_add(count);
+            if first {
+                self.budget.urls.fetch_add(1, Ordering::Relaxed);
+            }
+            break first;
+        };
+        // Once the domain is unlocked, as evicting locks the others.
+        if first {
+            self.evict(domain);
+        }
+
+        first
+    }
+
+    /// Evict the domains visited the least recently, apart from the `current` one and the pinned
+    /// ones, until the URLs fit in the budget.
+    fn evict(&self, current: &str) {
+        let max_urls = match self.budget.max_urls {
+            Some(max_urls) => max_urls,
+            None => return,
+        };
+        if self.budget.urls.load(Ordering::Relaxed) <= max_urls {
+            return;
+        }
+
+        let mut domains = self.domains.write().unwrap();
+        let pinned = self.pinned.lock().unwrap();
+        // A crawl with `include_subdomains` stores the pages of the other hosts under their own
+        // domain.
+        let is_pinned = |domain: &str| {
+            pinned.keys().any(|pinned| {
+                domain == pinned
+                    || domain
+                        .strip_suffix(pinned.as_str())
+                        .is_some_and(|subdomain| subdomain.ends_with('.'))
+            })
+        };
+        while self.budget.urls.load(Ordering::Relaxed) > max_urls {
+            let oldest = domains
+                .iter()
+                .filter(|(domain, _)| domain.as_str() != current && !is_pinned(domain))
+                .min_by_key(|(_, shard)| shard.last_visit.load(Ordering::Relaxed))
+                .map(|(domain, _)| domain.clone());
+            let oldest = match oldest {
+                Some(oldest) => oldest,
+                None => return,
+            };
+            let urls = self.remove_shard(&mut domains, &oldest).unwrap_or(0);
+            self.budget.evicted_domains.fetch_add(1, Ordering::Relaxed);
+            info!("Evicted {} from memory, with its {} URLs", oldest, urls);
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn add_visits(&self, domain: &str, path: &str, count: usize) -> Result<(), DbError> {
+        self.add_path(domain, path, count);
+
+        Ok(())
+    }
+
+    fn record_visit(&self, domain: &str, path: &str) -> Result<bool, DbError> {
+        Ok(self.add_path(domain, path, 1))
+    }
+
+    fn count(&self, domain: &str, path: &str) -> Result<Option<usize>, DbError> {
+        Ok(self.read(domain, |entry| entry.urls.get(path).copied().unwrap_or(0)))
+    }
+
+    fn paths(&self, domain: &str) -> Result<Vec<(String, usize)>, DbError> {
+        Ok(self
+            .read(domain, |entry| {
+                entry
+                    .urls
+                    .iter()
+                    .map(|(path, count)| (path.clone(), *count))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn remove_domain(&self, domain: &str) -> Result<bool, DbError> {
+        let mut domains = self.domains.write().unwrap();
+
+        Ok(self.remove_shard(&mut domains, domain).is_some())
+    }
+
+    fn path_count(&self) -> Result<usize, DbError> {
+        Ok(self.budget.urls.load(Ordering::Relaxed))
+    }
+
+    fn memory_usage(&self) -> Option<MemoryUsage> {
+        Some(MemoryUsage {
+            urls: self.budget.urls.load(Ordering::Relaxed),
+            max_urls: self.budget.max_urls,
+            evicted_domains: self.budget.evicted_domains.load(Ordering::Relaxed),
+        })
+    }
+
+    fn pin(&self, domain: &str) {
+        *self
+            .pinned
+            .lock()
+            .unwrap()
+            .entry(domain.to_string())
+            .or_insert(0) += 1;
+    }
+
+    fn unpin(&self, domain: &str) {
+        let mut pinned = self.pinned.lock().unwrap();
+        if let Some(crawls) = pinned.get_mut(domain) {
+            *crawls -= 1;
+            if *crawls == 0 {
+                pinned.remove(domain);
+            }
+        }
+    }
+
+    fn meta(&self, domain: &str) -> Result<Option<DomainMeta>, DbError> {
+        Ok(self.read(domain, |entry| entry.meta.clone()))
+    }
+
+    fn set_meta(&self, domain: &str, meta: DomainMeta) -> Result<(), DbError> {
+        self.write(domain, |entry| entry.meta = meta);
+
+        Ok(())
+    }
+
+    fn add_third_party(&self, domain: &str, host: &str, count: usize) -> Result<(), DbError> {
+        self.write(domain, |entry| {
+            let stored = entry.third_party.entry(host.to_string()).or_default();
+            *stored = stored.saturating_add(count);
+        });
+
+        Ok(())
+    }
+
+    fn third_party(&self, domain: &str) -> Result<Vec<(String, usize)>, DbError> {
+        Ok(self
+            .read(domain, |entry| {
+                entry
+                    .third_party
+                    .iter()
+                    .map(|(host, count)| (host.clone(), *count))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn domains(&self) -> Result<Vec<String>, DbError> {
+        Ok(self.domains.read().unwrap().keys().cloned().collect())
+    }
+
+    fn see(&self, domain: &str, path: &str, session: u64) -> Result<(), DbError> {
+        self.write(domain, |entry| {
+            entry
+                .lineage
+                .entry(path.to_string())
+                .and_modify(|lineage| lineage.see(session))
+                .or_insert_with(|| Lineage::new(session));
+        });
+
+        Ok(())
+    }
+
+    fn lineage(&self, domain: &str) -> Result<Vec<(String, Lineage)>, DbError> {
+        Ok(self
+            .read(domain, |entry| {
+                entry
+                    .lineage
+                    .iter()
+                    .map(|(path, lineage)| (path.clone(), *lineage))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn set_status(&self, domain: &str, path: &str, status: &FetchStatus) -> Result<(), DbError> {
+        self.write(domain, |entry| {
+            entry.statuses.insert(path.to_string(), status.clone())
+        });
+
+        Ok(())
+    }
+
+    fn statuses(&self, domain: &str) -> Result<Vec<(String, FetchStatus)>, DbError> {
+        Ok(self
+            .read(domain, |entry| {
+                entry
+                    .statuses
+                    .iter()
+                    .map(|(path, status)| (path.clone(), status.clone()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn link(&self, domain: &str, path: &str, from: &str) -> Result<(), DbError> {
+        self.write(domain, |entry| {
+            add_referrer(entry.referrers.entry(path.to_string()).or_default(), from)
+        });
+
+        Ok(())
+    }
+
+    fn status(&self, domain: &str, path: &str) -> Result<Option<FetchStatus>, DbError> {
+        Ok(self
+            .read(domain, |entry| entry.statuses.get(path).cloned())
+            .flatten())
+    }
+
+    fn referrers(&self, domain: &str, path: &str) -> Result<Vec<String>, DbError> {
+        Ok(self
+            .read(domain, |entry| entry.referrers.get(path).cloned())
+            .flatten()
+            .unwrap_or_default())
+    }
+
+    fn set_text(&self, domain: &str, path: &str, text: &str) -> Result<(), DbError> {
+        self.write(domain, |entry| {
+            entry.texts.insert(path.to_string(), text.to_string())
+        });
+
+        Ok(())
+    }
+
+    fn text(&self, domain: &str, path: &str) -> Result<Option<String>, DbError> {
+        Ok(self
+            .read(domain, |entry| entry.texts.get(path).cloned())
+            .flatten())
+    }
+
+    fn set_posting(
+        &self,
+        domain: &str,
+        term: &str,
+        path: &str,
+        count: usize,
+    ) -> Result<(), DbError> {
+        self.write(domain, |entry| {
+            let postings = entry.postings.entry(term.to_string()).or_default();
+            if count == 0 {
+                postings.remove(path);
+            } else {
+                postings.insert(path.to_string(), count);
+            }
+        });
+
+        Ok(())
+    }
+
+    fn postings(&self, domain: &str, term: &str) -> Result<Vec<(String, usize)>, DbError> {
+        Ok(self
+            .read(domain, |entry| {
+                entry.postings.get(term).map(|postings| {
+                    postings
+                        .iter()
+                        .map(|(path, count)| (path.clone(), *count))
+                        .collect()
+                })
+            })
+            .flatten()
+            .unwrap_or_default())
+    }
+
+    fn add_edge(&self, domain: &str, from: &str, to: &str) -> Result<(), DbError> {
+        self.write(domain, |entry| {
+            entry.edges.insert((from.to_string(), to.to_string()))
+        });
+
+        Ok(())
+    }
+
+    fn edges(&self, domain: &str) -> Result<Vec<(String, String)>, DbError> {
+        Ok(self
+            .read(domain, |entry| entry.edges.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn add_asset(
+        &self,
+        domain: &str,
+        url: &str,
+        kind: AssetKind,
+        count: usize,
+    ) -> Result<(), DbError> {
+        self.write(domain, |entry| {
+            let asset = entry
+                .assets
+                .entry(url.to_string())
+                .or_insert(Asset { kind, count: 0 });
+            asset.count = asset.count.saturating_add(count);
+        });
+
+        Ok(())
+    }
+
+    fn assets(&self, domain: &str) -> Result<Vec<(String, Asset)>, DbError> {
+        Ok(self
+            .read(domain, |entry| {
+                entry
+                    .assets
+                    .iter()
+                    .map(|(url, asset)| (url.clone(), *asset))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+/// Thread-safe database of the crawled URLs, over a `Storage` backend: in memory by default, or
+/// persisted on disk with `SledStorage`.
+/// To reduce use of system resources, story only the part after the domain URL for each unique URL and build
+/// it on the spot when the list is required.
+#[derive(Debug, Clone)]
+pub struct Db(Arc<dyn Storage>);
+
+impl Default for Db {
+    fn default() -> Self {
+        Self::new(MemoryStorage::default())
+    }
+}
+
+/// Keeps a domain from being evicted from memory until dropped, see `Db::pin`.
+#[derive(Debug)]
+pub(crate) struct DomainPin {
+    storage: Arc<dyn Storage>,
+    domain: String,
+}
+
+impl Drop for DomainPin {
+    fn drop(&mut self) {
+        self.storage.unpin(&self.domain);
+    }
+}
+
+impl Db {
+    pub fn new(storage: impl Storage + 'static) -> Self {
+        Self(Arc::new(storage))
+    }
+
+    /// A `Db` kept in memory holding at most `max_urls` URLs over all domains: beyond it, the
+    /// domains visited the least recently are forgotten, except the one being visited and the
+    /// pinned ones.
+    pub fn with_memory_budget(max_urls: usize) -> Self {
+        Self::new(MemoryStorage::with_budget(max_urls))
+    }
+
+    /// Keep `domain` and its subdomains in memory, whatever the budget, as long as the returned
+    /// pin lives, i.e. while they are crawled.
+    pub(crate) fn pin(&self, domain: &Url) -> Result<DomainPin, DbError> {
+        let domain = parse_domain(domain)?.into_owned();
+        self.0.pin(&domain);
+
+        Ok(DomainPin {
+            storage: Arc::clone(&self.0),
+            domain,
+        })
+    }
+
+    /// How much of its memory budget the `Db` uses, `None` if it is not kept in memory.
+    pub fn memory_usage(&self) -> Option<MemoryUsage> {
+        self.0.memory_usage()
+    }
+
+    /// Health of the storage backend.
+    pub fn health(&self) -> StorageHealth {
+        self.0.health()
+    }
+
+    /// The number of domains and unique URLs stored.
+    pub fn totals(&self) -> Result<DbTotals, DbError> {
+        Ok(DbTotals {
+            domains: self.0.domains()?.len(),
+            urls: self.0.path_count()?,
+        })
+    }
+
+    /// The key `domain` is stored under, following its alias, and the matching domain URL.
+    fn resolve(&self, domain: &Url) -> Result<(String, Url), DbError> {
+        let key = parse_domain(domain)?;
+        let meta = self.0.meta(&key)?.ok_or(DbError::DomainDoesNotExist)?;
+
+        match meta.alias {
+            Some(canonical) => {
+                let key = parse_domain(&canonical)?.into_owned();
+                self.0.meta(&key)?.ok_or(DbError::DomainDoesNotExist)?;
+                Ok((key, canonical))
+            }
+            None => Ok((key.into_owned(), domain.clone())),
+        }
+    }
+
+    /// Returns `true` if the `url` does not exist yet in the database.
+    #[cfg(test)]
+    pub(crate) fn is_first_visit(&self, url: &Url) -> Result<bool, DbError> {
+        let key = UrlKey::new(url)?;
+
+        Ok(self.0.count(&key.domain, &key.path)?.unwrap_or(0) == 0)
+    }
+
+    /// Increase the number of occurences of `url` for its domain.
+    pub(crate) fn visit(&self, url: Cow<Url>) -> Result<(), DbError> {
+        let key = UrlKey::new(&url)?;
+
+        self.0.visit(&key.domain, &key.path)
+    }
+
+    /// Like `visit`, returning `true` if `url` did not exist yet in the database. Of concurrent
+    /// calls for the same `url`, only one returns `true`.
+    pub(crate) fn record_visit(&self, url: &Url) -> Result<bool, DbError> {
+        let key = UrlKey::new(url)?;
+
+        self.0.record_visit(&key.domain, &key.path)
+    }
+
+    /// Create a list of unique URLs for a `domain`.
+    /// This function will combine the domain part with the relative URLs for the domain to build a
+    /// list of valid and complete URLs.
+    pub fn unique_urls_for_domain(&self, domain: &Url) -> Result<Vec<Url>, DbError> {
+        Ok(self
+            .url_counts_for_domain(domain)?
+            .into_iter()
+            .map(|(url, _)| url)
+            .collect())
+    }
+
+    /// Like `unique_urls_for_domain`, with the number of occurences of each URL.
+    pub fn url_counts_for_domain(&self, domain: &Url) -> Result<Vec<(Url, usize)>, DbError> {
+        let (key, domain) = self.resolve(domain)?;
+        let base = base_url(domain);
+
+        Ok(self
+            .0
+            .paths(&key)?
+            .iter()
+            .map(|(url, count)| (build_url(&base, url), *count))
+            .collect())
+    }
+
+    /// Like `url_counts_for_domain`, only the URLs matching `query` in its order: at most `limit`
+    /// of them after the first `offset` ones, with the number of URLs matching.
+    pub fn url_counts_page(
+        &self,
+        domain: &Url,
+        query: &UrlQuery,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<(Url, usize)>, usize), DbError> {
+        let (key, domain) = self.resolve(domain)?;
+        let base = base_url(domain);
+        let (paths, total) = self.query_paths(&key, query, offset, limit)?;
+
+        Ok((
+            paths
+                .iter()
+                .map(|(path, count)| (build_url(&base, path), *count))
+                .collect(),
+            total,
+        ))
+    }
+
+    /// Like `url_details_for_domain`, paginated as `url_counts_page`.
+    pub fn url_details_page(
+        &self,
+        domain: &Url,
+        query: &UrlQuery,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<UrlDetails>, usize), DbError> {
+        let (key, domain) = self.resolve(domain)?;
+        let base = base_url(domain);
+        let (paths, total) = self.query_paths(&key, query, offset, limit)?;
+        let urls = paths
+            .into_iter()
+            .map(|(path, count)| {
+                let status = self.0.status(&key, &path)?;
+                Ok((build_url(&base, &path), count, status))
+            })
+            .collect::<Result<_, DbError>>()?;
+
+        Ok((urls, total))
+    }
+
+    /// The page of the paths of the domain stored under `key` matching `query`, with their number.
+    /// Only the listing of every path by path is left to the storage, as the others need all the
+    /// paths to be read anyway.
+    fn query_paths(
+        &self,
+        key: &str,
+        query: &UrlQuery,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<(String, usize)>, usize), DbError> {
+        if *query == UrlQuery::default() {
+            return self.0.paths_page(key, offset, limit);
+        }
+
+        let mut paths: Vec<_> = self
+            .0
+            .paths(key)?
+            .into_iter()
+            .filter(|(path, count)| query.matches(path, *count))
+            .collect();
+        match query.sort {
+            UrlSort::Url => paths.sort_unstable(),
+            UrlSort::Count => paths.sort_unstable_by(|(a, a_count), (b, b_count)| {
+                b_count.cmp(a_count).then_with(|| a.cmp(b))
+            }),
+        }
+        let total = paths.len();
+
+        Ok((paths.into_iter().skip(offset).take(limit).collect(), total))
+    }
+
+    /// Like `url_counts_for_domain`, with how the last fetch of each URL went, if it was fetched.
+    pub fn url_details_for_domain(&self, domain: &Url) -> Result<Vec<UrlDetails>, DbError> {
+        let (key, domain) = self.resolve(domain)?;
+        let base = base_url(domain);
+        let mut statuses: HashMap<_, _> = self.0.statuses(&key)?.into_iter().collect();
+
+        Ok(self
+            .0
+            .paths(&key)?
+            .into_iter()
+            .map(|(path, count)| {
+                let status = statuses.remove(&path);
+                (build_url(&base, &path), count, status)
+            })
+            .collect())
+    }
+
+    /// Get the count of occurences for the given `url`.
+    pub fn url_count_for_domain(&self, url: &Url) -> Result<usize, DbError> {
+        let (key, _) = self.resolve(url)?;
+        let path = UrlKey::new(url)?.path;
+
+        Ok(self.0.count(&key, &path)?.unwrap_or(0))
+    }
+
+    /// Like `url_count_for_domain` for each of `urls`, in the same order, the counts being read
+    /// from the storage at once.
+    pub fn url_counts(&self, urls: &[Url]) -> Result<Vec<Result<usize, DbError>>, DbError> {
+        // The key each domain is stored under, resolved once.
+        let mut domains: HashMap<String, Result<String, DbError>> = HashMap::new();
+        let keys: Vec<Result<(String, Cow<str>), DbError>> = urls
+            .iter()
+            .map(|url| {
+                let key = UrlKey::new(url)?;
+                let domain = domains
+                    .entry(key.domain.into_owned())
+                    .or_insert_with(|| self.resolve(url).map(|(key, _)| key))
+                    .clone()?;
+                Ok((domain, key.path))
+            })
+            .collect();
+
+        let found: Vec<_> = keys
+            .iter()
+            .flatten()
+            .map(|(domain, path)| (domain.as_str(), path.as_ref()))
+            .collect();
+        let mut counts = self.0.counts(&found)?.into_iter();
+
+        Ok(keys
+            .into_iter()
+            .map(|key| {
+                key?;
+                Ok(counts.next().flatten().unwrap_or(0))
+            })
+            .collect())
+    }
+
+    /// Increase the number of links found from `domain` to the third-party `host`.
+    pub(crate) fn visit_third_party(&self, domain: &Url, host: &str) -> Result<(), DbError> {
+        self.0.visit_third_party(&parse_domain(domain)?, host)
+    }
+
+    /// The third-party hosts `domain` links to, with the number of links to each.
+    pub fn third_party_for_domain(&self, domain: &Url) -> Result<Vec<(String, usize)>, DbError> {
+        let (key, _) = self.resolve(domain)?;
+
+        self.0.third_party(&key)
+    }
+
+    /// Record whether the last crawl of `domain` was cut short by a limit.
+    pub(crate) fn set_truncated(&self, domain: &Url, truncated: bool) -> Result<(), DbError> {
+        let key = parse_domain(domain)?;
+        let meta = self.0.meta(&key)?.ok_or(DbError::DomainDoesNotExist)?;
+
+        self.0.set_meta(&key, DomainMeta { truncated, ..meta })
+    }
+
+    /// Whether the last crawl of `domain` was cut short by a limit.
+    pub fn is_truncated(&self, domain: &Url) -> Result<bool, DbError> {
+        let (key, _) = self.resolve(domain)?;
+
+        Ok(self.0.meta(&key)?.unwrap_or_default().truncated)
+    }
+
+    /// Record that `domain` redirects to `canonical`, so that the data of `domain` is looked up
+    /// under `canonical`. `None` removes a previous alias.
+    pub(crate) fn set_alias(&self, domain: &Url, canonical: Option<&Url>) -> Result<(), DbError> {
+        let key = parse_domain(domain)?;
+
+        match (self.0.meta(&key)?, canonical) {
+            (meta, Some(canonical)) => self.0.set_meta(
+                &key,
+                DomainMeta {
+                    alias: Some(canonical.clone()),
+                    ..meta.unwrap_or_default()
+                },
+            ),
+            (Some(meta), None) => self.0.set_meta(
+                &key,
+                DomainMeta {
+                    alias: None,
+                    ..meta
+                },
+            ),
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Forget everything stored for `domain`, and for the domain it is an alias of, if any.
+    /// Fails with `DbError::DomainDoesNotExist` if the domain was never stored.
+    pub fn remove_domain(&self, domain: &Url) -> Result<(), DbError> {
+        let key = parse_domain(domain)?;
+        if let Some(DomainMeta {
+            alias: Some(canonical),
+            ..
+        }) = self.0.meta(&key)?
+        {
+            self.0.remove_domain(&parse_domain(&canonical)?)?;
+        }
+
+        if self.0.remove_domain(&key)? {
+            Ok(())
+        } else {
+            Err(DbError::DomainDoesNotExist)
+        }
+    }
+
+    /// Keep the `robots.txt` fetched for `domain`, to check URLs against it later.
+    pub(crate) fn set_robots_txt(&self, domain: &Url, robots_txt: &str) -> Result<(), DbError> {
+        let key = parse_domain(domain)?;
+        let meta = self.0.meta(&key)?.unwrap_or_default();
+
+        self.0.set_meta(
+            &key,
+            DomainMeta {
+                robots_txt: Some(robots_txt.to_string()),
+                ..meta
+            },
+        )
+    }
+
+    /// The `robots.txt` fetched by the last crawl of `domain`, `None` if none was kept.
+    pub fn robots_txt(&self, domain: &Url) -> Result<Option<String>, DbError> {
+        let (key, _) = self.resolve(domain)?;
+
+        Ok(self.0.meta(&key)?.and_then(|meta| meta.robots_txt))
+    }
+
+    /// Start a new crawl session of `domain` treating `robots.txt` as `robots_mode` says, and
+    /// return its id: the number of sessions started on the domain so far.
+    pub(crate) fn start_session(
+        &self,
+        domain: &Url,
+        robots_mode: RobotsMode,
+    ) -> Result<u64, DbError> {
+        let key = parse_domain(domain)?;
+        let mut meta = self.0.meta(&key)?.unwrap_or_default();
+        meta.sessions.push(Utc::now());
+        let session = meta.sessions.len() as u64;
+        meta.robots_modes.resize(meta.sessions.len() - 1, None);
+        meta.robots_modes.push(Some(robots_mode));
+        self.0.set_meta(&key, meta)?;
+
+        Ok(session)
+    }
+
+    /// Record that the crawl session `session` of its domain found `url`.
+    pub(crate) fn see(&self, url: &Url, session: u64) -> Result<(), DbError> {
+        let key = UrlKey::new(url)?;
+
+        self.0.see(&key.domain, &key.path, session)
+    }
+
+    /// Start time of each crawl session of `domain`, the one of session `n` being at `n - 1`.
+    pub fn sessions(&self, domain: &Url) -> Result<Vec<DateTime<Utc>>, DbError> {
+        let (key, _) = self.resolve(domain)?;
+
+        Ok(self.0.meta(&key)?.unwrap_or_default().sessions)
+    }
+
+    /// How each crawl session of `domain` treated `robots.txt`, the one of session `n` being at
+    /// `n - 1`. `None` for the sessions started before it was recorded.
+    pub fn robots_modes(&self, domain: &Url) -> Result<Vec<Option<RobotsMode>>, DbError> {
+        let (key, _) = self.resolve(domain)?;
+        let meta = self.0.meta(&key)?.unwrap_or_default();
+        let mut modes = meta.robots_modes;
+        modes.resize(meta.sessions.len(), None);
+
+        Ok(modes)
+    }
+
+    /// Record how the last fetch of `url` went.
+    pub(crate) fn set_status(&self, url: &Url, status: &FetchStatus) -> Result<(), DbError> {
+        let key = UrlKey::new(url)?;
+
+        self.0.set_status(&key.domain, &key.path, status)
+    }
+
+    /// How the last fetch of `url` went, `None` if it was not fetched.
+    pub fn status(&self, url: &Url) -> Result<Option<FetchStatus>, DbError> {
+        let key = UrlKey::new(url)?;
 
-#[derive(Debug, Error, PartialEq, Eq)]
-pub enum DbError {
-    #[error("URL does not contain domain")]
-    DoesNotContainDomain,
-    #[error("Domain does not exist")]
-    DomainDoesNotExist,
-}
+        self.0.status(&key.domain, &key.path)
+    }
 
-/// Thread-safe in-memory database. For each domain, it stores a `HashMap` of unique URLs and the number of occurences.
-/// To reduce use of system resources, story only the part after the domain URL for each unique URL and build
-/// it on the spot when the list is required.
-/// Future work: this database can also be split into an in-memory cache part and a database stored on disk (PostgreSQL?).
-#[derive(Debug, Default, Clone)]
-pub struct Db(Arc<RwLock<DomainsMap>>);
+    /// Record that the page `from` links to `url`. The link is part of the graph of the domain
+    /// if `from` is one of its pages.
+    pub(crate) fn link(&self, url: &Url, from: &Url) -> Result<(), DbError> {
+        let key = UrlKey::new(url)?;
+        self.0.link(&key.domain, &key.path, from.as_str())?;
 
-impl Db {
-    /// Returns `true` if the `url` does not exist yet in the database.
-    pub(crate) fn is_first_visit(&self, url: &Url) -> Result<bool, DbError> {
-        let db = self.0.read().unwrap();
-        let after_domain = &url[Position::BeforePath..];
+        let from = UrlKey::new(from)?;
+        if from.domain == key.domain {
+            self.0.add_edge(&key.domain, &from.path, &key.path)?;
+        }
 
-        Ok(db
-            .get(parse_domain(url)?.as_ref())
-            .and_then(|urls| urls.get(after_domain))
-            .is_none())
+        Ok(())
     }
 
-    /// Increase the number of occurences of `url` for its domain.
-    pub(crate) fn visit(&self, url: Cow<Url>) -> Result<(), DbError> {
-        let mut db = self.0.write().unwrap();
-        let after_domain = &url[Position::BeforePath..];
+    /// How the last fetch of each fetched URL of `domain` went.
+    pub fn statuses_for_domain(&self, domain: &Url) -> Result<Vec<(Url, FetchStatus)>, DbError> {
+        let (key, domain) = self.resolve(domain)?;
+        let base = base_url(domain);
+
+        Ok(self
+            .0
+            .statuses(&key)?
+            .into_iter()
+            .map(|(path, status)| (build_url(&base, &path), status))
+            .collect())
+    }
 
-        db.entry(parse_domain(&url)?.into_owned())
-            .or_default()
-            .entry(after_domain.to_string())
-            .and_modify(|count| *count += 1)
-            .or_insert(1);
+    /// The URLs of `domain` whose last fetch downloaded the same content as another one, grouped
+    /// by the hash of their content. Each group is sorted, and so are the groups by their first URL.
+    pub fn duplicates_for_domain(&self, domain: &Url) -> Result<Vec<(String, Vec<Url>)>, DbError> {
+        let mut groups: HashMap<String, Vec<Url>> = HashMap::new();
+        for (url, status) in self.statuses_for_domain(domain)? {
+            if let Some(hash) = status.content_hash {
+                groups.entry(hash).or_default().push(url);
+            }
+        }
 
-        Ok(())
+        let mut duplicates: Vec<_> = groups
+            .into_iter()
+            .filter(|(_, urls)| urls.len() > 1)
+            .map(|(hash, mut urls)| {
+                urls.sort();
+                (hash, urls)
+            })
+            .collect();
+        duplicates.sort_by(|a, b| a.1.cmp(&b.1));
+
+        Ok(duplicates)
     }
 
-    /// Create a list of unique URLs for a `domain`.
-    /// This function will combine the domain part with the relative URLs for the domain to build a
-    /// list of valid and complete URLs.
-    pub(crate) fn unique_urls_for_domain(&self, domain: &Url) -> Result<Vec<Url>, DbError> {
-        let db = self.0.read().unwrap();
-
-        Ok(db
-            .get(parse_domain(&domain)?.as_ref())
-            .ok_or(DbError::DomainDoesNotExist)?
-            .keys()
-            .map(|url| domain.join(&url))
-            .filter_map(|r| r.ok())
+    /// Some of the pages found linking to `url`, at most `MAX_REFERRERS` of them.
+    pub fn referrers(&self, url: &Url) -> Result<Vec<Url>, DbError> {
+        let (key, _) = self.resolve(url)?;
+        let path = UrlKey::new(url)?.path;
+
+        Ok(self
+            .0
+            .referrers(&key, &path)?
+            .iter()
+            .filter_map(|referrer| Url::parse(referrer).ok())
             .collect())
     }
 
-    /// Get the count of occurences for the given `url`.
-    pub(crate) fn url_count_for_domain(&self, url: &Url) -> Result<usize, DbError> {
-        let db = self.0.read().unwrap();
+    /// The pages of `domain` and the links found between them, sorted.
+    pub fn graph_for_domain(&self, domain: &Url) -> Result<LinkGraph, DbError> {
+        let (key, domain) = self.resolve(domain)?;
+        let base = base_url(domain);
+
+        let mut nodes: Vec<_> = self
+            .0
+            .paths(&key)?
+            .iter()
+            .map(|(path, _)| build_url(&base, path))
+            .collect();
+        let mut edges: Vec<_> = self
+            .0
+            .edges(&key)?
+            .iter()
+            .map(|(from, to)| Edge {
+                from: build_url(&base, from),
+                to: build_url(&base, to),
+            })
+            .collect();
+        nodes.sort();
+        edges.sort();
+
+        Ok(LinkGraph { nodes, edges })
+    }
+
+    /// Record that a page of `domain` refers to the asset `url` of `kind`.
+    pub(crate) fn visit_asset(
+        &self,
+        domain: &Url,
+        url: &Url,
+        kind: AssetKind,
+    ) -> Result<(), DbError> {
+        self.0
+            .visit_asset(&parse_domain(domain)?, url.as_str(), kind)
+    }
+
+    /// The assets the pages of `domain` refer to, e.g. images and scripts, with the number of
+    /// references to each.
+    pub fn assets_for_domain(&self, domain: &Url) -> Result<Vec<(Url, Asset)>, DbError> {
+        let (key, _) = self.resolve(domain)?;
+
+        Ok(self
+            .0
+            .assets(&key)?
+            .into_iter()
+            .filter_map(|(url, asset)| Some((Url::parse(&url).ok()?, asset)))
+            .collect())
+    }
+
+    /// Index the text of the page `url`, replacing the one indexed before. Only the first
+    /// `MAX_INDEXED_BYTES` of it are.
+    pub(crate) fn index_text(&self, url: &Url, text: &str) -> Result<(), DbError> {
+        let key = UrlKey::new(url)?;
+
+        self.index_path(&key.domain, &key.path, text)
+    }
+
+    /// Like `index_text` for the page `path` of `domain`, as stored.
+    fn index_path(&self, domain: &str, path: &str, text: &str) -> Result<(), DbError> {
+        let text = search::truncate(text);
+        let terms = search::terms(text);
+
+        if let Some(previous) = self.0.text(domain, path)? {
+            for term in search::terms(&previous).keys() {
+                if !terms.contains_key(term) {
+                    self.0.set_posting(domain, term, path, 0)?;
+                }
+            }
+        }
+        for (term, count) in &terms {
+            self.0.set_posting(domain, term, path, *count)?;
+        }
+
+        self.0.set_text(domain, path, text)
+    }
+
+    /// The pages of `domain` whose indexed text contains every word of `query`, whatever their
+    /// case, the most occurences first. At most `limit` are returned.
+    pub fn search(
+        &self,
+        domain: &Url,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, DbError> {
+        let (key, domain) = self.resolve(domain)?;
+        let base = base_url(domain);
+
+        let mut terms: Vec<_> = search::words(query).collect();
+        terms.sort();
+        terms.dedup();
+        let mut scores: Option<HashMap<String, usize>> = None;
+        for term in &terms {
+            let postings = self.0.postings(&key, term)?.into_iter();
+            scores = Some(match scores {
+                None => postings.collect(),
+                Some(scores) => postings
+                    .filter_map(|(path, count)| Some((path.clone(), scores.get(&path)? + count)))
+                    .collect(),
+            });
+        }
+
+        let mut scores: Vec<_> = scores.unwrap_or_default().into_iter().collect();
+        scores.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scores.truncate(limit);
+        scores
+            .into_iter()
+            .map(|(path, score)| {
+                let text = self.0.text(&key, &path)?.unwrap_or_default();
+
+                Ok(SearchHit {
+                    url: build_url(&base, &path),
+                    score,
+                    snippet: search::snippet(&text, &terms),
+                })
+            })
+            .collect()
+    }
+
+    /// The sessions that found each URL of `domain` first and last. URLs stored before sessions
+    /// were tracked, or only fetched again by a warm start, have none.
+    pub fn lineage_for_domain(&self, domain: &Url) -> Result<Vec<(Url, Lineage)>, DbError> {
+        let (key, domain) = self.resolve(domain)?;
+        let base = base_url(domain);
+
+        Ok(self
+            .0
+            .lineage(&key)?
+            .iter()
+            .map(|(path, lineage)| (build_url(&base, path), *lineage))
+            .collect())
+    }
+
+    /// What the last crawl session of `domain` found different from the session before it, `None`
+    /// if the domain was never crawled by a session.
+    pub fn diff_for_domain(&self, domain: &Url) -> Result<Option<CrawlDiff>, DbError> {
+        let session = self.sessions(domain)?.len() as u64;
+        if session == 0 {
+            return Ok(None);
+        }
+
+        let mut diff = CrawlDiff {
+            session,
+            new: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        };
+        for (url, lineage) in self.lineage_for_domain(domain)? {
+            if lineage.first_seen == session {
+                diff.new.push(url);
+            } else if lineage.last_seen == session - 1 {
+                diff.removed.push(url);
+            }
+        }
+        diff.changed = self
+            .statuses_for_domain(domain)?
+            .into_iter()
+            .filter(|(_, status)| status.changed && status.session == Some(session))
+            .map(|(url, _)| url)
+            .collect();
+        diff.new.sort();
+        diff.removed.sort();
+        diff.changed.sort();
 
-        Ok(db
-            .get(parse_domain(&url)?.as_ref())
-            .ok_or(DbError::DomainDoesNotExist)?
-            .get(&url[Position::BeforePath..])
-            .copied()
-            .unwrap_or(0usize))
+        Ok(Some(diff))
     }
 }
 
-/// Mockito uses https://127.0.0.1 as URL for its paths. Compute the domain using this function,
-/// so that we parse the host part instead of the domain part when testing.
-fn parse_domain(url: &Url) -> Result<Cow<str>, DbError> {
-    #[cfg(not(test))]
-    let url = url.domain().ok_or(DbError::DoesNotContainDomain)?;
+/// What a crawl session of a domain found different from the session before it, see
+/// `Db::diff_for_domain`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrawlDiff {
+    /// The session, numbered from 1 for each domain.
+    pub session: u64,
+    /// URLs found for the first time.
+    pub new: Vec<Url>,
+    /// URLs found by the previous session, but not by this one.
+    pub removed: Vec<Url>,
+    /// URLs fetched again, with another status or content than when last fetched.
+    pub changed: Vec<Url>,
+}
 
-    #[cfg(test)]
-    let url = {
-        let url = url.host().ok_or(DbError::DoesNotContainDomain)?;
-        url.to_string()
+/// The URL stored URLs of `domain` are built on. Stored URLs never carry credentials.
+fn base_url(mut domain: Url) -> Url {
+    let _ = domain.set_username("");
+    let _ = domain.set_password(None);
+    domain.set_fragment(None);
+    domain
+}
+
+/// Rebuild an URL from the `base` domain URL and a stored path, query and route fragment.
+/// `Url::join` is not used, as it would read a path starting with `//` as a different host.
+fn build_url(base: &Url, path: &str) -> Url {
+    // Paths and queries never contain a raw `#`, while routes may contain a `?`.
+    let (path, fragment) = match path.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (path, None),
     };
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path, None),
+    };
+
+    let mut url = base.clone();
+    url.set_path(path);
+    url.set_query(query);
+    url.set_fragment(fragment);
+    url
+}
+
+/// Compute the domain an URL is stored under. IP hosts such as `127.0.0.1` (used by mockito and
+/// local dev servers) are accepted as well.
+/// A non-default port is part of the domain, `example.com:8080` is a different site than `example.com`.
+fn parse_domain(url: &Url) -> Result<Cow<'_, str>, DbError> {
+    if url.cannot_be_a_base() {
+        return Err(DbError::CannotBeABase);
+    }
+
+    let domain = url.host_str().ok_or(DbError::DoesNotContainDomain)?;
 
-    Ok(url.into())
+    // `Url::port` is `None` for the scheme's default port.
+    Ok(match url.port() {
+        Some(port) => format!("{}:{}", domain, port).into(),
+        None => domain.into(),
+    })
 }
 
 #[cfg(test)]
 pub(crate) mod tests {
+    use proptest::prelude::*;
     use std::{borrow::Cow, str::FromStr};
-    use url::Url;
+    use url::{Position, Url};
 
-    use super::{Db, DbError};
+    use super::{
+        normalize_escapes, Asset, AssetKind, Db, DbError, DbTotals, MemoryUsage, RobotsMode,
+        SearchHit, UrlKey, UrlQuery, UrlSort,
+    };
     use crate::tests::compare_sorted;
 
+    #[test]
+    fn test_totals() -> anyhow::Result<()> {
+        let db = Db::default();
+        assert_eq!(db.totals()?, DbTotals::default());
+
+        let domain = Url::from_str("https://example.com")?;
+        db.visit(Cow::Owned(domain.join("/foo")?))?;
+        db.visit(Cow::Owned(domain.join("/foo")?))?;
+        db.visit(Cow::Owned(domain.join("/bar")?))?;
+        db.visit(Cow::Owned(Url::from_str("https://foobar.com/foo")?))?;
+
+        assert_eq!(
+            db.totals()?,
+            DbTotals {
+                domains: 2,
+                urls: 3,
+            }
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_unique_urls_list_for_domain() -> anyhow::Result<()> {
         let db = Db::default();
@@ -111,7 +1663,7 @@ pub(crate) mod tests {
 
         db.visit(Cow::Owned(domain_one.join("/foo/test/1")?))?;
 
-        assert_eq!(db.is_first_visit(&domain_one.join("/foo/test/1")?)?, false);
+        assert!(!db.is_first_visit(&domain_one.join("/foo/test/1")?)?);
         db.visit(Cow::Owned(domain_one.join("/foo/test/1")?))?;
         db.visit(Cow::Owned(domain_one.join("/bar/test/1")?))?;
         db.visit(Cow::Owned(domain_one.join("/bar/test/1")?))?;
@@ -142,6 +1694,46 @@ pub(crate) mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_url_counts_page() -> anyhow::Result<()> {
+        let db = Db::default();
+        let domain = Url::from_str("https://example.com")?;
+        for (path, visits) in &[
+            ("/blog/a", 1),
+            ("/blog/b", 3),
+            ("/blog/c", 2),
+            ("/about", 5),
+        ] {
+            for _ in 0..*visits {
+                db.visit(Cow::Owned(domain.join(path)?))?;
+            }
+        }
+
+        let (urls, total) = db.url_counts_page(&domain, &UrlQuery::default(), 1, 2)?;
+        assert_eq!(total, 4);
+        assert_eq!(
+            urls,
+            vec![(domain.join("/blog/a")?, 1), (domain.join("/blog/b")?, 3)]
+        );
+
+        let query = UrlQuery {
+            prefix: Some("/blog/".to_string()),
+            min_count: 2,
+            sort: UrlSort::Count,
+        };
+        let (urls, total) = db.url_counts_page(&domain, &query, 0, usize::MAX)?;
+        assert_eq!(total, 2);
+        assert_eq!(
+            urls,
+            vec![(domain.join("/blog/b")?, 3), (domain.join("/blog/c")?, 2)]
+        );
+        let (urls, total) = db.url_details_page(&domain, &query, 1, 10)?;
+        assert_eq!(total, 2);
+        assert_eq!(urls, vec![(domain.join("/blog/c")?, 2, None)]);
+
+        Ok(())
+    }
+
     #[test]
     fn test_url_count_for_domain() -> anyhow::Result<()> {
         let db = Db::default();
@@ -158,6 +1750,22 @@ pub(crate) mod tests {
 
         assert_eq!(db.url_count_for_domain(&domain.join("/baz")?)?, 0);
 
+        // Escapes of unreserved characters are the characters themselves.
+        db.visit(Cow::Owned(domain.join("/%7efoo")?))?;
+        assert!(!db.is_first_visit(&domain.join("/~foo")?)?);
+        assert_eq!(db.url_count_for_domain(&domain.join("/%7Efoo")?)?, 1);
+
+        let counts = db.url_counts(&[
+            domain.join("/foo")?,
+            domain.join("/baz")?,
+            Url::from_str("https://foobar.com/foo")?,
+            domain.join("/bar")?,
+        ])?;
+        assert_eq!(
+            counts,
+            vec![Ok(2), Ok(0), Err(DbError::DomainDoesNotExist), Ok(3)]
+        );
+
         let non_existant_domain = Url::from_str("https://who.com")?;
         assert_eq!(
             db.url_count_for_domain(&non_existant_domain.join("/foo")?),
@@ -166,4 +1774,435 @@ pub(crate) mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_truncated() -> anyhow::Result<()> {
+        let db = Db::default();
+        let domain = Url::from_str("https://example.com")?;
+
+        assert_eq!(db.is_truncated(&domain), Err(DbError::DomainDoesNotExist));
+
+        db.visit(Cow::Owned(domain.join("/foo")?))?;
+        assert!(!db.is_truncated(&domain)?);
+
+        db.set_truncated(&domain, true)?;
+        assert!(db.is_truncated(&domain)?);
+        assert_eq!(db.url_count_for_domain(&domain.join("/foo")?)?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_third_party() -> anyhow::Result<()> {
+        let db = Db::default();
+        let domain = Url::from_str("https://example.com")?;
+
+        assert_eq!(
+            db.third_party_for_domain(&domain),
+            Err(DbError::DomainDoesNotExist)
+        );
+
+        db.visit_third_party(&domain, "cdn.net")?;
+        db.visit_third_party(&domain, "cdn.net")?;
+        db.visit_third_party(&domain, "ads.com")?;
+
+        compare_sorted(
+            db.third_party_for_domain(&domain)?,
+            vec![("cdn.net".to_string(), 2), ("ads.com".to_string(), 1)],
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_assets() -> anyhow::Result<()> {
+        let db = Db::default();
+        let domain = Url::from_str("https://example.com")?;
+        let logo = Url::from_str("https://example.com/logo.png")?;
+        let script = Url::from_str("https://cdn.net/app.js")?;
+
+        assert_eq!(
+            db.assets_for_domain(&domain),
+            Err(DbError::DomainDoesNotExist)
+        );
+
+        db.visit_asset(&domain, &logo, AssetKind::Image)?;
+        db.visit_asset(&domain, &script, AssetKind::Script)?;
+        // The kind it was first found as is kept.
+        db.visit_asset(&domain, &logo, AssetKind::Link)?;
+
+        let mut assets = db.assets_for_domain(&domain)?;
+        assets.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            assets,
+            vec![
+                (
+                    script,
+                    Asset {
+                        kind: AssetKind::Script,
+                        count: 1
+                    }
+                ),
+                (
+                    logo,
+                    Asset {
+                        kind: AssetKind::Image,
+                        count: 2
+                    }
+                ),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search() -> anyhow::Result<()> {
+        let db = Db::default();
+        let domain = Url::from_str("https://example.com")?;
+        let foo = domain.join("/foo")?;
+        let bar = domain.join("/bar")?;
+
+        assert_eq!(
+            db.search(&domain, "rust", 10),
+            Err(DbError::DomainDoesNotExist)
+        );
+
+        db.visit(Cow::Borrowed(&foo))?;
+        db.visit(Cow::Borrowed(&bar))?;
+        db.index_text(&foo, "Rust crawlers: a crawler written in Rust")?;
+        db.index_text(&bar, "Crawlers in Go")?;
+
+        let hits = db.search(&domain, "RUST", 10)?;
+        assert_eq!(
+            hits,
+            vec![SearchHit {
+                url: foo.clone(),
+                score: 2,
+                snippet: "Rust crawlers: a crawler written in Rust".to_string(),
+            }]
+        );
+        // Every word must be found, and the pages are ranked by their number of occurences.
+        let hits = db.search(&domain, "crawlers in", 10)?;
+        assert_eq!(
+            hits.iter()
+                .map(|hit| (&hit.url, hit.score))
+                .collect::<Vec<_>>(),
+            vec![(&bar, 2), (&foo, 2)]
+        );
+        assert_eq!(db.search(&domain, "crawlers in", 1)?.len(), 1);
+        assert!(db.search(&domain, "rust go", 10)?.is_empty());
+
+        // The text indexed before is replaced.
+        db.index_text(&foo, "Nothing to see")?;
+        assert!(db.search(&domain, "rust", 10)?.is_empty());
+        assert_eq!(db.search(&domain, "see", 10)?[0].url, foo);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lineage() -> anyhow::Result<()> {
+        let db = Db::default();
+        let domain = Url::from_str("https://example.com")?;
+
+        assert_eq!(db.start_session(&domain, RobotsMode::Respect)?, 1);
+        db.visit(Cow::Owned(domain.join("/kept")?))?;
+        db.see(&domain.join("/kept")?, 1)?;
+        db.see(&domain.join("/gone")?, 1)?;
+        assert_eq!(db.start_session(&domain, RobotsMode::Ignore)?, 2);
+        db.see(&domain.join("/kept")?, 2)?;
+        db.see(&domain.join("/new")?, 2)?;
+
+        let sessions = db.sessions(&domain)?;
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions[0] <= sessions[1]);
+        assert_eq!(
+            db.robots_modes(&domain)?,
+            vec![Some(RobotsMode::Respect), Some(RobotsMode::Ignore)]
+        );
+        compare_sorted(
+            db.lineage_for_domain(&domain)?
+                .into_iter()
+                .map(|(url, lineage)| (url.to_string(), lineage.first_seen, lineage.last_seen))
+                .collect(),
+            vec![
+                (domain.join("/kept")?.to_string(), 1, 2),
+                (domain.join("/gone")?.to_string(), 1, 1),
+                (domain.join("/new")?.to_string(), 2, 2),
+            ],
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alias() -> anyhow::Result<()> {
+        let db = Db::default();
+        let apex = Url::from_str("http://example.com")?;
+        let www = Url::from_str("https://www.example.com")?;
+
+        db.visit(Cow::Owned(www.join("/foo")?))?;
+        db.set_alias(&apex, Some(&www))?;
+
+        assert_eq!(db.unique_urls_for_domain(&apex)?, vec![www.join("/foo")?]);
+        assert_eq!(db.url_count_for_domain(&apex.join("/foo")?)?, 1);
+        assert!(!db.is_truncated(&apex)?);
+
+        db.set_alias(&apex, None)?;
+        assert_eq!(db.unique_urls_for_domain(&apex)?, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_budget() -> anyhow::Result<()> {
+        let db = Db::with_memory_budget(4);
+        let foo = Url::from_str("https://foo.com")?;
+        let bar = Url::from_str("https://bar.com")?;
+        let baz = Url::from_str("https://baz.com")?;
+
+        db.visit(Cow::Owned(foo.join("/1")?))?;
+        db.visit(Cow::Owned(bar.join("/1")?))?;
+        db.visit(Cow::Owned(foo.join("/2")?))?;
+        db.visit(Cow::Owned(bar.join("/2")?))?;
+        // Visited again, foo.com is not the least recently visited anymore.
+        db.visit(Cow::Owned(foo.join("/1")?))?;
+        db.visit(Cow::Owned(baz.join("/1")?))?;
+
+        assert_eq!(
+            db.unique_urls_for_domain(&bar),
+            Err(DbError::DomainDoesNotExist)
+        );
+        assert_eq!(db.url_count_for_domain(&foo.join("/1")?)?, 2);
+        assert_eq!(
+            db.memory_usage(),
+            Some(MemoryUsage {
+                urls: 3,
+                max_urls: Some(4),
+                evicted_domains: 1,
+            })
+        );
+        assert_eq!(db.totals()?.urls, 3);
+
+        // A single domain may go beyond the budget.
+        for i in 0..5 {
+            db.visit(Cow::Owned(baz.join(&format!("/page/{}", i))?))?;
+        }
+        assert_eq!(db.totals()?.domains, 1);
+        assert_eq!(db.memory_usage().unwrap().urls, 6);
+
+        // Neither pinned domains nor their subdomains are evicted, until unpinned.
+        let pin = db.pin(&baz)?;
+        let blog = Url::from_str("https://blog.baz.com")?;
+        db.visit(Cow::Owned(blog.join("/1")?))?;
+        db.visit(Cow::Owned(foo.join("/1")?))?;
+        assert_eq!(db.totals()?.domains, 3);
+        drop(pin);
+        db.visit(Cow::Owned(foo.join("/2")?))?;
+        assert_eq!(db.unique_urls_for_domain(&foo)?.len(), 2);
+        assert_eq!(
+            db.unique_urls_for_domain(&baz),
+            Err(DbError::DomainDoesNotExist)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_visit() -> anyhow::Result<()> {
+        let db = Db::with_memory_budget(100);
+        let domains: Vec<Url> = (0..4)
+            .map(|i| Url::from_str(&format!("https://{}.example.com", i)))
+            .collect::<Result<_, _>>()?;
+
+        // Crawlers racing on the same URLs: each is new to a single one of them.
+        let firsts: usize = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut firsts = 0;
+                        for domain in &domains {
+                            for i in 0..20 {
+                                let url = domain.join(&format!("/{}", i)).unwrap();
+                                if db.record_visit(&url).unwrap() {
+                                    firsts += 1;
+                                }
+                            }
+                        }
+                        firsts
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+
+        assert_eq!(firsts, 80);
+        assert_eq!(db.totals()?.urls, 80);
+        assert_eq!(db.url_count_for_domain(&domains[0].join("/0")?)?, 8);
+        assert!(!db.record_visit(&domains[0].join("/0")?)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_domain() -> anyhow::Result<()> {
+        let db = Db::default();
+        let apex = Url::from_str("http://example.com")?;
+        let www = Url::from_str("https://www.example.com")?;
+        let other = Url::from_str("https://foobar.com")?;
+
+        db.visit(Cow::Owned(www.join("/foo")?))?;
+        db.index_text(&www.join("/foo")?, "Some text")?;
+        db.set_alias(&apex, Some(&www))?;
+        db.visit(Cow::Owned(other.join("/foo")?))?;
+
+        // The domain an alias stands for goes with it.
+        db.remove_domain(&apex)?;
+        assert_eq!(
+            db.unique_urls_for_domain(&www),
+            Err(DbError::DomainDoesNotExist)
+        );
+        assert_eq!(
+            db.unique_urls_for_domain(&apex),
+            Err(DbError::DomainDoesNotExist)
+        );
+        assert_eq!(db.remove_domain(&www), Err(DbError::DomainDoesNotExist));
+        assert_eq!(
+            db.unique_urls_for_domain(&other)?,
+            vec![other.join("/foo")?]
+        );
+
+        // Crawled again from scratch.
+        db.visit(Cow::Owned(www.join("/bar")?))?;
+        assert_eq!(db.unique_urls_for_domain(&www)?, vec![www.join("/bar")?]);
+        assert!(db.search(&www, "text", 10)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ports() -> anyhow::Result<()> {
+        let db = Db::default();
+        let default_port = Url::from_str("https://example.com")?;
+        let explicit_default_port = Url::from_str("https://example.com:443")?;
+        let other_port = Url::from_str("https://example.com:8080")?;
+
+        db.visit(Cow::Owned(default_port.join("/foo")?))?;
+        db.visit(Cow::Owned(explicit_default_port.join("/foo")?))?;
+        db.visit(Cow::Owned(other_port.join("/bar")?))?;
+
+        assert_eq!(db.url_count_for_domain(&default_port.join("/foo")?)?, 2);
+        assert_eq!(db.url_count_for_domain(&other_port.join("/foo")?)?, 0);
+        assert_eq!(
+            db.unique_urls_for_domain(&default_port)?,
+            vec![default_port.join("/foo")?]
+        );
+        assert_eq!(
+            db.unique_urls_for_domain(&other_port)?,
+            vec![other_port.join("/bar")?]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_url_key_errors() -> anyhow::Result<()> {
+        let db = Db::default();
+
+        assert_eq!(
+            db.visit(Cow::Owned(Url::from_str("mailto:foo@example.com")?)),
+            Err(DbError::CannotBeABase)
+        );
+        assert_eq!(
+            db.visit(Cow::Owned(Url::from_str("data:text/plain,foo")?)),
+            Err(DbError::CannotBeABase)
+        );
+        assert_eq!(
+            db.visit(Cow::Owned(Url::from_str("file:///etc/passwd")?)),
+            Err(DbError::DoesNotContainDomain)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fragment_routes() -> anyhow::Result<()> {
+        let db = Db::default();
+        let route = Url::from_str("https://example.com/app?v=1#/products/123?tab=2")?;
+
+        db.visit(Cow::Borrowed(&route))?;
+        db.visit(Cow::Owned(Url::from_str(
+            "https://example.com/app?v=1#top",
+        )?))?;
+
+        assert_eq!(db.url_count_for_domain(&route)?, 1);
+        compare_sorted(
+            db.unique_urls_for_domain(&route)?,
+            vec![route, Url::from_str("https://example.com/app?v=1")?],
+        );
+
+        Ok(())
+    }
+
+    /// Valid http(s) URLs with optional userinfo, port, query and fragment, the fragment not
+    /// being a route.
+    fn weird_url() -> impl Strategy<Value = Url> {
+        (
+            prop_oneof!["http", "https"],
+            proptest::option::of("[a-z]{1,5}(:[a-z0-9]{0,5})?@"),
+            "[a-z]{1,10}\\.(com|org)",
+            proptest::option::of(1u16..),
+            proptest::collection::vec("[a-zA-Z0-9._~%-]{0,8}", 0..4),
+            proptest::option::of("[a-z0-9=&;]{0,10}"),
+            proptest::option::of("([a-z0-9][a-z0-9/]{0,9})?"),
+        )
+            .prop_map(
+                |(scheme, userinfo, host, port, segments, query, fragment)| {
+                    let mut url = format!("{}://{}{}", scheme, userinfo.unwrap_or_default(), host);
+                    if let Some(port) = port {
+                        url.push_str(&format!(":{}", port));
+                    }
+                    url.push('/');
+                    url.push_str(&segments.join("/"));
+                    if let Some(query) = query {
+                        url.push_str(&format!("?{}", query));
+                    }
+                    if let Some(fragment) = fragment {
+                        url.push_str(&format!("#{}", fragment));
+                    }
+
+                    Url::parse(&url).unwrap()
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn prop_visit_round_trip(url in weird_url()) {
+            let db = Db::default();
+            db.visit(Cow::Borrowed(&url)).unwrap();
+            db.visit(Cow::Borrowed(&url)).unwrap();
+
+            prop_assert!(!db.is_first_visit(&url).unwrap());
+            prop_assert_eq!(db.url_count_for_domain(&url).unwrap(), 2);
+
+            // The same page, without userinfo and fragment.
+            let mut plain = url.clone();
+            plain.set_username("").unwrap();
+            plain.set_password(None).unwrap();
+            plain.set_fragment(None);
+            prop_assert_eq!(db.url_count_for_domain(&plain).unwrap(), 2);
+
+            let urls = db.unique_urls_for_domain(&url).unwrap();
+            prop_assert_eq!(urls.len(), 1);
+            prop_assert_eq!(urls[0].username(), "");
+            prop_assert_eq!(urls[0].password(), None);
+            prop_assert_eq!(urls[0].fragment(), None);
+            prop_assert_eq!(
+                normalize_escapes(&urls[0][Position::BeforePath..Position::AfterQuery]),
+                UrlKey::new(&url).unwrap().path
+            );
+        }
+    }
 }
@@ -0,0 +1,558 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use url::Url;
+
+use super::{parse_domain, Db, DbError, DomainMeta, FetchStatus, Lineage};
+use crate::parser::AssetKind;
+
+/// Name in the header of every dump, telling it apart from other NDJSON.
+const FORMAT: &str = "web-crawler-dump";
+/// Version of the records written by `Db::export`, bumped on incompatible changes.
+const VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum DumpError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid record on line {line}: {source}")]
+    Record {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("Not a crawler dump")]
+    NotADump,
+    #[error("Unsupported dump version {0}, expected {}", VERSION)]
+    Version(u32),
+    #[error("Truncated dump, the footer is missing")]
+    Truncated,
+    #[error("Data after the footer of the dump")]
+    TrailingData,
+    #[error("Dump bigger than {0} bytes once decompressed")]
+    TooLarge(u64),
+    #[error("Corrupted dump, {0}")]
+    Corrupted(&'static str),
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+/// A line of a dump. The header comes first, then the records of each domain, then the footer
+/// with the number of records in between and the SHA-256 of every line before it.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record {
+    Header {
+        format: String,
+        version: u32,
+    },
+    Domain {
+        domain: String,
+        meta: DomainMeta,
+    },
+    Url {
+        domain: String,
+        path: String,
+        count: usize,
+        /// Missing from the dumps written before lineage was kept.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lineage: Option<Lineage>,
+        /// Missing from the dumps written before statuses and links were kept.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<FetchStatus>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        linked_from: Vec<String>,
+        /// The indexed text of the page, whose search postings are rebuilt from it on import.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+    },
+    ThirdParty {
+        domain: String,
+        host: String,
+        count: usize,
+    },
+    Asset {
+        domain: String,
+        url: String,
+        kind: AssetKind,
+        count: usize,
+    },
+    /// A link between two pages of `domain`, part of its link graph.
+    Edge {
+        domain: String,
+        from: String,
+        to: String,
+    },
+    Footer {
+        records: usize,
+        sha256: String,
+    },
+}
+
+/// What a dump holds, as written by `Db::export` or read by `Db::import`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpSummary {
+    pub domains: usize,
+    pub urls: usize,
+    pub third_party: usize,
+    #[serde(default)]
+    pub assets: usize,
+    #[serde(default)]
+    pub edges: usize,
+}
+
+impl DumpSummary {
+    fn count(&mut self, record: &Record) {
+        match record {
+            Record::Domain { .. } => self.domains += 1,
+            Record::Url { .. } => self.urls += 1,
+            Record::ThirdParty { .. } => self.third_party += 1,
+            Record::Asset { .. } => self.assets += 1,
+            Record::Edge { .. } => self.edges += 1,
+            Record::Header { .. } | Record::Footer { .. } => {}
+        }
+    }
+
+    fn records(&self) -> usize {
+        self.domains + self.urls + self.third_party + self.assets + self.edges
+    }
+}
+
+/// Writes records as lines, hashing them on the way.
+struct DumpWriter<W: Write> {
+    out: W,
+    hasher: Sha256,
+    summary: DumpSummary,
+}
+
+impl<W: Write> DumpWriter<W> {
+    fn write(&mut self, record: &Record) -> Result<(), DumpError> {
+        let mut line = serde_json::to_vec(record).expect("Records always serialize");
+        line.push(b'\n');
+        self.hasher.update(&line);
+        self.out.write_all(&line)?;
+        self.summary.count(record);
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(W, DumpSummary), DumpError> {
+        let footer = Record::Footer {
+            records: self.summary.records(),
+            sha256: format!("{:x}", self.hasher.finalize_reset()),
+        };
+        self.write(&footer)?;
+
+        Ok((self.out, self.summary))
+    }
+}
+
+impl Db {
+    /// Write a zstd-compressed NDJSON dump of `domain` to `out`, or of every domain if `None`.
+    /// The dump of a domain redirecting to another holds both of them.
+    pub fn export(&self, domain: Option<&Url>, out: impl Write) -> Result<DumpSummary, DumpError> {
+        let domains = match domain {
+            Some(domain) => {
+                let key = parse_domain(domain)?.into_owned();
+                let (canonical, _) = self.resolve(domain)?;
+                if canonical == key {
+                    vec![key]
+                } else {
+                    vec![key, canonical]
+                }
+            }
+            None => {
+                let mut domains = self.0.domains()?;
+                domains.sort();
+                domains
+            }
+        };
+
+        let mut writer = DumpWriter {
+            out: zstd::Encoder::new(out, 0)?,
+            hasher: Sha256::new(),
+            summary: DumpSummary::default(),
+        };
+        writer.write(&Record::Header {
+            format: FORMAT.to_string(),
+            version: VERSION,
+        })?;
+        for domain in domains {
+            let meta = self.0.meta(&domain)?.ok_or(DbError::DomainDoesNotExist)?;
+            let mut paths = self.0.paths(&domain)?;
+            paths.sort();
+            let mut third_party = self.0.third_party(&domain)?;
+            third_party.sort();
+            let mut assets = self.0.assets(&domain)?;
+            assets.sort_by(|a, b| a.0.cmp(&b.0));
+            let mut edges = self.0.edges(&domain)?;
+            edges.sort();
+            let mut lineage: HashMap<_, _> = self.0.lineage(&domain)?.into_iter().collect();
+            let mut statuses: HashMap<_, _> = self.0.statuses(&domain)?.into_iter().collect();
+
+            writer.write(&Record::Domain {
+                domain: domain.clone(),
+                meta,
+            })?;
+            for (path, count) in paths {
+                writer.write(&Record::Url {
+                    domain: domain.clone(),
+                    lineage: lineage.remove(&path),
+                    status: statuses.remove(&path),
+                    linked_from: self.0.referrers(&domain, &path)?,
+                    text: self.0.text(&domain, &path)?,
+                    path,
+                    count,
+                })?;
+            }
+            for (host, count) in third_party {
+                writer.write(&Record::ThirdParty {
+                    domain: domain.clone(),
+                    host,
+                    count,
+                })?;
+            }
+            for (url, asset) in assets {
+                writer.write(&Record::Asset {
+                    domain: domain.clone(),
+                    url,
+                    kind: asset.kind,
+                    count: asset.count,
+                })?;
+            }
+            for (from, to) in edges {
+                writer.write(&Record::Edge {
+                    domain: domain.clone(),
+                    from,
+                    to,
+                })?;
+            }
+        }
+
+        let (out, summary) = writer.finish()?;
+        out.finish()?;
+
+        Ok(summary)
+    }
+
+    /// Load a dump written by `Db::export`, of at most `max_bytes` once decompressed. The whole
+    /// dump is checked before anything is written, so that a corrupted one leaves the database as
+    /// it was: its records are kept in a temporary file until then, rather than in memory. Counts
+    /// are added to the ones already stored, the meta of a domain replaces its stored one.
+    pub fn import(&self, input: impl Read, max_bytes: u64) -> Result<DumpSummary, DumpError> {
+        // One byte past the limit tells a dump of exactly `max_bytes` from a bigger one.
+        let mut reader =
+            BufReader::new(zstd::Decoder::new(input)?.take(max_bytes.saturating_add(1)));
+        let mut read_line = |line: &mut Vec<u8>| -> Result<usize, DumpError> {
+            line.clear();
+            let read = reader.read_until(b'\n', line)?;
+            if reader.get_ref().limit() == 0 {
+                return Err(DumpError::TooLarge(max_bytes));
+            }
+            Ok(read)
+        };
+        let mut hasher = Sha256::new();
+        let mut records = BufWriter::new(tempfile::tempfile()?);
+        let mut summary = DumpSummary::default();
+
+        let mut line = Vec::new();
+        let mut line_no = 0;
+        let footer = loop {
+            if read_line(&mut line)? == 0 {
+                return Err(DumpError::Truncated);
+            }
+            line_no += 1;
+            let record: Record = serde_json::from_slice(&line).map_err(|source| match line_no {
+                // Not even the header, e.g. a file of another kind.
+                1 => DumpError::NotADump,
+                line => DumpError::Record { line, source },
+            })?;
+
+            match (&record, line_no) {
+                (Record::Header { format, version }, 1) => {
+                    if format != FORMAT {
+                        return Err(DumpError::NotADump);
+                    }
+                    if *version != VERSION {
+                        return Err(DumpError::Version(*version));
+                    }
+                }
+                (_, 1) => return Err(DumpError::NotADump),
+                (Record::Header { .. }, _) => {
+                    return Err(DumpError::Corrupted("header in the middle of the records"))
+                }
+                (Record::Footer { .. }, _) => break record,
+                _ => {
+                    summary.count(&record);
+                    records.write_all(&line)?;
+                }
+            }
+
+            hasher.update(&line);
+        };
+        if read_line(&mut line)? > 0 {
+            return Err(DumpError::TrailingData);
+        }
+
+        if let Record::Footer {
+            records: expected,
+            sha256,
+        } = footer
+        {
+            if expected != summary.records() {
+                return Err(DumpError::Corrupted("record count mismatch"));
+            }
+            if sha256 != format!("{:x}", hasher.finalize()) {
+                return Err(DumpError::Corrupted("checksum mismatch"));
+            }
+        }
+
+        let mut records = records.into_inner().map_err(|e| e.into_error())?;
+        records.seek(SeekFrom::Start(0))?;
+        for (line_no, line) in BufReader::new(records).split(b'\n').enumerate() {
+            // Read back as written, the header being the first line of the dump.
+            let record = serde_json::from_slice(&line?).map_err(|source| DumpError::Record {
+                line: line_no + 2,
+                source,
+            })?;
+            match record {
+                Record::Domain { domain, meta } => self.0.set_meta(&domain, meta)?,
+                Record::Url {
+                    domain,
+                    path,
+                    count,
+                    lineage,
+                    status,
+                    linked_from,
+                    text,
+                } => {
+                    if count > 0 {
+                        self.0.add_visits(&domain, &path, count)?;
+                    }
+                    if let Some(lineage) = lineage {
+                        self.0.see(&domain, &path, lineage.first_seen)?;
+                        self.0.see(&domain, &path, lineage.last_seen)?;
+                    }
+                    if let Some(status) = status {
+                        self.0.set_status(&domain, &path, &status)?;
+                    }
+                    for from in linked_from {
+                        self.0.link(&domain, &path, &from)?;
+                    }
+                    if let Some(text) = text {
+                        self.index_path(&domain, &path, &text)?;
+                    }
+                }
+                Record::ThirdParty {
+                    domain,
+                    host,
+                    count,
+                } => {
+                    if count > 0 {
+                        self.0.add_third_party(&domain, &host, count)?;
+                    }
+                }
+                Record::Asset {
+                    domain,
+                    url,
+                    kind,
+                    count,
+                } => {
+                    if count > 0 {
+                        self.0.add_asset(&domain, &url, kind, count)?;
+                    }
+                }
+                Record::Edge { domain, from, to } => self.0.add_edge(&domain, &from, &to)?,
+                Record::Header { .. } | Record::Footer { .. } => unreachable!(),
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, str::FromStr};
+
+    use url::Url;
+
+    use super::{DumpError, DumpSummary};
+    use crate::{
+        db::{parse_domain, Db, FetchStatus},
+        parser::AssetKind,
+        robots::RobotsMode,
+    };
+
+    fn dump(db: &Db, domain: Option<&Url>) -> Vec<u8> {
+        let mut out = Vec::new();
+        db.export(domain, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let db = Db::default();
+        let domain = Url::from_str("https://example.com")?;
+        let www = Url::from_str("https://www.example.com")?;
+        db.visit(Cow::Owned(www.join("/foo")?))?;
+        db.visit(Cow::Owned(www.join("/foo")?))?;
+        db.visit(Cow::Owned(www.join("/bar?q=1")?))?;
+        db.visit_third_party(&www, "cdn.net")?;
+        db.visit_asset(
+            &www,
+            &Url::from_str("https://cdn.net/app.js")?,
+            AssetKind::Script,
+        )?;
+        db.start_session(&www, RobotsMode::Respect)?;
+        db.see(&www.join("/foo")?, 1)?;
+        db.set_status(
+            &www.join("/bar?q=1")?,
+            &FetchStatus::failed(Some(404), "http_404"),
+        )?;
+        db.link(&www.join("/bar?q=1")?, &www.join("/foo")?)?;
+        db.index_text(&www.join("/foo")?, "Crawling the web, one page at a time")?;
+        db.set_truncated(&www, true)?;
+        db.set_alias(&domain, Some(&www))?;
+        db.visit(Cow::Owned(Url::from_str("https://foobar.com/baz")?))?;
+
+        let imported = Db::default();
+        let summary = imported.import(&dump(&db, None)[..], u64::MAX)?;
+        assert_eq!(
+            summary,
+            DumpSummary {
+                domains: 3,
+                urls: 3,
+                third_party: 1,
+                assets: 1,
+                edges: 1,
+            }
+        );
+        assert_eq!(imported.totals()?, db.totals()?);
+        let mut urls = imported.url_counts_for_domain(&domain)?;
+        urls.sort();
+        assert_eq!(
+            urls,
+            vec![(www.join("/bar?q=1")?, 1), (www.join("/foo")?, 2),]
+        );
+        assert!(imported.is_truncated(&domain)?);
+        assert_eq!(
+            imported.third_party_for_domain(&www)?,
+            vec![("cdn.net".to_string(), 1)]
+        );
+        assert_eq!(
+            imported.lineage_for_domain(&www)?,
+            db.lineage_for_domain(&www)?
+        );
+        assert_eq!(imported.sessions(&www)?, db.sessions(&www)?);
+        assert_eq!(imported.robots_modes(&www)?, db.robots_modes(&www)?);
+        assert_eq!(
+            imported.statuses_for_domain(&www)?,
+            db.statuses_for_domain(&www)?
+        );
+        assert_eq!(
+            imported.referrers(&www.join("/bar?q=1")?)?,
+            vec![www.join("/foo")?]
+        );
+        assert_eq!(
+            imported.assets_for_domain(&www)?,
+            db.assets_for_domain(&www)?
+        );
+        assert_eq!(imported.graph_for_domain(&www)?, db.graph_for_domain(&www)?);
+        assert_eq!(imported.graph_for_domain(&www)?.edges.len(), 1);
+        assert_eq!(
+            imported.search(&www, "page crawling", 10)?,
+            db.search(&www, "page crawling", 10)?
+        );
+        assert_eq!(imported.search(&www, "page crawling", 10)?.len(), 1);
+
+        // A single domain brings the one it redirects to along.
+        let imported = Db::default();
+        let summary = imported.import(&dump(&db, Some(&domain))[..], u64::MAX)?;
+        assert_eq!(summary.domains, 2);
+        assert_eq!(imported.url_count_for_domain(&domain.join("/foo")?)?, 2);
+        assert!(matches!(
+            db.export(Some(&Url::from_str("https://nope.com")?), Vec::new()),
+            Err(DumpError::Db(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_large_counts() -> anyhow::Result<()> {
+        let db = Db::default();
+        let domain = Url::from_str("https://example.com")?;
+        db.0.add_visits(&parse_domain(&domain)?, "/foo", 1 << 60)?;
+        db.0.add_third_party(&parse_domain(&domain)?, "cdn.net", 1 << 60)?;
+
+        // Added at once, not one by one.
+        let imported = Db::default();
+        imported.import(&dump(&db, None)[..], u64::MAX)?;
+        assert_eq!(
+            imported.url_count_for_domain(&domain.join("/foo")?)?,
+            1 << 60
+        );
+        assert_eq!(
+            imported.third_party_for_domain(&domain)?,
+            vec![("cdn.net".to_string(), 1 << 60)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupted() -> anyhow::Result<()> {
+        let db = Db::default();
+        db.visit(Cow::Owned(Url::from_str("https://example.com/foo")?))?;
+        let ndjson = zstd::decode_all(&dump(&db, None)[..])?;
+        let ndjson = String::from_utf8(ndjson)?;
+
+        let import_limited = |ndjson: &str, max_bytes: u64| {
+            let db = Db::default();
+            let result = db.import(
+                &zstd::encode_all(ndjson.as_bytes(), 0).unwrap()[..],
+                max_bytes,
+            );
+            // Nothing is written from a bad dump.
+            if result.is_err() {
+                assert_eq!(db.totals().unwrap().domains, 0);
+            }
+            result
+        };
+        let import = |ndjson: &str| import_limited(ndjson, u64::MAX);
+
+        assert!(import(&ndjson).is_ok());
+        assert!(import_limited(&ndjson, ndjson.len() as u64).is_ok());
+        assert!(matches!(
+            import_limited(&ndjson, ndjson.len() as u64 - 1),
+            Err(DumpError::TooLarge(_))
+        ));
+        assert!(matches!(
+            import(&ndjson.replace("/foo", "/bar")),
+            Err(DumpError::Corrupted("checksum mismatch"))
+        ));
+        let truncated: String = ndjson.lines().take(2).map(|l| format!("{}\n", l)).collect();
+        assert!(matches!(import(&truncated), Err(DumpError::Truncated)));
+        assert!(matches!(
+            import(&format!("{}{}", ndjson, ndjson)),
+            Err(DumpError::TrailingData)
+        ));
+        assert!(matches!(
+            import(&ndjson.replace("\"version\":1", "\"version\":2")),
+            Err(DumpError::Version(2))
+        ));
+        assert!(matches!(import("url,count\n"), Err(DumpError::NotADump)));
+        // Not compressed.
+        assert!(matches!(
+            db.import(ndjson.as_bytes(), u64::MAX),
+            Err(DumpError::Io(_))
+        ));
+
+        Ok(())
+    }
+}
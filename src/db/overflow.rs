@@ -0,0 +1,906 @@
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use tracing::{info, warn};
+
+use super::{
+    add_referrer, Asset, DbError, DomainMeta, FetchStatus, Lineage, MemoryUsage, Storage,
+    StorageHealth,
+};
+use crate::parser::AssetKind;
+
+/// Counts buffered per domain and path, or per domain and third-party host.
+type Counts = HashMap<(String, String), usize>;
+
+/// Writes that could not reach the backend yet.
+#[derive(Debug, Default)]
+struct Buffer {
+    visits: Counts,
+    third_party: Counts,
+    meta: HashMap<String, DomainMeta>,
+    lineage: HashMap<(String, String), Lineage>,
+    statuses: HashMap<(String, String), FetchStatus>,
+    referrers: HashMap<(String, String), Vec<String>>,
+    /// Links buffered per domain, from path and to path.
+    edges: HashSet<(String, String, String)>,
+    assets: HashMap<(String, String), Asset>,
+    texts: HashMap<(String, String), String>,
+    /// Number of occurences buffered per domain, term and path, 0 removing the path.
+    postings: HashMap<(String, String, String), usize>,
+}
+
+impl Buffer {
+    fn len(&self) -> usize {
+        self.visits.values().sum::<usize>()
+            + self.third_party.values().sum::<usize>()
+            + self.meta.len()
+            + self.lineage.len()
+            + self.statuses.len()
+            + self.referrers.values().map(Vec::len).sum::<usize>()
+            + self.edges.len()
+            + self.texts.len()
+            + self.postings.len()
+            + self.assets.values().map(|asset| asset.count).sum::<usize>()
+    }
+
+    /// Drop everything buffered for `domain`.
+    fn remove_domain(&mut self, domain: &str) {
+        self.visits.retain(|(d, _), _| d != domain);
+        self.third_party.retain(|(d, _), _| d != domain);
+        self.meta.remove(domain);
+        self.lineage.retain(|(d, _), _| d != domain);
+        self.statuses.retain(|(d, _), _| d != domain);
+        self.referrers.retain(|(d, _), _| d != domain);
+        self.edges.retain(|(d, _, _)| d != domain);
+        self.assets.retain(|(d, _), _| d != domain);
+        self.texts.retain(|(d, _), _| d != domain);
+        self.postings.retain(|(d, _, _), _| d != domain);
+    }
+
+    fn has_domain(&self, domain: &str) -> bool {
+        self.meta.contains_key(domain)
+            || self.visits.keys().any(|(d, _)| d == domain)
+            || self.third_party.keys().any(|(d, _)| d == domain)
+            || self.assets.keys().any(|(d, _)| d == domain)
+    }
+}
+
+/// Wraps a `Storage` so that its storage errors do not lose writes: while the backend fails,
+/// writes are kept in an in-memory buffer, flushed before the next write once it recovers.
+/// Reads merge the buffer in. `count` only sees the buffer while the backend fails, so that crawls
+/// can go on, at the cost of fetching again some URLs stored before. Other reads still fail, as
+/// the meta of a domain is read before being updated, and a default one would overwrite it.
+/// The buffer is not bounded, and is lost if the process stops before the backend recovers.
+#[derive(Debug)]
+pub struct OverflowStorage<S> {
+    inner: S,
+    buffer: Mutex<Buffer>,
+}
+
+impl<S: Storage> OverflowStorage<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            buffer: Mutex::default(),
+        }
+    }
+
+    /// Write the buffer to the backend, keeping what could not be written.
+    fn flush(&self, buffer: &mut Buffer) -> Result<(), DbError> {
+        let buffered = buffer.len();
+        if buffered == 0 {
+            return Ok(());
+        }
+
+        let domains: Vec<_> = buffer.meta.keys().cloned().collect();
+        for domain in domains {
+            self.inner.set_meta(&domain, buffer.meta[&domain].clone())?;
+            buffer.meta.remove(&domain);
+        }
+        replay(&mut buffer.visits, |domain, path, count| {
+            skip_corrupted(self.inner.add_visits(domain, path, count))
+        })?;
+        replay(&mut buffer.third_party, |domain, host, count| {
+            skip_corrupted(self.inner.add_third_party(domain, host, count))
+        })?;
+        let paths: Vec<_> = buffer.lineage.keys().cloned().collect();
+        for path in paths {
+            let lineage = buffer.lineage[&path];
+            skip_corrupted(self.inner.see(&path.0, &path.1, lineage.first_seen))?;
+            skip_corrupted(self.inner.see(&path.0, &path.1, lineage.last_seen))?;
+            buffer.lineage.remove(&path);
+        }
+        let paths: Vec<_> = buffer.statuses.keys().cloned().collect();
+        for path in paths {
+            self.inner
+                .set_status(&path.0, &path.1, &buffer.statuses[&path])?;
+            buffer.statuses.remove(&path);
+        }
+        let paths: Vec<_> = buffer.referrers.keys().cloned().collect();
+        for path in paths {
+            while let Some(from) = buffer.referrers[&path].first().cloned() {
+                self.inner.link(&path.0, &path.1, &from)?;
+                buffer.referrers.get_mut(&path).unwrap().remove(0);
+            }
+            buffer.referrers.remove(&path);
+        }
+        let edges: Vec<_> = buffer.edges.iter().cloned().collect();
+        for edge in edges {
+            self.inner.add_edge(&edge.0, &edge.1, &edge.2)?;
+            buffer.edges.remove(&edge);
+        }
+        let postings: Vec<_> = buffer.postings.keys().cloned().collect();
+        for posting in postings {
+            let count = buffer.postings[&posting];
+            self.inner
+                .set_posting(&posting.0, &posting.1, &posting.2, count)?;
+            buffer.postings.remove(&posting);
+        }
+        let paths: Vec<_> = buffer.texts.keys().cloned().collect();
+        for path in paths {
+            self.inner
+                .set_text(&path.0, &path.1, &buffer.texts[&path])?;
+            buffer.texts.remove(&path);
+        }
+        let urls: Vec<_> = buffer.assets.keys().cloned().collect();
+        for url in urls {
+            let asset = &buffer.assets[&url];
+            self.inner
+                .add_asset(&url.0, &url.1, asset.kind, asset.count)?;
+            buffer.assets.remove(&url);
+        }
+
+        info!("Storage recovered, flushed {} buffered writes", buffered);
+        Ok(())
+    }
+
+    /// Flush the buffer then `write`, or buffer it with `fallback` if the backend fails.
+    /// A corrupted value is not an outage: the error is returned, and nothing is buffered.
+    fn write(
+        &self,
+        write: impl FnOnce() -> Result<(), DbError>,
+        fallback: impl FnOnce(&mut Buffer),
+    ) -> Result<(), DbError> {
+        let mut buffer = self.buffer.lock().unwrap();
+
+        // Nothing is written while older writes are buffered, to keep them in order.
+        let result = self.flush(&mut buffer).and_then(|_| write());
+        match result {
+            Err(DbError::Storage(e)) => {
+                if buffer.len() == 0 {
+                    warn!("Storage unavailable, buffering writes: {}", e);
+                }
+                fallback(&mut buffer);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+/// Drop a buffered write to a corrupted value, which would otherwise fail every flush.
+fn skip_corrupted(result: Result<(), DbError>) -> Result<(), DbError> {
+    match result {
+        Err(DbError::Corrupted(e)) => {
+            warn!("Dropping a buffered write to a corrupted value: {}", e);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Replay the buffered `counts` with `write`, one occurence at a time, keeping the ones that
+/// could not be written.
+fn replay(
+    counts: &mut Counts,
+    write: impl Fn(&str, &str, usize) -> Result<(), DbError>,
+) -> Result<(), DbError> {
+    let keys: Vec<_> = counts.keys().cloned().collect();
+    for key in keys {
+        write(&key.0, &key.1, counts[&key])?;
+        counts.remove(&key);
+    }
+
+    Ok(())
+}
+
+/// Add the buffered `counts` of `domain` to `stored`.
+fn merge(mut stored: Vec<(String, usize)>, counts: &Counts, domain: &str) -> Vec<(String, usize)> {
+    for ((d, rest), count) in counts {
+        if d != domain {
+            continue;
+        }
+        match stored.iter_mut().find(|(stored, _)| stored == rest) {
+            Some((_, stored)) => *stored += count,
+            None => stored.push((rest.clone(), *count)),
+        }
+    }
+
+    stored
+}
+
+impl<S: Storage> Storage for OverflowStorage<S> {
+    fn add_visits(&self, domain: &str, path: &str, count: usize) -> Result<(), DbError> {
+        self.write(
+            || self.inner.add_visits(domain, path, count),
+            |buffer| {
+                let buffered = buffer
+                    .visits
+                    .entry((domain.to_string(), path.to_string()))
+                    .or_default();
+                *buffered = buffered.saturating_add(count);
+            },
+        )
+    }
+
+    fn record_visit(&self, domain: &str, path: &str) -> Result<bool, DbError> {
+        let first = Cell::new(false);
+        self.write(
+            || {
+                first.set(self.inner.record_visit(domain, path)?);
+                Ok(())
+            },
+            |buffer| {
+                // The stored count cannot be read, only the buffered visits tell whether it is new.
+                let count = buffer
+                    .visits
+                    .entry((domain.to_string(), path.to_string()))
+                    .or_default();
+                first.set(*count == 0);
+                *count += 1;
+            },
+        )?;
+
+        Ok(first.get())
+    }
+
+    fn count(&self, domain: &str, path: &str) -> Result<Option<usize>, DbError> {
+        let stored = match self.inner.count(domain, path) {
+            Ok(stored) => stored,
+            Err(DbError::Storage(_)) => None,
+            Err(e) => return Err(e),
+        };
+
+        let buffer = self.buffer.lock().unwrap();
+        let buffered = buffer
+            .visits
+            .get(&(domain.to_string(), path.to_string()))
+            .copied()
+            .unwrap_or(0);
+        Ok(match stored {
+            Some(stored) => Some(stored + buffered),
+            None if buffer.has_domain(domain) => Some(buffered),
+            None => None,
+        })
+    }
+
+    fn paths(&self, domain: &str) -> Result<Vec<(String, usize)>, DbError> {
+        let stored = self.inner.paths(domain)?;
+
+        Ok(merge(stored, &self.buffer.lock().unwrap().visits, domain))
+    }
+
+    fn meta(&self, domain: &str) -> Result<Option<DomainMeta>, DbError> {
+        if let Some(meta) = self.buffer.lock().unwrap().meta.get(domain) {
+            return Ok(Some(meta.clone()));
+        }
+
+        self.inner.meta(domain)
+    }
+
+    /// Not buffered: fails while the backend does, the buffered writes of the domain being kept.
+    fn remove_domain(&self, domain: &str) -> Result<bool, DbError> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let removed = self.inner.remove_domain(domain)?;
+        let buffered = buffer.has_domain(domain);
+        buffer.remove_domain(domain);
+
+        Ok(removed || buffered)
+    }
+
+    fn set_meta(&self, domain: &str, meta: DomainMeta) -> Result<(), DbError> {
+        let buffered = meta.clone();
+        self.write(
+            || self.inner.set_meta(domain, meta),
+            |buffer| {
+                buffer.meta.insert(domain.to_string(), buffered);
+            },
+        )
+    }
+
+    fn add_third_party(&self, domain: &str, host: &str, count: usize) -> Result<(), DbError> {
+        self.write(
+            || self.inner.add_third_party(domain, host, count),
+            |buffer| {
+                let buffered = buffer
+                    .third_party
+                    .entry((domain.to_string(), host.to_string()))
+                    .or_default();
+                *buffered = buffered.saturating_add(count);
+            },
+        )
+    }
+
+    fn third_party(&self, domain: &str) -> Result<Vec<(String, usize)>, DbError> {
+        let stored = self.inner.third_party(domain)?;
+
+        Ok(merge(
+            stored,
+            &self.buffer.lock().unwrap().third_party,
+            domain,
+        ))
+    }
+
+    fn domains(&self) -> Result<Vec<String>, DbError> {
+        let mut domains = self.inner.domains()?;
+        let buffer = self.buffer.lock().unwrap();
+        let buffered = buffer
+            .meta
+            .keys()
+            .chain(buffer.visits.keys().map(|(domain, _)| domain))
+            .chain(buffer.third_party.keys().map(|(domain, _)| domain));
+        for domain in buffered {
+            if !domains.contains(domain) {
+                domains.push(domain.clone());
+            }
+        }
+
+        Ok(domains)
+    }
+
+    fn see(&self, domain: &str, path: &str, session: u64) -> Result<(), DbError> {
+        self.write(
+            || self.inner.see(domain, path, session),
+            |buffer| {
+                buffer
+                    .lineage
+                    .entry((domain.to_string(), path.to_string()))
+                    .and_modify(|lineage| lineage.see(session))
+                    .or_insert_with(|| Lineage::new(session));
+            },
+        )
+    }
+
+    fn lineage(&self, domain: &str) -> Result<Vec<(String, Lineage)>, DbError> {
+        let mut stored = self.inner.lineage(domain)?;
+
+        let buffer = self.buffer.lock().unwrap();
+        for ((d, path), buffered) in &buffer.lineage {
+            if d != domain {
+                continue;
+            }
+            match stored.iter_mut().find(|(stored, _)| stored == path) {
+                Some((_, stored)) => {
+                    stored.see(buffered.first_seen);
+                    stored.see(buffered.last_seen);
+                }
+                None => stored.push((path.clone(), *buffered)),
+            }
+        }
+
+        Ok(stored)
+    }
+
+    fn set_status(&self, domain: &str, path: &str, status: &FetchStatus) -> Result<(), DbError> {
+        self.write(
+            || self.inner.set_status(domain, path, status),
+            |buffer| {
+                buffer
+                    .statuses
+                    .insert((domain.to_string(), path.to_string()), status.clone());
+            },
+        )
+    }
+
+    fn statuses(&self, domain: &str) -> Result<Vec<(String, FetchStatus)>, DbError> {
+        let mut stored = self.inner.statuses(domain)?;
+
+        let buffer = self.buffer.lock().unwrap();
+        for ((d, path), buffered) in &buffer.statuses {
+            if d != domain {
+                continue;
+            }
+            match stored.iter_mut().find(|(stored, _)| stored == path) {
+                Some((_, stored)) => *stored = buffered.clone(),
+                None => stored.push((path.clone(), buffered.clone())),
+            }
+        }
+
+        Ok(stored)
+    }
+
+    fn status(&self, domain: &str, path: &str) -> Result<Option<FetchStatus>, DbError> {
+        let key = (domain.to_string(), path.to_string());
+        if let Some(buffered) = self.buffer.lock().unwrap().statuses.get(&key) {
+            return Ok(Some(buffered.clone()));
+        }
+
+        self.inner.status(domain, path)
+    }
+
+    fn link(&self, domain: &str, path: &str, from: &str) -> Result<(), DbError> {
+        self.write(
+            || self.inner.link(domain, path, from),
+            |buffer| {
+                let referrers = buffer
+                    .referrers
+                    .entry((domain.to_string(), path.to_string()))
+                    .or_default();
+                add_referrer(referrers, from);
+            },
+        )
+    }
+
+    fn referrers(&self, domain: &str, path: &str) -> Result<Vec<String>, DbError> {
+        let mut stored = self.inner.referrers(domain, path)?;
+
+        let buffer = self.buffer.lock().unwrap();
+        let key = (domain.to_string(), path.to_string());
+        for from in buffer.referrers.get(&key).into_iter().flatten() {
+            add_referrer(&mut stored, from);
+        }
+
+        Ok(stored)
+    }
+
+    fn set_text(&self, domain: &str, path: &str, text: &str) -> Result<(), DbError> {
+        self.write(
+            || self.inner.set_text(domain, path, text),
+            |buffer| {
+                buffer
+                    .texts
+                    .insert((domain.to_string(), path.to_string()), text.to_string());
+            },
+        )
+    }
+
+    fn text(&self, domain: &str, path: &str) -> Result<Option<String>, DbError> {
+        let key = (domain.to_string(), path.to_string());
+        if let Some(buffered) = self.buffer.lock().unwrap().texts.get(&key) {
+            return Ok(Some(buffered.clone()));
+        }
+
+        self.inner.text(domain, path)
+    }
+
+    fn set_posting(
+        &self,
+        domain: &str,
+        term: &str,
+        path: &str,
+        count: usize,
+    ) -> Result<(), DbError> {
+        self.write(
+            || self.inner.set_posting(domain, term, path, count),
+            |buffer| {
+                buffer.postings.insert(
+                    (domain.to_string(), term.to_string(), path.to_string()),
+                    count,
+                );
+            },
+        )
+    }
+
+    fn postings(&self, domain: &str, term: &str) -> Result<Vec<(String, usize)>, DbError> {
+        let mut stored = self.inner.postings(domain, term)?;
+
+        let buffer = self.buffer.lock().unwrap();
+        for ((d, t, path), count) in &buffer.postings {
+            if d != domain || t != term {
+                continue;
+            }
+            stored.retain(|(stored, _)| stored != path);
+            if *count > 0 {
+                stored.push((path.clone(), *count));
+            }
+        }
+
+        Ok(stored)
+    }
+
+    fn add_edge(&self, domain: &str, from: &str, to: &str) -> Result<(), DbError> {
+        self.write(
+            || self.inner.add_edge(domain, from, to),
+            |buffer| {
+                buffer
+                    .edges
+                    .insert((domain.to_string(), from.to_string(), to.to_string()));
+            },
+        )
+    }
+
+    fn edges(&self, domain: &str) -> Result<Vec<(String, String)>, DbError> {
+        let mut stored = self.inner.edges(domain)?;
+
+        let buffer = self.buffer.lock().unwrap();
+        for (d, from, to) in &buffer.edges {
+            let edge = (from.clone(), to.clone());
+            if d == domain && !stored.contains(&edge) {
+                stored.push(edge);
+            }
+        }
+
+        Ok(stored)
+    }
+
+    fn add_asset(
+        &self,
+        domain: &str,
+        url: &str,
+        kind: AssetKind,
+        count: usize,
+    ) -> Result<(), DbError> {
+        self.write(
+            || self.inner.add_asset(domain, url, kind, count),
+            |buffer| {
+                let asset = buffer
+                    .assets
+                    .entry((domain.to_string(), url.to_string()))
+                    .or_insert(Asset { kind, count: 0 });
+                asset.count = asset.count.saturating_add(count);
+            },
+        )
+    }
+
+    fn assets(&self, domain: &str) -> Result<Vec<(String, Asset)>, DbError> {
+        let mut stored = self.inner.assets(domain)?;
+
+        let buffer = self.buffer.lock().unwrap();
+        for ((d, url), buffered) in &buffer.assets {
+            if d != domain {
+                continue;
+            }
+            match stored.iter_mut().find(|(stored, _)| stored == url) {
+                Some((_, stored)) => stored.count += buffered.count,
+                None => stored.push((url.clone(), *buffered)),
+            }
+        }
+
+        Ok(stored)
+    }
+
+    fn health(&self) -> StorageHealth {
+        let buffered = self.buffer.lock().unwrap().len();
+
+        StorageHealth {
+            degraded: buffered > 0,
+            buffered,
+        }
+    }
+
+    fn memory_usage(&self) -> Option<MemoryUsage> {
+        self.inner.memory_usage()
+    }
+
+    fn pin(&self, domain: &str) {
+        self.inner.pin(domain);
+    }
+
+    fn unpin(&self, domain: &str) {
+        self.inner.unpin(domain);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        borrow::Cow,
+        str::FromStr,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use url::Url;
+
+    use super::OverflowStorage;
+    use crate::{
+        db::{
+            Asset, Db, DbError, DomainMeta, FetchStatus, Lineage, MemoryStorage, Storage,
+            StorageHealth,
+        },
+        graph::Edge,
+        parser::AssetKind,
+    };
+
+    /// Path whose stored count `FlakyStorage` cannot read.
+    const CORRUPTED: &str = "/corrupted";
+
+    /// A `MemoryStorage` that can be made to fail.
+    #[derive(Debug, Default)]
+    struct FlakyStorage {
+        inner: MemoryStorage,
+        down: Arc<AtomicBool>,
+    }
+
+    impl FlakyStorage {
+        fn check(&self) -> Result<(), DbError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(DbError::Storage("connection refused".to_string()));
+            }
+            Ok(())
+        }
+
+        fn check_count(&self, path: &str) -> Result<(), DbError> {
+            self.check()?;
+            if path == CORRUPTED {
+                return Err(DbError::Corrupted("Invalid count".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    impl Storage for FlakyStorage {
+        fn add_visits(&self, domain: &str, path: &str, count: usize) -> Result<(), DbError> {
+            self.check_count(path)?;
+            self.inner.add_visits(domain, path, count)
+        }
+
+        fn record_visit(&self, domain: &str, path: &str) -> Result<bool, DbError> {
+            self.check_count(path)?;
+            self.inner.record_visit(domain, path)
+        }
+
+        fn count(&self, domain: &str, path: &str) -> Result<Option<usize>, DbError> {
+            self.check_count(path)?;
+            self.inner.count(domain, path)
+        }
+
+        fn paths(&self, domain: &str) -> Result<Vec<(String, usize)>, DbError> {
+            self.check()?;
+            self.inner.paths(domain)
+        }
+
+        fn meta(&self, domain: &str) -> Result<Option<DomainMeta>, DbError> {
+            self.check()?;
+            self.inner.meta(domain)
+        }
+
+        fn set_meta(&self, domain: &str, meta: DomainMeta) -> Result<(), DbError> {
+            self.check()?;
+            self.inner.set_meta(domain, meta)
+        }
+
+        fn remove_domain(&self, domain: &str) -> Result<bool, DbError> {
+            self.check()?;
+            self.inner.remove_domain(domain)
+        }
+
+        fn add_third_party(&self, domain: &str, host: &str, count: usize) -> Result<(), DbError> {
+            self.check()?;
+            self.inner.add_third_party(domain, host, count)
+        }
+
+        fn third_party(&self, domain: &str) -> Result<Vec<(String, usize)>, DbError> {
+            self.check()?;
+            self.inner.third_party(domain)
+        }
+
+        fn domains(&self) -> Result<Vec<String>, DbError> {
+            self.check()?;
+            self.inner.domains()
+        }
+
+        fn see(&self, domain: &str, path: &str, session: u64) -> Result<(), DbError> {
+            self.check()?;
+            self.inner.see(domain, path, session)
+        }
+
+        fn lineage(&self, domain: &str) -> Result<Vec<(String, Lineage)>, DbError> {
+            self.check()?;
+            self.inner.lineage(domain)
+        }
+
+        fn set_status(
+            &self,
+            domain: &str,
+            path: &str,
+            status: &FetchStatus,
+        ) -> Result<(), DbError> {
+            self.check()?;
+            self.inner.set_status(domain, path, status)
+        }
+
+        fn statuses(&self, domain: &str) -> Result<Vec<(String, FetchStatus)>, DbError> {
+            self.check()?;
+            self.inner.statuses(domain)
+        }
+
+        fn status(&self, domain: &str, path: &str) -> Result<Option<FetchStatus>, DbError> {
+            self.check()?;
+            self.inner.status(domain, path)
+        }
+
+        fn link(&self, domain: &str, path: &str, from: &str) -> Result<(), DbError> {
+            self.check()?;
+            self.inner.link(domain, path, from)
+        }
+
+        fn referrers(&self, domain: &str, path: &str) -> Result<Vec<String>, DbError> {
+            self.check()?;
+            self.inner.referrers(domain, path)
+        }
+
+        fn set_text(&self, domain: &str, path: &str, text: &str) -> Result<(), DbError> {
+            self.check()?;
+            self.inner.set_text(domain, path, text)
+        }
+
+        fn text(&self, domain: &str, path: &str) -> Result<Option<String>, DbError> {
+            self.check()?;
+            self.inner.text(domain, path)
+        }
+
+        fn set_posting(
+            &self,
+            domain: &str,
+            term: &str,
+            path: &str,
+            count: usize,
+        ) -> Result<(), DbError> {
+            self.check()?;
+            self.inner.set_posting(domain, term, path, count)
+        }
+
+        fn postings(&self, domain: &str, term: &str) -> Result<Vec<(String, usize)>, DbError> {
+            self.check()?;
+            self.inner.postings(domain, term)
+        }
+
+        fn add_edge(&self, domain: &str, from: &str, to: &str) -> Result<(), DbError> {
+            self.check()?;
+            self.inner.add_edge(domain, from, to)
+        }
+
+        fn edges(&self, domain: &str) -> Result<Vec<(String, String)>, DbError> {
+            self.check()?;
+            self.inner.edges(domain)
+        }
+
+        fn add_asset(
+            &self,
+            domain: &str,
+            url: &str,
+            kind: AssetKind,
+            count: usize,
+        ) -> Result<(), DbError> {
+            self.check()?;
+            self.inner.add_asset(domain, url, kind, count)
+        }
+
+        fn assets(&self, domain: &str) -> Result<Vec<(String, Asset)>, DbError> {
+            self.check()?;
+            self.inner.assets(domain)
+        }
+    }
+
+    #[test]
+    fn test_overflow() -> anyhow::Result<()> {
+        let flaky = FlakyStorage::default();
+        let down = Arc::clone(&flaky.down);
+        let db = Db::new(OverflowStorage::new(flaky));
+        let domain = Url::from_str("https://example.com")?;
+
+        db.visit(Cow::Owned(domain.join("/foo")?))?;
+        assert_eq!(db.health(), StorageHealth::default());
+
+        down.store(true, Ordering::SeqCst);
+        db.visit(Cow::Owned(domain.join("/foo")?))?;
+        assert!(db.record_visit(&domain.join("/bar")?)?);
+        db.visit_third_party(&domain, "cdn.net")?;
+        db.see(&domain.join("/bar")?, 1)?;
+        db.see(&domain.join("/bar")?, 2)?;
+        db.set_status(&domain.join("/bar")?, &FetchStatus::ok(200))?;
+        db.link(&domain.join("/bar")?, &domain.join("/foo")?)?;
+        db.visit_asset(&domain, &domain.join("/logo.png")?, AssetKind::Image)?;
+        // The text indexed before has to be read to be replaced.
+        assert!(db.index_text(&domain.join("/bar")?, "Lost text").is_err());
+
+        assert_eq!(
+            db.health(),
+            StorageHealth {
+                degraded: true,
+                buffered: 8,
+            }
+        );
+        // The crawler still knows what it visited since the backend went down.
+        assert!(!db.is_first_visit(&domain.join("/bar")?)?);
+        assert_eq!(
+            db.status(&domain.join("/bar")?)?,
+            Some(FetchStatus::ok(200))
+        );
+        assert!(db.is_first_visit(&domain.join("/baz")?)?);
+
+        down.store(false, Ordering::SeqCst);
+        assert_eq!(db.url_count_for_domain(&domain.join("/foo")?)?, 2);
+        assert_eq!(db.totals()?.urls, 2);
+
+        db.visit(Cow::Owned(domain.join("/baz")?))?;
+        assert_eq!(db.health(), StorageHealth::default());
+        assert_eq!(db.url_count_for_domain(&domain.join("/foo")?)?, 2);
+        assert_eq!(db.url_count_for_domain(&domain.join("/bar")?)?, 1);
+        assert_eq!(
+            db.third_party_for_domain(&domain)?,
+            vec![("cdn.net".to_string(), 1)]
+        );
+        assert_eq!(
+            db.lineage_for_domain(&domain)?,
+            vec![(
+                domain.join("/bar")?,
+                Lineage {
+                    first_seen: 1,
+                    last_seen: 2,
+                }
+            )]
+        );
+        assert_eq!(
+            db.statuses_for_domain(&domain)?,
+            vec![(domain.join("/bar")?, FetchStatus::ok(200))]
+        );
+        assert_eq!(
+            db.referrers(&domain.join("/bar")?)?,
+            vec![domain.join("/foo")?]
+        );
+        assert_eq!(
+            db.graph_for_domain(&domain)?.edges,
+            vec![Edge {
+                from: domain.join("/foo")?,
+                to: domain.join("/bar")?,
+            }]
+        );
+        assert_eq!(
+            db.assets_for_domain(&domain)?,
+            vec![(
+                domain.join("/logo.png")?,
+                Asset {
+                    kind: AssetKind::Image,
+                    count: 1
+                }
+            )]
+        );
+
+        // Removing a domain is not buffered.
+        down.store(true, Ordering::SeqCst);
+        assert!(db.remove_domain(&domain).is_err());
+        down.store(false, Ordering::SeqCst);
+        db.remove_domain(&domain)?;
+        assert_eq!(
+            db.unique_urls_for_domain(&domain),
+            Err(DbError::DomainDoesNotExist)
+        );
+        assert_eq!(db.totals()?.urls, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overflow_corrupted() -> anyhow::Result<()> {
+        let flaky = FlakyStorage::default();
+        let down = Arc::clone(&flaky.down);
+        let db = Db::new(OverflowStorage::new(flaky));
+        let domain = Url::from_str("https://example.com")?;
+        let corrupted = Err(DbError::Corrupted("Invalid count".to_string()));
+
+        // Reported, without buffering the writes that come after.
+        assert_eq!(db.record_visit(&domain.join(CORRUPTED)?), corrupted);
+        assert_eq!(db.is_first_visit(&domain.join(CORRUPTED)?), corrupted);
+        db.visit(Cow::Owned(domain.join("/foo")?))?;
+        assert_eq!(db.health(), StorageHealth::default());
+
+        // Buffered during an outage, then dropped instead of failing every flush.
+        down.store(true, Ordering::SeqCst);
+        db.visit(Cow::Owned(domain.join(CORRUPTED)?))?;
+        db.visit(Cow::Owned(domain.join("/foo")?))?;
+        down.store(false, Ordering::SeqCst);
+        db.visit(Cow::Owned(domain.join("/bar")?))?;
+        assert_eq!(db.health(), StorageHealth::default());
+        assert_eq!(db.url_count_for_domain(&domain.join("/foo")?)?, 2);
+        assert_eq!(db.url_count_for_domain(&domain.join("/bar")?)?, 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,662 @@
+use std::{convert::TryFrom, path::Path};
+
+use super::{add_referrer, Asset, DbError, DomainMeta, FetchStatus, Lineage, Storage};
+use crate::parser::AssetKind;
+
+/// Separates the domain from the path or host in the keys of the `urls` and `third_party` trees,
+/// and the two paths or the term and the path in the keys of the `edges` and `postings` trees.
+/// Hosts, paths and terms never contain it.
+const SEPARATOR: u8 = 0;
+
+/// Storage persisted on disk with `sled`, surviving restarts.
+/// Each kind of data has its own tree: `domains` maps a domain to its JSON `DomainMeta`, `urls`
+/// and `third_party` map `<domain>\0<path or host>` to a big-endian count, and `lineage` maps
+/// `<domain>\0<path>` to the big-endian first and last sessions that found the path.
+/// `statuses` and `referrers` map `<domain>\0<path>` to the JSON `FetchStatus` of the path and
+/// to the JSON list of pages linking to it, and `assets` maps `<domain>\0<asset URL>` to its
+/// JSON `Asset`. `edges` has a `<domain>\0<from path>\0<to path>` key, with no value, for each
+/// link between two pages. `texts` maps `<domain>\0<path>` to the indexed text of the page, and
+/// `postings` maps `<domain>\0<term>\0<path>` to the big-endian number of occurences of the term
+/// in the text.
+#[derive(Debug)]
+pub struct SledStorage {
+    domains: sled::Tree,
+    urls: sled::Tree,
+    third_party: sled::Tree,
+    lineage: sled::Tree,
+    statuses: sled::Tree,
+    referrers: sled::Tree,
+    edges: sled::Tree,
+    assets: sled::Tree,
+    texts: sled::Tree,
+    postings: sled::Tree,
+}
+
+impl SledStorage {
+    /// Open, or create, the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, DbError> {
+        Self::from_db(sled::open(path).map_err(storage_error)?)
+    }
+
+    fn from_db(db: sled::Db) -> Result<Self, DbError> {
+        Ok(Self {
+            domains: db.open_tree("domains").map_err(storage_error)?,
+            urls: db.open_tree("urls").map_err(storage_error)?,
+            third_party: db.open_tree("third_party").map_err(storage_error)?,
+            lineage: db.open_tree("lineage").map_err(storage_error)?,
+            statuses: db.open_tree("statuses").map_err(storage_error)?,
+            referrers: db.open_tree("referrers").map_err(storage_error)?,
+            edges: db.open_tree("edges").map_err(storage_error)?,
+            assets: db.open_tree("assets").map_err(storage_error)?,
+            texts: db.open_tree("texts").map_err(storage_error)?,
+            postings: db.open_tree("postings").map_err(storage_error)?,
+        })
+    }
+
+    /// Make sure `domain` exists, as it does once anything is stored for it.
+    fn touch(&self, domain: &str) -> Result<(), DbError> {
+        let meta = serde_json::to_vec(&DomainMeta::default()).map_err(storage_error)?;
+        self.domains
+            .compare_and_swap(domain, None as Option<&[u8]>, Some(meta))
+            .map_err(storage_error)?
+            // Already exists.
+            .ok();
+
+        Ok(())
+    }
+
+    fn exists(&self, domain: &str) -> Result<bool, DbError> {
+        self.domains.contains_key(domain).map_err(storage_error)
+    }
+}
+
+impl Storage for SledStorage {
+    fn add_visits(&self, domain: &str, path: &str, count: usize) -> Result<(), DbError> {
+        self.touch(domain)?;
+        add(&self.urls, &key(domain, path), count)
+    }
+
+    fn record_visit(&self, domain: &str, path: &str) -> Result<bool, DbError> {
+        self.touch(domain)?;
+        let mut error = None;
+        let previous = self
+            .urls
+            .fetch_and_update(key(domain, path), |count| {
+                match count.map_or(Ok(0), decode) {
+                    Ok(count) => Some((count.saturating_add(1) as u64).to_be_bytes().to_vec()),
+                    // Left as it is, to be reported once the update is done.
+                    Err(e) => {
+                        error = Some(e);
+                        count.map(<[u8]>::to_vec)
+                    }
+                }
+            })
+            .map_err(storage_error)?;
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        Ok(previous.is_none())
+    }
+
+    fn count(&self, domain: &str, path: &str) -> Result<Option<usize>, DbError> {
+        if !self.exists(domain)? {
+            return Ok(None);
+        }
+
+        let count = self
+            .urls
+            .get(key(domain, path))
+            .map_err(storage_error)?
+            .map_or(Ok(0), |count| decode(&count))?;
+
+        Ok(Some(count))
+    }
+
+    fn paths(&self, domain: &str) -> Result<Vec<(String, usize)>, DbError> {
+        scan(&self.urls, domain).collect()
+    }
+
+    fn paths_page(
+        &self,
+        domain: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<(String, usize)>, usize), DbError> {
+        // The keys are sorted by path already, and the counts need not be read to be counted.
+        let mut total = 0;
+        for entry in self.urls.scan_prefix(key(domain, "")).keys() {
+            entry.map_err(storage_error)?;
+            total += 1;
+        }
+        let paths = scan(&self.urls, domain)
+            .skip(offset)
+            .take(limit)
+            .collect::<Result<_, _>>()?;
+
+        Ok((paths, total))
+    }
+
+    fn meta(&self, domain: &str) -> Result<Option<DomainMeta>, DbError> {
+        self.domains
+            .get(domain)
+            .map_err(storage_error)?
+            .map(|meta| serde_json::from_slice(&meta).map_err(storage_error))
+            .transpose()
+    }
+
+    fn set_meta(&self, domain: &str, meta: DomainMeta) -> Result<(), DbError> {
+        let meta = serde_json::to_vec(&meta).map_err(storage_error)?;
+        self.domains.insert(domain, meta).map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn remove_domain(&self, domain: &str) -> Result<bool, DbError> {
+        let prefix = key(domain, "");
+        let trees = [
+            &self.urls,
+            &self.third_party,
+            &self.lineage,
+            &self.statuses,
+            &self.referrers,
+            &self.edges,
+            &self.assets,
+            &self.texts,
+            &self.postings,
+        ];
+        for tree in trees.iter() {
+            for key in tree.scan_prefix(&prefix).keys() {
+                tree.remove(key.map_err(storage_error)?)
+                    .map_err(storage_error)?;
+            }
+        }
+        // Removed last, so that the domain still exists to be removed again if a tree failed.
+        let meta = self.domains.remove(domain).map_err(storage_error)?;
+
+        Ok(meta.is_some())
+    }
+
+    fn add_third_party(&self, domain: &str, host: &str, count: usize) -> Result<(), DbError> {
+        self.touch(domain)?;
+        add(&self.third_party, &key(domain, host), count)
+    }
+
+    fn third_party(&self, domain: &str) -> Result<Vec<(String, usize)>, DbError> {
+        scan(&self.third_party, domain).collect()
+    }
+
+    fn domains(&self) -> Result<Vec<String>, DbError> {
+        self.domains
+            .iter()
+            .keys()
+            .map(|domain| {
+                let domain = domain.map_err(storage_error)?;
+                Ok(String::from_utf8_lossy(&domain).into_owned())
+            })
+            .collect()
+    }
+
+    fn path_count(&self) -> Result<usize, DbError> {
+        Ok(self.urls.len())
+    }
+
+    fn see(&self, domain: &str, path: &str, session: u64) -> Result<(), DbError> {
+        let mut error = None;
+        self.lineage
+            .update_and_fetch(key(domain, path), |lineage| match lineage {
+                Some(bytes) => match decode_lineage(bytes) {
+                    Ok(mut lineage) => {
+                        lineage.see(session);
+                        Some(encode_lineage(lineage))
+                    }
+                    // Left as it is, to be reported once the update is done.
+                    Err(e) => {
+                        error = Some(e);
+                        Some(bytes.to_vec())
+                    }
+                },
+                None => Some(encode_lineage(Lineage::new(session))),
+            })
+            .map_err(storage_error)?;
+
+        error.map_or(Ok(()), Err)
+    }
+
+    fn lineage(&self, domain: &str) -> Result<Vec<(String, Lineage)>, DbError> {
+        let prefix = key(domain, "");
+
+        self.lineage
+            .scan_prefix(&prefix)
+            .map(|entry| {
+                let (key, lineage) = entry.map_err(storage_error)?;
+                let path = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+
+                Ok((path, decode_lineage(&lineage)?))
+            })
+            .collect()
+    }
+
+    fn set_status(&self, domain: &str, path: &str, status: &FetchStatus) -> Result<(), DbError> {
+        let status = serde_json::to_vec(status).map_err(storage_error)?;
+        self.statuses
+            .insert(key(domain, path), status)
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn statuses(&self, domain: &str) -> Result<Vec<(String, FetchStatus)>, DbError> {
+        let prefix = key(domain, "");
+
+        self.statuses
+            .scan_prefix(&prefix)
+            .map(|entry| {
+                let (key, status) = entry.map_err(storage_error)?;
+                let path = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+
+                Ok((
+                    path,
+                    serde_json::from_slice(&status).map_err(storage_error)?,
+                ))
+            })
+            .collect()
+    }
+
+    fn status(&self, domain: &str, path: &str) -> Result<Option<FetchStatus>, DbError> {
+        self.statuses
+            .get(key(domain, path))
+            .map_err(storage_error)?
+            .map(|status| serde_json::from_slice(&status).map_err(storage_error))
+            .transpose()
+    }
+
+    fn link(&self, domain: &str, path: &str, from: &str) -> Result<(), DbError> {
+        self.referrers
+            .update_and_fetch(key(domain, path), |referrers| {
+                let mut referrers: Vec<String> = referrers
+                    .and_then(|referrers| serde_json::from_slice(referrers).ok())
+                    .unwrap_or_default();
+                add_referrer(&mut referrers, from);
+                // Serializing strings does not fail.
+                serde_json::to_vec(&referrers).ok()
+            })
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn referrers(&self, domain: &str, path: &str) -> Result<Vec<String>, DbError> {
+        self.referrers
+            .get(key(domain, path))
+            .map_err(storage_error)?
+            .map_or(Ok(Vec::new()), |referrers| {
+                serde_json::from_slice(&referrers).map_err(storage_error)
+            })
+    }
+
+    fn set_text(&self, domain: &str, path: &str, text: &str) -> Result<(), DbError> {
+        self.texts
+            .insert(key(domain, path), text.as_bytes())
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn text(&self, domain: &str, path: &str) -> Result<Option<String>, DbError> {
+        Ok(self
+            .texts
+            .get(key(domain, path))
+            .map_err(storage_error)?
+            .map(|text| String::from_utf8_lossy(&text).into_owned()))
+    }
+
+    fn set_posting(
+        &self,
+        domain: &str,
+        term: &str,
+        path: &str,
+        count: usize,
+    ) -> Result<(), DbError> {
+        let mut key = key(domain, term);
+        key.push(SEPARATOR);
+        key.extend_from_slice(path.as_bytes());
+        if count == 0 {
+            self.postings.remove(key).map_err(storage_error)?;
+        } else {
+            self.postings
+                .insert(key, &(count as u64).to_be_bytes())
+                .map_err(storage_error)?;
+        }
+
+        Ok(())
+    }
+
+    fn postings(&self, domain: &str, term: &str) -> Result<Vec<(String, usize)>, DbError> {
+        let mut prefix = key(domain, term);
+        prefix.push(SEPARATOR);
+
+        self.postings
+            .scan_prefix(&prefix)
+            .map(|entry| {
+                let (key, count) = entry.map_err(storage_error)?;
+                let path = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+
+                Ok((path, decode(&count)?))
+            })
+            .collect()
+    }
+
+    fn add_edge(&self, domain: &str, from: &str, to: &str) -> Result<(), DbError> {
+        let mut key = key(domain, from);
+        key.push(SEPARATOR);
+        key.extend_from_slice(to.as_bytes());
+        self.edges.insert(key, &[]).map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn edges(&self, domain: &str) -> Result<Vec<(String, String)>, DbError> {
+        let prefix = key(domain, "");
+
+        self.edges
+            .scan_prefix(&prefix)
+            .keys()
+            .map(|key| {
+                let key = key.map_err(storage_error)?;
+                let rest = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+                let (from, to) = rest
+                    .split_once(SEPARATOR as char)
+                    .ok_or_else(|| DbError::Corrupted("Invalid edge key".to_string()))?;
+
+                Ok((from.to_string(), to.to_string()))
+            })
+            .collect()
+    }
+
+    fn add_asset(
+        &self,
+        domain: &str,
+        url: &str,
+        kind: AssetKind,
+        count: usize,
+    ) -> Result<(), DbError> {
+        self.touch(domain)?;
+        self.assets
+            .update_and_fetch(key(domain, url), |asset| {
+                let mut asset: Asset = asset
+                    .and_then(|asset| serde_json::from_slice(asset).ok())
+                    .unwrap_or(Asset { kind, count: 0 });
+                asset.count = asset.count.saturating_add(count);
+                serde_json::to_vec(&asset).ok()
+            })
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+
+    fn assets(&self, domain: &str) -> Result<Vec<(String, Asset)>, DbError> {
+        let prefix = key(domain, "");
+
+        self.assets
+            .scan_prefix(&prefix)
+            .map(|entry| {
+                let (key, asset) = entry.map_err(storage_error)?;
+                let url = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+
+                Ok((url, serde_json::from_slice(&asset).map_err(storage_error)?))
+            })
+            .collect()
+    }
+}
+
+fn key(domain: &str, rest: &str) -> Vec<u8> {
+    let mut key = Vec::with_capacity(domain.len() + 1 + rest.len());
+    key.extend_from_slice(domain.as_bytes());
+    key.push(SEPARATOR);
+    key.extend_from_slice(rest.as_bytes());
+    key
+}
+
+/// The entries of `tree` for `domain`, without the domain part of their key.
+fn scan<'a>(
+    tree: &'a sled::Tree,
+    domain: &str,
+) -> impl Iterator<Item = Result<(String, usize), DbError>> + 'a {
+    let prefix = key(domain, "");
+    let prefix_len = prefix.len();
+
+    tree.scan_prefix(prefix).map(move |entry| {
+        let (key, count) = entry.map_err(storage_error)?;
+        let rest = String::from_utf8_lossy(&key[prefix_len..]).into_owned();
+
+        Ok((rest, decode(&count)?))
+    })
+}
+
+/// Add `n` to the count at `key` in `tree`.
+fn add(tree: &sled::Tree, key: &[u8], n: usize) -> Result<(), DbError> {
+    let mut error = None;
+    tree.update_and_fetch(key, |count| match count.map_or(Ok(0), decode) {
+        Ok(count) => Some((count.saturating_add(n) as u64).to_be_bytes().to_vec()),
+        // Left as it is, to be reported once the update is done.
+        Err(e) => {
+            error = Some(e);
+            count.map(<[u8]>::to_vec)
+        }
+    })
+    .map_err(storage_error)?;
+
+    error.map_or(Ok(()), Err)
+}
+
+/// A big-endian count, which a corrupted database may not hold.
+fn decode(count: &[u8]) -> Result<usize, DbError> {
+    let bytes =
+        <[u8; 8]>::try_from(count).map_err(|_| DbError::Corrupted("Invalid count".to_string()))?;
+
+    Ok(u64::from_be_bytes(bytes) as usize)
+}
+
+fn encode_lineage(lineage: Lineage) -> Vec<u8> {
+    let mut bytes = lineage.first_seen.to_be_bytes().to_vec();
+    bytes.extend_from_slice(&lineage.last_seen.to_be_bytes());
+    bytes
+}
+
+fn decode_lineage(bytes: &[u8]) -> Result<Lineage, DbError> {
+    if bytes.len() != 16 {
+        return Err(DbError::Corrupted("Invalid lineage".to_string()));
+    }
+    let (first_seen, last_seen) = bytes.split_at(8);
+
+    Ok(Lineage {
+        first_seen: decode(first_seen)? as u64,
+        last_seen: decode(last_seen)? as u64,
+    })
+}
+
+fn storage_error(e: impl std::fmt::Display) -> DbError {
+    DbError::Storage(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, str::FromStr};
+
+    use url::Url;
+
+    use super::{key, SledStorage};
+    use crate::{
+        db::{
+            Asset, Db, DbError, DbTotals, FetchStatus, Lineage, Storage, UrlQuery, MAX_REFERRERS,
+        },
+        graph::Edge,
+        parser::AssetKind,
+        robots::RobotsMode,
+        tests::compare_sorted,
+    };
+
+    fn temporary_db() -> Db {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        Db::new(SledStorage::from_db(db).unwrap())
+    }
+
+    #[test]
+    fn test_sled_storage() -> anyhow::Result<()> {
+        let db = temporary_db();
+        let domain = Url::from_str("https://example.com")?;
+        let other = Url::from_str("https://example.com.au")?;
+
+        assert_eq!(
+            db.unique_urls_for_domain(&domain),
+            Err(DbError::DomainDoesNotExist)
+        );
+        assert!(db.is_first_visit(&domain.join("/foo")?)?);
+
+        assert!(db.record_visit(&domain.join("/foo")?)?);
+        assert!(!db.record_visit(&domain.join("/foo")?)?);
+        db.visit(Cow::Owned(domain.join("/bar?a=1")?))?;
+        db.visit(Cow::Owned(other.join("/baz")?))?;
+        db.visit_third_party(&domain, "cdn.net")?;
+        db.set_truncated(&domain, true)?;
+        let session = db.start_session(&domain, RobotsMode::Respect)?;
+        db.see(&domain.join("/foo")?, session)?;
+        db.see(&domain.join("/foo")?, session + 1)?;
+        db.set_status(&domain.join("/foo")?, &FetchStatus::ok(200))?;
+        db.set_status(&domain.join("/foo")?, &FetchStatus::failed(None, "timeout"))?;
+        for i in 0..=MAX_REFERRERS {
+            db.link(&domain.join("/foo")?, &domain.join(&format!("/{}", i))?)?;
+        }
+        db.link(&domain.join("/bar?a=1")?, &domain.join("/foo")?)?;
+        db.link(&domain.join("/bar?a=1")?, &domain.join("/foo")?)?;
+        db.link(&domain.join("/foo")?, &other.join("/baz")?)?;
+        db.index_text(&domain.join("/foo")?, "Sled stores the text")?;
+        db.index_text(&domain.join("/bar?a=1")?, "Sled stores this text too")?;
+        db.index_text(&domain.join("/bar?a=1")?, "Replaced")?;
+        let logo = Url::from_str("https://cdn.net/logo.png")?;
+        db.visit_asset(&domain, &logo, AssetKind::Image)?;
+        db.visit_asset(&domain, &logo, AssetKind::Image)?;
+
+        assert!(!db.is_first_visit(&domain.join("/foo")?)?);
+        assert_eq!(db.url_count_for_domain(&domain.join("/foo")?)?, 2);
+        assert_eq!(db.url_count_for_domain(&domain.join("/baz")?)?, 0);
+        assert_eq!(
+            db.url_counts_page(&domain, &UrlQuery::default(), 1, 10)?,
+            (vec![(domain.join("/foo")?, 2)], 2)
+        );
+        compare_sorted(
+            db.unique_urls_for_domain(&domain)?,
+            vec![domain.join("/foo")?, domain.join("/bar?a=1")?],
+        );
+        assert_eq!(
+            db.third_party_for_domain(&domain)?,
+            vec![("cdn.net".to_string(), 1)]
+        );
+        assert!(db.is_truncated(&domain)?);
+        assert!(!db.is_truncated(&other)?);
+        assert_eq!(db.sessions(&domain)?.len(), 1);
+        assert_eq!(
+            db.lineage_for_domain(&domain)?,
+            vec![(
+                domain.join("/foo")?,
+                Lineage {
+                    first_seen: 1,
+                    last_seen: 2,
+                }
+            )]
+        );
+        assert_eq!(
+            db.statuses_for_domain(&domain)?,
+            vec![(domain.join("/foo")?, FetchStatus::failed(None, "timeout"))]
+        );
+        assert_eq!(
+            db.status(&domain.join("/foo")?)?,
+            Some(FetchStatus::failed(None, "timeout"))
+        );
+        assert_eq!(db.status(&domain.join("/bar?a=1")?)?, None);
+        let referrers = db.referrers(&domain.join("/foo")?)?;
+        assert_eq!(referrers.len(), MAX_REFERRERS);
+        assert_eq!(referrers[0], domain.join("/0")?);
+        let hits = db.search(&domain, "sled text", 10)?;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].url, domain.join("/foo")?);
+        assert_eq!(hits[0].snippet, "Sled stores the text");
+        assert_eq!(db.search(&domain, "replaced", 10)?.len(), 1);
+        // Every link between two pages of the domain is part of its graph, once.
+        let graph = db.graph_for_domain(&domain)?;
+        assert_eq!(
+            graph.nodes,
+            vec![domain.join("/bar?a=1")?, domain.join("/foo")?]
+        );
+        assert_eq!(graph.edges.len(), MAX_REFERRERS + 2);
+        assert!(graph.edges.contains(&Edge {
+            from: domain.join("/foo")?,
+            to: domain.join("/bar?a=1")?,
+        }));
+        assert!(graph.edges.contains(&Edge {
+            from: domain.join(&format!("/{}", MAX_REFERRERS))?,
+            to: domain.join("/foo")?,
+        }));
+        assert_eq!(
+            db.assets_for_domain(&domain)?,
+            vec![(
+                logo,
+                Asset {
+                    kind: AssetKind::Image,
+                    count: 2
+                }
+            )]
+        );
+        assert_eq!(
+            db.totals()?,
+            DbTotals {
+                domains: 2,
+                urls: 3,
+            }
+        );
+
+        // The keys of `example.com` do not start with the ones of `example.com.au`.
+        db.remove_domain(&other)?;
+        assert_eq!(
+            db.unique_urls_for_domain(&other),
+            Err(DbError::DomainDoesNotExist)
+        );
+        assert_eq!(db.remove_domain(&other), Err(DbError::DomainDoesNotExist));
+        assert_eq!(
+            db.totals()?,
+            DbTotals {
+                domains: 1,
+                urls: 2,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_values() -> anyhow::Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let storage = SledStorage::from_db(db)?;
+        storage.add_visits("example.com", "/ok", 1)?;
+        storage.urls.insert(key("example.com", "/short"), &[1])?;
+        storage.lineage.insert(key("example.com", "/short"), &[1])?;
+
+        // Reported as errors, and left as they are.
+        let count = Some(DbError::Corrupted("Invalid count".to_string()));
+        let lineage = Some(DbError::Corrupted("Invalid lineage".to_string()));
+        assert_eq!(storage.count("example.com", "/short").err(), count);
+        assert_eq!(storage.record_visit("example.com", "/short").err(), count);
+        assert_eq!(storage.add_visits("example.com", "/short", 1).err(), count);
+        assert_eq!(storage.paths("example.com").err(), count);
+        assert_eq!(storage.see("example.com", "/short", 1).err(), lineage);
+        assert_eq!(storage.lineage("example.com").err(), lineage);
+        assert_eq!(
+            storage.urls.get(key("example.com", "/short"))?.as_deref(),
+            Some(&[1][..])
+        );
+        assert_eq!(storage.count("example.com", "/ok")?, Some(1));
+
+        Ok(())
+    }
+}
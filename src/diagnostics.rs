@@ -0,0 +1,301 @@
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1251, WINDOWS_1252};
+use serde::{Deserialize, Serialize};
+
+/// Number of bytes at the start of a body searched for a `<meta>` charset, as browsers do.
+const META_CHARSET_BYTES: usize = 1024;
+
+/// Something wrong with a downloaded body that did not make the download fail, but may have
+/// corrupted what was extracted from it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "issue", rename_all = "snake_case")]
+pub enum ContentIssue {
+    /// The body is not as long as its `Content-Length`, e.g. the connection closed early and
+    /// the page was truncated.
+    ContentLengthMismatch { declared: u64, received: u64 },
+    /// The body is not valid UTF-8, although no other charset is declared. Invalid bytes were
+    /// replaced if it is declared as UTF-8, otherwise its charset was detected.
+    InvalidUtf8,
+    /// The body declares a charset that is not known, e.g. `x-mac-klingon`, and has non-ASCII
+    /// bytes, decoded with the charset detected instead.
+    CharsetNotDecoded { charset: String },
+    /// The `Content-Type` header and a `<meta>` tag of the page declare different charsets.
+    CharsetConflict { header: String, meta: String },
+}
+
+impl ContentIssue {
+    /// Short label used to count issues in crawl statistics, e.g. `invalid_utf8`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ContentIssue::ContentLengthMismatch { .. } => "content_length_mismatch",
+            ContentIssue::InvalidUtf8 => "invalid_utf8",
+            ContentIssue::CharsetNotDecoded { .. } => "charset_not_decoded",
+            ContentIssue::CharsetConflict { .. } => "charset_conflict",
+        }
+    }
+}
+
+/// The text of a `body` served with the `content_type` header, decoded from its charset, along
+/// with the charset and the issues with it. Bodies of other types, e.g. images, are taken as
+/// UTF-8 and have no issues.
+pub(crate) fn decode(
+    content_type: Option<&str>,
+    body: Vec<u8>,
+) -> (String, &'static Encoding, Vec<ContentIssue>) {
+    let (encoding, issues) = match content_type.is_none_or(is_text) {
+        true => charset(content_type, &body),
+        false => (UTF_8, Vec::new()),
+    };
+    let text = if encoding == UTF_8 && Encoding::for_bom(&body).is_none() {
+        // Valid UTF-8 becomes the text as is, only invalid bodies are copied to replace bytes.
+        String::from_utf8(body)
+            .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+    } else {
+        encoding.decode(&body).0.into_owned()
+    };
+
+    (text, encoding, issues)
+}
+
+/// The charset of a text `body` served with the `content_type` header, and the issues with it.
+/// As in browsers, a byte order mark wins over the header, which wins over a `<meta>` tag, and
+/// the charset is detected if none of them declares a known one.
+fn charset(content_type: Option<&str>, body: &[u8]) -> (&'static Encoding, Vec<ContentIssue>) {
+    let mut issues = Vec::new();
+    let header = content_type.and_then(header_charset);
+    let meta = meta_charset(&body[..body.len().min(META_CHARSET_BYTES)]);
+    if let (Some(header), Some(meta)) = (&header, &meta) {
+        let same = match (known_charset(header), known_charset(meta)) {
+            (Some(header), Some(meta)) => header == meta,
+            _ => canonical(header) == canonical(meta),
+        };
+        if !same {
+            issues.push(ContentIssue::CharsetConflict {
+                header: header.clone(),
+                meta: meta.clone(),
+            });
+        }
+    }
+
+    if let Some((encoding, _)) = Encoding::for_bom(body) {
+        return (encoding, issues);
+    }
+    // A `<meta>` tag is read as ASCII, so it cannot declare UTF-16: it is taken as UTF-8 then.
+    let declared = match (header, meta) {
+        (Some(header), _) => Some((known_charset(&header), header)),
+        (None, Some(meta)) => Some((known_charset(&meta).map(Encoding::output_encoding), meta)),
+        (None, None) => None,
+    };
+    match declared {
+        Some((Some(encoding), _)) => {
+            if encoding == UTF_8 && std::str::from_utf8(body).is_err() {
+                issues.push(ContentIssue::InvalidUtf8);
+            }
+            (encoding, issues)
+        }
+        Some((None, charset)) => {
+            if !body.is_ascii() {
+                issues.push(ContentIssue::CharsetNotDecoded { charset });
+            }
+            (detect(body), issues)
+        }
+        None => {
+            let encoding = detect(body);
+            if encoding != UTF_8 {
+                issues.push(ContentIssue::InvalidUtf8);
+            }
+            (encoding, issues)
+        }
+    }
+}
+
+/// The charset of a `body` that does not declare one: UTF-8 if it is valid UTF-8, otherwise one
+/// of the single-byte charsets browsers fall back to. That is `windows-1251` if most non-ASCII
+/// bytes are next to another one, as in words of Cyrillic letters, and `windows-1252` if they are
+/// mostly on their own, as accented letters among ASCII ones.
+fn detect(body: &[u8]) -> &'static Encoding {
+    if std::str::from_utf8(body).is_ok() {
+        return UTF_8;
+    }
+
+    let (mut non_ascii, mut in_words) = (0, 0);
+    for (i, byte) in body.iter().enumerate() {
+        if byte.is_ascii() {
+            continue;
+        }
+        non_ascii += 1;
+        let after = i > 0 && !body[i - 1].is_ascii();
+        let before = body.get(i + 1).is_some_and(|next| !next.is_ascii());
+        in_words += (after || before) as usize;
+    }
+    if in_words * 2 > non_ascii {
+        WINDOWS_1251
+    } else {
+        WINDOWS_1252
+    }
+}
+
+/// The charset named `name`, e.g. `windows-1252` for `latin1`, if it is a known one.
+fn known_charset(name: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(name.as_bytes())
+}
+
+fn is_text(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    mime.starts_with("text/") || mime.ends_with("xml") || mime.ends_with("json")
+}
+
+/// The lowercase name of `charset`, the usual aliases of UTF-8 and ASCII being folded.
+fn canonical(charset: &str) -> &str {
+    match charset {
+        "utf8" => "utf-8",
+        "ascii" => "us-ascii",
+        charset => charset,
+    }
+}
+
+/// The `charset` parameter of a `Content-Type` header, lowercase.
+fn header_charset(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"').to_ascii_lowercase())
+    })
+}
+
+/// The charset of the first `<meta charset>` or `<meta http-equiv="Content-Type">` tag of `head`,
+/// lowercase.
+fn meta_charset(head: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+    head.split("<meta").skip(1).find_map(|tag| {
+        let tag = tag.split('>').next().unwrap_or_default();
+        let value = &tag[tag.find("charset=")? + "charset=".len()..];
+        let charset: String = value
+            .trim_start_matches(['"', '\''])
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+            .collect();
+        (!charset.is_empty()).then_some(charset)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{UTF_16LE, UTF_8, WINDOWS_1251, WINDOWS_1252};
+
+    use super::{charset, decode, meta_charset, ContentIssue};
+
+    #[test]
+    fn test_meta_charset() {
+        assert_eq!(
+            meta_charset(br#"<head><meta charset="UTF-8"></head>"#).as_deref(),
+            Some("utf-8")
+        );
+        assert_eq!(
+            meta_charset(
+                br#"<meta name="viewport"><meta http-equiv="Content-Type" content="text/html; charset=iso-8859-1">"#
+            )
+            .as_deref(),
+            Some("iso-8859-1")
+        );
+        assert_eq!(meta_charset(b"<p>charset=utf-8</p>"), None);
+    }
+
+    #[test]
+    fn test_charset() {
+        let html = Some("text/html");
+        let latin1 = b"<p>caf\xe9</p>";
+
+        assert_eq!(charset(html, "<p>café</p>".as_bytes()), (UTF_8, vec![]));
+        assert_eq!(
+            charset(None, latin1),
+            (WINDOWS_1252, vec![ContentIssue::InvalidUtf8])
+        );
+        assert_eq!(
+            charset(Some("text/html; charset=UTF8"), latin1),
+            (UTF_8, vec![ContentIssue::InvalidUtf8])
+        );
+        assert_eq!(
+            charset(Some("text/html; charset=\"ISO-8859-1\""), latin1),
+            (WINDOWS_1252, vec![])
+        );
+        assert_eq!(
+            charset(html, br#"<meta charset="windows-1251"><p>\xef\xf0\xe8</p>"#),
+            (WINDOWS_1251, vec![])
+        );
+        assert_eq!(
+            charset(Some("text/html; charset=x-klingon"), latin1),
+            (
+                WINDOWS_1252,
+                vec![ContentIssue::CharsetNotDecoded {
+                    charset: "x-klingon".to_string()
+                }]
+            )
+        );
+        assert_eq!(
+            charset(
+                Some("text/html; charset=utf-8"),
+                br#"<meta charset="windows-1252"><p>cafe</p>"#
+            ),
+            (
+                UTF_8,
+                vec![ContentIssue::CharsetConflict {
+                    header: "utf-8".to_string(),
+                    meta: "windows-1252".to_string()
+                }]
+            )
+        );
+        // Names of the same charset do not conflict.
+        assert_eq!(
+            charset(
+                Some("text/html; charset=iso-8859-1"),
+                br#"<meta charset="windows-1252"><p>cafe</p>"#
+            ),
+            (WINDOWS_1252, vec![])
+        );
+        // The byte order mark wins.
+        assert_eq!(
+            charset(Some("text/html; charset=utf-8"), b"\xff\xfe<\0p\0>\0"),
+            (UTF_16LE, vec![])
+        );
+        // A `<meta>` tag cannot declare UTF-16.
+        assert_eq!(
+            charset(html, br#"<meta charset="utf-16"><p>cafe</p>"#),
+            (UTF_8, vec![])
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let (text, encoding, issues) = decode(
+            Some("text/html; charset=iso-8859-1"),
+            b"<p>caf\xe9</p>".to_vec(),
+        );
+        assert_eq!(
+            (text.as_str(), encoding, issues),
+            ("<p>café</p>", WINDOWS_1252, vec![])
+        );
+
+        // Cyrillic words, without any charset declared.
+        let (text, encoding, _) = decode(
+            None,
+            b"<p>\xcf\xf0\xe8\xe2\xe5\xf2, \xec\xe8\xf0</p>".to_vec(),
+        );
+        assert_eq!(
+            (text.as_str(), encoding),
+            ("<p>Привет, мир</p>", WINDOWS_1251)
+        );
+
+        let (text, encoding, _) = decode(
+            Some("text/html"),
+            b"\xef\xbb\xbf<p>caf\xc3\xa9</p>".to_vec(),
+        );
+        assert_eq!((text.as_str(), encoding), ("<p>café</p>", UTF_8));
+
+        let (text, encoding, issues) = decode(Some("image/png"), b"\x89PNG\xff".to_vec());
+        assert_eq!(
+            (text.as_str(), encoding, issues),
+            ("\u{fffd}PNG\u{fffd}", UTF_8, vec![])
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use serde::{Deserialize, Serialize};
+use url::{Host, Url};
+
+/// Where the downloader may send requests. Checked before every request, redirects included, so
+/// that neither a crafted link nor a redirect can reach hosts the crawl should not, whatever the
+/// crawler lets through.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EgressConfig {
+    /// Hosts requests may be sent to, with their subdomains. Any host if empty.
+    pub allow_hosts: Vec<String>,
+    /// Hosts requests are never sent to, with their subdomains.
+    pub deny_hosts: Vec<String>,
+    /// Send requests to hosts resolving to private, loopback or link-local addresses, e.g.
+    /// `10.0.0.1`, `localhost` or `169.254.169.254`.
+    pub allow_private: bool,
+    /// Let only the host of the crawled domain resolve to such an address, e.g. to crawl a local
+    /// dev server without letting its links reach any other private host.
+    pub allow_private_domain: bool,
+}
+
+impl EgressConfig {
+    /// Why no request may be sent to `url` whatever its address, `None` if it may.
+    pub(crate) fn check_host(&self, url: &Url) -> Option<&'static str> {
+        let host = match url.host_str() {
+            Some(host) => host,
+            None => return Some("no host"),
+        };
+        if matches_any(&self.deny_hosts, host) {
+            return Some("denied host");
+        }
+        if !self.allow_hosts.is_empty() && !matches_any(&self.allow_hosts, host) {
+            return Some("host not allowed");
+        }
+
+        None
+    }
+}
+
+/// Whether `host` is one of `hosts` or one of their subdomains.
+pub(crate) fn matches_any(hosts: &[String], host: &str) -> bool {
+    hosts.iter().any(|pattern| {
+        let pattern = pattern.trim_start_matches('.');
+        host.eq_ignore_ascii_case(pattern)
+            || host
+                .strip_suffix(pattern)
+                .is_some_and(|subdomain| subdomain.ends_with('.'))
+    })
+}
+
+/// The address of the host of `url` if it is an IP address, `None` if it has to be resolved.
+pub(crate) fn literal_address(url: &Url) -> Option<IpAddr> {
+    match url.host()? {
+        Host::Ipv4(ip) => Some(IpAddr::V4(ip)),
+        Host::Ipv6(ip) => Some(IpAddr::V6(ip)),
+        Host::Domain(_) => None,
+    }
+}
+
+/// Whether `ip` is not a public address: private, loopback, link-local, shared, unspecified...
+pub(crate) fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_v4(ip),
+        // IPv4-mapped and IPv4-compatible addresses reach the IPv4 address they embed, and so do
+        // the ones of NAT64 gateways, 64:ff9b::/96.
+        IpAddr::V6(ip) => match (ip.to_ipv4(), ip.segments()) {
+            (Some(ip), _) => is_private_v4(ip),
+            (_, [0x64, 0xff9b, 0, 0, 0, 0, high, low]) => {
+                is_private_v4(Ipv4Addr::from(u32::from(high) << 16 | u32::from(low)))
+            }
+            _ => is_private_v6(ip),
+        },
+    }
+}
+
+fn is_private_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        // "This network", 0.0.0.0/8, reaching the host itself on most systems.
+        || a == 0
+        // Shared address space of carrier-grade NATs, 100.64.0.0/10.
+        || (a == 100 && (b & 0b1100_0000) == 64)
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    ip.is_loopback() || ip.is_unspecified() || ip.is_unique_local() || ip.is_unicast_link_local()
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{is_private, literal_address, EgressConfig};
+
+    #[test]
+    fn test_check_host() {
+        let config = EgressConfig {
+            allow_hosts: vec!["example.com".to_string()],
+            deny_hosts: vec!["admin.example.com".to_string()],
+            allow_private: false,
+            allow_private_domain: false,
+        };
+        let check = |url: &str| config.check_host(&Url::parse(url).unwrap());
+
+        assert_eq!(check("https://example.com/"), None);
+        assert_eq!(check("https://www.example.com/"), None);
+        assert_eq!(check("https://admin.example.com/"), Some("denied host"));
+        assert_eq!(check("https://api.admin.example.com/"), Some("denied host"));
+        assert_eq!(check("https://notexample.com/"), Some("host not allowed"));
+        assert_eq!(check("data:text/plain,foo"), Some("no host"));
+        assert_eq!(
+            EgressConfig::default().check_host(&Url::parse("https://foobar.com").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_is_private() {
+        let private = |url: &str| is_private(literal_address(&Url::parse(url).unwrap()).unwrap());
+
+        assert!(private("http://127.0.0.1/"));
+        assert!(private("http://10.1.2.3/"));
+        assert!(private("http://172.16.0.1/"));
+        assert!(private("http://192.168.1.1/"));
+        assert!(private("http://169.254.169.254/latest/meta-data"));
+        assert!(private("http://100.64.0.1/"));
+        assert!(private("http://0.0.0.0/"));
+        assert!(private("http://0.1.2.3/"));
+        assert!(private("http://[::1]/"));
+        assert!(private("http://[fd00::1]/"));
+        assert!(private("http://[fe80::1]/"));
+        assert!(private("http://[::ffff:10.0.0.1]/"));
+        assert!(private("http://[::127.0.0.1]/"));
+        assert!(private("http://[64:ff9b::a9fe:a9fe]/"));
+        assert!(!private("http://[64:ff9b::5db8:d822]/"));
+        assert!(!private("http://93.184.216.34/"));
+        assert!(!private("http://100.128.0.1/"));
+        assert!(!private("http://[2606:2800:220:1::]/"));
+        assert!(literal_address(&Url::parse("http://localhost/").unwrap()).is_none());
+    }
+}
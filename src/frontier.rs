@@ -0,0 +1,224 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    convert::TryFrom,
+    fmt,
+    sync::Arc,
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The order the URLs found by a crawl are fetched in, when they cannot all be fetched at once,
+/// e.g. with `max_concurrency`. The ones fetched first are also the ones that fit in `max_pages`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrontierOrder {
+    /// Least links followed from the seed first.
+    #[default]
+    BreadthFirst,
+    /// Least path segments first, e.g. `/blog/` before `/blog/2019/05/post`, then breadth first.
+    ShortestPath,
+    /// In the order they were found.
+    Discovery,
+}
+
+impl FrontierOrder {
+    /// The score of `url`, found at `depth`: lower ones are fetched first.
+    pub fn score(self, url: &Url, depth: usize) -> (usize, usize) {
+        match self {
+            Self::BreadthFirst => (depth, 0),
+            Self::ShortestPath => {
+                let segments = url
+                    .path_segments()
+                    .map_or(0, |segments| segments.filter(|s| !s.is_empty()).count());
+                (segments, depth)
+            }
+            Self::Discovery => (0, 0),
+        }
+    }
+}
+
+/// Scores the URLs found by a crawl in place of its `FrontierOrder`, from the URL and the depth
+/// it was found at: lower ones are fetched first, ties in the order they were found. Only set from
+/// code, see `CrawlerConfig::frontier_scorer`.
+#[derive(Clone)]
+pub(crate) struct FrontierScorer(Arc<ScoreFn>);
+
+type ScoreFn = dyn Fn(&Url, usize) -> i64 + Send + Sync;
+
+impl FrontierScorer {
+    pub(crate) fn new(score: impl Fn(&Url, usize) -> i64 + Send + Sync + 'static) -> Self {
+        Self(Arc::new(score))
+    }
+}
+
+impl fmt::Debug for FrontierScorer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FrontierScorer")
+    }
+}
+
+/// An URL waiting to be fetched.
+#[derive(Debug)]
+pub(crate) struct Scheduled {
+    pub(crate) url: Url,
+    pub(crate) depth: usize,
+    /// When the URL entered the frontier.
+    pub(crate) enqueued_at: Instant,
+    score: (i64, i64),
+    // Breaks ties in the order the URLs were pushed.
+    sequence: u64,
+}
+
+impl Scheduled {
+    fn key(&self) -> Reverse<((i64, i64), u64)> {
+        Reverse((self.score, self.sequence))
+    }
+}
+
+impl PartialEq for Scheduled {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scheduled {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// URLs to fetch, popped in the `FrontierOrder` of the crawl, or as ranked by its scorer.
+#[derive(Debug, Default)]
+pub(crate) struct Frontier {
+    order: FrontierOrder,
+    scorer: Option<FrontierScorer>,
+    queue: BinaryHeap<Scheduled>,
+    pushed: u64,
+}
+
+impl Frontier {
+    pub(crate) fn new(order: FrontierOrder, scorer: Option<FrontierScorer>) -> Self {
+        Self {
+            order,
+            scorer,
+            ..Self::default()
+        }
+    }
+
+    fn score(&self, url: &Url, depth: usize) -> (i64, i64) {
+        match &self.scorer {
+            Some(scorer) => ((scorer.0)(url, depth), 0),
+            None => {
+                let (first, second) = self.order.score(url, depth);
+                let saturate = |score| i64::try_from(score).unwrap_or(i64::MAX);
+                (saturate(first), saturate(second))
+            }
+        }
+    }
+
+    pub(crate) fn push(&mut self, url: Url, depth: usize) {
+        self.queue.push(Scheduled {
+            score: self.score(&url, depth),
+            sequence: self.pushed,
+            url,
+            depth,
+            enqueued_at: Instant::now(),
+        });
+        self.pushed += 1;
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Scheduled> {
+        self.queue.pop()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{Frontier, FrontierOrder, FrontierScorer};
+
+    fn popped(order: FrontierOrder, urls: &[(&str, usize)]) -> Vec<String> {
+        popped_by(Frontier::new(order, None), urls)
+    }
+
+    fn popped_by(mut frontier: Frontier, urls: &[(&str, usize)]) -> Vec<String> {
+        let domain = Url::parse("https://example.com").unwrap();
+        for (path, depth) in urls {
+            frontier.push(domain.join(path).unwrap(), *depth);
+        }
+
+        std::iter::from_fn(|| frontier.pop())
+            .map(|scheduled| scheduled.url.path().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_frontier() {
+        let urls = [
+            ("/archive/2019/05/post", 1),
+            ("/a/b", 2),
+            ("/about", 1),
+            ("/a/b/c", 3),
+            ("/contact", 2),
+        ];
+
+        assert_eq!(
+            popped(FrontierOrder::BreadthFirst, &urls),
+            vec![
+                "/archive/2019/05/post",
+                "/about",
+                "/a/b",
+                "/contact",
+                "/a/b/c"
+            ]
+        );
+        assert_eq!(
+            popped(FrontierOrder::ShortestPath, &urls),
+            vec![
+                "/about",
+                "/contact",
+                "/a/b",
+                "/a/b/c",
+                "/archive/2019/05/post"
+            ]
+        );
+        assert_eq!(
+            popped(FrontierOrder::Discovery, &urls),
+            vec![
+                "/archive/2019/05/post",
+                "/a/b",
+                "/about",
+                "/a/b/c",
+                "/contact"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scorer() {
+        let urls = [("/a", 1), ("/contact", 2), ("/about", 1), ("/a/b", 2)];
+        // The longest paths first, whatever the order.
+        let scorer = FrontierScorer::new(|url, _| -(url.path().len() as i64));
+
+        assert_eq!(
+            popped_by(Frontier::new(FrontierOrder::Discovery, Some(scorer)), &urls),
+            vec!["/contact", "/about", "/a/b", "/a"]
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// A link from the page `from` to the page `to`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Edge {
+    pub from: Url,
+    pub to: Url,
+}
+
+/// The pages of a domain and the links between them, see `Db::graph_for_domain`. Serialized as
+/// JSON, it lists the `nodes` and the `edges`. Render it with `to_dot` or `to_graphml` to open it
+/// in Graphviz or Gephi.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkGraph {
+    pub nodes: Vec<Url>,
+    pub edges: Vec<Edge>,
+}
+
+impl LinkGraph {
+    /// The graph in the DOT language of Graphviz, pages being identified by their URL.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph links {\n");
+        for node in &self.nodes {
+            let _ = writeln!(dot, "    {};", dot_id(node));
+        }
+        for edge in &self.edges {
+            let _ = writeln!(dot, "    {} -> {};", dot_id(&edge.from), dot_id(&edge.to));
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    /// The graph as a GraphML document, pages being identified by their URL.
+    pub fn to_graphml(&self) -> String {
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <graph id=\"links\" edgedefault=\"directed\">\n",
+        ));
+        for node in &self.nodes {
+            let _ = writeln!(xml, "    <node id=\"{}\"/>", xml_escape(node.as_str()));
+        }
+        for edge in &self.edges {
+            let _ = writeln!(
+                xml,
+                "    <edge source=\"{}\" target=\"{}\"/>",
+                xml_escape(edge.from.as_str()),
+                xml_escape(edge.to.as_str())
+            );
+        }
+        xml.push_str("  </graph>\n</graphml>\n");
+
+        xml
+    }
+}
+
+/// `url` as a quoted DOT identifier.
+fn dot_id(url: &Url) -> String {
+    format!(
+        "\"{}\"",
+        url.as_str().replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// `text` escaped to be used in an XML attribute.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{Edge, LinkGraph};
+
+    #[test]
+    fn test_render() {
+        let home = Url::parse("https://example.com/").unwrap();
+        let search = Url::parse("https://example.com/search?q=a&p=2").unwrap();
+        let graph = LinkGraph {
+            nodes: vec![home.clone(), search.clone()],
+            edges: vec![Edge {
+                from: home,
+                to: search,
+            }],
+        };
+
+        assert_eq!(
+            graph.to_dot(),
+            concat!(
+                "digraph links {\n",
+                "    \"https://example.com/\";\n",
+                "    \"https://example.com/search?q=a&p=2\";\n",
+                "    \"https://example.com/\" -> \"https://example.com/search?q=a&p=2\";\n",
+                "}\n",
+            )
+        );
+        let graphml = graph.to_graphml();
+        assert!(graphml.contains(r#"<graph id="links" edgedefault="directed">"#));
+        assert!(graphml.contains(r#"<node id="https://example.com/search?q=a&amp;p=2"/>"#));
+        assert!(graphml.contains(
+            r#"<edge source="https://example.com/" target="https://example.com/search?q=a&amp;p=2"/>"#
+        ));
+        assert_eq!(
+            serde_json::to_value(&graph).unwrap()["edges"][0]["to"],
+            "https://example.com/search?q=a&p=2"
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Upper bounds of the histogram buckets, in milliseconds.
+const BUCKETS_MS: [u64; 10] = [1, 5, 10, 50, 100, 500, 1_000, 5_000, 10_000, 60_000];
+
+/// Number of samples that fell in one bucket.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bucket {
+    /// Upper bound of the bucket in milliseconds. `None` for the last, unbounded bucket.
+    pub le_ms: Option<u64>,
+    pub count: usize,
+}
+
+/// Latency histogram with fixed buckets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Histogram {
+    pub buckets: Vec<Bucket>,
+    pub count: usize,
+    pub sum_ms: u64,
+    pub max_ms: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        let buckets = BUCKETS_MS
+            .iter()
+            .map(|&le_ms| Some(le_ms))
+            .chain(std::iter::once(None))
+            .map(|le_ms| Bucket { le_ms, count: 0 })
+            .collect();
+
+        Self {
+            buckets,
+            count: 0,
+            sum_ms: 0,
+            max_ms: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub(crate) fn record(&mut self, duration: Duration) {
+        let ms = duration.as_millis() as u64;
+        let bucket = self
+            .buckets
+            .iter_mut()
+            .find(|bucket| bucket.le_ms.is_none_or(|le_ms| ms <= le_ms))
+            .unwrap();
+        bucket.count += 1;
+
+        self.count += 1;
+        self.sum_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Histogram;
+
+    #[test]
+    fn test_record() {
+        let mut histogram = Histogram::default();
+        histogram.record(Duration::from_micros(200));
+        histogram.record(Duration::from_millis(7));
+        histogram.record(Duration::from_millis(10));
+        histogram.record(Duration::from_secs(120));
+
+        let counts: Vec<(Option<u64>, usize)> = histogram
+            .buckets
+            .iter()
+            .filter(|bucket| bucket.count > 0)
+            .map(|bucket| (bucket.le_ms, bucket.count))
+            .collect();
+        assert_eq!(counts, vec![(Some(1), 1), (Some(10), 2), (None, 1)]);
+        assert_eq!(histogram.count, 4);
+        assert_eq!(histogram.sum_ms, 120_017);
+        assert_eq!(histogram.max_ms, 120_000);
+    }
+}
@@ -0,0 +1,473 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use url::Url;
+
+use crate::{
+    crawler::{CrawlControl, CrawlEvent, CrawlProgress, ProgressCounters, SkipReason},
+    summary::CrawlOutcome,
+};
+
+/// Identifier of a crawl job, unique for the lifetime of the server.
+pub(crate) type JobId = u64;
+
+/// What an active job is registered under: its domain and the hash of its scope prefixes, so that
+/// crawls of different parts of a domain can run side by side.
+type JobKey = (Url, u64);
+
+/// Hash of the scope prefixes of a crawl, whatever their order.
+fn scope_hash(scope: &[String]) -> u64 {
+    let mut scope: Vec<_> = scope.iter().collect();
+    scope.sort_unstable();
+    scope.dedup();
+
+    let mut hasher = DefaultHasher::new();
+    scope.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Where a job is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum JobState {
+    /// Registered, the crawl has not started yet.
+    Queued,
+    Running,
+    /// Spawns no new download until resumed.
+    Paused,
+    /// The crawl ended by itself, see the `outcome` of the job.
+    Finished,
+    /// The crawl was stopped by a cancel request or on shutdown.
+    Cancelled,
+}
+
+impl JobState {
+    fn is_active(self) -> bool {
+        matches!(
+            self,
+            JobState::Queued | JobState::Running | JobState::Paused
+        )
+    }
+}
+
+/// A crawl registered in `Jobs`.
+#[derive(Debug)]
+struct Job {
+    domain: Url,
+    scope: Vec<String>,
+    state: JobState,
+    cancel: CancellationToken,
+    progress: Arc<CrawlProgress>,
+    control: CrawlControl,
+    outcome: Option<CrawlOutcome>,
+    created_at: DateTime<Utc>,
+    started_at: Option<DateTime<Utc>>,
+    finished_at: Option<DateTime<Utc>>,
+}
+
+/// What the job status request returns about a job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JobStatus {
+    pub(crate) id: JobId,
+    pub(crate) domain: Url,
+    /// Path prefixes the crawl is restricted to, the whole domain if empty.
+    pub(crate) scope: Vec<String>,
+    pub(crate) state: JobState,
+    /// Live counters of the crawl, `fetched` being the pages visited.
+    #[serde(flatten)]
+    pub(crate) progress: ProgressCounters,
+    /// How the crawl ended, once it did.
+    pub(crate) outcome: Option<CrawlOutcome>,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) started_at: Option<DateTime<Utc>>,
+    pub(crate) finished_at: Option<DateTime<Utc>>,
+    /// Time spent crawling so far, or in total once finished.
+    pub(crate) duration_ms: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+struct JobTable {
+    last_id: JobId,
+    jobs: HashMap<JobId, Job>,
+    /// The queued, running or paused job of each domain and scope.
+    active: HashMap<JobKey, JobId>,
+}
+
+impl JobTable {
+    /// The active jobs of `domain`, oldest first, only the one with the given scope if any.
+    fn matching(&self, domain: &Url, scope: Option<&[String]>) -> Vec<JobId> {
+        let mut ids: Vec<_> = match scope {
+            Some(scope) => self
+                .active
+                .get(&(domain.clone(), scope_hash(scope)))
+                .copied()
+                .into_iter()
+                .collect(),
+            None => self
+                .active
+                .iter()
+                .filter(|((active, _), _)| active == domain)
+                .map(|(_, &id)| id)
+                .collect(),
+        };
+        ids.sort_unstable();
+
+        ids
+    }
+}
+
+/// Thread-safe table of the crawl jobs, at most one of them being active per domain and scope.
+/// Jobs are kept once done, so that their status can still be asked for.
+/// The jobs of a domain are selected by their scope, or all of them when no scope is given.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Jobs(Arc<Mutex<JobTable>>);
+
+impl Jobs {
+    /// Register a queued job crawling `scope` of `domain`, unless the same scope of the domain
+    /// already has an active one, whose id is then returned as an error.
+    pub(crate) fn create(
+        &self,
+        domain: Url,
+        scope: Vec<String>,
+        cancel: CancellationToken,
+        progress: Arc<CrawlProgress>,
+        control: CrawlControl,
+    ) -> Result<JobId, JobId> {
+        let mut table = self.0.lock().unwrap();
+        let key = (domain.clone(), scope_hash(&scope));
+        if let Some(&id) = table.active.get(&key) {
+            return Err(id);
+        }
+
+        table.last_id += 1;
+        let id = table.last_id;
+        table.active.insert(key, id);
+        table.jobs.insert(
+            id,
+            Job {
+                domain,
+                scope,
+                state: JobState::Queued,
+                cancel,
+                progress,
+                control,
+                outcome: None,
+                created_at: Utc::now(),
+                started_at: None,
+                finished_at: None,
+            },
+        );
+
+        Ok(id)
+    }
+
+    /// Mark the job as running, unless it was cancelled or paused while queued.
+    pub(crate) fn start(&self, id: JobId) {
+        if let Some(job) = self.0.lock().unwrap().jobs.get_mut(&id) {
+            if job.state.is_active() {
+                job.started_at = Some(Utc::now());
+            }
+            if job.state == JobState::Queued {
+                job.state = JobState::Running;
+            }
+        }
+    }
+
+    /// Mark the job as done with `outcome`.
+    pub(crate) fn finish(&self, id: JobId, outcome: CrawlOutcome) {
+        let mut table = self.0.lock().unwrap();
+        let job = match table.jobs.get_mut(&id) {
+            Some(job) => job,
+            None => return,
+        };
+
+        if job.state.is_active() {
+            job.state = match outcome {
+                CrawlOutcome::Cancelled => JobState::Cancelled,
+                _ => JobState::Finished,
+            };
+        }
+        job.outcome = Some(outcome);
+        job.finished_at = Some(Utc::now());
+        let key = (job.domain.clone(), scope_hash(&job.scope));
+        // A cancelled job may have been replaced by a new one for the same domain since.
+        if table.active.get(&key) == Some(&id) {
+            table.active.remove(&key);
+        }
+    }
+
+    /// Cancel the active jobs of `domain`, returning their ids.
+    pub(crate) fn cancel(&self, domain: &Url, scope: Option<&[String]>) -> Vec<JobId> {
+        let mut table = self.0.lock().unwrap();
+        let ids = table.matching(domain, scope);
+        for id in &ids {
+            if let Some(job) = table.jobs.get_mut(id) {
+                job.cancel.cancel();
+                job.state = JobState::Cancelled;
+            }
+        }
+        table.active.retain(|_, id| !ids.contains(id));
+
+        ids
+    }
+
+    /// Pause the active jobs of `domain`, returning their ids.
+    pub(crate) fn pause(&self, domain: &Url, scope: Option<&[String]>) -> Vec<JobId> {
+        self.set_paused(domain, scope, true)
+    }
+
+    /// Resume the active jobs of `domain`, returning their ids.
+    pub(crate) fn resume(&self, domain: &Url, scope: Option<&[String]>) -> Vec<JobId> {
+        self.set_paused(domain, scope, false)
+    }
+
+    fn set_paused(&self, domain: &Url, scope: Option<&[String]>, paused: bool) -> Vec<JobId> {
+        let mut table = self.0.lock().unwrap();
+        let ids = table.matching(domain, scope);
+        for id in &ids {
+            let job = match table.jobs.get_mut(id) {
+                Some(job) => job,
+                None => continue,
+            };
+            if paused {
+                job.control.pause();
+                job.state = JobState::Paused;
+            } else {
+                job.control.resume();
+                job.state = match job.started_at {
+                    Some(_) => JobState::Running,
+                    None => JobState::Queued,
+                };
+            }
+        }
+
+        ids
+    }
+
+    /// The id and the live counters of the oldest active job of `domain`, if any.
+    pub(crate) fn active(
+        &self,
+        domain: &Url,
+        scope: Option<&[String]>,
+    ) -> Option<(JobId, ProgressCounters)> {
+        let table = self.0.lock().unwrap();
+        let id = *table.matching(domain, scope).first()?;
+
+        Some((id, table.jobs[&id].progress.counters()))
+    }
+
+    /// The jobs of `domain` that did not end yet, oldest first, cancelled ones still stopping
+    /// included.
+    pub(crate) fn unfinished(&self, domain: &Url) -> Vec<JobId> {
+        self.0
+            .lock()
+            .unwrap()
+            .jobs
+            .iter()
+            .filter(|(_, job)| &job.domain == domain && job.outcome.is_none())
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    /// The number of queued, running or paused jobs.
+    pub(crate) fn active_count(&self) -> usize {
+        self.0.lock().unwrap().active.len()
+    }
+
+    /// Subscribe to the events of the oldest active job of `domain`, if any.
+    pub(crate) fn subscribe(
+        &self,
+        domain: &Url,
+        scope: Option<&[String]>,
+    ) -> Option<broadcast::Receiver<CrawlEvent>> {
+        let table = self.0.lock().unwrap();
+        let id = *table.matching(domain, scope).first()?;
+
+        Some(table.jobs[&id].progress.subscribe())
+    }
+
+    /// The status of the job `id`, if it exists.
+    pub(crate) fn status(&self, id: JobId) -> Option<JobStatus> {
+        let table = self.0.lock().unwrap();
+
+        table.jobs.get(&id).map(|job| job.status(id))
+    }
+
+    /// Number of URLs rejected by each of the limits of the crawls, over every job since the
+    /// server started.
+    pub(crate) fn rejected(&self) -> BTreeMap<SkipReason, usize> {
+        let table = self.0.lock().unwrap();
+        let mut rejected = BTreeMap::new();
+        for job in table.jobs.values() {
+            for (reason, count) in job.progress.rejected() {
+                *rejected.entry(reason).or_default() += count;
+            }
+        }
+
+        rejected
+    }
+
+    /// The status of every queued, running or paused job, oldest first.
+    pub(crate) fn active_statuses(&self) -> Vec<JobStatus> {
+        let table = self.0.lock().unwrap();
+        let mut ids: Vec<_> = table.active.values().copied().collect();
+        ids.sort_unstable();
+
+        ids.into_iter()
+            .map(|id| table.jobs[&id].status(id))
+            .collect()
+    }
+}
+
+impl Job {
+    fn status(&self, id: JobId) -> JobStatus {
+        let duration_ms = self.started_at.map(|started_at| {
+            (self.finished_at.unwrap_or_else(Utc::now) - started_at).num_milliseconds()
+        });
+
+        JobStatus {
+            id,
+            domain: self.domain.clone(),
+            scope: self.scope.clone(),
+            state: self.state,
+            progress: self.progress.counters(),
+            outcome: self.outcome.clone(),
+            created_at: self.created_at,
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+            duration_ms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio_util::sync::CancellationToken;
+    use url::Url;
+
+    use super::{JobState, Jobs};
+    use crate::{crawler::CrawlControl, summary::CrawlOutcome};
+
+    #[test]
+    fn test_lifecycle() {
+        let jobs = Jobs::default();
+        let domain = Url::parse("https://example.com").unwrap();
+        let token = CancellationToken::new();
+
+        let id = jobs
+            .create(
+                domain.clone(),
+                Vec::new(),
+                token.clone(),
+                Arc::default(),
+                CrawlControl::default(),
+            )
+            .unwrap();
+        assert_eq!(jobs.status(id).unwrap().state, JobState::Queued);
+        assert_eq!(jobs.status(id).unwrap().duration_ms, None);
+        // A domain has at most one active job per scope.
+        assert_eq!(
+            jobs.create(
+                domain.clone(),
+                Vec::new(),
+                CancellationToken::new(),
+                Arc::default(),
+                CrawlControl::default()
+            ),
+            Err(id)
+        );
+
+        jobs.start(id);
+        assert_eq!(jobs.status(id).unwrap().state, JobState::Running);
+        assert_eq!(jobs.active(&domain, None).unwrap().0, id);
+        assert_eq!(jobs.active_count(), 1);
+        let active = jobs.active_statuses();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].id, id);
+        assert!(active[0].started_at.is_some());
+
+        let control = jobs.0.lock().unwrap().jobs[&id].control.clone();
+        assert_eq!(jobs.pause(&domain, None), vec![id]);
+        assert!(control.is_paused());
+        assert_eq!(jobs.status(id).unwrap().state, JobState::Paused);
+        assert_eq!(jobs.active_count(), 1);
+        assert_eq!(jobs.resume(&domain, None), vec![id]);
+        assert!(!control.is_paused());
+        assert_eq!(jobs.status(id).unwrap().state, JobState::Running);
+
+        assert_eq!(jobs.cancel(&domain, None), vec![id]);
+        assert!(token.is_cancelled());
+        assert!(jobs.active(&domain, None).is_none());
+
+        // The cancelled crawl ends after a new one was started.
+        let new_id = jobs
+            .create(
+                domain.clone(),
+                Vec::new(),
+                CancellationToken::new(),
+                Arc::default(),
+                CrawlControl::default(),
+            )
+            .unwrap();
+        assert_ne!(new_id, id);
+        jobs.finish(id, CrawlOutcome::Cancelled);
+
+        let status = jobs.status(id).unwrap();
+        assert_eq!(status.state, JobState::Cancelled);
+        assert!(status.finished_at.is_some());
+        assert_eq!(jobs.active(&domain, None).unwrap().0, new_id);
+
+        jobs.start(new_id);
+        jobs.finish(new_id, CrawlOutcome::Completed);
+        assert_eq!(jobs.status(new_id).unwrap().state, JobState::Finished);
+        assert!(jobs.active(&domain, None).is_none());
+        assert_eq!(jobs.active_count(), 0);
+        assert!(jobs.status(new_id + 1).is_none());
+    }
+
+    #[test]
+    fn test_scoped_jobs() {
+        let jobs = Jobs::default();
+        let domain = Url::parse("https://example.com").unwrap();
+        let create = |scope: &[&str]| {
+            jobs.create(
+                domain.clone(),
+                scope.iter().map(|prefix| prefix.to_string()).collect(),
+                CancellationToken::new(),
+                Arc::default(),
+                CrawlControl::default(),
+            )
+        };
+
+        let blog = create(&["/blog/"]).unwrap();
+        let docs = create(&["/docs/", "/api/"]).unwrap();
+        let all = create(&[]).unwrap();
+        // The order of the prefixes does not matter.
+        assert_eq!(create(&["/api/", "/docs/"]), Err(docs));
+        assert_eq!(jobs.active_count(), 3);
+        assert_eq!(jobs.status(docs).unwrap().scope, vec!["/docs/", "/api/"]);
+
+        let scope = ["/blog/".to_string()];
+        assert_eq!(jobs.pause(&domain, Some(&scope)), vec![blog]);
+        assert_eq!(jobs.status(blog).unwrap().state, JobState::Paused);
+        assert_eq!(jobs.status(docs).unwrap().state, JobState::Queued);
+        assert_eq!(jobs.active(&domain, Some(&[])).unwrap().0, all);
+        // The oldest job of the domain when no scope is given.
+        assert_eq!(jobs.active(&domain, None).unwrap().0, blog);
+
+        jobs.finish(blog, CrawlOutcome::Completed);
+        assert!(jobs.active(&domain, Some(&scope)).is_none());
+        assert_eq!(jobs.cancel(&domain, None), vec![docs, all]);
+        assert_eq!(jobs.active_count(), 0);
+        assert_eq!(jobs.status(all).unwrap().state, JobState::Cancelled);
+    }
+}
@@ -0,0 +1,96 @@
+//! A web crawler, usable as a library or through the webserver of the `web-crawler-server`
+//! binary.
+//!
+//! A `Crawler` crawls one domain, storing the URLs it finds in a `Db`:
+//!
+//! ```no_run
+//! use tokio_util::sync::CancellationToken;
+//! use url::Url;
+//! use web_crawler_server::{Crawler, CrawlerConfig, Db};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let config = CrawlerConfig::default().max_pages(100).max_depth(3);
+//! let mut crawler = Crawler::new(Url::parse("https://example.com")?, config)?;
+//!
+//! let db = Db::default();
+//! let summary = crawler.crawl(db.clone(), CancellationToken::new()).await;
+//! println!("{:?}: {} pages", summary.outcome, summary.stats.fetched);
+//! println!("{:?}", db.unique_urls_for_domain(crawler.domain())?);
+//! # Ok(())
+//! # }
+//! ```
+
+// The server's warp filters nest deeply enough to go past the default limit.
+#![recursion_limit = "256"]
+
+mod artifacts;
+mod audit;
+mod crawler;
+mod db;
+mod diagnostics;
+mod downloader;
+mod egress;
+mod frontier;
+mod graph;
+mod histogram;
+mod jobs;
+mod normalize;
+mod overrides;
+mod parser;
+mod patterns;
+mod robots;
+mod schedules;
+mod search;
+mod seen_filter;
+mod server;
+mod settings;
+mod sink;
+mod sitemap;
+mod slow_start;
+mod summary;
+mod task;
+mod throttle;
+
+pub use crawler::{
+    CrawlControl, CrawlEvent, CrawlEventKind, CrawlProgress, CrawlReport, CrawlStats, Crawler,
+    CrawlerConfig, DevMode, ErrorPage, FailOn, LinkClass, ProbeRecommendation, ProbeReport,
+    ProgressCounters, SkipReason, SkippedUrl, UrlCheck, WarmStart,
+};
+pub use db::{
+    Asset, CrawlDiff, Db, DbError, DbTotals, DomainMeta, DumpError, DumpSummary, FetchStatus,
+    Lineage, MemoryUsage, OverflowStorage, SledStorage, Storage, StorageHealth, UrlDetails,
+    UrlQuery, UrlSort, MAX_REFERRERS,
+};
+pub use diagnostics::ContentIssue;
+pub use downloader::{
+    CircuitBreakerConfig, Credentials, DownloadError, Downloader, DownloaderConfig, ProxyConfig,
+    RetryConfig, Validators, DEFAULT_USER_AGENT,
+};
+pub use egress::EgressConfig;
+pub use frontier::FrontierOrder;
+pub use graph::{Edge, LinkGraph};
+pub use histogram::{Bucket, Histogram};
+pub use normalize::{NormalizeConfig, SessionIdRules};
+pub use overrides::DomainOverrides;
+pub use parser::{parse_selectors, AssetKind, Link, MetaRobots, Parser};
+pub use robots::RobotsMode;
+pub use search::SearchHit;
+pub use seen_filter::SeenFilter;
+pub use server::{server, spawn_server, ServerHandle};
+pub use settings::{BodyLimits, RateLimit, Settings, Tls};
+pub use sink::{Sink, SinkConfig};
+pub use slow_start::SlowStart;
+pub use summary::{CrawlOutcome, CrawlSummary, SUMMARY_VERSION};
+
+#[cfg(test)]
+pub(crate) mod tests {
+    pub(crate) fn compare_sorted<T>(mut first: Vec<T>, mut second: Vec<T>)
+    where
+        T: Ord + std::fmt::Debug,
+    {
+        first.sort();
+        second.sort();
+
+        assert_eq!(first, second)
+    }
+}
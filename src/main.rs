@@ -1,34 +1,180 @@
-use db::Db;
-use tokio;
+use std::{fs::File, io::Write, path::PathBuf};
 
-mod crawler;
-mod db;
-mod downloader;
-mod parser;
-mod server;
-mod task;
+use clap::{Parser, Subcommand};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+use url::Url;
+use web_crawler_server::{
+    server, Crawler, CrawlerConfig, Db, OverflowStorage, Settings, SledStorage,
+};
+
+#[derive(Debug, Parser)]
+#[clap(about = "Crawl websites, from the terminal or through an HTTP API")]
+struct Cli {
+    /// TOML file with the settings, e.g. the port and the default crawl options.
+    #[clap(long, env = "CRAWLER_CONFIG", global = true)]
+    config: Option<PathBuf>,
+    /// Keep the crawled URLs on disk at this path, in memory otherwise.
+    #[clap(long, env = "DB_PATH", global = true)]
+    db: Option<PathBuf>,
+    /// What to do, serving the API if not given.
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Serve the HTTP API, on port 3030 unless set otherwise.
+    Serve,
+    /// Crawl a domain once and write the URLs found.
+    Crawl {
+        /// Domain to crawl, e.g. `https://example.com`.
+        domain: Url,
+        /// Only follow links up to this many levels deep from the root.
+        #[clap(long)]
+        max_depth: Option<usize>,
+        /// Stop after fetching this many pages.
+        #[clap(long)]
+        max_pages: Option<usize>,
+        /// Download at most this many pages at once.
+        #[clap(long)]
+        max_concurrency: Option<usize>,
+        /// Crawl options as in the body of POST /domains, e.g. `{"delay_ms": 250}`, over the
+        /// ones of the settings. The other flags take precedence.
+        #[clap(long)]
+        options: Option<String>,
+        /// Write the URLs as JSON to this file instead of stdout.
+        #[clap(long)]
+        out: Option<PathBuf>,
+    },
+    /// Write the URLs stored for a domain, e.g. by a previous crawl with the same `--db`.
+    List {
+        /// Domain whose URLs to write, e.g. `https://example.com`.
+        domain: Url,
+        /// Write the URLs as JSON to this file instead of stdout.
+        #[clap(long)]
+        out: Option<PathBuf>,
+    },
+    /// Write a checksummed dump of the database, to be loaded elsewhere with `import`.
+    Export {
+        /// Only dump this domain, e.g. `https://example.com`, every domain if not given.
+        #[clap(long)]
+        domain: Option<Url>,
+        /// Write the dump to this file, e.g. `dump.ndjson.zst`, instead of stdout.
+        #[clap(long)]
+        out: Option<PathBuf>,
+    },
+    /// Load a dump written by `export`, adding to what is already stored. Nothing is loaded from
+    /// a corrupted dump.
+    Import {
+        /// The dump to load.
+        file: PathBuf,
+    },
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let cli = Cli::parse();
+    let settings = Settings::load(cli.config.as_deref())?;
+
+    // Logs go to stderr, so that the URLs written to stdout can be piped.
     tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
+        .with_max_level(settings.log_level()?)
+        .with_writer(std::io::stderr)
         .try_init()?;
 
-    let db = Db::default();
-    server::server(db).await;
+    let db = match &cli.db {
+        Some(path) => {
+            info!("Using database at {:?}", path);
+            let storage = SledStorage::open(path)?;
+            if settings.storage_overflow {
+                Db::new(OverflowStorage::new(storage))
+            } else {
+                Db::new(storage)
+            }
+        }
+        None => match settings.memory_max_urls {
+            Some(max_urls) => Db::with_memory_budget(max_urls),
+            None => Db::default(),
+        },
+    };
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => server(db, settings).await,
+        Command::Crawl {
+            domain,
+            max_depth,
+            max_pages,
+            max_concurrency,
+            options,
+            out,
+        } => {
+            let mut config: CrawlerConfig = match options {
+                Some(options) => settings
+                    .crawl
+                    .parse_options(serde_json::from_str(&options)?)?,
+                None => settings.crawl,
+            };
+            config = config.allow_local_sinks(true);
+            if let Some(max_depth) = max_depth {
+                config = config.max_depth(max_depth);
+            }
+            if let Some(max_pages) = max_pages {
+                config = config.max_pages(max_pages);
+            }
+            if let Some(max_concurrency) = max_concurrency {
+                config = config.max_concurrency(max_concurrency);
+            }
+            for (domain, overrides) in settings.domain_overrides {
+                config = config.domain_override(domain, overrides);
+            }
+
+            let mut crawler = Crawler::new(domain.clone(), config)?;
+            let cancel = CancellationToken::new();
+            let ctrl_c = cancel.clone();
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    info!("Received Ctrl-C, stopping the crawl.");
+                    ctrl_c.cancel();
+                }
+            });
+
+            let summary = crawler.crawl(db.clone(), cancel).await;
+            info!("Crawl done: {:?}, {:?}", summary.outcome, summary.stats);
+            write_urls(&db, &domain, out)?;
+        }
+        Command::List { domain, out } => write_urls(&db, &domain, out)?,
+        Command::Export { domain, out } => {
+            let writer: Box<dyn Write> = match out {
+                Some(path) => Box::new(File::create(path)?),
+                None => Box::new(std::io::stdout()),
+            };
+            let summary = db.export(domain.as_ref(), writer)?;
+            info!("Exported {:?}", summary);
+        }
+        Command::Import { file } => {
+            // Local files are trusted, whatever their size.
+            let summary = db.import(File::open(file)?, u64::MAX)?;
+            info!("Imported {:?}", summary);
+        }
+    }
 
     Ok(())
 }
 
-#[cfg(test)]
-pub(crate) mod tests {
-    pub(crate) fn compare_sorted<T>(mut first: Vec<T>, mut second: Vec<T>)
-    where
-        T: Ord + std::fmt::Debug,
-    {
-        first.sort();
-        second.sort();
+/// Write the URLs stored for `domain` as a JSON array, to `out` or stdout.
+fn write_urls(
+    db: &Db,
+    domain: &Url,
+    out: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let urls = db.unique_urls_for_domain(domain)?;
+    let mut writer: Box<dyn Write> = match out {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    serde_json::to_writer_pretty(&mut writer, &urls)?;
+    writeln!(writer)?;
 
-        assert_eq!(first, second)
-    }
+    Ok(())
 }
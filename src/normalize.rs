@@ -0,0 +1,372 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Rules used to canonicalize URLs before they are deduplicated and stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NormalizeConfig {
+    /// Drop the `#fragment` part of the URL.
+    pub strip_fragment: bool,
+    /// Keep the client-side routes of single-page apps, `#/route` or the `#!/route` hashbang,
+    /// whatever `strip_fragment` says, so that each route is a URL of its own. Hashbangs become
+    /// `#/route`, and `strip_trailing_slash` applies to the route as well.
+    pub fragment_routes: bool,
+    /// Drop the whole query string.
+    pub strip_query: bool,
+    /// Query parameters to remove. A trailing `*` matches by prefix, e.g. `utm_*`.
+    pub remove_params: Vec<String>,
+    /// Sort the remaining query parameters by key.
+    pub sort_query: bool,
+    /// Remove the trailing slash from non-root paths.
+    pub strip_trailing_slash: bool,
+    /// Decode the percent-encoded unreserved characters of the path and query, e.g. `/%7Efoo` to
+    /// `/~foo`, and uppercase the other escapes. Default ports are always dropped.
+    pub normalize_escapes: bool,
+    /// Session identifiers embedded in the path, stripped before any other rule.
+    pub session_ids: SessionIdRules,
+}
+
+/// Patterns of session identifiers embedded in paths, e.g. `/cart;jsessionid=1F2E3D`, which make
+/// every visit of a page look like a new URL.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionIdRules {
+    /// `;name=value` path parameters to remove, e.g. `jsessionid`. A trailing `*` matches by prefix.
+    pub path_params: Vec<String>,
+    /// Remove path segments made only of hex digits that are at least this long.
+    pub hex_segment_len: Option<usize>,
+}
+
+impl SessionIdRules {
+    /// Strips the session identifiers from the path of `url`. Returns whether any was found.
+    fn strip(&self, url: &mut Url) -> bool {
+        if self.path_params.is_empty() && self.hex_segment_len.is_none() {
+            return false;
+        }
+
+        let mut stripped = false;
+        let segments: Vec<String> = url
+            .path()
+            .split('/')
+            .filter_map(|segment| {
+                let mut parts = segment.split(';');
+                let name = parts.next().unwrap_or_default();
+                let params: Vec<&str> = parts
+                    .filter(|param| {
+                        let key = param.split('=').next().unwrap_or_default();
+                        let keep = !self.path_params.iter().any(|p| matches(p, key));
+                        stripped |= !keep;
+                        keep
+                    })
+                    .collect();
+
+                if self.is_hex_id(name) {
+                    stripped = true;
+                    return None;
+                }
+
+                Some(
+                    std::iter::once(name)
+                        .chain(params)
+                        .collect::<Vec<_>>()
+                        .join(";"),
+                )
+            })
+            .collect();
+
+        if stripped {
+            url.set_path(&segments.join("/"));
+        }
+        stripped
+    }
+
+    fn is_hex_id(&self, segment: &str) -> bool {
+        self.hex_segment_len.is_some_and(|len| {
+            segment.len() >= len && segment.chars().all(|c| c.is_ascii_hexdigit())
+        })
+    }
+}
+
+impl Default for NormalizeConfig {
+    fn default() -> Self {
+        Self {
+            strip_fragment: true,
+            fragment_routes: false,
+            strip_query: false,
+            remove_params: Vec::new(),
+            sort_query: false,
+            strip_trailing_slash: false,
+            normalize_escapes: true,
+            session_ids: SessionIdRules::default(),
+        }
+    }
+}
+
+impl NormalizeConfig {
+    /// Returns the canonical form of `url` according to these rules.
+    pub fn normalize(&self, url: &Url) -> Url {
+        self.rewrite(url).0
+    }
+
+    /// Like `normalize`, but also returns whether a session identifier was stripped.
+    pub fn rewrite(&self, url: &Url) -> (Url, bool) {
+        let mut url = url.clone();
+        let session_id = self.session_ids.strip(&mut url);
+        if self.normalize_escapes {
+            if let Cow::Owned(path) = normalize_escapes(url.path()) {
+                url.set_path(&path);
+            }
+            if let Some(Cow::Owned(query)) = url.query().map(normalize_escapes) {
+                url.set_query(Some(&query));
+            }
+        }
+
+        match self.fragment_route(&url) {
+            Some(route) => url.set_fragment(Some(&route)),
+            None if self.strip_fragment => url.set_fragment(None),
+            None => {}
+        }
+
+        if self.strip_query {
+            url.set_query(None);
+        } else if url.query().is_some() {
+            let mut pairs: Vec<(String, String)> = url
+                .query_pairs()
+                .filter(|(key, _)| !self.is_removed_param(key))
+                .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                .collect();
+
+            if self.sort_query {
+                pairs.sort();
+            }
+
+            if pairs.is_empty() {
+                url.set_query(None);
+            } else if self.sort_query || !self.remove_params.is_empty() {
+                url.query_pairs_mut().clear().extend_pairs(pairs);
+            }
+        }
+
+        if self.strip_trailing_slash && url.path().len() > 1 && url.path().ends_with('/') {
+            let path = url.path().trim_end_matches('/').to_string();
+            url.set_path(&path);
+        }
+
+        (url, session_id)
+    }
+
+    /// The canonical `/route` of the fragment of `url`, if it is one and `fragment_routes` is set.
+    /// The root route `/` is the page itself, and is left out.
+    fn fragment_route(&self, url: &Url) -> Option<String> {
+        if !self.fragment_routes {
+            return None;
+        }
+
+        let fragment = url.fragment()?;
+        let mut route = fragment.strip_prefix('!').unwrap_or(fragment);
+        if !route.starts_with('/') {
+            return None;
+        }
+        if self.strip_trailing_slash {
+            route = route.trim_end_matches('/');
+        }
+
+        match route {
+            "" | "/" => None,
+            route => Some(route.to_string()),
+        }
+    }
+
+    fn is_removed_param(&self, key: &str) -> bool {
+        self.remove_params.iter().any(|param| matches(param, key))
+    }
+}
+
+/// Decode the percent-encoded unreserved characters of `s`, e.g. `%7E` to `~`, and uppercase the
+/// hex digits of the other escapes, e.g. `%2f` to `%2F`, which does not change the URL it is part
+/// of (RFC 3986, section 6.2.2).
+pub(crate) fn normalize_escapes(s: &str) -> Cow<'_, str> {
+    if !s.contains('%') {
+        return Cow::Borrowed(s);
+    }
+
+    let mut normalized = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(at) = rest.find('%') {
+        normalized.push_str(&rest[..at]);
+        let escape = &rest[at..];
+        match escape
+            .get(1..3)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        {
+            Some(hex) => {
+                let byte = u8::from_str_radix(hex, 16).unwrap();
+                if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                    normalized.push(byte as char);
+                } else {
+                    normalized.push('%');
+                    normalized.push_str(&hex.to_ascii_uppercase());
+                }
+                rest = &escape[3..];
+            }
+            None => {
+                normalized.push('%');
+                rest = &escape[1..];
+            }
+        }
+    }
+    normalized.push_str(rest);
+
+    Cow::Owned(normalized)
+}
+
+/// Whether `key` matches `pattern`. A trailing `*` in the pattern matches by prefix.
+fn matches(pattern: &str, key: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => key.starts_with(prefix),
+        None => key == pattern,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{normalize_escapes, NormalizeConfig, SessionIdRules};
+
+    fn normalize(config: &NormalizeConfig, url: &str) -> String {
+        config.normalize(&Url::parse(url).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_default_strips_fragment_only() {
+        let config = NormalizeConfig::default();
+
+        assert_eq!(
+            normalize(&config, "https://example.com/foo/?b=2&a=1#section"),
+            "https://example.com/foo/?b=2&a=1"
+        );
+    }
+
+    #[test]
+    fn test_escapes() {
+        let config = NormalizeConfig::default();
+
+        assert_eq!(
+            normalize(&config, "http://example.com:80/%7efoo/%2f%41?q=%61%20b"),
+            "http://example.com/~foo/%2FA?q=a%20b"
+        );
+        assert_eq!(normalize_escapes("100%"), "100%");
+        assert_eq!(normalize_escapes("%zz%4"), "%zz%4");
+        assert_eq!(normalize_escapes("%c3%a9"), "%C3%A9");
+
+        let config = NormalizeConfig {
+            normalize_escapes: false,
+            ..NormalizeConfig::default()
+        };
+        assert_eq!(
+            normalize(&config, "https://example.com/%7efoo"),
+            "https://example.com/%7efoo"
+        );
+    }
+
+    #[test]
+    fn test_fragment_routes() {
+        let config = NormalizeConfig {
+            fragment_routes: true,
+            strip_trailing_slash: true,
+            ..NormalizeConfig::default()
+        };
+
+        assert_eq!(
+            normalize(&config, "https://example.com/#/products/123/"),
+            "https://example.com/#/products/123"
+        );
+        assert_eq!(
+            normalize(&config, "https://example.com/app#!/products/123"),
+            "https://example.com/app#/products/123"
+        );
+        assert_eq!(
+            normalize(&config, "https://example.com/#/"),
+            "https://example.com/"
+        );
+        assert_eq!(
+            normalize(&config, "https://example.com/faq#pricing"),
+            "https://example.com/faq"
+        );
+
+        // Routes are stripped like any other fragment by default.
+        assert_eq!(
+            normalize(
+                &NormalizeConfig::default(),
+                "https://example.com/#/products/123"
+            ),
+            "https://example.com/"
+        );
+    }
+
+    #[test]
+    fn test_query_rules() {
+        let config = NormalizeConfig {
+            remove_params: vec!["utm_*".to_string(), "ref".to_string()],
+            sort_query: true,
+            strip_trailing_slash: true,
+            ..NormalizeConfig::default()
+        };
+
+        assert_eq!(
+            normalize(
+                &config,
+                "https://example.com/foo/?utm_source=x&b=2&ref=y&a=1&referrer=z"
+            ),
+            "https://example.com/foo?a=1&b=2&referrer=z"
+        );
+        assert_eq!(
+            normalize(&config, "https://example.com/?utm_medium=email"),
+            "https://example.com/"
+        );
+
+        let config = NormalizeConfig {
+            strip_query: true,
+            ..NormalizeConfig::default()
+        };
+        assert_eq!(
+            normalize(&config, "https://example.com/foo?a=1"),
+            "https://example.com/foo"
+        );
+    }
+
+    #[test]
+    fn test_session_ids() {
+        let config = NormalizeConfig {
+            session_ids: SessionIdRules {
+                path_params: vec!["jsessionid".to_string(), "sid*".to_string()],
+                hex_segment_len: Some(16),
+            },
+            ..NormalizeConfig::default()
+        };
+        let rewrite = |url: &str| {
+            let (url, rewritten) = config.rewrite(&Url::parse(url).unwrap());
+            (url.to_string(), rewritten)
+        };
+
+        assert_eq!(
+            rewrite("https://example.com/cart;jsessionid=1F2E3D?item=1"),
+            ("https://example.com/cart?item=1".to_string(), true)
+        );
+        assert_eq!(
+            rewrite("https://example.com/a;v=2;sid_x=1/b"),
+            ("https://example.com/a;v=2/b".to_string(), true)
+        );
+        assert_eq!(
+            rewrite("https://example.com/0123456789abcdef0123/page"),
+            ("https://example.com/page".to_string(), true)
+        );
+        assert_eq!(
+            rewrite("https://example.com/cafe/deadbeef"),
+            ("https://example.com/cafe/deadbeef".to_string(), false)
+        );
+    }
+}
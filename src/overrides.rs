@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::crawler::CrawlerConfig;
+
+/// Politeness settings enforced on every crawl of a domain and its subdomains, whatever its
+/// options, e.g. gentler ones for a partner site known to be fragile:
+///
+/// ```toml
+/// [domain_overrides."partner.example.com"]
+/// delay_ms = 2000
+/// max_concurrency = 1
+/// user_agent = "examplebot/1.0 (+https://example.com/bot)"
+/// headers = { From = "crawler@example.com" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DomainOverrides {
+    /// Minimum delay between the start of two requests, in milliseconds. A longer `delay_ms` of
+    /// the crawl is kept.
+    pub delay_ms: Option<u64>,
+    /// Maximum number of downloads at once. A lower `max_concurrency` of the crawl is kept.
+    pub max_concurrency: Option<usize>,
+    /// `User-Agent` header sent instead of the one of the crawl.
+    pub user_agent: Option<String>,
+    /// Headers sent along with the ones of the crawl, replacing those of the same name, e.g.
+    /// `From` or `Accept-Language`.
+    pub headers: BTreeMap<String, String>,
+}
+
+impl DomainOverrides {
+    /// Enforce these settings on the crawl `config`.
+    pub(crate) fn apply(&self, config: &mut CrawlerConfig) {
+        if let Some(delay_ms) = self.delay_ms {
+            config.delay_ms = Some(config.delay_ms.map_or(delay_ms, |d| d.max(delay_ms)));
+        }
+        if let Some(max) = self.max_concurrency {
+            config.max_concurrency = Some(config.max_concurrency.map_or(max, |m| m.min(max)));
+        }
+        if let Some(user_agent) = &self.user_agent {
+            config.downloader.user_agent = Some(user_agent.clone());
+        }
+        for (name, value) in &self.headers {
+            config
+                .downloader
+                .headers
+                .retain(|header, _| !header.eq_ignore_ascii_case(name));
+            config
+                .downloader
+                .headers
+                .insert(name.clone(), value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DomainOverrides;
+    use crate::crawler::CrawlerConfig;
+
+    #[test]
+    fn test_apply() {
+        let overrides = DomainOverrides {
+            delay_ms: Some(1000),
+            max_concurrency: Some(2),
+            user_agent: Some("gentlebot".to_string()),
+            headers: [("From".to_string(), "ops@example.com".to_string())].into(),
+        };
+
+        let mut config = CrawlerConfig::default().delay_ms(100).max_concurrency(8);
+        config
+            .downloader
+            .headers
+            .insert("from".to_string(), "me@example.com".to_string());
+        overrides.apply(&mut config);
+        assert_eq!(config.delay_ms, Some(1000));
+        assert_eq!(config.max_concurrency, Some(2));
+        assert_eq!(config.downloader.user_agent.as_deref(), Some("gentlebot"));
+        assert_eq!(
+            config.downloader.headers,
+            [("From".to_string(), "ops@example.com".to_string())].into()
+        );
+
+        // Gentler crawl options are kept.
+        let mut config = CrawlerConfig::default().delay_ms(5000).max_concurrency(1);
+        overrides.apply(&mut config);
+        assert_eq!(config.delay_ms, Some(5000));
+        assert_eq!(config.max_concurrency, Some(1));
+
+        let mut config = CrawlerConfig::default();
+        DomainOverrides::default().apply(&mut config);
+        assert_eq!(config.delay_ms, None);
+        assert_eq!(config.max_concurrency, None);
+        assert_eq!(config.downloader.user_agent, None);
+    }
+}
@@ -1,32 +1,248 @@
+use std::collections::HashSet;
+
 use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+
+/// A link of a parsed HTML document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link<'a> {
+    pub href: &'a str,
+    /// The `rel` attribute, e.g. `nofollow noopener`.
+    pub rel: Option<&'a str>,
+    /// Whether the link is inside an element matching one of the `exclude` selectors.
+    pub excluded: bool,
+}
+
+impl Link<'_> {
+    /// Whether the page asks crawlers not to follow the link, with `rel="nofollow"`.
+    pub fn is_nofollow(&self) -> bool {
+        self.rel.is_some_and(|rel| {
+            rel.split_ascii_whitespace()
+                .any(|value| value.eq_ignore_ascii_case("nofollow"))
+        })
+    }
+}
+
+/// Directives of the `<meta name="robots">` tags of a document.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MetaRobots {
+    /// The page asks not to be indexed, with `noindex` or `none`.
+    pub noindex: bool,
+    /// The page asks for none of its links to be followed, with `nofollow` or `none`.
+    pub nofollow: bool,
+}
+
+/// Kind of a resource a document loads or refers to, other than the pages it links to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetKind {
+    /// `<img src>`, and the candidates of `srcset` attributes.
+    Image,
+    /// `<script src>`.
+    Script,
+    /// `<link rel="stylesheet" href>`.
+    Stylesheet,
+    /// Any other `<link href>`, e.g. an icon or a preloaded font.
+    Link,
+    /// `<iframe src>`.
+    Iframe,
+}
 
 /// HTML parser
 #[derive(Debug)]
-pub(crate) struct Parser {
+pub struct Parser {
     selector: Selector,
     html: Html,
 }
 
 impl Parser {
     /// Create a new parser for `html`.
-    pub(crate) fn new(html: &str) -> Self {
+    pub fn new(html: &str) -> Self {
         Self {
             selector: Selector::parse("a").unwrap(),
             html: Html::parse_document(html),
         }
     }
 
-    /// Returns an iterator over the URLs in the parsed HTML.
-    pub(crate) fn extract_urls(&self) -> impl Iterator<Item = &str> {
-        self.html
-            .select(&self.selector)
-            .filter_map(|el| el.value().attr("href"))
+    /// The text of the `<title>` of the document, with its whitespace collapsed, `None` if it has
+    /// none or it is empty.
+    pub fn title(&self) -> Option<String> {
+        let selector = Selector::parse("title").unwrap();
+        let title = self.html.select(&selector).next()?;
+        let text: Vec<_> = title.text().flat_map(str::split_whitespace).collect();
+
+        (!text.is_empty()).then(|| text.join(" "))
+    }
+
+    /// The visible text of the `<body>` of the document, with its whitespace collapsed. The text
+    /// of scripts, styles and templates is left out.
+    pub fn text(&self) -> String {
+        let selector = Selector::parse("body").unwrap();
+        let body = match self.html.select(&selector).next() {
+            Some(body) => body,
+            None => return String::new(),
+        };
+        let words: Vec<_> = body
+            .descendants()
+            .filter_map(|node| {
+                let text = node.value().as_text()?;
+                let hidden = node.ancestors().any(|ancestor| {
+                    ancestor.value().as_element().is_some_and(|el| {
+                        matches!(el.name(), "script" | "style" | "noscript" | "template")
+                    })
+                });
+                (!hidden).then_some(&**text)
+            })
+            .flat_map(str::split_whitespace)
+            .collect();
+
+        words.join(" ")
+    }
+
+    /// The `href` of the first `<base>` element of the document, that relative links are resolved
+    /// against, `None` if it has none or it is empty.
+    pub fn base_href(&self) -> Option<&str> {
+        let selector = Selector::parse("base[href]").unwrap();
+        let href = self
+            .html
+            .select(&selector)
+            .next()?
+            .value()
+            .attr("href")?
+            .trim();
+
+        (!href.is_empty()).then_some(href)
+    }
+
+    /// The resources the document loads or refers to, e.g. images and scripts, in document order.
+    /// Every candidate of the `srcset` of `<img>` and `<source>` elements is an image.
+    pub fn assets(&self) -> Vec<(AssetKind, &str)> {
+        let selector =
+            Selector::parse("img, script[src], link[href], iframe[src], source").unwrap();
+        let mut assets = Vec::new();
+        for el in self.html.select(&selector) {
+            let el = el.value();
+            let (kind, url) = match el.name() {
+                "img" => (AssetKind::Image, el.attr("src")),
+                "script" => (AssetKind::Script, el.attr("src")),
+                "iframe" => (AssetKind::Iframe, el.attr("src")),
+                "link" => {
+                    let stylesheet = el.attr("rel").is_some_and(|rel| {
+                        rel.split_ascii_whitespace()
+                            .any(|value| value.eq_ignore_ascii_case("stylesheet"))
+                    });
+                    let kind = if stylesheet {
+                        AssetKind::Stylesheet
+                    } else {
+                        AssetKind::Link
+                    };
+                    (kind, el.attr("href"))
+                }
+                // `<source>` only has a `srcset`.
+                _ => (AssetKind::Image, None),
+            };
+            assets.extend(
+                url.map(str::trim)
+                    .filter(|url| !url.is_empty())
+                    .map(|url| (kind, url)),
+            );
+
+            // Candidates are separated by commas, each being an URL and an optional descriptor.
+            let candidates = el
+                .attr("srcset")
+                .into_iter()
+                .flat_map(|srcset| srcset.split(','))
+                .filter_map(|candidate| candidate.split_ascii_whitespace().next());
+            assets.extend(candidates.map(|url| (AssetKind::Image, url)));
+        }
+
+        assets
+    }
+
+    /// The directives of the `<meta name="robots">` tags of the document, e.g.
+    /// `<meta name="robots" content="noindex, nofollow">`.
+    pub fn meta_robots(&self) -> MetaRobots {
+        let selector = Selector::parse("meta[name][content]").unwrap();
+        let mut robots = MetaRobots::default();
+        let directives = self
+            .html
+            .select(&selector)
+            .filter(|el| {
+                el.value()
+                    .attr("name")
+                    .is_some_and(|name| name.trim().eq_ignore_ascii_case("robots"))
+            })
+            .filter_map(|el| el.value().attr("content"))
+            .flat_map(|content| content.split(','))
+            .map(|directive| directive.trim().to_ascii_lowercase());
+        for directive in directives {
+            match directive.as_str() {
+                "noindex" => robots.noindex = true,
+                "nofollow" => robots.nofollow = true,
+                "none" => {
+                    robots.noindex = true;
+                    robots.nofollow = true;
+                }
+                _ => {}
+            }
+        }
+
+        robots
+    }
+
+    /// Returns an iterator over the URLs in the parsed HTML, leaving out the links inside
+    /// elements matching one of the `exclude` selectors, e.g. `nav` or `.footer`.
+    pub fn extract_urls(&self, exclude: &[Selector]) -> impl Iterator<Item = &str> {
+        self.extract_links(exclude)
+            .filter(|(_, excluded)| !excluded)
+            .map(|(url, _)| url)
+    }
+
+    /// Returns an iterator over the URLs in the parsed HTML, each with whether the link is inside
+    /// an element matching one of the `exclude` selectors.
+    pub fn extract_links(&self, exclude: &[Selector]) -> impl Iterator<Item = (&str, bool)> {
+        self.links(exclude).map(|link| (link.href, link.excluded))
+    }
+
+    /// Returns an iterator over the links in the parsed HTML, with their attributes.
+    pub fn links(&self, exclude: &[Selector]) -> impl Iterator<Item = Link<'_>> {
+        let excluded: HashSet<_> = exclude
+            .iter()
+            .flat_map(|selector| self.html.select(selector))
+            .map(|el| el.id())
+            .collect();
+
+        self.html.select(&self.selector).filter_map(move |el| {
+            let href = el.value().attr("href")?;
+            let is_excluded = !excluded.is_empty()
+                && std::iter::once(*el)
+                    .chain(el.ancestors())
+                    .any(|node| excluded.contains(&node.id()));
+
+            Some(Link {
+                href,
+                rel: el.value().attr("rel"),
+                excluded: is_excluded,
+            })
+        })
     }
 }
 
+/// Parse CSS selectors, e.g. the ones of sections excluded from link extraction.
+pub fn parse_selectors(selectors: &[String]) -> anyhow::Result<Vec<Selector>> {
+    selectors
+        .iter()
+        .map(|selector| {
+            Selector::parse(selector)
+                .map_err(|e| anyhow::anyhow!("Invalid selector {:?}: {:?}", selector, e.kind))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Parser;
+    use super::{parse_selectors, AssetKind, MetaRobots, Parser};
+    use crate::tests::compare_sorted;
 
     #[test]
     fn test_basic() {
@@ -38,14 +254,170 @@ mod tests {
     <body>
         <h1>HTML</h1>
         <a href="/foo">Go</a>
-        <a href="https://example.com/bar>Go absolute</a>
+        <a href="https://example.com/bar">Go absolute</a>
+    </body>
+</html>
+"#;
+
+        let parser = Parser::new(html);
+        let expected = vec!["/foo", "https://example.com/bar"];
+        let urls: Vec<&str> = parser.extract_urls(&[]).collect();
+        compare_sorted(urls, expected);
+        assert_eq!(parser.title().as_deref(), Some("HTML!"));
+
+        let parser = Parser::new("<title>\n  Two\n  lines </title><p>No title</p>");
+        assert_eq!(parser.title().as_deref(), Some("Two lines"));
+        assert_eq!(Parser::new("<p>No title</p>").title(), None);
+        assert_eq!(parser.text(), "No title");
+    }
+
+    #[test]
+    fn test_text() {
+        let html = r#"
+<html>
+    <head><title>Not in the body</title><style>p { color: red; }</style></head>
+    <body>
+        <h1>Hello,
+            world</h1>
+        <script>var hidden = true;</script>
+        <p>Some <b>bold</b> text.</p>
+        <noscript>Enable JavaScript</noscript>
+    </body>
+</html>
+"#;
+
+        assert_eq!(Parser::new(html).text(), "Hello, world Some bold text.");
+    }
+
+    #[test]
+    fn test_exclude() {
+        let html = r#"
+<html>
+    <body>
+        <nav><ul><li><a href="/menu">Menu</a></li></ul></nav>
+        <a href="/foo">Go</a>
+        <div class="footer"><a href="/legal">Legal</a></div>
+        <a id="skip" href="/skip">Skip</a>
+    </body>
+</html>
+"#;
+
+        let parser = Parser::new(html);
+        let exclude = parse_selectors(&[
+            "nav".to_string(),
+            ".footer".to_string(),
+            "#skip".to_string(),
+        ])
+        .unwrap();
+        let urls: Vec<&str> = parser.extract_urls(&exclude).collect();
+        assert_eq!(urls, vec!["/foo"]);
+        let excluded = parser
+            .extract_links(&exclude)
+            .filter(|(_, excluded)| *excluded)
+            .count();
+        assert_eq!(excluded, 3);
+
+        assert!(parse_selectors(&["a[".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_robots_hints() {
+        let html = r#"
+<html>
+    <head>
+        <meta name="Robots" content="NOINDEX">
+        <meta name="description" content="nofollow">
+    </head>
+    <body>
+        <a href="/foo">Go</a>
+        <a href="/ad" rel="sponsored NoFollow">Ad</a>
+    </body>
+</html>
+"#;
+
+        let parser = Parser::new(html);
+        let nofollow: Vec<_> = parser
+            .links(&[])
+            .filter(|link| link.is_nofollow())
+            .map(|link| link.href)
+            .collect();
+        assert_eq!(nofollow, vec!["/ad"]);
+        assert_eq!(
+            parser.meta_robots(),
+            MetaRobots {
+                noindex: true,
+                nofollow: false
+            }
+        );
+
+        let parser = Parser::new(r#"<meta name="robots" content="none">"#);
+        assert!(parser.meta_robots().noindex && parser.meta_robots().nofollow);
+        let parser = Parser::new(r#"<meta name="robots" content="index, nofollow">"#);
+        assert!(!parser.meta_robots().noindex && parser.meta_robots().nofollow);
+        assert_eq!(
+            Parser::new("<p>Hi</p>").meta_robots(),
+            MetaRobots::default()
+        );
+    }
+
+    #[test]
+    fn test_base_href() {
+        let html = r#"
+<html>
+    <head>
+        <base href=" https://example.com/sub/ " target="_blank">
+        <base href="/other/">
+    </head>
+    <body><a href="foo">Go</a></body>
+</html>
+"#;
+
+        assert_eq!(
+            Parser::new(html).base_href(),
+            Some("https://example.com/sub/")
+        );
+        assert_eq!(Parser::new(r#"<base target="_blank">"#).base_href(), None);
+        assert_eq!(Parser::new(r#"<base href="">"#).base_href(), None);
+        assert_eq!(Parser::new("<a href=\"foo\">Go</a>").base_href(), None);
+    }
+
+    #[test]
+    fn test_assets() {
+        let html = r#"
+<html>
+    <head>
+        <link rel="Stylesheet" href="/main.css">
+        <link rel="icon" href="/favicon.ico">
+        <script src="/app.js"></script>
+        <script>inline()</script>
+    </head>
+    <body>
+        <img src="/logo.png" srcset="/logo-2x.png 2x, /logo-3x.png 3x">
+        <img alt="no source">
+        <picture><source srcset="/hero.webp"><img src=" /hero.jpg "></picture>
+        <iframe src="https://video.example.com/embed/1"></iframe>
+        <a href="/page">Page</a>
     </body>
 </html>
 "#;
 
         let parser = Parser::new(html);
-        let mut expected = vec!["/foo", "https://example.com/bar"];
-        let mut urls: Vec<&str> = parser.extract_urls().collect();
-        assert_eq!(urls.sort(), expected.sort());
+        assert_eq!(
+            parser.assets(),
+            vec![
+                (AssetKind::Stylesheet, "/main.css"),
+                (AssetKind::Link, "/favicon.ico"),
+                (AssetKind::Script, "/app.js"),
+                (AssetKind::Image, "/logo.png"),
+                (AssetKind::Image, "/logo-2x.png"),
+                (AssetKind::Image, "/logo-3x.png"),
+                (AssetKind::Image, "/hero.webp"),
+                (AssetKind::Image, "/hero.jpg"),
+                (AssetKind::Iframe, "https://video.example.com/embed/1"),
+            ]
+        );
+        assert!(Parser::new("<a href=\"/page\">Page</a>")
+            .assets()
+            .is_empty());
     }
 }
@@ -0,0 +1,90 @@
+use regex::Regex;
+use url::Url;
+
+/// Parsed `include` and `exclude` patterns of a crawl, matched against the path and query of
+/// URLs, e.g. `/docs/*` or `/search?q=*`.
+/// A pattern is a glob where `*` matches any characters, `/` included, unless it starts with
+/// `re:`, the rest being a regular expression, e.g. `re:^/\d{4}/`. Globs match the whole path
+/// and query, regular expressions any part of it.
+#[derive(Debug, Default)]
+pub(crate) struct UrlPatterns {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl UrlPatterns {
+    pub(crate) fn new(include: &[String], exclude: &[String]) -> anyhow::Result<Self> {
+        let parse = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    parse_pattern(pattern)
+                        .map_err(|e| anyhow::anyhow!("Invalid pattern {:?}: {}", pattern, e))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+
+        Ok(Self {
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+        })
+    }
+
+    /// Whether `url` matches one of the `include` patterns if there are any, and none of the
+    /// `exclude` ones.
+    pub(crate) fn allows(&self, url: &Url) -> bool {
+        let target = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+
+        (self.include.is_empty() || self.include.iter().any(|re| re.is_match(&target)))
+            && !self.exclude.iter().any(|re| re.is_match(&target))
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    if let Some(regex) = pattern.strip_prefix("re:") {
+        return Regex::new(regex);
+    }
+
+    let glob = pattern
+        .split('*')
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join(".*");
+    Regex::new(&format!("^{}$", glob))
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::UrlPatterns;
+
+    #[test]
+    fn test_allows() {
+        let patterns = UrlPatterns::new(
+            &["/docs/*".to_string(), r"re:^/\d{4}/".to_string()],
+            &["*/drafts/*".to_string(), "/docs/search?q=*".to_string()],
+        )
+        .unwrap();
+        let allows = |url: &str| patterns.allows(&Url::parse(url).unwrap());
+
+        assert!(allows("https://example.com/docs/"));
+        assert!(allows("https://example.com/docs/api/index.html"));
+        assert!(allows("https://example.com/2024/05/post"));
+        assert!(!allows("https://example.com/"));
+        assert!(!allows("https://example.com/docs"));
+        assert!(!allows("https://example.com/blog/2024/05/post"));
+        assert!(!allows("https://example.com/docs/drafts/new"));
+        assert!(!allows("https://example.com/docs/search?q=crawler"));
+        assert!(allows("https://example.com/docs/search"));
+
+        let patterns = UrlPatterns::new(&[], &["/wp-admin/*".to_string()]).unwrap();
+        assert!(patterns.allows(&Url::parse("https://example.com/about").unwrap()));
+        assert!(!patterns.allows(&Url::parse("https://example.com/wp-admin/post.php").unwrap()));
+
+        assert!(UrlPatterns::new(&["re:(".to_string()], &[]).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use std::time::Duration;
+
+use robotstxt::{
+    get_path_params_query,
+    matcher::{LongestMatchRobotsMatchStrategy, RobotsMatchStrategy},
+    parse_robotstxt, DefaultMatcher, RobotsParseHandler,
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Longest `Crawl-delay` honoured, so that a single `robots.txt` line cannot stall a crawl.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Directives of `robots.txt` that are not about which paths are allowed.
+/// Only the groups applying to every user agent (`*`) are taken into account, like for the rules.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct RobotsDirectives {
+    /// `Crawl-delay`, in seconds.
+    pub(crate) crawl_delay: Option<f64>,
+    /// `Request-rate`, in requests per second.
+    pub(crate) request_rate: Option<f64>,
+    /// `Sitemap` URLs, listed anywhere in the file.
+    pub(crate) sitemaps: Vec<String>,
+}
+
+impl RobotsDirectives {
+    pub(crate) fn parse(robots_txt: &str) -> Self {
+        let mut handler = Handler::default();
+        parse_robotstxt(robots_txt, &mut handler);
+
+        handler.directives
+    }
+
+    /// The minimum interval between two requests asked for by the site, if any.
+    pub(crate) fn interval(&self) -> Option<Duration> {
+        let delay = self
+            .crawl_delay
+            .filter(|delay| delay.is_finite() && *delay > 0.0)
+            .map(|delay| Duration::from_secs_f64(delay.min(MAX_DELAY.as_secs_f64())));
+        let rate = self
+            .request_rate
+            .filter(|rate| rate.is_finite() && *rate > 0.0)
+            .map(|rate| Duration::from_secs_f64((1.0 / rate).min(MAX_DELAY.as_secs_f64())));
+
+        delay.max(rate)
+    }
+}
+
+/// How a crawl treats `robots.txt`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RobotsMode {
+    /// Skip the URLs it disallows.
+    #[default]
+    Respect,
+    /// Neither fetch nor enforce it, e.g. for staging sites serving `Disallow: /` to keep search
+    /// engines out. The API only allows it for the `owned_domains` of the settings.
+    Ignore,
+    /// Fetch it and count the URLs it disallows, but fetch them anyway.
+    RecordOnly,
+}
+
+/// An `Allow` or `Disallow` line of `robots.txt`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct RobotsRule {
+    /// Line number in `robots.txt`, starting at 1.
+    pub(crate) line: u32,
+    pub(crate) allow: bool,
+    pub(crate) pattern: String,
+}
+
+/// Whether a URL may be fetched, with the rule that decided it. No rule means that nothing in
+/// `robots.txt` matched, so the URL is allowed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct RobotsDecision {
+    pub(crate) allowed: bool,
+    pub(crate) rule: Option<RobotsRule>,
+}
+
+/// Check `url` against `robots.txt` for every user agent (`*`).
+/// The decision is the one of the `robotstxt` matcher; the rule is the longest pattern matching
+/// the path, `Allow` winning ties, as the matcher does.
+pub(crate) fn check(robots_txt: &str, url: &Url) -> RobotsDecision {
+    let allowed = DefaultMatcher::default().allowed_by_robots(robots_txt, vec!["*"], url.as_str());
+
+    let mut handler = Handler::default();
+    parse_robotstxt(robots_txt, &mut handler);
+    let path = get_path_params_query(url.as_str());
+    let rule = handler
+        .rules
+        .into_iter()
+        .filter(|rule| {
+            !rule.pattern.is_empty()
+                && LongestMatchRobotsMatchStrategy::matches(&path, &rule.pattern)
+        })
+        .max_by_key(|rule| (rule.pattern.len(), rule.allow));
+
+    RobotsDecision { allowed, rule }
+}
+
+#[derive(Debug, Default)]
+struct Handler {
+    directives: RobotsDirectives,
+    // `Allow` and `Disallow` lines of the groups applying to all user agents.
+    rules: Vec<RobotsRule>,
+    // The current group of rules applies to all user agents.
+    applies: bool,
+    // A rule was seen since the last `User-agent` line, so the next one starts a new group.
+    in_rules: bool,
+}
+
+impl Handler {
+    fn rule(&mut self) {
+        self.in_rules = true;
+    }
+
+    fn path_rule(&mut self, line: u32, allow: bool, pattern: &str) {
+        self.rule();
+        if self.applies {
+            self.rules.push(RobotsRule {
+                line,
+                allow,
+                pattern: pattern.to_string(),
+            });
+        }
+    }
+}
+
+impl RobotsParseHandler for Handler {
+    fn handle_robots_start(&mut self) {}
+
+    fn handle_robots_end(&mut self) {}
+
+    fn handle_user_agent(&mut self, _line_num: u32, user_agent: &str) {
+        if self.in_rules {
+            self.applies = false;
+            self.in_rules = false;
+        }
+        self.applies |= user_agent.trim() == "*";
+    }
+
+    fn handle_allow(&mut self, line_num: u32, value: &str) {
+        self.path_rule(line_num, true, value);
+    }
+
+    fn handle_disallow(&mut self, line_num: u32, value: &str) {
+        self.path_rule(line_num, false, value);
+    }
+
+    fn handle_sitemap(&mut self, _line_num: u32, value: &str) {
+        self.directives.sitemaps.push(value.to_string());
+    }
+
+    fn handle_unknown_action(&mut self, _line_num: u32, action: &str, value: &str) {
+        self.rule();
+        if !self.applies {
+            return;
+        }
+
+        if action.eq_ignore_ascii_case("crawl-delay") {
+            self.directives.crawl_delay = value.trim().parse().ok();
+        } else if action.eq_ignore_ascii_case("request-rate") {
+            self.directives.request_rate = parse_request_rate(value);
+        }
+    }
+}
+
+/// Parse a `Request-rate` value such as `1/5` (one request every 5 seconds) or `30/1m`,
+/// into requests per second.
+fn parse_request_rate(value: &str) -> Option<f64> {
+    let (requests, period) = value.trim().split_once('/')?;
+    let requests: f64 = requests.trim().parse().ok()?;
+
+    let period = period.trim();
+    let (amount, unit) = match period.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => period.split_at(i),
+        None => (period, "s"),
+    };
+    let seconds = match unit.to_ascii_lowercase().as_str() {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return None,
+    };
+    let period: f64 = amount.trim().parse().ok()?;
+
+    Some(requests / (period * seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use url::Url;
+
+    use super::{check, RobotsDecision, RobotsDirectives, RobotsRule};
+
+    #[test]
+    fn test_parse() {
+        let robots_txt = "\
+User-agent: googlebot
+Crawl-delay: 1
+
+User-agent: other
+User-agent: *
+Disallow: /private
+Crawl-delay: 2.5
+Request-rate: 30/1m
+
+Sitemap: https://example.com/sitemap.xml
+";
+
+        let directives = RobotsDirectives::parse(robots_txt);
+
+        assert_eq!(
+            directives,
+            RobotsDirectives {
+                crawl_delay: Some(2.5),
+                request_rate: Some(0.5),
+                sitemaps: vec!["https://example.com/sitemap.xml".to_string()],
+            }
+        );
+        assert_eq!(directives.interval(), Some(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn test_interval() {
+        assert_eq!(RobotsDirectives::parse("").interval(), None);
+
+        let directives = RobotsDirectives::parse("User-agent: *\nRequest-rate: 1/5\n");
+        assert_eq!(directives.interval(), Some(Duration::from_secs(5)));
+
+        let directives = RobotsDirectives::parse("User-agent: *\nCrawl-delay: 86400\n");
+        assert_eq!(directives.interval(), Some(Duration::from_secs(60)));
+
+        let directives = RobotsDirectives::parse("User-agent: bot\nCrawl-delay: 10\n");
+        assert_eq!(directives.interval(), None);
+    }
+
+    #[test]
+    fn test_check() {
+        let robots_txt = "\
+User-agent: bot
+Disallow: /
+
+User-agent: *
+Disallow: /private
+Allow: /private/public
+";
+        let url = |path| {
+            Url::parse("https://example.com")
+                .unwrap()
+                .join(path)
+                .unwrap()
+        };
+
+        assert_eq!(
+            check(robots_txt, &url("/private/secret")),
+            RobotsDecision {
+                allowed: false,
+                rule: Some(RobotsRule {
+                    line: 5,
+                    allow: false,
+                    pattern: "/private".to_string(),
+                }),
+            }
+        );
+        assert_eq!(
+            check(robots_txt, &url("/private/public/page")),
+            RobotsDecision {
+                allowed: true,
+                rule: Some(RobotsRule {
+                    line: 6,
+                    allow: true,
+                    pattern: "/private/public".to_string(),
+                }),
+            }
+        );
+        assert_eq!(
+            check(robots_txt, &url("/about")),
+            RobotsDecision {
+                allowed: true,
+                rule: None,
+            }
+        );
+    }
+}
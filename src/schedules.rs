@@ -0,0 +1,327 @@
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{crawler::CrawlerConfig, jobs::JobId};
+
+/// Shortest interval between two runs of a schedule, cron expressions having minutes as their
+/// finest unit as well.
+pub(crate) const MIN_INTERVAL_SECS: u64 = 60;
+
+/// Longest interval between two runs of a schedule: a year.
+pub(crate) const MAX_INTERVAL_SECS: u64 = 366 * 24 * 60 * 60;
+
+/// Identifier of a schedule, unique for the lifetime of the server.
+pub(crate) type ScheduleId = u64;
+
+/// When a recurring crawl runs.
+#[derive(Debug, Clone)]
+pub(crate) enum Cadence {
+    /// On a standard cron expression of five fields, in UTC, e.g. `0 3 * * *` for every night at
+    /// 3:00, or on a shortcut such as `@daily`.
+    Cron(String, Box<cron::Schedule>),
+    /// Every given number of seconds, starting one interval after the schedule was registered.
+    Interval(u64),
+}
+
+impl Cadence {
+    /// The cadence of exactly one of a `cron` expression or an `interval_secs`.
+    pub(crate) fn new(cron: Option<String>, interval_secs: Option<u64>) -> Result<Self, String> {
+        match (cron, interval_secs) {
+            (Some(expression), None) => {
+                let fields = expression.split_whitespace().count();
+                // The parser wants a leading field for the seconds, which are always 0 here.
+                let parsed = match fields {
+                    5 => cron::Schedule::from_str(&format!("0 {}", expression)),
+                    1 if expression.starts_with('@') => cron::Schedule::from_str(&expression),
+                    _ => {
+                        return Err(format!(
+                            "Invalid cron expression {:?}: expected 5 fields, got {}",
+                            expression, fields
+                        ))
+                    }
+                };
+                parsed
+                    .map(|schedule| Cadence::Cron(expression.clone(), Box::new(schedule)))
+                    .map_err(|e| format!("Invalid cron expression {:?}: {}", expression, e))
+            }
+            (None, Some(secs)) if !(MIN_INTERVAL_SECS..=MAX_INTERVAL_SECS).contains(&secs) => {
+                Err(format!(
+                    "The interval must be between {} and {} seconds",
+                    MIN_INTERVAL_SECS, MAX_INTERVAL_SECS
+                ))
+            }
+            (None, Some(secs)) => Ok(Cadence::Interval(secs)),
+            _ => Err("Exactly one of cron and interval_secs must be given".to_string()),
+        }
+    }
+
+    /// The first time the schedule runs after `after`, if it ever does, and if that time can be
+    /// represented.
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Cadence::Cron(_, schedule) => schedule.after(&after).next(),
+            Cadence::Interval(secs) => {
+                let secs = i64::try_from((*secs).min(MAX_INTERVAL_SECS)).ok()?;
+                after.checked_add_signed(Duration::seconds(secs))
+            }
+        }
+    }
+}
+
+/// A recurring crawl registered in `Schedules`.
+#[derive(Debug)]
+struct Schedule {
+    domain: Url,
+    config: CrawlerConfig,
+    cadence: Cadence,
+    created_at: DateTime<Utc>,
+    next_run_at: Option<DateTime<Utc>>,
+    last_run_at: Option<DateTime<Utc>>,
+    last_job: Option<JobId>,
+    runs: usize,
+    skipped_runs: usize,
+}
+
+/// What the schedule requests return about a schedule.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ScheduleStatus {
+    pub(crate) id: ScheduleId,
+    pub(crate) domain: Url,
+    /// Path prefixes the crawls are restricted to, the whole domain if empty.
+    pub(crate) scope: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cron: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) interval_secs: Option<u64>,
+    pub(crate) created_at: DateTime<Utc>,
+    /// When the next crawl is due, none if the cron expression never matches again.
+    pub(crate) next_run_at: Option<DateTime<Utc>>,
+    pub(crate) last_run_at: Option<DateTime<Utc>>,
+    /// The job of the last crawl started, see GET /jobs/<id>.
+    pub(crate) last_job: Option<JobId>,
+    /// Number of crawls started.
+    pub(crate) runs: usize,
+    /// Number of crawls not started when they were due, e.g. as the domain was still being
+    /// crawled.
+    pub(crate) skipped_runs: usize,
+}
+
+/// A crawl of a schedule that is due.
+#[derive(Debug)]
+pub(crate) struct DueRun {
+    pub(crate) id: ScheduleId,
+    pub(crate) domain: Url,
+    pub(crate) config: CrawlerConfig,
+}
+
+#[derive(Debug, Default)]
+struct ScheduleTable {
+    last_id: ScheduleId,
+    schedules: BTreeMap<ScheduleId, Schedule>,
+}
+
+/// Thread-safe table of the recurring crawls. The schedules are only kept in memory, and have to
+/// be registered again after a restart.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Schedules(Arc<Mutex<ScheduleTable>>);
+
+impl Schedules {
+    /// Register a crawl of `domain` with `config`, recurring on `cadence` from `now`.
+    pub(crate) fn add(
+        &self,
+        domain: Url,
+        config: CrawlerConfig,
+        cadence: Cadence,
+        now: DateTime<Utc>,
+    ) -> ScheduleStatus {
+        let mut table = self.0.lock().unwrap();
+        table.last_id += 1;
+        let id = table.last_id;
+        let schedule = Schedule {
+            domain,
+            config,
+            next_run_at: cadence.next_after(now),
+            cadence,
+            created_at: now,
+            last_run_at: None,
+            last_job: None,
+            runs: 0,
+            skipped_runs: 0,
+        };
+        let status = status(id, &schedule);
+        table.schedules.insert(id, schedule);
+
+        status
+    }
+
+    /// All schedules, oldest first, optionally only the ones of `domain`.
+    pub(crate) fn list(&self, domain: Option<&Url>) -> Vec<ScheduleStatus> {
+        self.0
+            .lock()
+            .unwrap()
+            .schedules
+            .iter()
+            .filter(|(_, schedule)| domain.is_none_or(|domain| &schedule.domain == domain))
+            .map(|(&id, schedule)| status(id, schedule))
+            .collect()
+    }
+
+    /// Unregister the schedule `id`, returning its last status. Its running crawl, if any, goes on.
+    pub(crate) fn remove(&self, id: ScheduleId) -> Option<ScheduleStatus> {
+        self.0
+            .lock()
+            .unwrap()
+            .schedules
+            .remove(&id)
+            .map(|schedule| status(id, &schedule))
+    }
+
+    /// The crawls due at `now`, whose schedules then wait for their next run after `now`. Runs
+    /// missed meanwhile, e.g. while the server was busy, are not caught up on.
+    pub(crate) fn due(&self, now: DateTime<Utc>) -> Vec<DueRun> {
+        let mut table = self.0.lock().unwrap();
+        table
+            .schedules
+            .iter_mut()
+            .filter(|(_, schedule)| schedule.next_run_at.is_some_and(|next| next <= now))
+            .map(|(&id, schedule)| {
+                schedule.next_run_at = schedule.cadence.next_after(now);
+                DueRun {
+                    id,
+                    domain: schedule.domain.clone(),
+                    config: schedule.config.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Record that the schedule `id` started the crawl `job` at `now`.
+    pub(crate) fn started(&self, id: ScheduleId, job: JobId, now: DateTime<Utc>) {
+        if let Some(schedule) = self.0.lock().unwrap().schedules.get_mut(&id) {
+            schedule.last_run_at = Some(now);
+            schedule.last_job = Some(job);
+            schedule.runs += 1;
+        }
+    }
+
+    /// Record that a crawl of the schedule `id` was due, but not started.
+    pub(crate) fn skipped(&self, id: ScheduleId) {
+        if let Some(schedule) = self.0.lock().unwrap().schedules.get_mut(&id) {
+            schedule.skipped_runs += 1;
+        }
+    }
+}
+
+fn status(id: ScheduleId, schedule: &Schedule) -> ScheduleStatus {
+    let (cron, interval_secs) = match &schedule.cadence {
+        Cadence::Cron(expression, _) => (Some(expression.clone()), None),
+        Cadence::Interval(secs) => (None, Some(*secs)),
+    };
+
+    ScheduleStatus {
+        id,
+        domain: schedule.domain.clone(),
+        scope: schedule.config.scope.clone(),
+        cron,
+        interval_secs,
+        created_at: schedule.created_at,
+        next_run_at: schedule.next_run_at,
+        last_run_at: schedule.last_run_at,
+        last_job: schedule.last_job,
+        runs: schedule.runs,
+        skipped_runs: schedule.skipped_runs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use url::Url;
+
+    use super::{Cadence, Schedules};
+    use crate::crawler::CrawlerConfig;
+
+    #[test]
+    fn test_cadence() {
+        let now = Utc.ymd(2024, 5, 1).and_hms(10, 30, 15);
+
+        let nightly = Cadence::new(Some("0 3 * * *".to_string()), None).unwrap();
+        assert_eq!(
+            nightly.next_after(now),
+            Some(Utc.ymd(2024, 5, 2).and_hms(3, 0, 0))
+        );
+        let hourly = Cadence::new(Some("@hourly".to_string()), None).unwrap();
+        assert_eq!(
+            hourly.next_after(now),
+            Some(Utc.ymd(2024, 5, 1).and_hms(11, 0, 0))
+        );
+        let interval = Cadence::new(None, Some(3600)).unwrap();
+        assert_eq!(interval.next_after(now), Some(now + Duration::hours(1)));
+
+        assert!(Cadence::new(Some("0 0 3 * * *".to_string()), None).is_err());
+        assert!(Cadence::new(Some("0 25 * * *".to_string()), None).is_err());
+        assert!(Cadence::new(None, Some(10)).is_err());
+        assert!(Cadence::new(None, Some(u64::MAX)).is_err());
+        assert!(Cadence::new(None, Some(super::MAX_INTERVAL_SECS + 1)).is_err());
+        // Past the last representable time.
+        let interval = Cadence::Interval(u64::MAX);
+        assert_eq!(interval.next_after(chrono::MAX_DATETIME), None);
+        assert!(Cadence::new(None, None).is_err());
+        assert!(Cadence::new(Some("@daily".to_string()), Some(3600)).is_err());
+    }
+
+    #[test]
+    fn test_due() {
+        let schedules = Schedules::default();
+        let now = Utc.ymd(2024, 5, 1).and_hms(10, 0, 0);
+        let foo = Url::parse("https://foo.com").unwrap();
+        let bar = Url::parse("https://bar.com").unwrap();
+
+        let every_minute = schedules.add(
+            foo.clone(),
+            CrawlerConfig::default().scope("/docs/"),
+            Cadence::new(None, Some(60)).unwrap(),
+            now,
+        );
+        schedules.add(
+            bar,
+            CrawlerConfig::default(),
+            Cadence::new(Some("0 12 * * *".to_string()), None).unwrap(),
+            now,
+        );
+        assert_eq!(every_minute.scope, vec!["/docs/".to_string()]);
+        assert_eq!(every_minute.next_run_at, Some(now + Duration::minutes(1)));
+        assert!(schedules.due(now).is_empty());
+
+        // Runs missed are not caught up on.
+        let later = now + Duration::minutes(5);
+        let due = schedules.due(later);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, every_minute.id);
+        assert_eq!(due[0].domain, foo);
+        assert!(schedules.due(later).is_empty());
+
+        schedules.started(every_minute.id, 7, later);
+        schedules.skipped(every_minute.id);
+        let listed = schedules.list(Some(&foo));
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].last_job, Some(7));
+        assert_eq!(listed[0].last_run_at, Some(later));
+        assert_eq!(listed[0].runs, 1);
+        assert_eq!(listed[0].skipped_runs, 1);
+        assert_eq!(listed[0].next_run_at, Some(later + Duration::minutes(1)));
+        assert_eq!(schedules.list(None).len(), 2);
+
+        assert!(schedules.remove(every_minute.id).is_some());
+        assert!(schedules.remove(every_minute.id).is_none());
+        assert_eq!(schedules.due(later + Duration::hours(3)).len(), 1);
+    }
+}
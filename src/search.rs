@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Maximum number of bytes of the text of a page that are indexed, the rest being left out.
+pub(crate) const MAX_INDEXED_BYTES: usize = 64 * 1024;
+
+/// Terms longer than this, e.g. encoded data, are not indexed.
+const MAX_TERM_CHARS: usize = 64;
+
+/// Number of words of a snippet on each side of the first matching term.
+const SNIPPET_CONTEXT_WORDS: usize = 8;
+
+/// A page of a domain matching a search, see `Db::search`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub url: Url,
+    /// Number of occurences of the terms of the query in the text of the page.
+    pub score: usize,
+    /// The words of the page around the first of the terms it contains.
+    pub snippet: String,
+}
+
+/// The terms of `text` with their number of occurences: its lowercase alphanumeric words.
+pub(crate) fn terms(text: &str) -> HashMap<String, usize> {
+    let mut terms = HashMap::new();
+    for term in words(text) {
+        *terms.entry(term).or_default() += 1;
+    }
+
+    terms
+}
+
+/// The lowercase alphanumeric words of `text`, in order, the ones too long to be indexed left out.
+pub(crate) fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && word.chars().count() <= MAX_TERM_CHARS)
+        .map(str::to_lowercase)
+}
+
+/// `text` cut to at most `MAX_INDEXED_BYTES`, on a character boundary.
+pub(crate) fn truncate(text: &str) -> &str {
+    let mut end = text.len().min(MAX_INDEXED_BYTES);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    &text[..end]
+}
+
+/// The words of `text` around the first of `terms` it contains, `…` marking the words left out.
+pub(crate) fn snippet(text: &str, terms: &[String]) -> String {
+    let words: Vec<_> = text.split_whitespace().collect();
+    let first = words
+        .iter()
+        .position(|word| self::words(word).any(|term| terms.contains(&term)))
+        .unwrap_or_default();
+    let start = first.saturating_sub(SNIPPET_CONTEXT_WORDS);
+    let end = (first + SNIPPET_CONTEXT_WORDS + 1).min(words.len());
+
+    let mut snippet = words[start..end].join(" ");
+    if start > 0 {
+        snippet.insert_str(0, "… ");
+    }
+    if end < words.len() {
+        snippet.push_str(" …");
+    }
+
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{snippet, terms, truncate, MAX_INDEXED_BYTES};
+
+    #[test]
+    fn test_terms() {
+        let terms = terms("Rust, rust and RUST-lang: 2021 édition");
+        assert_eq!(terms["rust"], 3);
+        assert_eq!(terms["lang"], 1);
+        assert_eq!(terms["2021"], 1);
+        assert_eq!(terms["édition"], 1);
+        assert_eq!(terms.len(), 5);
+
+        let long = "é".repeat(MAX_INDEXED_BYTES);
+        assert_eq!(truncate(&long).len(), MAX_INDEXED_BYTES);
+        assert_eq!(truncate("short"), "short");
+    }
+
+    #[test]
+    fn test_snippet() {
+        let text = "one two three four five six seven eight nine ten Crawler, eleven twelve";
+        assert_eq!(
+            snippet(text, &["crawler".to_string()]),
+            "… three four five six seven eight nine ten Crawler, eleven twelve"
+        );
+        assert_eq!(
+            snippet("A short page", &["page".to_string()]),
+            "A short page"
+        );
+        assert_eq!(
+            snippet(&"word ".repeat(20), &["missing".to_string()]),
+            format!("{} …", ["word"; 9].join(" "))
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    borrow::Cow,
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::{error, info};
+use url::Url;
+
+use crate::db::Db;
+
+/// Maximum `SeenFilter::expected_urls`.
+const MAX_EXPECTED_URLS: usize = 1_000_000_000;
+
+/// Maximum size of a `BloomFilter`, in bits: 512 MiB.
+const MAX_BITS: f64 = (512u64 * 1024 * 1024 * 8) as f64;
+
+/// Probabilistic set of the URLs a crawl found, e.g. `{"expected_urls": 5000000}`, deciding which
+/// ones to fetch instead of the database, for domains too large to look every URL up in it.
+/// A URL never found before may be taken for one already found, and not fetched, with a
+/// probability of `false_positive_rate` once `expected_urls` URLs were found. URLs stored by
+/// previous crawls of the domain are not known to it, and are fetched again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeenFilter {
+    /// Number of URLs the crawl is expected to find, the filter being sized for it. More of them
+    /// raise the false positive rate.
+    pub expected_urls: usize,
+    /// Probability of taking a new URL for one already found, between 0 and 1.
+    pub false_positive_rate: f64,
+}
+
+impl Default for SeenFilter {
+    fn default() -> Self {
+        Self {
+            expected_urls: 1_000_000,
+            false_positive_rate: 0.001,
+        }
+    }
+}
+
+impl SeenFilter {
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        if self.expected_urls == 0 {
+            anyhow::bail!("The seen filter needs expected_urls above 0");
+        }
+        if self.expected_urls > MAX_EXPECTED_URLS {
+            anyhow::bail!(
+                "The seen filter takes at most {} expected_urls",
+                MAX_EXPECTED_URLS
+            );
+        }
+        if !(self.false_positive_rate > 0.0 && self.false_positive_rate < 1.0) {
+            anyhow::bail!("The false positive rate of the seen filter must be between 0 and 1");
+        }
+        if self.bits() > MAX_BITS {
+            anyhow::bail!(
+                "The seen filter would take more than {} MiB, raise its false positive rate",
+                MAX_BITS as u64 / 8 / 1024 / 1024
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Number of bits of a Bloom filter of `expected_urls` with `false_positive_rate`.
+    fn bits(&self) -> f64 {
+        let ln2 = std::f64::consts::LN_2;
+        (-(self.expected_urls as f64) * self.false_positive_rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(64.0)
+    }
+}
+
+/// Bloom filter of strings, sized for a number of items and a false positive rate.
+pub(crate) struct BloomFilter {
+    bits: Vec<u64>,
+    // Number of bits set per item.
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// A filter for `config`, capped to `MAX_BITS` if it was not validated.
+    pub(crate) fn new(config: &SeenFilter) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let bits = config.bits().min(MAX_BITS) as usize;
+        let hashes = ((bits as f64 / config.expected_urls as f64) * ln2)
+            .round()
+            .max(1.0) as u32;
+
+        Self {
+            bits: vec![0; bits.div_ceil(64)],
+            hashes,
+        }
+    }
+
+    /// Add `item`, returning `true` if it was not in the filter. Never `false` for a new item,
+    /// unless it is a false positive.
+    pub(crate) fn insert(&mut self, item: &str) -> bool {
+        let len = self.bits.len() as u64 * 64;
+        let (first, second) = hash(item);
+        let mut new = false;
+        for i in 0..u64::from(self.hashes) {
+            // Double hashing, see Kirsch and Mitzenmacher, "Less Hashing, Same Performance".
+            let bit = first.wrapping_add(i.wrapping_mul(second)) % len;
+            let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+            new |= self.bits[word] & mask == 0;
+            self.bits[word] |= mask;
+        }
+
+        new
+    }
+
+    /// Size of the filter, in bytes.
+    pub(crate) fn bytes(&self) -> usize {
+        self.bits.len() * 8
+    }
+}
+
+impl fmt::Debug for BloomFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BloomFilter")
+            .field("bytes", &self.bytes())
+            .field("hashes", &self.hashes)
+            .finish()
+    }
+}
+
+/// Two independent hashes of `item`, the second one odd so that it steps through every bit.
+fn hash(item: &str) -> (u64, u64) {
+    let mut hasher = DefaultHasher::new();
+    item.hash(&mut hasher);
+    let first = hasher.finish();
+    0xa5a5_a5a5_u32.hash(&mut hasher);
+    let second = hasher.finish() | 1;
+
+    (first, second)
+}
+
+/// The URLs found by a crawl with a `SeenFilter`. Their visits are recorded to the database in the
+/// background, for the crawl not to wait for it.
+#[derive(Debug)]
+pub(crate) struct SeenUrls {
+    filter: BloomFilter,
+    visits: mpsc::UnboundedSender<Url>,
+    recorder: JoinHandle<()>,
+}
+
+impl SeenUrls {
+    pub(crate) fn new(config: &SeenFilter, db: Db) -> Self {
+        let filter = BloomFilter::new(config);
+        info!(
+            "Deciding which URLs to fetch with a {} bytes filter",
+            filter.bytes()
+        );
+        let (visits, mut rx) = mpsc::unbounded_channel::<Url>();
+        let recorder = tokio::task::spawn_blocking(move || {
+            while let Some(url) = rx.blocking_recv() {
+                if let Err(e) = db.visit(Cow::Borrowed(&url)) {
+                    error!("Could not record the visit of {}: {}", url, e);
+                }
+            }
+        });
+
+        Self {
+            filter,
+            visits,
+            recorder,
+        }
+    }
+
+    /// Record a visit of `url`, returning `true` if it was not found before.
+    pub(crate) fn visit(&mut self, url: &Url) -> bool {
+        // The receiver lives as long as `self`.
+        let _ = self.visits.send(url.clone());
+
+        self.filter.insert(url.as_str())
+    }
+
+    /// Add `url` without recording a visit, e.g. for one already stored.
+    pub(crate) fn insert(&mut self, url: &Url) {
+        self.filter.insert(url.as_str());
+    }
+
+    /// Wait for all the visits to be recorded.
+    pub(crate) async fn finish(self) {
+        drop(self.visits);
+        if let Err(e) = self.recorder.await {
+            error!("Could not record the visits: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BloomFilter, SeenFilter};
+
+    #[test]
+    fn test_bloom_filter() {
+        let config = SeenFilter {
+            expected_urls: 10_000,
+            false_positive_rate: 0.01,
+        };
+        let mut filter = BloomFilter::new(&config);
+        // About 9.6 bits and 7 hashes per item.
+        assert_eq!(filter.bytes(), 11_984);
+        assert_eq!(filter.hashes, 7);
+
+        // The false positive rate is reached with the last items only.
+        let false_positives = (0..10_000)
+            .filter(|i| !filter.insert(&format!("/page/{}", i)))
+            .count();
+        assert!(false_positives < 100, "{} false positives", false_positives);
+        // No false negatives.
+        for i in 0..10_000 {
+            assert!(!filter.insert(&format!("/page/{}", i)));
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(SeenFilter::default().validate().is_ok());
+        assert!(SeenFilter {
+            expected_urls: 0,
+            ..SeenFilter::default()
+        }
+        .validate()
+        .is_err());
+        assert!(SeenFilter {
+            false_positive_rate: 1.0,
+            ..SeenFilter::default()
+        }
+        .validate()
+        .is_err());
+        assert!(SeenFilter {
+            expected_urls: 1_000_000_000_000,
+            ..SeenFilter::default()
+        }
+        .validate()
+        .is_err());
+        assert!(SeenFilter {
+            expected_urls: 100_000_000,
+            false_positive_rate: 1e-300,
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -1,41 +1,353 @@
-use tokio::sync::broadcast;
+use std::{net::SocketAddr, sync::Arc, time::Instant};
+
 use warp::Filter;
 
-use super::{handlers, CountOptions, CrawlersDb, ListOptions};
-use crate::db::Db;
+use super::{
+    handlers, AssetsOptions, AuditOptions, BatchCountRequest, CountOptions, CrawlRequest,
+    CrawlSpawner, DumpOptions, ExportOptions, GraphOptions, LineageOptions, ListOptions,
+    NormalizeRequest, PurgeOptions, RobotsCheckRequest, ScheduleOptions, ScheduleRequest,
+    SearchOptions, SummariesDb, TagFilter, UrlCheckRequest,
+};
+use crate::{
+    artifacts::Artifacts,
+    audit::AuditLog,
+    crawler::CrawlerConfig,
+    db::Db,
+    jobs::{JobId, Jobs},
+    schedules::{ScheduleId, Schedules},
+};
 
 fn with_db(db: Db) -> impl Filter<Extract = (Db,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || db.clone())
 }
 
+fn with_jobs(
+    jobs: Jobs,
+) -> impl Filter<Extract = (Jobs,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || jobs.clone())
+}
+
+fn with_audit(
+    audit: AuditLog,
+) -> impl Filter<Extract = (AuditLog,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || audit.clone())
+}
+
+fn with_artifacts(
+    artifacts: Artifacts,
+) -> impl Filter<Extract = (Artifacts,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || artifacts.clone())
+}
+
+/// Who is making the request, for the audit log: the `X-Api-Key` header, masked so that the
+/// key itself is not stored, or else the remote address.
+fn with_actor() -> impl Filter<Extract = (Option<String>,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("x-api-key")
+        .and(warp::addr::remote())
+        .map(
+            |api_key: Option<String>, remote: Option<SocketAddr>| match api_key {
+                Some(key) => Some(format!("key:{}…", key.chars().take(4).collect::<String>())),
+                None => remote.map(|addr| addr.to_string()),
+            },
+        )
+}
+
 /// POST /domains with JSON body
 pub(super) fn crawl(
-    shutdown: broadcast::Sender<()>,
+    spawner: CrawlSpawner,
     db: Db,
-    spawned_crawlers: CrawlersDb,
+    jobs: Jobs,
+    summaries: SummariesDb,
+    audit: AuditLog,
+    body_limit: u64,
+    defaults: Arc<CrawlerConfig>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("domains")
         .and(warp::path::end())
         .and(warp::post())
-        .and(warp::body::content_length_limit(1024))
+        .and(warp::body::content_length_limit(body_limit))
         .and(warp::body::json())
-        .and(warp::any().map(move || shutdown.clone()))
+        .map(move |body| {
+            let mut domain = defaults.parse_options::<CrawlRequest>(body)?;
+            domain.config.owned_domains = defaults.owned_domains.clone();
+            domain.config.domain_overrides = defaults.domain_overrides.clone();
+            Ok(domain)
+        })
+        .and(warp::any().map(move || spawner.clone()))
         .and(with_db(db))
-        .and(warp::any().map(move || spawned_crawlers.clone()))
+        .and(with_jobs(jobs))
+        .and(warp::any().map(move || summaries.clone()))
+        .and(with_audit(audit))
+        .and(with_actor())
         .and_then(handlers::crawl)
 }
 
-/// GET /domains?domain=<url>
+/// POST /domains/recrawl with JSON body
+pub(super) fn recrawl(
+    spawner: CrawlSpawner,
+    db: Db,
+    jobs: Jobs,
+    summaries: SummariesDb,
+    audit: AuditLog,
+    body_limit: u64,
+    defaults: Arc<CrawlerConfig>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "recrawl")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(body_limit))
+        .and(warp::body::json())
+        .map(move |body| {
+            let mut domain = defaults.parse_options::<CrawlRequest>(body)?;
+            domain.config.owned_domains = defaults.owned_domains.clone();
+            domain.config.domain_overrides = defaults.domain_overrides.clone();
+            Ok(domain)
+        })
+        .and(warp::any().map(move || spawner.clone()))
+        .and(with_db(db))
+        .and(with_jobs(jobs))
+        .and(warp::any().map(move || summaries.clone()))
+        .and(with_audit(audit))
+        .and(with_actor())
+        .and_then(handlers::recrawl)
+}
+
+/// GET /domains/diff?domain=<url>
+pub(super) fn diff(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "diff")
+        .and(warp::get())
+        .and(warp::query::<ListOptions>())
+        .and(with_db(db))
+        .and_then(handlers::diff)
+}
+
+/// GET /domains/graph?domain=<url>&format=json|dot|graphml, the format being optional
+pub(super) fn graph(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "graph")
+        .and(warp::get())
+        .and(warp::query::<GraphOptions>())
+        .and(with_db(db))
+        .and_then(handlers::graph)
+}
+
+/// GET /domains/search?domain=<url>&q=<words>&limit=<n>, the limit being optional
+pub(super) fn search(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "search")
+        .and(warp::get())
+        .and(warp::query::<SearchOptions>())
+        .and(with_db(db))
+        .and_then(handlers::search)
+}
+
+/// POST /urls/check with JSON body
+pub(super) fn check(
+    spawner: CrawlSpawner,
+    db: Db,
+    body_limit: u64,
+    defaults: Arc<CrawlerConfig>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("urls" / "check")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(body_limit))
+        .and(warp::body::json())
+        .map(move |body| {
+            let mut request = defaults.parse_options::<UrlCheckRequest>(body)?;
+            request.config.owned_domains = defaults.owned_domains.clone();
+            request.config.domain_overrides = defaults.domain_overrides.clone();
+            Ok(request)
+        })
+        .and(warp::any().map(move || spawner.clone()))
+        .and(with_db(db))
+        .and_then(handlers::check)
+}
+
+/// DELETE /domains?domain=<url>
+pub(super) fn cancel(
+    jobs: Jobs,
+    audit: AuditLog,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains")
+        .and(warp::delete())
+        .and(warp::query::<ListOptions>())
+        .and(with_jobs(jobs))
+        .and(with_audit(audit))
+        .and(with_actor())
+        .and_then(handlers::cancel)
+}
+
+/// DELETE /domains/data?domain=<url>&cancel=true, cancel being optional
+pub(super) fn purge(
+    db: Db,
+    jobs: Jobs,
+    summaries: SummariesDb,
+    audit: AuditLog,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "data")
+        .and(warp::delete())
+        .and(warp::query::<PurgeOptions>())
+        .and(with_db(db))
+        .and(with_jobs(jobs))
+        .and(warp::any().map(move || summaries.clone()))
+        .and(with_audit(audit))
+        .and(with_actor())
+        .and_then(handlers::purge)
+}
+
+/// POST /domains/pause?domain=<url>
+pub(super) fn pause(
+    jobs: Jobs,
+    audit: AuditLog,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "pause")
+        .and(warp::post())
+        .and(warp::query::<ListOptions>())
+        .and(with_jobs(jobs))
+        .and(with_audit(audit))
+        .and(with_actor())
+        .and_then(|options, jobs, audit, actor| handlers::pause(options, jobs, audit, actor, true))
+}
+
+/// POST /domains/resume?domain=<url>
+pub(super) fn resume(
+    jobs: Jobs,
+    audit: AuditLog,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "resume")
+        .and(warp::post())
+        .and(warp::query::<ListOptions>())
+        .and(with_jobs(jobs))
+        .and(with_audit(audit))
+        .and(with_actor())
+        .and_then(|options, jobs, audit, actor| handlers::pause(options, jobs, audit, actor, false))
+}
+
+/// GET /domains?domain=<url>&progress=true, the progress being optional
 pub(super) fn list(
     db: Db,
+    jobs: Jobs,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path!("domains")
         .and(warp::get())
         .and(warp::query::<ListOptions>())
         .and(with_db(db))
+        .and(with_jobs(jobs))
         .and_then(handlers::list)
 }
 
+/// GET /domains/progress?domain=<url>, as Server-Sent Events
+pub(super) fn progress(
+    jobs: Jobs,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "progress")
+        .and(warp::get())
+        .and(warp::query::<ListOptions>())
+        .and(with_jobs(jobs))
+        .and_then(handlers::progress)
+}
+
+/// GET /jobs/<id>
+pub(super) fn job(
+    jobs: Jobs,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("jobs" / JobId)
+        .and(warp::get())
+        .and(with_jobs(jobs))
+        .and_then(handlers::job)
+}
+
+/// GET /jobs/<id>/artifacts
+pub(super) fn artifacts(
+    jobs: Jobs,
+    artifacts: Artifacts,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("jobs" / JobId / "artifacts")
+        .and(warp::get())
+        .and(with_jobs(jobs))
+        .and(with_artifacts(artifacts))
+        .and_then(handlers::artifacts)
+}
+
+/// GET /jobs/<id>/artifacts/<name>, with an optional `Range` header
+pub(super) fn artifact(
+    jobs: Jobs,
+    artifacts: Artifacts,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("jobs" / JobId / "artifacts" / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("range"))
+        .and(with_jobs(jobs))
+        .and(with_artifacts(artifacts))
+        .and_then(handlers::artifact)
+}
+
+/// GET /crawlers
+pub(super) fn crawlers(
+    jobs: Jobs,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("crawlers")
+        .and(warp::get())
+        .and(with_jobs(jobs))
+        .and_then(handlers::crawlers)
+}
+
+fn with_schedules(
+    schedules: Schedules,
+) -> impl Filter<Extract = (Schedules,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || schedules.clone())
+}
+
+/// POST /schedules with JSON body
+pub(super) fn create_schedule(
+    schedules: Schedules,
+    audit: AuditLog,
+    body_limit: u64,
+    defaults: Arc<CrawlerConfig>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("schedules")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(body_limit))
+        .and(warp::body::json())
+        .map(move |body| {
+            let mut request = defaults.parse_options::<ScheduleRequest>(body)?;
+            request.config.owned_domains = defaults.owned_domains.clone();
+            request.config.domain_overrides = defaults.domain_overrides.clone();
+            Ok(request)
+        })
+        .and(with_schedules(schedules))
+        .and(with_audit(audit))
+        .and(with_actor())
+        .and_then(handlers::create_schedule)
+}
+
+/// GET /schedules?domain=<url>
+pub(super) fn schedules(
+    schedules: Schedules,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("schedules")
+        .and(warp::get())
+        .and(warp::query::<ScheduleOptions>())
+        .and(with_schedules(schedules))
+        .and_then(handlers::schedules)
+}
+
+/// DELETE /schedules/<id>
+pub(super) fn delete_schedule(
+    schedules: Schedules,
+    audit: AuditLog,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("schedules" / ScheduleId)
+        .and(warp::delete())
+        .and(with_schedules(schedules))
+        .and(with_audit(audit))
+        .and(with_actor())
+        .and_then(handlers::delete_schedule)
+}
+
 /// GET /domains/urls?url=<url>
 pub(super) fn count(
     db: Db,
@@ -47,16 +359,256 @@ pub(super) fn count(
         .and_then(handlers::count)
 }
 
+/// POST /domains/urls/batch-count with JSON body
+pub(super) fn batch_count(
+    db: Db,
+    body_limit: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "urls" / "batch-count")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(body_limit))
+        .and(warp::body::json::<BatchCountRequest>())
+        .and(with_db(db))
+        .and_then(handlers::batch_count)
+}
+
+/// GET /domains/third-party?domain=<url>
+pub(super) fn third_party(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "third-party")
+        .and(warp::get())
+        .and(warp::query::<ListOptions>())
+        .and(with_db(db))
+        .and_then(handlers::third_party)
+}
+
+/// GET /domains/assets?domain=<url>
+pub(super) fn assets(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "assets")
+        .and(warp::get())
+        .and(warp::query::<AssetsOptions>())
+        .and(with_db(db))
+        .and_then(handlers::assets)
+}
+
+/// GET /domains/lineage?domain=<url>&not_seen_since=<RFC 3339 time>, the time being optional
+pub(super) fn lineage(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "lineage")
+        .and(warp::get())
+        .and(warp::query::<LineageOptions>())
+        .and(with_db(db))
+        .and_then(handlers::lineage)
+}
+
+/// GET /domains/broken?domain=<url>
+pub(super) fn broken(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "broken")
+        .and(warp::get())
+        .and(warp::query::<ListOptions>())
+        .and(with_db(db))
+        .and_then(handlers::broken)
+}
+
+/// GET /domains/issues?domain=<url>
+pub(super) fn issues(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "issues")
+        .and(warp::get())
+        .and(warp::query::<ListOptions>())
+        .and(with_db(db))
+        .and_then(handlers::issues)
+}
+
+/// GET /domains/duplicates?domain=<url>
+pub(super) fn duplicates(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "duplicates")
+        .and(warp::get())
+        .and(warp::query::<ListOptions>())
+        .and(with_db(db))
+        .and_then(handlers::duplicates)
+}
+
+/// GET /domains/export?domain=<url>&format=json|csv, the format being optional
+pub(super) fn export(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "export")
+        .and(warp::get())
+        .and(warp::query::<ExportOptions>())
+        .and(with_db(db))
+        .and_then(handlers::export)
+}
+
+/// GET /dump?domain=<url>, the domain being optional
+pub(super) fn dump(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("dump")
+        .and(warp::get())
+        .and(warp::query::<DumpOptions>())
+        .and(with_db(db))
+        .and_then(handlers::dump)
+}
+
+/// POST /dump with a dump as body, of at most `decompressed_limit` bytes once decompressed
+pub(super) fn import(
+    db: Db,
+    body_limit: u64,
+    decompressed_limit: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("dump")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(body_limit))
+        .and(warp::body::bytes())
+        .and(with_db(db))
+        .and(warp::any().map(move || decompressed_limit))
+        .and_then(handlers::import)
+}
+
+/// GET /domains/summary?domain=<url>
+pub(super) fn summary(
+    summaries: SummariesDb,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "summary")
+        .and(warp::get())
+        .and(warp::query::<ListOptions>())
+        .and(warp::any().map(move || summaries.clone()))
+        .and_then(handlers::summary)
+}
+
+/// GET /domains/summaries?<tag>=<value>, the tags being optional
+pub(super) fn summaries(
+    summaries: SummariesDb,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("domains" / "summaries")
+        .and(warp::get())
+        .and(warp::query::<TagFilter>())
+        .and(warp::any().map(move || summaries.clone()))
+        .and_then(handlers::summaries)
+}
+
+/// GET /admin/audit?domain=<url>, the domain being optional
+pub(super) fn audit(
+    audit: AuditLog,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("admin" / "audit")
+        .and(warp::get())
+        .and(warp::query::<AuditOptions>())
+        .and(with_audit(audit))
+        .and_then(handlers::audit)
+}
+
+/// GET /healthz
+pub(super) fn healthz() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    warp::path!("healthz").and(warp::get()).map(|| "ok")
+}
+
+/// GET /status
+pub(super) fn status(
+    db: Db,
+    jobs: Jobs,
+    started: Instant,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("status")
+        .and(warp::get())
+        .and(with_db(db))
+        .and(with_jobs(jobs))
+        .and(warp::any().map(move || started))
+        .and_then(handlers::status)
+}
+
+/// GET /readyz
+pub(super) fn readyz(
+    db: Db,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("readyz")
+        .and(warp::get())
+        .and(with_db(db))
+        .and_then(handlers::readyz)
+}
+
+/// GET /metrics
+pub(super) fn metrics(
+    db: Db,
+    jobs: Jobs,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("metrics")
+        .and(warp::get())
+        .and(with_db(db))
+        .and(with_jobs(jobs))
+        .and_then(handlers::metrics)
+}
+
+/// POST /normalize with JSON body
+pub(super) fn normalize(
+    body_limit: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("normalize")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(body_limit))
+        .and(warp::body::json::<NormalizeRequest>())
+        .and_then(handlers::normalize)
+}
+
+/// POST /debug/robots-check with JSON body
+pub(super) fn robots_check(
+    db: Db,
+    body_limit: u64,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("debug" / "robots-check")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(body_limit))
+        .and(warp::body::json::<RobotsCheckRequest>())
+        .and(with_db(db))
+        .and_then(handlers::robots_check)
+}
+
 #[cfg(test)]
 mod tests {
-    use std::borrow::Cow;
+    use std::{borrow::Cow, sync::Arc, time::Instant};
 
-    use crate::db::Db;
+    use chrono::{SecondsFormat, Utc};
 
-    use crate::server::{CountResult, CrawlersDb};
-    use tokio::sync::broadcast;
+    use crate::{
+        artifacts::{Artifact, Artifacts},
+        audit::{AuditAction, AuditEntry, AuditLog},
+        crawler::{
+            CrawlControl, CrawlEvent, CrawlEventKind, CrawlProgress, CrawlStats, CrawlerConfig,
+            SkipReason, UrlCheck,
+        },
+        db::{CrawlDiff, Db, DbError, DumpSummary, FetchStatus},
+        diagnostics::ContentIssue,
+        graph::{Edge, LinkGraph},
+        jobs::{JobState, JobStatus, Jobs},
+        parser::AssetKind,
+        robots::RobotsMode,
+        schedules::{ScheduleStatus, Schedules},
+        search::SearchHit,
+        settings::BodyLimits,
+        summary::{CrawlOutcome, CrawlSummary, SUMMARY_VERSION},
+    };
+
+    use crate::server::{
+        BatchCount, BrokenLink, CancelResult, CountResult, CrawlSpawner, CrawlTasks, DomainAsset,
+        DuplicateGroup, ExportedUrl, InvalidUrl, LineageResult, ListResult, NormalizeResult,
+        PageIssues, PurgeResult, Readiness, RobotsCheck, Status, SummariesDb, ThirdPartyDomain,
+        UrlDetail, MAX_BATCH_COUNT_URLS, MAX_LIST_LIMIT,
+    };
+    use tokio::runtime::Handle;
+    use tokio_util::sync::CancellationToken;
     use url::Url;
-    use warp::http::StatusCode;
+    use warp::{http::StatusCode, Filter};
 
     fn filled_db(domain: &Url) -> Db {
         let db = Db::default();
@@ -73,10 +625,23 @@ mod tests {
     #[tokio::test]
     async fn test_crawl() {
         let db = Db::default();
-        let cdb = CrawlersDb::default();
+        let jobs = Jobs::default();
 
-        let (tx, _rx) = broadcast::channel(1);
-        let filter = super::crawl(tx, db, cdb.clone());
+        let audit = AuditLog::default();
+        let filter = super::crawl(
+            CrawlSpawner {
+                runtime: Handle::current(),
+                shutdown: CancellationToken::new(),
+                artifacts: Artifacts::default(),
+                tasks: CrawlTasks::default(),
+            },
+            db,
+            jobs.clone(),
+            SummariesDb::default(),
+            audit.clone(),
+            BodyLimits::default().crawl,
+            Arc::new(CrawlerConfig::default().max_pages(10)),
+        );
 
         let response = warp::test::request()
             .method("POST")
@@ -85,8 +650,14 @@ mod tests {
             .reply(&filter)
             .await;
 
-        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let job: JobStatus = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            response.headers()["location"],
+            format!("/jobs/{}", job.id).as_str()
+        );
 
+        // The domain is already being crawled, by the same job.
         let response = warp::test::request()
             .method("POST")
             .body(r#"{"domain":"https://example.com"}"#)
@@ -94,7 +665,14 @@ mod tests {
             .reply(&filter)
             .await;
 
-        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            response.headers()["location"],
+            format!("/jobs/{}", job.id).as_str()
+        );
+        let status: JobStatus = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(status.id, job.id);
+        assert_ne!(status.state, JobState::Finished);
 
         let response = warp::test::request()
             .method("POST")
@@ -104,65 +682,1687 @@ mod tests {
             .await;
 
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
-    }
 
-    #[tokio::test]
-    async fn test_list_empty_db() {
-        let db = Db::default();
-        let filter = super::list(db);
-        let response = warp::test::request().path("/domains").reply(&filter).await;
+        // Invalid options, of the wrong type or refused by the crawler.
+        for options in [
+            r#""max_pages": "ten""#,
+            r#""exclude_selectors": ["<<"]"#,
+            r#""downloader": {"user_agent": "bot\n"}"#,
+            r#""channel_capacity": 0"#,
+        ] {
+            let response = warp::test::request()
+                .method("POST")
+                .body(format!(r#"{{"domain":"https://example.net",{}}}"#, options))
+                .path("/domains")
+                .reply(&filter)
+                .await;
 
-        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", options);
+            let error: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+            assert!(error["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("Invalid crawl request"));
+        }
+
+        for domain in [
+            "ftp://example.com",
+            "file:///etc/passwd",
+            "data:text/plain,foo",
+        ] {
+            let response = warp::test::request()
+                .method("POST")
+                .body(format!(r#"{{"domain":"{}"}}"#, domain))
+                .path("/domains")
+                .reply(&filter)
+                .await;
+
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            let invalid: InvalidUrl = serde_json::from_slice(response.body()).unwrap();
+            assert_eq!(invalid.code, "unsupported_scheme");
+            assert_eq!(invalid.supported_schemes, vec!["http", "https"]);
+        }
 
         let response = warp::test::request()
-            .path("/domains?domain=https://example.com")
+            .method("POST")
+            .body(format!(
+                r#"{{"domain":"https://example.org","pad":"{}"}}"#,
+                "x".repeat(5000)
+            ))
+            .path("/domains")
             .reply(&filter)
             .await;
 
-        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Only the request that actually started a crawler is audited.
+        let audit_filter = super::audit(audit);
+        let response = warp::test::request()
+            .path("/admin/audit?domain=https://example.com")
+            .reply(&audit_filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let entries: Vec<AuditEntry> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(entries.len(), 1);
     }
 
     #[tokio::test]
-    async fn test_list_with_db() {
-        let domain = Url::parse("https://example.com").unwrap();
-
-        let db = filled_db(&domain);
-        let filter = super::list(db.clone());
+    async fn test_crawl_callback() {
+        // The crawl stops right away, as the server shuts down.
+        let shutdown = CancellationToken::new();
+        shutdown.cancel();
+        let filter = super::crawl(
+            CrawlSpawner {
+                runtime: Handle::current(),
+                shutdown,
+                artifacts: Artifacts::default(),
+                tasks: CrawlTasks::default(),
+            },
+            Db::default(),
+            Jobs::default(),
+            SummariesDb::default(),
+            AuditLog::default(),
+            BodyLimits::default().crawl,
+            Arc::new(CrawlerConfig::default()),
+        );
 
         let response = warp::test::request()
-            .path(&format!("/domains?domain={}", domain))
+            .method("POST")
+            .body(r#"{"domain":"https://example.com","callback_url":"ftp://example.com/done"}"#)
+            .path("/domains")
             .reply(&filter)
             .await;
 
-        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let invalid: InvalidUrl = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(invalid.code, "unsupported_scheme");
 
-        let urls: Vec<Url> = serde_json::from_slice(response.body()).unwrap();
-        assert_eq!(db.unique_urls_for_domain(&domain).unwrap(), urls);
+        let callback = mockito::mock("POST", "/crawl-done")
+            .match_header("content-type", "application/json")
+            .match_body(mockito::Matcher::Regex(
+                r#""state":"cancelled""#.to_string(),
+            ))
+            .create();
+        let response = warp::test::request()
+            .method("POST")
+            .body(format!(
+                r#"{{"domain":"{0}","callback_url":"{0}/crawl-done",
+                    "downloader":{{"egress":{{"allow_private_domain":true}}}}}}"#,
+                mockito::server_url()
+            ))
+            .path("/domains")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let deadline = Instant::now() + std::time::Duration::from_secs(5);
+        while !callback.matched() && Instant::now() < deadline {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        callback.assert();
     }
 
     #[tokio::test]
-    async fn test_count() {
+    async fn test_cancel() {
+        let jobs = Jobs::default();
+        let audit = AuditLog::default();
+        let filter = super::cancel(jobs.clone(), audit.clone());
+
         let domain = Url::parse("https://example.com").unwrap();
-        let url = Url::parse("https://example.com/foo").unwrap();
+        let token = CancellationToken::new();
+        let id = jobs
+            .create(
+                domain.clone(),
+                Vec::new(),
+                token.clone(),
+                Arc::default(),
+                CrawlControl::default(),
+            )
+            .unwrap();
+        // A crawl of another part of the domain runs alongside.
+        let blog = jobs
+            .create(
+                domain.clone(),
+                vec!["/blog/".to_string()],
+                CancellationToken::new(),
+                Arc::default(),
+                CrawlControl::default(),
+            )
+            .unwrap();
 
-        let db = filled_db(&domain);
-        let filter = super::count(db.clone());
+        let response = warp::test::request()
+            .method("DELETE")
+            .path("/domains?domain=https://example.com&scope=/blog/")
+            .reply(&filter)
+            .await;
 
+        let result: CancelResult = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(result.jobs, vec![blog]);
+        assert!(!token.is_cancelled());
+
+        jobs.create(
+            domain.clone(),
+            vec!["/blog/".to_string()],
+            CancellationToken::new(),
+            Arc::default(),
+            CrawlControl::default(),
+        )
+        .unwrap();
         let response = warp::test::request()
-            .path(&format!("/domains/urls?url={}", url))
+            .method("DELETE")
+            .path("/domains?domain=https://example.com")
             .reply(&filter)
             .await;
 
         assert_eq!(response.status(), StatusCode::OK);
-
-        let count_result: CountResult = serde_json::from_slice(response.body()).unwrap();
-        assert_eq!(db.url_count_for_domain(&url).unwrap(), count_result.count);
+        let result: CancelResult = serde_json::from_slice(response.body()).unwrap();
+        assert!(result.running);
+        assert_eq!(result.job, Some(id));
+        assert_eq!(result.jobs.len(), 2);
+        assert!(token.is_cancelled());
+        assert_eq!(jobs.status(id).unwrap().state, JobState::Cancelled);
+        assert_eq!(audit.entries(Some(&domain)).len(), 2);
 
         let response = warp::test::request()
-            .path(r#"/domains/urls?url=https://who.com"#)
+            .method("DELETE")
+            .path("/domains?domain=https://example.com")
+            .reply(&filter)
+            .await;
+
+        let result: CancelResult = serde_json::from_slice(response.body()).unwrap();
+        assert!(!result.running);
+    }
+
+    #[tokio::test]
+    async fn test_purge() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        let jobs = Jobs::default();
+        let summaries = SummariesDb::default();
+        let audit = AuditLog::default();
+        let filter = super::purge(
+            db.clone(),
+            jobs.clone(),
+            Arc::clone(&summaries),
+            audit.clone(),
+        );
+        let summary = CrawlSummary::new(
+            domain.clone(),
+            CrawlerConfig::default(),
+            Utc::now(),
+            CrawlOutcome::Completed,
+            CrawlStats::default(),
+        );
+        summaries.lock().await.insert(domain.clone(), summary);
+
+        // A crawl that stops shortly after being cancelled.
+        let token = CancellationToken::new();
+        let id = jobs
+            .create(
+                domain.clone(),
+                Vec::new(),
+                token.clone(),
+                Arc::default(),
+                CrawlControl::default(),
+            )
+            .unwrap();
+        tokio::spawn({
+            let jobs = jobs.clone();
+            async move {
+                token.cancelled().await;
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                jobs.finish(id, CrawlOutcome::Cancelled);
+            }
+        });
+
+        let response = warp::test::request()
+            .method("DELETE")
+            .path("/domains/data?domain=https://example.com")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(db.unique_urls_for_domain(&domain).is_ok());
+
+        let response = warp::test::request()
+            .method("DELETE")
+            .path("/domains/data?domain=https://example.com&cancel=true")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let result: PurgeResult = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(result.cancelled, vec![id]);
+        assert_eq!(
+            jobs.status(id).unwrap().outcome,
+            Some(CrawlOutcome::Cancelled)
+        );
+        assert_eq!(
+            db.unique_urls_for_domain(&domain),
+            Err(DbError::DomainDoesNotExist)
+        );
+        assert!(summaries.lock().await.is_empty());
+        let actions: Vec<_> = audit
+            .entries(Some(&domain))
+            .into_iter()
+            .map(|entry| entry.action)
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                AuditAction::CrawlCancelled {
+                    domain: domain.clone()
+                },
+                AuditAction::DataPurged {
+                    domain: domain.clone()
+                },
+            ]
+        );
+
+        let response = warp::test::request()
+            .method("DELETE")
+            .path("/domains/data?domain=https://example.com")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        let jobs = Jobs::default();
+        let audit = AuditLog::default();
+        let filter = super::pause(jobs.clone(), audit.clone())
+            .or(super::resume(jobs.clone(), audit.clone()));
+
+        let domain = Url::parse("https://example.com").unwrap();
+        let control = CrawlControl::default();
+        let id = jobs
+            .create(
+                domain.clone(),
+                Vec::new(),
+                CancellationToken::new(),
+                Arc::default(),
+                control.clone(),
+            )
+            .unwrap();
+        jobs.start(id);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/domains/pause?domain=https://example.com")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let status: JobStatus = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(status.state, JobState::Paused);
+        assert!(control.is_paused());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/domains/resume?domain=https://example.com")
+            .reply(&filter)
+            .await;
+
+        let status: JobStatus = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(status.state, JobState::Running);
+        assert!(!control.is_paused());
+        assert_eq!(audit.entries(Some(&domain)).len(), 2);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/domains/pause?domain=https://foobar.com")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_list_empty_db() {
+        let db = Db::default();
+        let filter = super::list(db, Jobs::default());
+        let response = warp::test::request().path("/domains").reply(&filter).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = warp::test::request()
+            .path("/domains?domain=https://example.com")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_list_with_db() {
+        let domain = Url::parse("https://example.com").unwrap();
+
+        let db = filled_db(&domain);
+        let filter = super::list(db.clone(), Jobs::default());
+
+        let response = warp::test::request()
+            .path(&format!("/domains?domain={}", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(response.headers()["x-crawl-truncated"], "false");
+
+        // Ordered by path.
+        let urls: Vec<Url> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            urls,
+            vec![domain.join("/bar").unwrap(), domain.join("/foo").unwrap()]
+        );
+
+        db.set_truncated(&domain, true).unwrap();
+        let response = warp::test::request()
+            .path(&format!("/domains?domain={}", domain))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.headers()["x-crawl-truncated"], "true");
+
+        let mut status = FetchStatus::ok(200);
+        status.title = Some("Foo".to_string());
+        db.set_status(&domain.join("/foo").unwrap(), &status)
+            .unwrap();
+        let response = warp::test::request()
+            .path(&format!("/domains?domain={}&detail=true", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut urls: Vec<UrlDetail> = serde_json::from_slice(response.body()).unwrap();
+        urls.sort_by(|a, b| a.url.cmp(&b.url));
+        assert_eq!(urls[0].url.path(), "/bar");
+        assert_eq!(urls[0].count, 2);
+        assert_eq!(urls[1].count, 4);
+        assert_eq!(urls[1].fetch, Some(status));
+    }
+
+    #[tokio::test]
+    async fn test_list_progress() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        let jobs = Jobs::default();
+        let filter = super::list(db, jobs.clone());
+
+        let response = warp::test::request()
+            .path(&format!("/domains?domain={}&progress=true", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let result: ListResult = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(result.urls.len(), 2);
+        assert!(!result.crawl_in_progress);
+        assert!(result.progress.is_none());
+
+        // A crawl that has not stored anything yet.
+        let running = Url::parse("https://running.com").unwrap();
+        jobs.create(
+            running.clone(),
+            Vec::new(),
+            CancellationToken::new(),
+            Arc::default(),
+            CrawlControl::default(),
+        )
+        .unwrap();
+        let response = warp::test::request()
+            .path(&format!("/domains?domain={}&progress=true", running))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let result: ListResult = serde_json::from_slice(response.body()).unwrap();
+        assert!(result.urls.is_empty());
+        assert!(result.crawl_in_progress);
+        assert_eq!(result.progress.unwrap().fetched, 0);
+
+        let response = warp::test::request()
+            .path(&format!("/domains?domain={}", running))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_list_pages() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        db.visit(Cow::Owned(domain.join("/baz").unwrap())).unwrap();
+        db.set_status(&domain.join("/baz").unwrap(), &FetchStatus::ok(200))
+            .unwrap();
+        let filter = super::list(db.clone(), Jobs::default());
+
+        let response = warp::test::request()
+            .path(&format!("/domains?domain={}&limit=2", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let result: ListResult = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            result.urls,
+            vec![domain.join("/bar").unwrap(), domain.join("/baz").unwrap()]
+        );
+        assert_eq!(result.total, 3);
+        assert_eq!(result.next_offset, Some(2));
+        assert!(!result.crawl_in_progress);
+
+        let response = warp::test::request()
+            .path(&format!("/domains?domain={}&offset=1&detail=true", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let result: ListResult<UrlDetail> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(result.urls.len(), 2);
+        assert_eq!(result.urls[0].url.path(), "/baz");
+        assert_eq!(result.urls[0].fetch, Some(FetchStatus::ok(200)));
+        assert_eq!(result.urls[1].count, 4);
+        assert_eq!(result.total, 3);
+        assert!(result.next_offset.is_none());
+
+        // The most visited URL under `/ba` visited more than once.
+        db.visit(Cow::Owned(domain.join("/baz").unwrap())).unwrap();
+        db.visit(Cow::Owned(domain.join("/baz").unwrap())).unwrap();
+        db.visit(Cow::Owned(domain.join("/bat").unwrap())).unwrap();
+        let response = warp::test::request()
+            .path(&format!(
+                "/domains?domain={}&prefix=/ba&min_count=2&sort=count",
+                domain
+            ))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let urls: Vec<Url> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            urls,
+            vec![domain.join("/baz").unwrap(), domain.join("/bar").unwrap()]
+        );
+
+        let response = warp::test::request()
+            .path(&format!("/domains?domain={}&prefix=ba", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        for limit in &[0, MAX_LIST_LIMIT + 1] {
+            let response = warp::test::request()
+                .path(&format!("/domains?domain={}&limit={}", domain, limit))
+                .reply(&filter)
+                .await;
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_progress() {
+        let jobs = Jobs::default();
+        let filter = super::progress(jobs.clone());
+        let domain = Url::parse("https://example.com").unwrap();
+
+        let response = warp::test::request()
+            .path(&format!("/domains/progress?domain={}", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let progress = Arc::new(CrawlProgress::default());
+        jobs.create(
+            domain.clone(),
+            Vec::new(),
+            CancellationToken::new(),
+            Arc::clone(&progress),
+            CrawlControl::default(),
+        )
+        .unwrap();
+        // Published once the request subscribed, the stream ending with the crawl.
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            progress.found(&domain.join("/foo").unwrap());
+            progress.finished(&CrawlOutcome::Completed);
+        });
+
+        let response = warp::test::request()
+            .path("/domains/progress?domain=https://example.com")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        let body = std::str::from_utf8(response.body()).unwrap();
+        let events: Vec<CrawlEvent> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0].kind,
+            CrawlEventKind::Found {
+                url: Url::parse("https://example.com/foo").unwrap()
+            }
+        );
+        assert_eq!(events[0].counters.found, 1);
+        assert_eq!(
+            events[1].kind,
+            CrawlEventKind::Finished {
+                outcome: CrawlOutcome::Completed
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_artifacts() {
+        let dir =
+            std::env::temp_dir().join(format!("crawler-filters-artifacts-{}", std::process::id()));
+        let artifacts = Artifacts::new(Some(&dir), Utc::now());
+        let jobs = Jobs::default();
+        let id = jobs
+            .create(
+                Url::parse("https://example.com").unwrap(),
+                Vec::new(),
+                CancellationToken::new(),
+                Arc::default(),
+                CrawlControl::default(),
+            )
+            .unwrap();
+        let list = super::artifacts(jobs.clone(), artifacts.clone());
+        let filter = super::artifact(jobs, artifacts.clone());
+
+        let response = warp::test::request()
+            .path(&format!("/jobs/{}/artifacts", id))
+            .reply(&list)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), "[]");
+
+        artifacts
+            .write(id, "urls.csv", b"url,count\nhttps://example.com/,1\n")
+            .await
+            .unwrap();
+
+        let response = warp::test::request()
+            .path(&format!("/jobs/{}/artifacts", id))
+            .reply(&list)
+            .await;
+        let listed: Vec<Artifact> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "urls.csv");
+        assert_eq!(listed[0].size, 33);
+
+        let response = warp::test::request()
+            .path(&format!("/jobs/{}/artifacts/urls.csv", id))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/csv");
+        assert_eq!(response.headers()["accept-ranges"], "bytes");
+        assert_eq!(response.body(), "url,count\nhttps://example.com/,1\n");
+
+        let response = warp::test::request()
+            .path(&format!("/jobs/{}/artifacts/urls.csv", id))
+            .header("range", "bytes=10-")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()["content-range"], "bytes 10-32/33");
+        assert_eq!(response.body(), "https://example.com/,1\n");
+
+        let response = warp::test::request()
+            .path(&format!("/jobs/{}/artifacts/urls.csv", id))
+            .header("range", "bytes=33-")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()["content-range"], "bytes */33");
+
+        for path in [
+            format!("/jobs/{}/artifacts/report.html", id),
+            format!("/jobs/{}/artifacts/..", id),
+            format!("/jobs/{}/artifacts/urls.csv", id + 1),
+        ] {
+            let response = warp::test::request().path(&path).reply(&filter).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+        }
+        let response = warp::test::request()
+            .path(&format!("/jobs/{}/artifacts", id + 1))
+            .reply(&list)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_job() {
+        let jobs = Jobs::default();
+        let filter = super::job(jobs.clone());
+        let id = jobs
+            .create(
+                Url::parse("https://example.com").unwrap(),
+                Vec::new(),
+                CancellationToken::new(),
+                Arc::default(),
+                CrawlControl::default(),
+            )
+            .unwrap();
+        jobs.start(id);
+
+        let response = warp::test::request()
+            .path(&format!("/jobs/{}", id))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let status: JobStatus = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(status.id, id);
+        assert_eq!(status.state, JobState::Running);
+        assert_eq!(status.progress.fetched, 0);
+        assert!(status.started_at.is_some());
+
+        let response = warp::test::request()
+            .path(&format!("/jobs/{}", id + 1))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = warp::test::request()
+            .path("/crawlers")
+            .reply(&super::crawlers(jobs.clone()))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let crawlers: Vec<JobStatus> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(crawlers.len(), 1);
+        assert_eq!(crawlers[0].id, id);
+
+        jobs.finish(id, CrawlOutcome::Completed);
+        let response = warp::test::request()
+            .path("/crawlers")
+            .reply(&super::crawlers(jobs))
+            .await;
+        assert_eq!(response.body(), "[]");
+    }
+
+    #[tokio::test]
+    async fn test_schedules() {
+        let schedules = Schedules::default();
+        let audit = AuditLog::default();
+        let create = super::create_schedule(
+            schedules.clone(),
+            audit.clone(),
+            BodyLimits::default().crawl,
+            Arc::new(CrawlerConfig::default().max_pages(10)),
+        );
+        let post = |body: &str| {
+            warp::test::request()
+                .method("POST")
+                .body(body)
+                .path("/schedules")
+                .reply(&create)
+        };
+
+        let response = post(r#"{"domain":"https://example.com","cron":"0 3 * * *"}"#).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let nightly: ScheduleStatus = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(nightly.cron.as_deref(), Some("0 3 * * *"));
+        assert!(nightly.next_run_at.is_some());
+
+        let response =
+            post(r#"{"domain":"https://example.org","interval_secs":3600,"scope":["/docs/"]}"#)
+                .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let hourly: ScheduleStatus = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(hourly.interval_secs, Some(3600));
+        assert_eq!(hourly.scope, vec!["/docs/".to_string()]);
+
+        for body in [
+            r#"{"domain":"https://example.com"}"#,
+            r#"{"domain":"https://example.com","cron":"0 3 * * *","interval_secs":3600}"#,
+            r#"{"domain":"https://example.com","cron":"every night"}"#,
+            r#"{"domain":"https://example.com","interval_secs":1}"#,
+            r#"{"domain":"https://example.com","interval_secs":3600,"dry_run":true}"#,
+            r#"{"domain":"https://example.com","interval_secs":3600,"include":["re:("]}"#,
+        ] {
+            assert_eq!(
+                post(body).await.status(),
+                StatusCode::BAD_REQUEST,
+                "{}",
+                body
+            );
+        }
+        let response = post(r#"{"domain":"ftp://example.com","interval_secs":3600}"#).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let list = super::schedules(schedules.clone());
+        let response = warp::test::request()
+            .path("/schedules?domain=https://example.com")
+            .reply(&list)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let listed: Vec<ScheduleStatus> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, nightly.id);
+
+        let delete = super::delete_schedule(schedules.clone(), audit.clone());
+        let response = warp::test::request()
+            .method("DELETE")
+            .path(&format!("/schedules/{}", nightly.id))
+            .reply(&delete)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = warp::test::request()
+            .method("DELETE")
+            .path(&format!("/schedules/{}", nightly.id))
+            .reply(&delete)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = warp::test::request().path("/schedules").reply(&list).await;
+        let listed: Vec<ScheduleStatus> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, hourly.id);
+
+        let actions: Vec<_> = audit
+            .entries(None)
+            .into_iter()
+            .map(|entry| entry.action)
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                AuditAction::ScheduleCreated {
+                    domain: nightly.domain.clone()
+                },
+                AuditAction::ScheduleCreated {
+                    domain: hourly.domain
+                },
+                AuditAction::ScheduleDeleted {
+                    domain: nightly.domain
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_count() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let url = Url::parse("https://example.com/foo").unwrap();
+
+        let db = filled_db(&domain);
+        let filter = super::count(db.clone());
+
+        let response = warp::test::request()
+            .path(&format!("/domains/urls?url={}", url))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let count_result: CountResult = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(db.url_count_for_domain(&url).unwrap(), count_result.count);
+
+        let response = warp::test::request()
+            .path(r#"/domains/urls?url=https://who.com"#)
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_batch_count() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        let filter = super::batch_count(db, BodyLimits::default().batch_count);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/domains/urls/batch-count")
+            .json(&serde_json::json!({"urls": [
+                "https://example.com/foo",
+                "https://example.com/baz",
+                "https://who.com/foo",
+                "https://example.com/foo",
+            ]}))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let counts: Vec<BatchCount> = serde_json::from_slice(response.body()).unwrap();
+        let counts: Vec<_> = counts.iter().map(|count| count.count).collect();
+        assert_eq!(counts, vec![Some(4), Some(0), None, Some(4)]);
+
+        let urls = vec!["https://example.com/foo"; MAX_BATCH_COUNT_URLS + 1];
+        let response = warp::test::request()
+            .method("POST")
+            .path("/domains/urls/batch-count")
+            .json(&serde_json::json!({ "urls": urls }))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_normalize() {
+        let filter = super::normalize(BodyLimits::default().normalize);
+
+        let response = warp::test::request()
+            .method("POST")
+            .body(
+                r#"{
+                    "urls": [
+                        "https://example.com/foo?utm_source=x#top",
+                        "https://example.com/foo",
+                        "https://example.com/bar"
+                    ],
+                    "config": {"remove_params": ["utm_*"]}
+                }"#,
+            )
+            .path("/normalize")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let result: NormalizeResult = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(result.urls.len(), 3);
+        assert_eq!(
+            result.urls[0].normalized,
+            Url::parse("https://example.com/foo").unwrap()
+        );
+        assert_eq!(
+            result.unique,
+            vec![
+                Url::parse("https://example.com/foo").unwrap(),
+                Url::parse("https://example.com/bar").unwrap(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        db.visit(Cow::Owned(domain.join("/a,b").unwrap())).unwrap();
+        let filter = super::export(db);
+
+        let response = warp::test::request()
+            .path(&format!("/domains/export?domain={}", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/json");
+
+        let urls: Vec<ExportedUrl> = serde_json::from_slice(response.body()).unwrap();
+        let urls: Vec<_> = urls
+            .iter()
+            .map(|exported| (exported.url.path(), exported.count))
+            .collect();
+        assert_eq!(urls, vec![("/a,b", 1), ("/bar", 2), ("/foo", 4)]);
+
+        let response = warp::test::request()
+            .path(&format!("/domains/export?domain={}&format=csv", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/csv");
+        assert_eq!(
+            response.body(),
+            "url,count\n\"https://example.com/a,b\",1\nhttps://example.com/bar,2\nhttps://example.com/foo,4\n"
+        );
+
+        let response = warp::test::request()
+            .path(&format!("/domains/export?domain={}&format=xml", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = warp::test::request()
+            .path("/domains/export?domain=https://foobar.com")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_graph() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        let foo = domain.join("/foo").unwrap();
+        let bar = domain.join("/bar").unwrap();
+        db.link(&bar, &foo).unwrap();
+        let filter = super::graph(db);
+
+        let response = warp::test::request()
+            .path(&format!("/domains/graph?domain={}", domain))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/json");
+        let graph: LinkGraph = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(graph.nodes, vec![bar.clone(), foo.clone()]);
+        assert_eq!(graph.edges, vec![Edge { from: foo, to: bar }]);
+
+        let response = warp::test::request()
+            .path(&format!("/domains/graph?domain={}&format=dot", domain))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/vnd.graphviz");
+        assert!(String::from_utf8_lossy(response.body())
+            .contains(r#""https://example.com/foo" -> "https://example.com/bar";"#));
+
+        let response = warp::test::request()
+            .path(&format!("/domains/graph?domain={}&format=graphml", domain))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/graphml+xml"
+        );
+
+        let response = warp::test::request()
+            .path(&format!("/domains/graph?domain={}&format=png", domain))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = warp::test::request()
+            .path("/domains/graph?domain=https://foobar.com")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        let foo = domain.join("/foo").unwrap();
+        db.index_text(&foo, "Search the crawled pages").unwrap();
+        let filter = super::search(db);
+
+        let response = warp::test::request()
+            .path(&format!("/domains/search?domain={}&q=Crawled", domain))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let hits: Vec<SearchHit> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            hits,
+            vec![SearchHit {
+                url: foo,
+                score: 1,
+                snippet: "Search the crawled pages".to_string(),
+            }]
+        );
+
+        let response = warp::test::request()
+            .path(&format!("/domains/search?domain={}&q=%20!", domain))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = warp::test::request()
+            .path(&format!(
+                "/domains/search?domain={}&q=pages&limit=1000",
+                domain
+            ))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = warp::test::request()
+            .path("/domains/search?domain=https://foobar.com&q=pages")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_robots_check() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        db.set_robots_txt(&domain, "User-agent: *\nDisallow: /private\n")
+            .unwrap();
+        let filter = super::robots_check(db, BodyLimits::default().robots_check);
+
+        let response = warp::test::request()
+            .method("POST")
+            .body(
+                r#"{
+                    "domain": "https://example.com",
+                    "urls": ["https://example.com/private/a", "https://example.com/public"]
+                }"#,
+            )
+            .path("/debug/robots-check")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let checks: Vec<RobotsCheck> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(checks.len(), 2);
+        assert!(!checks[0].decision.allowed);
+        assert_eq!(checks[0].decision.rule.as_ref().unwrap().line, 2);
+        assert!(checks[1].decision.allowed);
+        assert!(checks[1].decision.rule.is_none());
+
+        let response = warp::test::request()
+            .method("POST")
+            .body(r#"{"domain": "https://foobar.com", "urls": []}"#)
+            .path("/debug/robots-check")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_check() {
+        let db = Db::default();
+        let filter = super::check(
+            CrawlSpawner {
+                runtime: Handle::current(),
+                shutdown: CancellationToken::new(),
+                artifacts: Artifacts::default(),
+                tasks: CrawlTasks::default(),
+            },
+            db.clone(),
+            BodyLimits::default().crawl,
+            Arc::new(CrawlerConfig::default()),
+        );
+
+        let response = warp::test::request()
+            .method("POST")
+            .body(format!(
+                r#"{{"url": "https://example.com/foo", "domain": "{}"}}"#,
+                mockito::server_url()
+            ))
+            .path("/urls/check")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let check: UrlCheck = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(check.skipped, Some(SkipReason::OffDomain));
+        assert!(check.status.is_none());
+        assert_eq!(db.totals().unwrap().domains, 0);
+
+        let response = warp::test::request()
+            .method("POST")
+            .body(r#"{"domain": "https://example.com"}"#)
+            .path("/urls/check")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = warp::test::request()
+            .method("POST")
+            .body(r#"{"url": "https://example.com/foo", "domain": "ftp://example.com"}"#)
+            .path("/urls/check")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_dump_and_import() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let filter = super::dump(filled_db(&domain));
+
+        let response = warp::test::request()
+            .path("/dump?domain=https://example.com")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/zstd");
+        let dump = response.body().clone();
+
+        let response = warp::test::request()
+            .path("/dump?domain=https://foobar.com")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let db = Db::default();
+        let filter = super::import(db.clone(), 1024, 1024 * 1024);
+        let response = warp::test::request()
+            .method("POST")
+            .path("/dump")
+            .body(dump.clone())
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let summary: DumpSummary = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(summary.urls, 2);
+        assert_eq!(
+            db.url_count_for_domain(&domain.join("/foo").unwrap()),
+            Ok(4)
+        );
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/dump")
+            .body(&dump[..dump.len() / 2])
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            db.url_count_for_domain(&domain.join("/foo").unwrap()),
+            Ok(4)
+        );
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/dump")
+            .body(dump)
+            .reply(&super::import(db.clone(), 1024, 64))
+            .await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            db.url_count_for_domain(&domain.join("/foo").unwrap()),
+            Ok(4)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_healthz_and_status() {
+        let response = warp::test::request()
+            .path("/healthz")
+            .reply(&super::healthz())
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), "ok");
+
+        let domain = Url::parse("https://example.com").unwrap();
+        let jobs = Jobs::default();
+        jobs.create(
+            domain.clone(),
+            Vec::new(),
+            CancellationToken::new(),
+            Arc::default(),
+            CrawlControl::default(),
+        )
+        .unwrap();
+        let filter = super::status(filled_db(&domain), jobs, Instant::now());
+
+        let response = warp::test::request().path("/status").reply(&filter).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let status: Status = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(status.active_crawlers, 1);
+        assert_eq!(status.totals.domains, 1);
+        assert_eq!(status.totals.urls, 2);
+        let memory = status.memory.unwrap();
+        assert_eq!(memory.urls, 2);
+        assert_eq!(memory.max_urls, None);
+    }
+
+    #[tokio::test]
+    async fn test_readyz_and_metrics() {
+        let db = Db::default();
+
+        let response = warp::test::request()
+            .path("/readyz")
+            .reply(&super::readyz(db.clone()))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let readiness: Readiness = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(readiness.status, "ready");
+        assert!(!readiness.storage.degraded);
+
+        let response = warp::test::request()
+            .path("/metrics")
+            .reply(&super::metrics(db, Jobs::default()))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = std::str::from_utf8(response.body()).unwrap();
+        assert!(body.contains("\ncrawler_storage_degraded 0\n"));
+        assert!(body.contains("\ncrawler_storage_buffered_writes 0\n"));
+        assert!(body.contains("\ncrawler_urls_rejected_total{reason=\"url_too_long\"} 0\n"));
+        assert!(body.contains("\ncrawler_urls_rejected_total{reason=\"page_link_limit\"} 0\n"));
+    }
+
+    #[tokio::test]
+    async fn test_third_party() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        db.visit_third_party(&domain, "ads.com").unwrap();
+        db.visit_third_party(&domain, "cdn.net").unwrap();
+        db.visit_third_party(&domain, "cdn.net").unwrap();
+        let filter = super::third_party(db);
+
+        let response = warp::test::request()
+            .path(&format!("/domains/third-party?domain={}", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let domains: Vec<ThirdPartyDomain> = serde_json::from_slice(response.body()).unwrap();
+        let domains: Vec<_> = domains
+            .iter()
+            .map(|third_party| (third_party.domain.as_str(), third_party.links))
+            .collect();
+        assert_eq!(domains, vec![("cdn.net", 2), ("ads.com", 1)]);
+
+        let response = warp::test::request()
+            .path("/domains/third-party?domain=https://foobar.com")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_assets() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        let logo = domain.join("/logo.png").unwrap();
+        let script = Url::parse("https://cdn.net/app.js").unwrap();
+        db.visit_asset(&domain, &script, AssetKind::Script).unwrap();
+        db.visit_asset(&domain, &logo, AssetKind::Image).unwrap();
+        db.visit_asset(&domain, &logo, AssetKind::Image).unwrap();
+        let filter = super::assets(db);
+
+        let response = warp::test::request()
+            .path(&format!("/domains/assets?domain={}", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let assets: Vec<DomainAsset> = serde_json::from_slice(response.body()).unwrap();
+        let assets: Vec<_> = assets
+            .iter()
+            .map(|asset| (asset.url.as_str(), asset.kind, asset.count))
+            .collect();
+        assert_eq!(
+            assets,
+            vec![
+                ("https://cdn.net/app.js", AssetKind::Script, 1),
+                ("https://example.com/logo.png", AssetKind::Image, 2),
+            ]
+        );
+
+        let response = warp::test::request()
+            .path(&format!("/domains/assets?domain={}&kind=image", domain))
+            .reply(&filter)
+            .await;
+
+        let assets: Vec<DomainAsset> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(assets.len(), 1);
+        assert_eq!(assets[0].url, logo);
+
+        let response = warp::test::request()
+            .path("/domains/assets?domain=https://foobar.com")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_broken() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        let foo = domain.join("/foo").unwrap();
+        let bar = domain.join("/bar").unwrap();
+        db.set_status(&domain, &FetchStatus::ok(200)).unwrap();
+        db.set_status(&foo, &FetchStatus::failed(Some(404), "http_404"))
+            .unwrap();
+        db.set_status(&bar, &FetchStatus::failed(None, "blocked"))
+            .unwrap();
+        db.link(&foo, &domain).unwrap();
+        db.link(&foo, &bar).unwrap();
+        db.link(&foo, &domain).unwrap();
+        let filter = super::broken(db);
+
+        let response = warp::test::request()
+            .path(&format!("/domains/broken?domain={}", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let broken: Vec<BrokenLink> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].url, foo);
+        assert_eq!(broken[0].status.status, Some(404));
+        assert_eq!(broken[0].linked_from, vec![domain, bar]);
+
+        let response = warp::test::request()
+            .path("/domains/broken?domain=https://foobar.com")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_issues() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        let foo = domain.join("/foo").unwrap();
+        let truncated = ContentIssue::ContentLengthMismatch {
+            declared: 100,
+            received: 60,
+        };
+        db.set_status(&domain, &FetchStatus::ok(200)).unwrap();
+        db.set_status(
+            &foo,
+            &FetchStatus {
+                content_type: Some("text/html".to_string()),
+                issues: vec![truncated.clone(), ContentIssue::InvalidUtf8],
+                ..FetchStatus::ok(200)
+            },
+        )
+        .unwrap();
+        let filter = super::issues(db);
+
+        let response = warp::test::request()
+            .path(&format!("/domains/issues?domain={}", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let pages: Vec<PageIssues> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].url, foo);
+        assert_eq!(pages[0].content_type.as_deref(), Some("text/html"));
+        assert_eq!(pages[0].issues, vec![truncated, ContentIssue::InvalidUtf8]);
+
+        let response = warp::test::request()
+            .path("/domains/issues?domain=https://foobar.com")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_duplicates() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        let foo = domain.join("/foo").unwrap();
+        let bar = domain.join("/bar").unwrap();
+        let hashed = |hash: &str| FetchStatus {
+            content_hash: Some(hash.to_string()),
+            ..FetchStatus::ok(200)
+        };
+        db.set_status(&domain, &hashed("home")).unwrap();
+        db.set_status(&foo, &hashed("same")).unwrap();
+        db.set_status(&bar, &hashed("same")).unwrap();
+        let filter = super::duplicates(db);
+
+        let response = warp::test::request()
+            .path(&format!("/domains/duplicates?domain={}", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let duplicates: Vec<DuplicateGroup> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].content_hash, "same");
+        assert_eq!(duplicates[0].urls, vec![bar, foo]);
+
+        let response = warp::test::request()
+            .path("/domains/duplicates?domain=https://foobar.com")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_lineage() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        db.start_session(&domain, RobotsMode::Respect).unwrap();
+        db.see(&domain.join("/foo").unwrap(), 1).unwrap();
+        db.see(&domain.join("/bar").unwrap(), 1).unwrap();
+        let since = Utc::now();
+        db.start_session(&domain, RobotsMode::RecordOnly).unwrap();
+        db.see(&domain.join("/foo").unwrap(), 2).unwrap();
+        let filter = super::lineage(db);
+
+        let response = warp::test::request()
+            .path(&format!("/domains/lineage?domain={}", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let result: LineageResult = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(result.sessions.len(), 2);
+        assert_eq!(result.sessions[1].id, 2);
+        assert_eq!(result.sessions[1].robots_mode, Some(RobotsMode::RecordOnly));
+        let urls: Vec<_> = result
+            .urls
+            .iter()
+            .map(|url| {
+                (
+                    url.url.path(),
+                    url.lineage.first_seen,
+                    url.lineage.last_seen,
+                )
+            })
+            .collect();
+        assert_eq!(urls, vec![("/bar", 1, 1), ("/foo", 1, 2)]);
+
+        let response = warp::test::request()
+            .path(&format!(
+                "/domains/lineage?domain={}&not_seen_since={}",
+                domain,
+                since.to_rfc3339_opts(SecondsFormat::Micros, true)
+            ))
+            .reply(&filter)
+            .await;
+
+        let result: LineageResult = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(result.urls.len(), 1);
+        assert_eq!(result.urls[0].url, domain.join("/bar").unwrap());
+
+        let response = warp::test::request()
+            .path("/domains/lineage?domain=https://foobar.com")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_recrawl_and_diff() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let db = filled_db(&domain);
+        let foo = domain.join("/foo").unwrap();
+        let bar = domain.join("/bar").unwrap();
+        let baz = domain.join("/baz").unwrap();
+        let jobs = Jobs::default();
+        // The recrawl stops right away.
+        let shutdown = CancellationToken::new();
+        shutdown.cancel();
+        let recrawl = super::recrawl(
+            CrawlSpawner {
+                runtime: Handle::current(),
+                shutdown,
+                artifacts: Artifacts::default(),
+                tasks: CrawlTasks::default(),
+            },
+            db.clone(),
+            jobs.clone(),
+            SummariesDb::default(),
+            AuditLog::default(),
+            BodyLimits::default().crawl,
+            Arc::new(CrawlerConfig::default()),
+        );
+        let diff = super::diff(db.clone());
+
+        let response = warp::test::request()
+            .method("POST")
+            .body(format!(r#"{{"domain":"{}"}}"#, domain))
+            .path("/domains/recrawl")
+            .reply(&recrawl)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = warp::test::request()
+            .path(&format!("/domains/diff?domain={}", domain))
+            .reply(&diff)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        db.start_session(&domain, RobotsMode::Respect).unwrap();
+        db.see(&foo, 1).unwrap();
+        db.see(&bar, 1).unwrap();
+        db.start_session(&domain, RobotsMode::Respect).unwrap();
+        db.see(&foo, 2).unwrap();
+        db.visit(Cow::Borrowed(&baz)).unwrap();
+        db.see(&baz, 2).unwrap();
+        db.set_status(
+            &foo,
+            &FetchStatus {
+                session: Some(2),
+                changed: true,
+                ..FetchStatus::ok(200)
+            },
+        )
+        .unwrap();
+
+        let response = warp::test::request()
+            .path(&format!("/domains/diff?domain={}", domain))
+            .reply(&diff)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let result: CrawlDiff = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            result,
+            CrawlDiff {
+                session: 2,
+                new: vec![baz],
+                removed: vec![bar],
+                changed: vec![foo],
+            }
+        );
+
+        let response = warp::test::request()
+            .method("POST")
+            .body(format!(r#"{{"domain":"{}"}}"#, domain))
+            .path("/domains/recrawl")
+            .reply(&recrawl)
+            .await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let job: JobStatus = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(job.domain, domain);
+
+        let response = warp::test::request()
+            .method("POST")
+            .body(r#"{"domain":"abc"}"#)
+            .path("/domains/recrawl")
+            .reply(&recrawl)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_summaries() {
+        let summaries = SummariesDb::default();
+        let filter = super::summaries(summaries.clone());
+
+        for (domain, team) in [
+            ("https://example.com", "seo"),
+            ("https://foobar.com", "web"),
+        ] {
+            let domain = Url::parse(domain).unwrap();
+            let mut config = CrawlerConfig::default();
+            config.tags.insert("team".to_string(), team.to_string());
+            let summary = CrawlSummary::new(
+                domain.clone(),
+                config,
+                Utc::now(),
+                CrawlOutcome::Completed,
+                CrawlStats::default(),
+            );
+            summaries.lock().await.insert(domain, summary);
+        }
+
+        let response = warp::test::request()
+            .path("/domains/summaries")
+            .reply(&filter)
+            .await;
+        let all: Vec<CrawlSummary> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(all.len(), 2);
+
+        let response = warp::test::request()
+            .path("/domains/summaries?team=seo")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let seo: Vec<CrawlSummary> = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(seo.len(), 1);
+        assert_eq!(seo[0].domain.as_str(), "https://example.com/");
+
+        let response = warp::test::request()
+            .path("/domains/summaries?team=seo&ticket=WEB-1")
+            .reply(&filter)
+            .await;
+        let none: Vec<CrawlSummary> = serde_json::from_slice(response.body()).unwrap();
+        assert!(none.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_summary() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let summaries = SummariesDb::default();
+        let filter = super::summary(summaries.clone());
+
+        let response = warp::test::request()
+            .path(&format!("/domains/summary?domain={}", domain))
             .reply(&filter)
             .await;
 
         assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let summary = CrawlSummary::new(
+            domain.clone(),
+            CrawlerConfig::default(),
+            Utc::now(),
+            CrawlOutcome::Completed,
+            CrawlStats::default(),
+        );
+        summaries.lock().await.insert(domain.clone(), summary);
+
+        let response = warp::test::request()
+            .path(&format!("/domains/summary?domain={}", domain))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let summary: CrawlSummary = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(summary.version, SUMMARY_VERSION);
+        assert_eq!(summary.domain, domain);
     }
 }
@@ -1,89 +1,941 @@
-use std::convert::Infallible;
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    time::{Duration, Instant},
+};
 
-use super::{CountOptions, CountResult, CrawlersDb, Domain, ListOptions};
-use crate::{crawler::Crawler, db::Db};
+use chrono::Utc;
+
+use super::{
+    AssetsOptions, AuditOptions, BatchCount, BatchCountRequest, BrokenLink, CancelResult,
+    CountOptions, CountResult, CrawlRequest, CrawlSession, CrawlSpawner, DomainAsset, DumpOptions,
+    DuplicateGroup, ExportFormat, ExportOptions, ExportedUrl, GraphFormat, GraphOptions,
+    InvalidUrl, LineageOptions, LineageResult, ListOptions, ListResult, NormalizeRequest,
+    NormalizeResult, NormalizedUrl, PageIssues, PurgeOptions, PurgeResult, Readiness, RobotsCheck,
+    RobotsCheckRequest, ScheduleOptions, ScheduleRequest, SearchOptions, Status, SummariesDb,
+    TagFilter, ThirdPartyDomain, UrlCheckRequest, UrlDetail, UrlLineage, DEFAULT_LIST_LIMIT,
+    DEFAULT_SEARCH_LIMIT, MAX_BATCH_COUNT_URLS, MAX_LIST_LIMIT, MAX_SEARCH_LIMIT,
+    PURGE_CANCEL_TIMEOUT,
+};
+use crate::{
+    artifacts::{Artifacts, ByteRange},
+    audit::{AuditAction, AuditLog},
+    crawler::{CrawlEventKind, Crawler, ProgressCounters, SkipReason, WarmStart},
+    db::{Db, DbError, DumpError, UrlQuery},
+    downloader::Downloader,
+    jobs::{JobId, JobStatus, Jobs},
+    robots,
+    schedules::{Cadence, ScheduleId, Schedules},
+    summary::CrawlSummary,
+};
 use serde::Serialize;
-use tokio::sync::broadcast;
-use tracing::{info, log::warn};
-use warp::http::StatusCode;
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    sync::broadcast::error::RecvError,
+    task::JoinHandle,
+};
+use tokio_util::io::ReaderStream;
+use tracing::{error, info, log::warn};
+use url::Url;
+use warp::{
+    http::{header, Response, StatusCode},
+    hyper::Body,
+    Reply,
+};
 
 #[derive(Debug, Serialize)]
 struct Error {
     error: String,
 }
 
-/// Handle a crawl request. Spawn a new crawler if one doesn't already exist for the given domain.
-/// If a crawl request is already in progress, just return 200 OK.
-/// We should probably respond with a Location: /domains?domain=<domain> header as well, but leave that
-/// for the future.
+/// Handle a crawl request. Spawn a new crawler if one doesn't already exist for the given domain
+/// and scope, so that different parts of a domain can be crawled at once.
+/// Respond with `202 Accepted` and the status of the new job, or with `409 Conflict` and the
+/// status of the job already crawling the domain and scope, the `Location` header being the
+/// path of the job either way.
+/// The crawl runs on the crawl runtime. A dry run is answered directly with the `CrawlReport` of the
+/// seed page, and a probe with its `ProbeReport`, both run on the crawl runtime too, with
+/// `502 Bad Gateway` if the site could not be fetched.
+/// Once the crawl is done, its artifacts are written before the job is marked as finished, and
+/// the final status of the job is then sent to the `callback_url`, if any.
+/// Respond with `400 Bad Request` if the body is not a valid crawl request, and with
+/// `422 Unprocessable Entity` if the domain or the `callback_url` is not an `http` or `https` URL
+/// with a host.
 pub(super) async fn crawl(
-    domain: Domain,
-    shutdown: broadcast::Sender<()>,
+    domain: serde_json::Result<CrawlRequest>,
+    spawner: CrawlSpawner,
     db: Db,
-    spawned_crawlers: CrawlersDb,
-) -> Result<impl warp::Reply, Infallible> {
-    let mut cdb = spawned_crawlers.lock().await;
-    if cdb.contains(&domain.domain) {
+    jobs: Jobs,
+    summaries: SummariesDb,
+    audit: AuditLog,
+    actor: Option<String>,
+) -> Result<warp::reply::Response, Infallible> {
+    let CrawlRequest { domain, config } = match domain {
+        Ok(domain) => domain,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: format!("Invalid crawl request: {}", e),
+                }),
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response());
+        }
+    };
+
+    if let Some(invalid) = std::iter::once(&domain)
+        .chain(&config.callback_url)
+        .find_map(|url| InvalidUrl::check(url).err())
+    {
         return Ok(warp::reply::with_status(
-            warp::reply::json(&"{}".to_string()),
-            StatusCode::OK,
-        ));
+            warp::reply::json(&invalid),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )
+        .into_response());
     }
 
-    let mut crawler = match Crawler::new(domain.domain.clone()) {
+    // The options parsed, but some of them are invalid, e.g. a malformed pattern or selector.
+    let mut crawler = match Crawler::new(domain.clone(), config) {
         Ok(crawler) => crawler,
         Err(e) => {
             warn!("Crawler error: {}", e);
             let error = Error {
-                error: e.to_string(),
+                error: format!("Invalid crawl request: {}", e),
             };
             return Ok(warp::reply::with_status(
                 warp::reply::json(&error),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ));
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response());
         }
     };
 
-    cdb.insert(domain.domain);
+    // A dry run or a probe does not touch the database, so it is neither registered nor spawned.
+    if crawler.config().probe {
+        let probe = spawner.runtime.spawn(async move { crawler.probe().await });
+        return Ok(report_reply(probe).await);
+    }
+    if crawler.config().dry_run {
+        let dry_run = spawner
+            .runtime
+            .spawn(async move { crawler.dry_run().await });
+        return Ok(report_reply(dry_run).await);
+    }
+
+    let (status, code) =
+        match spawn_crawl(crawler, spawner, db, jobs.clone(), summaries, audit, actor) {
+            Ok(status) => (Some(status), StatusCode::ACCEPTED),
+            Err(id) => (jobs.status(id), StatusCode::CONFLICT),
+        };
+    let reply = warp::reply::with_status(warp::reply::json(&status), code);
+
+    Ok(match status {
+        Some(status) => {
+            warp::reply::with_header(reply, header::LOCATION, format!("/jobs/{}", status.id))
+                .into_response()
+        }
+        None => reply.into_response(),
+    })
+}
+
+/// Reply with the report of a probe or a dry run once its `task` is done, or with
+/// `502 Bad Gateway` if it failed. `500 Internal Server Error` if the task did not complete, e.g.
+/// as the server is shutting down.
+async fn report_reply<T: Serialize>(task: JoinHandle<anyhow::Result<T>>) -> warp::reply::Response {
+    let (error, status) = match task.await {
+        Ok(Ok(report)) => {
+            return warp::reply::with_status(warp::reply::json(&report), StatusCode::OK)
+                .into_response()
+        }
+        Ok(Err(e)) => (e.to_string(), StatusCode::BAD_GATEWAY),
+        Err(e) => (e.to_string(), StatusCode::INTERNAL_SERVER_ERROR),
+    };
 
-    let cdb = spawned_crawlers.clone();
-    tokio::spawn(async move {
-        crawler.crawl(db, shutdown).await;
+    warp::reply::with_status(warp::reply::json(&Error { error }), status).into_response()
+}
 
-        // Remove ourselves from crawler db
-        let mut cdb = cdb.lock().await;
-        cdb.remove(crawler.domain());
-        info!("Crawler done");
+/// Handle a recrawl request: crawl an already crawled domain again, fetching the URLs stored for it
+/// as well, with a warm start, for its pages that changed to be found. Once the crawl is done,
+/// GET /domains/diff reports what it found new, removed or changed. The response is the one of a
+/// crawl request.
+/// Respond with `404 Not Found` if the domain in body has not been crawled.
+pub(super) async fn recrawl(
+    mut domain: serde_json::Result<CrawlRequest>,
+    spawner: CrawlSpawner,
+    db: Db,
+    jobs: Jobs,
+    summaries: SummariesDb,
+    audit: AuditLog,
+    actor: Option<String>,
+) -> Result<warp::reply::Response, Infallible> {
+    // An invalid body is answered as by a crawl request.
+    if let Ok(CrawlRequest { domain, config }) = &mut domain {
+        if !db
+            .sessions(domain)
+            .is_ok_and(|sessions| !sessions.is_empty())
+        {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: format!("{} has not been crawled", domain),
+                }),
+                StatusCode::NOT_FOUND,
+            )
+            .into_response());
+        }
+        if config.warm_start == WarmStart::Off {
+            config.warm_start = WarmStart::Append;
+        }
+    }
+
+    crawl(domain, spawner, db, jobs, summaries, audit, actor).await
+}
+
+/// Report what the last crawl session of the domain in query found different from the one
+/// before it: the URLs it found new, the ones it did not find anymore, and the ones whose status or
+/// content changed. Only meaningful for a session that fetched the stored URLs again, e.g. a
+/// recrawl.
+/// Respond with `404 Not Found` if the domain in query has not been crawled.
+pub(super) async fn diff(options: ListOptions, db: Db) -> Result<impl warp::Reply, Infallible> {
+    let reply = match db.diff_for_domain(&options.domain) {
+        Ok(Some(diff)) => warp::reply::with_status(warp::reply::json(&diff), StatusCode::OK),
+        Ok(None) => warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: format!("{} has not been crawled", options.domain),
+            }),
+            StatusCode::NOT_FOUND,
+        ),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: e.to_string(),
+            }),
+            StatusCode::NOT_FOUND,
+        ),
+    };
+
+    Ok(reply)
+}
+
+/// Register a job for `crawler` and spawn it on the crawl runtime, the crawl being audited as
+/// requested by `actor`. Return the status of the new job, before the crawl starts, or the id of
+/// the job already crawling the domain and scope as an error.
+pub(super) fn spawn_crawl(
+    mut crawler: Crawler,
+    spawner: CrawlSpawner,
+    db: Db,
+    jobs: Jobs,
+    summaries: SummariesDb,
+    audit: AuditLog,
+    actor: Option<String>,
+) -> Result<JobStatus, JobId> {
+    let domain = crawler.domain().clone();
+    let cancel = spawner.shutdown.child_token();
+    let id = jobs.create(
+        domain.clone(),
+        crawler.config().scope.clone(),
+        cancel.clone(),
+        crawler.progress(),
+        crawler.control(),
+    )?;
+
+    audit.record(
+        actor,
+        AuditAction::CrawlStarted {
+            domain: domain.clone(),
+        },
+    );
+
+    let status = jobs.status(id).expect("The job was just created");
+    let artifacts = spawner.artifacts.clone();
+    // Everything is keyed by the requested domain, the crawler may end up crawling the host it
+    // redirects to.
+    spawner.spawn(async move {
+        jobs.start(id);
+        let summary = crawler.crawl(db.clone(), cancel).await;
+        info!(
+            "Crawler done for {}: {:?}, {:?}",
+            crawler.domain(),
+            summary.outcome,
+            summary.stats
+        );
+        write_artifacts(&artifacts, id, &domain, &summary, &db).await;
+        // Stored before the job is finished, so that it is there once the job is, e.g. for a
+        // purge waiting for the crawl to stop.
+        let outcome = summary.outcome.clone();
+        summaries.lock().await.insert(domain, summary);
+        jobs.finish(id, outcome);
+        if let (Some(url), Some(status)) = (&crawler.config().callback_url, jobs.status(id)) {
+            notify_callback(crawler.client(), url, &status).await;
+        }
     });
 
+    Ok(status)
+}
+
+/// POST the final `status` of a crawl job to its `callback_url`, as GET /jobs/<id> would return
+/// it. A failure is only logged, once the retries are exhausted.
+async fn notify_callback(client: &Downloader, url: &Url, status: &JobStatus) {
+    let body = serde_json::to_vec(status).unwrap();
+    match client
+        .post_with_retries(url, "application/json", body)
+        .await
+    {
+        Ok(()) => info!("Notified {} that job {} is done", url, status.id),
+        Err(e) => warn!(
+            "Could not notify {} that job {} is done: {}",
+            url, status.id, e
+        ),
+    }
+}
+
+/// Write the artifacts of the crawl job `id` of `domain`, if they are kept: its `summary.json`, and
+/// the URLs of the domain as `urls.csv` and as a dump, `dump.ndjson.zst`. Failures are only
+/// logged, the crawl being done anyway.
+async fn write_artifacts(
+    artifacts: &Artifacts,
+    id: JobId,
+    domain: &Url,
+    summary: &CrawlSummary,
+    db: &Db,
+) {
+    let mut files = vec![("summary.json", serde_json::to_vec_pretty(summary).unwrap())];
+    match db.url_counts_for_domain(domain) {
+        Ok(mut urls) => {
+            urls.sort();
+            let csv = std::iter::once("url,count\n".to_string())
+                .chain(
+                    urls.into_iter()
+                        .map(|(url, count)| csv_row(&ExportedUrl { url, count })),
+                )
+                .collect::<String>();
+            files.push(("urls.csv", csv.into_bytes()));
+        }
+        Err(e) => warn!("Could not export the URLs of {}: {}", domain, e),
+    }
+    let mut dump = Vec::new();
+    match db.export(Some(domain), &mut dump) {
+        Ok(_) => files.push(("dump.ndjson.zst", dump)),
+        Err(e) => warn!("Could not dump {}: {}", domain, e),
+    }
+
+    for (name, contents) in files {
+        if let Err(e) = artifacts.write(id, name, &contents).await {
+            warn!("Could not write artifact {} of job {}: {}", name, id, e);
+        }
+    }
+}
+
+/// Fetch the URL in the body right away with the client a crawl would use, record it, and return
+/// what was found. The check runs on the crawl runtime, as crawls do.
+/// Respond with `400 Bad Request` if the body is not a valid check request, and with
+/// `422 Unprocessable Entity` if an URL is not an `http` or `https` URL with a host.
+pub(super) async fn check(
+    request: serde_json::Result<UrlCheckRequest>,
+    spawner: CrawlSpawner,
+    db: Db,
+) -> Result<impl warp::Reply, Infallible> {
+    let UrlCheckRequest {
+        url,
+        domain,
+        config,
+    } = match request {
+        Ok(request) => request,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: format!("Invalid check request: {}", e),
+                }),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+
+    if let Some(invalid) = std::iter::once(&url)
+        .chain(&domain)
+        .find_map(|url| InvalidUrl::check(url).err())
+    {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&invalid),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ));
+    }
+
+    let domain = match domain.or_else(|| url.join("/").ok()) {
+        Some(domain) => domain,
+        None => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: "URL cannot be a base".to_string(),
+                }),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+    let mut crawler = match Crawler::new(domain, config) {
+        Ok(crawler) => crawler,
+        Err(e) => {
+            warn!("Crawler error: {}", e);
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: format!("Invalid crawl request: {}", e),
+                }),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+
+    let check = spawner
+        .runtime
+        .spawn(async move { crawler.check(&url, &db).await });
+    Ok(match check.await {
+        Ok(check) => warp::reply::with_status(warp::reply::json(&check), StatusCode::OK),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: e.to_string(),
+            }),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+    })
+}
+
+/// Cancel the running crawls of the domain in query, if any, and respond with whether there was one.
+pub(super) async fn cancel(
+    options: ListOptions,
+    jobs: Jobs,
+    audit: AuditLog,
+    actor: Option<String>,
+) -> Result<impl warp::Reply, Infallible> {
+    let cancelled = jobs.cancel(&options.domain, options.scope().as_deref());
+    if !cancelled.is_empty() {
+        audit.record(
+            actor,
+            AuditAction::CrawlCancelled {
+                domain: options.domain.clone(),
+            },
+        );
+    }
+
+    Ok(warp::reply::json(&CancelResult {
+        domain: options.domain,
+        running: !cancelled.is_empty(),
+        job: cancelled.first().copied(),
+        jobs: cancelled,
+    }))
+}
+
+/// Forget everything stored for the domain in query: its URLs with their statuses, links, assets
+/// and indexed text, and the summary of its last crawl. The audit log and the artifacts of its
+/// jobs are kept. With `cancel`, its running crawls are cancelled first, and waited for until
+/// they stop, for up to `PURGE_CANCEL_TIMEOUT`.
+/// Respond with `404 Not Found` if the domain has not been crawled, and with `409 Conflict` if a
+/// crawl of the domain is running without `cancel`, or did not stop in time.
+pub(super) async fn purge(
+    options: PurgeOptions,
+    db: Db,
+    jobs: Jobs,
+    summaries: SummariesDb,
+    audit: AuditLog,
+    actor: Option<String>,
+) -> Result<impl warp::Reply, Infallible> {
+    let conflict = |error: &str| {
+        Ok(warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: error.to_string(),
+            }),
+            StatusCode::CONFLICT,
+        ))
+    };
+
+    // A cancelled crawl may still be storing what it fetched until it stops.
+    let mut cancelled = Vec::new();
+    if !jobs.unfinished(&options.domain).is_empty() {
+        if !options.cancel {
+            return conflict("A crawl of the domain is running");
+        }
+        cancelled = jobs.cancel(&options.domain, None);
+        if !cancelled.is_empty() {
+            audit.record(
+                actor.clone(),
+                AuditAction::CrawlCancelled {
+                    domain: options.domain.clone(),
+                },
+            );
+        }
+
+        let deadline = Instant::now() + PURGE_CANCEL_TIMEOUT;
+        while !jobs.unfinished(&options.domain).is_empty() {
+            if Instant::now() >= deadline {
+                return conflict("The crawls of the domain did not stop in time");
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    match db.remove_domain(&options.domain) {
+        Ok(()) => {}
+        Err(e @ DbError::DomainDoesNotExist) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: e.to_string(),
+                }),
+                StatusCode::NOT_FOUND,
+            ))
+        }
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: e.to_string(),
+                }),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+    summaries.lock().await.remove(&options.domain);
+    audit.record(
+        actor,
+        AuditAction::DataPurged {
+            domain: options.domain.clone(),
+        },
+    );
+
     Ok(warp::reply::with_status(
-        warp::reply::json(&"{}".to_string()),
+        warp::reply::json(&PurgeResult {
+            domain: options.domain,
+            cancelled,
+        }),
+        StatusCode::OK,
+    ))
+}
+
+/// Pause the running crawls of the domain in query, or resume them if not `paused`, and return the
+/// status of the oldest job. Downloads in flight finish, and the URLs found meanwhile are fetched
+/// once the crawl resumes.
+/// Respond with `404 Not Found` if no crawl of the domain is running.
+pub(super) async fn pause(
+    options: ListOptions,
+    jobs: Jobs,
+    audit: AuditLog,
+    actor: Option<String>,
+    paused: bool,
+) -> Result<impl warp::Reply, Infallible> {
+    let scope = options.scope();
+    let ids = if paused {
+        jobs.pause(&options.domain, scope.as_deref())
+    } else {
+        jobs.resume(&options.domain, scope.as_deref())
+    };
+    let status = match ids.first().and_then(|&id| jobs.status(id)) {
+        Some(status) => status,
+        None => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: "No running crawl for domain".to_string(),
+                }),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+    };
+
+    let domain = options.domain;
+    audit.record(
+        actor,
+        if paused {
+            AuditAction::CrawlPaused { domain }
+        } else {
+            AuditAction::CrawlResumed { domain }
+        },
+    );
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&status),
         StatusCode::OK,
     ))
 }
 
 /// Handle a list request.
-/// Retrieve the currently crawled unique URLs from the database.
+/// Retrieve the currently crawled unique URLs from the database, ordered by path unless sorted by
+/// `count`, only the ones under the `prefix` and with at least `min_count` occurences if given.
 /// Respond with `404 Not Found` if the domain in query has not been crawled.
-pub(super) async fn list(options: ListOptions, db: Db) -> Result<impl warp::Reply, Infallible> {
-    let urls = match db.unique_urls_for_domain(&options.domain) {
-        Ok(urls) => urls,
-        Err(e) => {
+/// The `X-Crawl-Truncated` header tells whether the last crawl stopped early because of a limit.
+/// With `progress`, the URLs come in a `ListResult` telling whether a crawl of the domain is still
+/// running, in which case a domain with no URL stored yet has an empty list instead of a 404.
+/// With an `offset` or a `limit`, they come in a `ListResult` as well, a page of them along with
+/// the total number of URLs listed and the offset of the next page.
+/// Respond with `400 Bad Request` if the limit is 0 or above `MAX_LIST_LIMIT`, or if the prefix
+/// does not start with `/`.
+pub(super) async fn list(
+    options: ListOptions,
+    db: Db,
+    jobs: Jobs,
+) -> Result<impl warp::Reply, Infallible> {
+    let page = match (options.offset, options.limit) {
+        (None, None) => None,
+        (offset, limit) => Some((offset.unwrap_or(0), limit.unwrap_or(DEFAULT_LIST_LIMIT))),
+    };
+    let wrapped = options.progress || page.is_some();
+    let progress = if wrapped {
+        jobs.active(&options.domain, options.scope().as_deref())
+            .map(|(_, progress)| progress)
+    } else {
+        None
+    };
+    let query = UrlQuery {
+        prefix: options.prefix.clone(),
+        min_count: options.min_count,
+        sort: options.sort,
+    };
+    let (offset, limit) = page.unwrap_or((0, usize::MAX));
+    let reply = match page {
+        Some((_, limit)) if limit == 0 || limit > MAX_LIST_LIMIT => warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: format!("The limit must be between 1 and {}", MAX_LIST_LIMIT),
+            }),
+            StatusCode::BAD_REQUEST,
+        ),
+        _ if !options
+            .prefix
+            .as_deref()
+            .is_none_or(|prefix| prefix.starts_with('/')) =>
+        {
+            warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: "The prefix must start with /".to_string(),
+                }),
+                StatusCode::BAD_REQUEST,
+            )
+        }
+        _ if options.detail => {
+            let urls = db.url_details_page(&options.domain, &query, offset, limit);
+            let urls = urls.map(|(urls, total)| {
+                let urls = urls
+                    .into_iter()
+                    .map(|(url, count, fetch)| UrlDetail { url, count, fetch })
+                    .collect();
+                (urls, total)
+            });
+            list_reply(urls, page.map(|(offset, _)| offset), wrapped, progress)
+        }
+        _ => {
+            let urls = db
+                .url_counts_page(&options.domain, &query, offset, limit)
+                .map(|(urls, total)| (urls.into_iter().map(|(url, _)| url).collect(), total));
+            list_reply(urls, page.map(|(offset, _)| offset), wrapped, progress)
+        }
+    };
+    let truncated = db.is_truncated(&options.domain).unwrap_or(false);
+
+    Ok(warp::reply::with_header(
+        reply,
+        "x-crawl-truncated",
+        truncated.to_string(),
+    ))
+}
+
+/// Reply with the listed `urls` out of `total`, in a `ListResult` if `wrapped`, i.e. if asked for
+/// the `progress` of the crawl or for the page at `offset`.
+fn list_reply<T: Serialize>(
+    urls: Result<(Vec<T>, usize), DbError>,
+    offset: Option<usize>,
+    wrapped: bool,
+    progress: Option<ProgressCounters>,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    // A running crawl may not have stored any URL yet.
+    let urls = match urls {
+        Err(DbError::DomainDoesNotExist) if progress.is_some() => Ok((Vec::new(), 0)),
+        urls => urls,
+    };
+
+    match urls {
+        Ok((urls, total)) if wrapped => {
+            let result = ListResult {
+                next_offset: offset
+                    .map(|offset| offset + urls.len())
+                    .filter(|&next| next < total),
+                urls,
+                total,
+                crawl_in_progress: progress.is_some(),
+                progress,
+            };
+            warp::reply::with_status(warp::reply::json(&result), StatusCode::OK)
+        }
+        Ok((urls, _)) => warp::reply::with_status(warp::reply::json(&urls), StatusCode::OK),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: e.to_string(),
+            }),
+            StatusCode::NOT_FOUND,
+        ),
+    }
+}
+
+/// Stream the events of the oldest running crawl of the domain in query as Server-Sent Events, each one
+/// being a JSON `CrawlEvent`, until the `finished` one. A client too slow to keep up gets a
+/// `lagged` event with the number of events it missed.
+/// Respond with `404 Not Found` if no crawl of the domain is running.
+pub(super) async fn progress(
+    options: ListOptions,
+    jobs: Jobs,
+) -> Result<warp::reply::Response, Infallible> {
+    let events = match jobs.subscribe(&options.domain, options.scope().as_deref()) {
+        Some(events) => events,
+        None => {
             return Ok(warp::reply::with_status(
                 warp::reply::json(&Error {
-                    error: e.to_string(),
+                    error: "No running crawl for domain".to_string(),
                 }),
                 StatusCode::NOT_FOUND,
-            ));
+            )
+            .into_response());
+        }
+    };
+
+    // The receiver is dropped after the `finished` event, ending the stream.
+    let events = futures::stream::unfold(Some(events), |events| async move {
+        let mut events = events?;
+        let (event, events) = match events.recv().await {
+            Ok(event) => {
+                let finished = matches!(event.kind, CrawlEventKind::Finished { .. });
+                let sse = warp::sse::Event::default().json_data(&event);
+                (sse, if finished { None } else { Some(events) })
+            }
+            Err(RecvError::Lagged(missed)) => {
+                let lagged = serde_json::json!({ "event": "lagged", "missed": missed });
+                (warp::sse::Event::default().json_data(lagged), Some(events))
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        Some((event, events))
+    });
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)).into_response())
+}
+
+/// Return the status of the job in path.
+/// Respond with `404 Not Found` if there is no such job.
+pub(super) async fn job(id: JobId, jobs: Jobs) -> Result<impl warp::Reply, Infallible> {
+    let reply = match jobs.status(id) {
+        Some(status) => warp::reply::with_status(warp::reply::json(&status), StatusCode::OK),
+        None => warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: "No such job".to_string(),
+            }),
+            StatusCode::NOT_FOUND,
+        ),
+    };
+
+    Ok(reply)
+}
+
+/// Return the artifacts of the job in path, with their size and when they were written. The list is
+/// empty until the crawl is done, or if artifacts are not kept.
+/// Respond with `404 Not Found` if there is no such job.
+pub(super) async fn artifacts(
+    id: JobId,
+    jobs: Jobs,
+    artifacts: Artifacts,
+) -> Result<impl warp::Reply, Infallible> {
+    if jobs.status(id).is_none() {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: "No such job".to_string(),
+            }),
+            StatusCode::NOT_FOUND,
+        ));
+    }
+
+    Ok(match artifacts.list(id).await {
+        Ok(list) => warp::reply::with_status(warp::reply::json(&list), StatusCode::OK),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: e.to_string(),
+            }),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+    })
+}
+
+/// Return the artifact in path of the job in path, streamed from disk. A single byte range can be
+/// asked for with a `Range` header, e.g. to resume the download of a large dump.
+/// Respond with `206 Partial Content` for a range, with `416 Range Not Satisfiable` for a range
+/// starting past the end of the file, and with `404 Not Found` if there is no such job or
+/// artifact.
+pub(super) async fn artifact(
+    id: JobId,
+    name: String,
+    range: Option<String>,
+    jobs: Jobs,
+    artifacts: Artifacts,
+) -> Result<warp::reply::Response, Infallible> {
+    let not_found = |error: &str| {
+        Ok(warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: error.to_string(),
+            }),
+            StatusCode::NOT_FOUND,
+        )
+        .into_response())
+    };
+    if jobs.status(id).is_none() {
+        return not_found("No such job");
+    }
+    let path = match artifacts.path(id, &name) {
+        Some(path) => path,
+        None => return not_found("No such artifact"),
+    };
+    let mut file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(_) => return not_found("No such artifact"),
+    };
+    let len = match file.metadata().await {
+        Ok(metadata) if metadata.is_file() => metadata.len(),
+        _ => return not_found("No such artifact"),
+    };
+
+    let content_type = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => "application/json",
+        Some("csv") => "text/csv",
+        Some("zst") => "application/zstd",
+        _ => "application/octet-stream",
+    };
+    let response = Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    let response = match ByteRange::parse(range.as_deref(), len) {
+        ByteRange::Whole => response
+            .header(header::CONTENT_LENGTH, len)
+            .body(Body::wrap_stream(ReaderStream::new(file))),
+        ByteRange::Partial { start, end } => {
+            if let Err(e) = file.seek(std::io::SeekFrom::Start(start)).await {
+                warn!("Could not read artifact {} of job {}: {}", name, id, e);
+                return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+            }
+            response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_LENGTH, end - start + 1)
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, end, len),
+                )
+                .body(Body::wrap_stream(ReaderStream::new(
+                    file.take(end - start + 1),
+                )))
         }
+        ByteRange::Unsatisfiable => response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .body(Body::empty()),
+    };
+
+    Ok(response.unwrap())
+}
+
+/// Return the status of every queued or running crawl, e.g. its domain, when it started and the
+/// number of pages fetched so far.
+pub(super) async fn crawlers(jobs: Jobs) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&jobs.active_statuses()))
+}
+
+/// Register a recurring crawl of the domain in body, and respond with the status of the schedule,
+/// e.g. when its first crawl is due. Crawl options are checked as for a crawl request.
+/// Respond with `400 Bad Request` if the body is not a valid schedule request, e.g. with an
+/// invalid cron expression, or with crawl options no crawler can be built with, and with
+/// `422 Unprocessable Entity` if the domain or the `callback_url` is not an `http` or `https` URL
+/// with a host.
+pub(super) async fn create_schedule(
+    request: serde_json::Result<ScheduleRequest>,
+    schedules: Schedules,
+    audit: AuditLog,
+    actor: Option<String>,
+) -> Result<impl warp::Reply, Infallible> {
+    let bad_request = |error: String| {
+        Ok(warp::reply::with_status(
+            warp::reply::json(&Error { error }),
+            StatusCode::BAD_REQUEST,
+        ))
+    };
+    let ScheduleRequest {
+        domain,
+        cron,
+        interval_secs,
+        config,
+    } = match request {
+        Ok(request) => request,
+        Err(e) => return bad_request(format!("Invalid schedule request: {}", e)),
+    };
+
+    if let Some(invalid) = std::iter::once(&domain)
+        .chain(&config.callback_url)
+        .find_map(|url| InvalidUrl::check(url).err())
+    {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&invalid),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ));
+    }
+    if config.dry_run || config.probe {
+        return bad_request("A dry run or a probe cannot be scheduled".to_string());
+    }
+    let cadence = match Cadence::new(cron, interval_secs) {
+        Ok(cadence) => cadence,
+        Err(e) => return bad_request(e),
     };
+    if let Err(e) = Crawler::new(domain.clone(), config.clone()) {
+        return bad_request(e.to_string());
+    }
+
+    audit.record(
+        actor,
+        AuditAction::ScheduleCreated {
+            domain: domain.clone(),
+        },
+    );
+    let status = schedules.add(domain, config, cadence, Utc::now());
+    info!("Registered schedule {} of {}", status.id, status.domain);
 
     Ok(warp::reply::with_status(
-        warp::reply::json(&urls),
+        warp::reply::json(&status),
         StatusCode::OK,
     ))
 }
 
+/// Return the recurring crawls, oldest first, optionally only the ones of the domain in query.
+pub(super) async fn schedules(
+    options: ScheduleOptions,
+    schedules: Schedules,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&schedules.list(options.domain.as_ref())))
+}
+
+/// Unregister the schedule in path, and respond with its last status. A crawl it started goes on,
+/// and can be cancelled as any other.
+/// Respond with `404 Not Found` if there is no such schedule.
+pub(super) async fn delete_schedule(
+    id: ScheduleId,
+    schedules: Schedules,
+    audit: AuditLog,
+    actor: Option<String>,
+) -> Result<impl warp::Reply, Infallible> {
+    let reply = match schedules.remove(id) {
+        Some(status) => {
+            audit.record(
+                actor,
+                AuditAction::ScheduleDeleted {
+                    domain: status.domain.clone(),
+                },
+            );
+            warp::reply::with_status(warp::reply::json(&status), StatusCode::OK)
+        }
+        None => warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: "No such schedule".to_string(),
+            }),
+            StatusCode::NOT_FOUND,
+        ),
+    };
+
+    Ok(reply)
+}
+
 /// Count the occurences for the URL in query.
 /// Respond with 404 Not Found if the domain part of the URL has not been crawled.
 pub(super) async fn count(options: CountOptions, db: Db) -> Result<impl warp::Reply, Infallible> {
@@ -109,3 +961,672 @@ pub(super) async fn count(options: CountOptions, db: Db) -> Result<impl warp::Re
         StatusCode::OK,
     ))
 }
+
+/// Count the occurences of each URL of the body, in the same order, reading them from the
+/// database at once. The URLs of domains that have not been crawled have no count, but an error.
+/// Respond with `400 Bad Request` if there are more than `MAX_BATCH_COUNT_URLS` URLs, and with
+/// `500 Internal Server Error` if the database fails.
+pub(super) async fn batch_count(
+    request: BatchCountRequest,
+    db: Db,
+) -> Result<impl warp::Reply, Infallible> {
+    if request.urls.len() > MAX_BATCH_COUNT_URLS {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: format!(
+                    "At most {} URLs can be counted at once",
+                    MAX_BATCH_COUNT_URLS
+                ),
+            }),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let counts = match db.url_counts(&request.urls) {
+        Ok(counts) => counts,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: e.to_string(),
+                }),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+    };
+    let counts: Vec<_> = request
+        .urls
+        .into_iter()
+        .zip(counts)
+        .map(|(url, count)| match count {
+            Ok(count) => BatchCount {
+                url,
+                count: Some(count),
+                error: None,
+            },
+            Err(e) => BatchCount {
+                url,
+                count: None,
+                error: Some(e.to_string()),
+            },
+        })
+        .collect();
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&counts),
+        StatusCode::OK,
+    ))
+}
+
+/// List the third-party hosts the domain in query links to, most linked first.
+/// Respond with `404 Not Found` if the domain in query has not been crawled.
+pub(super) async fn third_party(
+    options: ListOptions,
+    db: Db,
+) -> Result<impl warp::Reply, Infallible> {
+    let mut hosts = match db.third_party_for_domain(&options.domain) {
+        Ok(hosts) => hosts,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: e.to_string(),
+                }),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+    };
+    hosts.sort_by(|(a_host, a_links), (b_host, b_links)| {
+        b_links.cmp(a_links).then_with(|| a_host.cmp(b_host))
+    });
+
+    let domains: Vec<_> = hosts
+        .into_iter()
+        .map(|(domain, links)| ThirdPartyDomain { domain, links })
+        .collect();
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&domains),
+        StatusCode::OK,
+    ))
+}
+
+/// List the assets the pages of the domain in query refer to, only the ones of `kind` if set,
+/// sorted by URL.
+/// Respond with `404 Not Found` if the domain in query has not been crawled.
+pub(super) async fn assets(options: AssetsOptions, db: Db) -> Result<impl warp::Reply, Infallible> {
+    let mut assets: Vec<_> = match db.assets_for_domain(&options.domain) {
+        Ok(assets) => assets
+            .into_iter()
+            .filter(|(_, asset)| options.kind.is_none_or(|kind| asset.kind == kind))
+            .map(|(url, asset)| DomainAsset {
+                url,
+                kind: asset.kind,
+                count: asset.count,
+            })
+            .collect(),
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: e.to_string(),
+                }),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+    };
+    assets.sort_by(|a, b| a.url.cmp(&b.url));
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&assets),
+        StatusCode::OK,
+    ))
+}
+
+/// List the crawl sessions of the domain in query, and the sessions that found each of its URLs
+/// first and last, the ones found since `not_seen_since` left out.
+/// Respond with `404 Not Found` if the domain in query has not been crawled.
+pub(super) async fn lineage(
+    options: LineageOptions,
+    db: Db,
+) -> Result<impl warp::Reply, Infallible> {
+    let result = db.sessions(&options.domain).and_then(|sessions| {
+        Ok((
+            sessions,
+            db.robots_modes(&options.domain)?,
+            db.lineage_for_domain(&options.domain)?,
+        ))
+    });
+    let (sessions, robots_modes, mut urls) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: e.to_string(),
+                }),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+    };
+
+    if let Some(since) = options.not_seen_since {
+        urls.retain(|(_, lineage)| {
+            let last_seen = (lineage.last_seen as usize)
+                .checked_sub(1)
+                .and_then(|session| sessions.get(session));
+            last_seen.is_some_and(|started_at| *started_at < since)
+        });
+    }
+    urls.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&LineageResult {
+            sessions: sessions
+                .into_iter()
+                .zip(1..)
+                .zip(robots_modes)
+                .map(|((started_at, id), robots_mode)| CrawlSession {
+                    id,
+                    started_at,
+                    robots_mode,
+                })
+                .collect(),
+            urls: urls
+                .into_iter()
+                .map(|(url, lineage)| UrlLineage { url, lineage })
+                .collect(),
+        }),
+        StatusCode::OK,
+    ))
+}
+
+/// List the URLs of the domain in query whose last fetch failed with a `4xx` or `5xx` status or
+/// could not reach the server, with some of the pages linking to them, sorted by URL.
+/// Respond with `404 Not Found` if the domain in query has not been crawled.
+pub(super) async fn broken(options: ListOptions, db: Db) -> Result<impl warp::Reply, Infallible> {
+    let result = db
+        .statuses_for_domain(&options.domain)
+        .and_then(|statuses| {
+            statuses
+                .into_iter()
+                .filter(|(_, status)| status.is_broken())
+                .map(|(url, status)| {
+                    let linked_from = db.referrers(&url)?;
+                    Ok(BrokenLink {
+                        url,
+                        status,
+                        linked_from,
+                    })
+                })
+                .collect::<Result<Vec<_>, DbError>>()
+        });
+    let mut broken = match result {
+        Ok(broken) => broken,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: e.to_string(),
+                }),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+    };
+    broken.sort_by(|a, b| a.url.cmp(&b.url));
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&broken),
+        StatusCode::OK,
+    ))
+}
+
+/// List the URLs of the domain in query whose last fetch downloaded a body with issues, e.g. a
+/// truncated one or one in another charset than declared, sorted by URL.
+/// Respond with `404 Not Found` if the domain in query has not been crawled.
+pub(super) async fn issues(options: ListOptions, db: Db) -> Result<impl warp::Reply, Infallible> {
+    let mut pages: Vec<_> = match db.statuses_for_domain(&options.domain) {
+        Ok(statuses) => statuses
+            .into_iter()
+            .filter(|(_, status)| !status.issues.is_empty())
+            .map(|(url, status)| PageIssues {
+                url,
+                content_type: status.content_type,
+                issues: status.issues,
+            })
+            .collect(),
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: e.to_string(),
+                }),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+    };
+    pages.sort_by(|a, b| a.url.cmp(&b.url));
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&pages),
+        StatusCode::OK,
+    ))
+}
+
+/// List the groups of URLs of the domain in query whose last fetch downloaded the same content.
+/// Respond with `404 Not Found` if the domain in query has not been crawled.
+pub(super) async fn duplicates(
+    options: ListOptions,
+    db: Db,
+) -> Result<impl warp::Reply, Infallible> {
+    let duplicates = match db.duplicates_for_domain(&options.domain) {
+        Ok(duplicates) => duplicates,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: e.to_string(),
+                }),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+    };
+    let duplicates: Vec<_> = duplicates
+        .into_iter()
+        .map(|(content_hash, urls)| DuplicateGroup { content_hash, urls })
+        .collect();
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&duplicates),
+        StatusCode::OK,
+    ))
+}
+
+/// Return the pages of the domain in query and the links between them, as JSON, or rendered for
+/// Graphviz or Gephi.
+/// Respond with `404 Not Found` if the domain in query has not been crawled.
+pub(super) async fn graph(options: GraphOptions, db: Db) -> Result<impl warp::Reply, Infallible> {
+    let graph = match db.graph_for_domain(&options.domain) {
+        Ok(graph) => graph,
+        Err(e) => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: e.to_string(),
+                }),
+                StatusCode::NOT_FOUND,
+            )
+            .into_response());
+        }
+    };
+
+    let (content_type, body) = match options.format {
+        GraphFormat::Json => ("application/json", serde_json::to_string(&graph).unwrap()),
+        GraphFormat::Dot => ("text/vnd.graphviz", graph.to_dot()),
+        GraphFormat::Graphml => ("application/graphml+xml", graph.to_graphml()),
+    };
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .unwrap())
+}
+
+/// Return the pages of the domain in query whose text contains every word of `q`, the most
+/// occurences first, with a snippet of their text. Only the pages crawled with `index_text` can
+/// be found.
+/// Respond with `400 Bad Request` if `q` has no words or the limit is above `MAX_SEARCH_LIMIT`,
+/// and with `404 Not Found` if the domain in query has not been crawled.
+pub(super) async fn search(options: SearchOptions, db: Db) -> Result<impl warp::Reply, Infallible> {
+    let limit = options.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+    if limit > MAX_SEARCH_LIMIT {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: format!("The limit must be at most {}", MAX_SEARCH_LIMIT),
+            }),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    if options.q.chars().all(|c| !c.is_alphanumeric()) {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: "The query has no words".to_string(),
+            }),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    Ok(match db.search(&options.domain, &options.q, limit) {
+        Ok(hits) => warp::reply::with_status(warp::reply::json(&hits), StatusCode::OK),
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: e.to_string(),
+            }),
+            StatusCode::NOT_FOUND,
+        ),
+    })
+}
+
+/// Export the URLs of the domain in query with their number of occurences, as a JSON array or
+/// as CSV with a `url,count` header. The rows are streamed, so that big domains are not
+/// serialized in one go.
+/// Respond with `404 Not Found` if the domain in query has not been crawled, or
+/// `500 Internal Server Error` if it cannot be read.
+pub(super) async fn export(options: ExportOptions, db: Db) -> Result<impl warp::Reply, Infallible> {
+    let mut urls = match db.url_counts_for_domain(&options.domain) {
+        Ok(urls) => urls,
+        Err(e) => {
+            let status = match e {
+                DbError::DomainDoesNotExist => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: e.to_string(),
+                }),
+                status,
+            )
+            .into_response());
+        }
+    };
+    urls.sort();
+
+    let rows = urls
+        .into_iter()
+        .map(|(url, count)| ExportedUrl { url, count });
+    // Each row is only serialized once the body gets to it.
+    let (content_type, chunks): (_, Box<dyn Iterator<Item = String> + Send>) = match options.format
+    {
+        ExportFormat::Json => {
+            let items = rows.enumerate().map(|(i, row)| {
+                let separator = if i == 0 { "" } else { "," };
+                format!("{}{}", separator, serde_json::to_string(&row).unwrap())
+            });
+            let chunks = std::iter::once("[".to_string())
+                .chain(items)
+                .chain(std::iter::once("]".to_string()));
+            ("application/json", Box::new(chunks))
+        }
+        ExportFormat::Csv => {
+            let lines = rows.map(|row| csv_row(&row));
+            let chunks = std::iter::once("url,count\n".to_string()).chain(lines);
+            ("text/csv", Box::new(chunks))
+        }
+    };
+    let body = Body::wrap_stream(futures::stream::iter(chunks.map(Ok::<_, Infallible>)));
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .body(body)
+        .unwrap())
+}
+
+/// Return a dump of the domain in query, or of every domain, to be loaded with the import request
+/// or the `import` command.
+/// Respond with `404 Not Found` if the domain does not exist.
+pub(super) async fn dump(options: DumpOptions, db: Db) -> Result<impl warp::Reply, Infallible> {
+    let mut dump = Vec::new();
+    if let Err(e) = db.export(options.domain.as_ref(), &mut dump) {
+        let status = match e {
+            DumpError::Db(DbError::DomainDoesNotExist) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: e.to_string(),
+            }),
+            status,
+        )
+        .into_response());
+    }
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/zstd")
+        .body(Body::from(dump))
+        .unwrap())
+}
+
+/// Load the dump in the body into the database, away from the threads serving the API, and
+/// return what it held. Respond with `400 Bad Request`, writing nothing, if the dump is corrupted
+/// or of another version, and with `413 Payload Too Large` if it is bigger than
+/// `decompressed_limit` once decompressed.
+pub(super) async fn import(
+    dump: warp::hyper::body::Bytes,
+    db: Db,
+    decompressed_limit: u64,
+) -> Result<impl warp::Reply, Infallible> {
+    let imported =
+        tokio::task::spawn_blocking(move || db.import(&dump[..], decompressed_limit)).await;
+    let reply = match imported {
+        Ok(Ok(summary)) => {
+            info!("Imported {:?}", summary);
+            warp::reply::with_status(warp::reply::json(&summary), StatusCode::OK)
+        }
+        Err(e) => {
+            error!("Import panicked: {}", e);
+            warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: "Import failed".to_string(),
+                }),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        }
+        Ok(Err(e)) => {
+            let status = match e {
+                DumpError::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                DumpError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: e.to_string(),
+                }),
+                status,
+            )
+        }
+    };
+
+    Ok(reply)
+}
+
+/// The CSV line of an exported URL, under the `url,count` header.
+fn csv_row(row: &ExportedUrl) -> String {
+    format!("{},{}\n", csv_field(row.url.as_str()), row.count)
+}
+
+/// Quote a CSV field if it contains a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Return the summary of the last finished crawl of the domain in query.
+/// Respond with `404 Not Found` if no crawl of the domain has finished yet.
+pub(super) async fn summary(
+    options: ListOptions,
+    summaries: SummariesDb,
+) -> Result<impl warp::Reply, Infallible> {
+    let summaries = summaries.lock().await;
+    let reply = match summaries.get(&options.domain) {
+        Some(summary) => warp::reply::with_status(warp::reply::json(summary), StatusCode::OK),
+        None => warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: "No finished crawl for domain".to_string(),
+            }),
+            StatusCode::NOT_FOUND,
+        ),
+    };
+
+    Ok(reply)
+}
+
+/// Return the summaries of the last finished crawl of every domain that has all the tags in
+/// query, oldest first.
+pub(super) async fn summaries(
+    tags: TagFilter,
+    summaries: SummariesDb,
+) -> Result<impl warp::Reply, Infallible> {
+    let summaries = summaries.lock().await;
+    let mut matching: Vec<_> = summaries
+        .values()
+        .filter(|summary| {
+            tags.iter()
+                .all(|(key, value)| summary.options.tags.get(key) == Some(value))
+        })
+        .collect();
+    matching.sort_by_key(|summary| summary.finished_at);
+
+    Ok(warp::reply::json(&matching))
+}
+
+/// Return the audit log, oldest entry first, optionally only for the domain in query.
+pub(super) async fn audit(
+    options: AuditOptions,
+    audit: AuditLog,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&audit.entries(options.domain.as_ref())))
+}
+
+/// Report the uptime, the number of running crawls and how much the database holds.
+/// Respond with `503 Service Unavailable` if the database cannot be read.
+pub(super) async fn status(
+    db: Db,
+    jobs: Jobs,
+    started: Instant,
+) -> Result<impl warp::Reply, Infallible> {
+    let reply = match db.totals() {
+        Ok(totals) => {
+            let status = Status {
+                uptime_secs: started.elapsed().as_secs(),
+                active_crawlers: jobs.active_count(),
+                totals,
+                memory: db.memory_usage(),
+            };
+            warp::reply::with_status(warp::reply::json(&status), StatusCode::OK)
+        }
+        Err(e) => warp::reply::with_status(
+            warp::reply::json(&Error {
+                error: e.to_string(),
+            }),
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+    };
+
+    Ok(reply)
+}
+
+/// Tell whether the service can take requests. A degraded storage does not make it unready, as
+/// writes are kept in memory until the backend recovers.
+pub(super) async fn readyz(db: Db) -> Result<impl warp::Reply, Infallible> {
+    let storage = db.health();
+    let status = if storage.degraded {
+        "degraded"
+    } else {
+        "ready"
+    };
+
+    Ok(warp::reply::json(&Readiness {
+        status: status.to_string(),
+        storage,
+    }))
+}
+
+/// Expose metrics in the Prometheus text format.
+pub(super) async fn metrics(db: Db, jobs: Jobs) -> Result<impl warp::Reply, Infallible> {
+    let storage = db.health();
+    let metrics = [
+        (
+            "crawler_storage_degraded",
+            "Whether the storage backend fails and writes are kept in memory.",
+            storage.degraded as usize,
+        ),
+        (
+            "crawler_storage_buffered_writes",
+            "Number of writes waiting for the storage backend to recover.",
+            storage.buffered,
+        ),
+    ];
+
+    let mut body: String = metrics
+        .iter()
+        .map(|(name, help, value)| {
+            format!(
+                "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n",
+                name = name,
+                help = help,
+                value = value
+            )
+        })
+        .collect();
+
+    // Every limit is listed, rejecting nothing yet or not.
+    let rejected = jobs.rejected();
+    body.push_str(
+        "# HELP crawler_urls_rejected_total Number of found URLs not fetched as they hit a limit \
+         of their crawl.\n# TYPE crawler_urls_rejected_total counter\n",
+    );
+    for reason in SkipReason::GUARDS {
+        body.push_str(&format!(
+            "crawler_urls_rejected_total{{reason=\"{}\"}} {}\n",
+            reason.as_str(),
+            rejected.get(&reason).copied().unwrap_or(0)
+        ));
+    }
+
+    Ok(warp::reply::with_header(
+        body,
+        header::CONTENT_TYPE,
+        "text/plain; version=0.0.4",
+    ))
+}
+
+/// Check the URLs in the request against the `robots.txt` kept from the last crawl of the domain,
+/// to see why the crawler skipped some of them.
+/// Respond with `404 Not Found` if no `robots.txt` was kept for the domain.
+pub(super) async fn robots_check(
+    request: RobotsCheckRequest,
+    db: Db,
+) -> Result<impl warp::Reply, Infallible> {
+    let robots_txt = match db.robots_txt(&request.domain) {
+        Ok(Some(robots_txt)) => robots_txt,
+        _ => {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&Error {
+                    error: "No robots.txt kept for domain".to_string(),
+                }),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+    };
+
+    let checks: Vec<_> = request
+        .urls
+        .into_iter()
+        .map(|url| RobotsCheck {
+            decision: robots::check(&robots_txt, &url),
+            url,
+        })
+        .collect();
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&checks),
+        StatusCode::OK,
+    ))
+}
+
+/// Canonicalize the URLs in the request using the given rules, without crawling anything.
+/// Shows both the per-URL result and the deduplicated list the crawler would end up with.
+pub(super) async fn normalize(request: NormalizeRequest) -> Result<impl warp::Reply, Infallible> {
+    let NormalizeRequest { urls, config } = request;
+    let mut seen = HashSet::new();
+    let mut unique = Vec::new();
+
+    let urls = urls
+        .into_iter()
+        .map(|url| {
+            let normalized = config.normalize(&url);
+            if seen.insert(normalized.clone()) {
+                unique.push(normalized.clone());
+            }
+
+            NormalizedUrl { url, normalized }
+        })
+        .collect();
+
+    Ok(warp::reply::json(&NormalizeResult { urls, unique }))
+}
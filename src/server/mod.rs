@@ -1,25 +1,151 @@
 mod filters;
 mod handlers;
+mod openapi;
+mod rate_limit;
+mod scheduler;
+mod systemd;
+mod tls;
 
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
+use chrono::{DateTime, Utc};
+use futures::future::{BoxFuture, FutureExt};
 use serde::{Deserialize, Serialize};
 
-use tokio::{signal::{self, unix::SignalKind}, sync::{broadcast, Mutex}};
-use tracing::info;
+use tokio::{
+    runtime::{self, Runtime},
+    signal::{self, unix::SignalKind},
+    sync::{Mutex, Notify},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
 use url::Url;
 
 use warp::Filter;
 
-use crate::db::Db;
+use crate::{
+    artifacts::Artifacts,
+    audit::AuditLog,
+    crawler::{CrawlerConfig, ProgressCounters},
+    db::{Db, DbTotals, FetchStatus, Lineage, MemoryUsage, StorageHealth, UrlSort},
+    diagnostics::ContentIssue,
+    jobs::{JobId, Jobs},
+    normalize::NormalizeConfig,
+    parser::AssetKind,
+    robots::{RobotsDecision, RobotsMode},
+    schedules::Schedules,
+    settings::Settings,
+    summary::CrawlSummary,
+};
 
-/// Database of running crawlers.
-type CrawlersDb = Arc<Mutex<HashSet<Url>>>;
+/// Maximum number of URLs of a batch count request.
+const MAX_BATCH_COUNT_URLS: usize = 10_000;
+
+/// Number of URLs a page of the list request has unless it gives a `limit`, and the most it can
+/// ask for.
+const DEFAULT_LIST_LIMIT: usize = 1000;
+const MAX_LIST_LIMIT: usize = 10_000;
+
+/// Number of pages a search request returns unless it gives a `limit`, and the most it can ask for.
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+const MAX_SEARCH_LIMIT: usize = 100;
+
+/// Longest time the crawls cancelled on shutdown are waited for, to store their summary and call
+/// their `callback_url`, before the crawl runtime drops them.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Schemes of the URLs that can be crawled.
+const SUPPORTED_SCHEMES: [&str; 2] = ["http", "https"];
+
+/// Error returned with `422 Unprocessable Entity` for an URL that cannot be crawled, e.g.
+/// `ftp://example.com` or `file:///etc/passwd`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvalidUrl {
+    error: String,
+    /// `unsupported_scheme` or `missing_host`.
+    code: String,
+    supported_schemes: Vec<String>,
+}
+
+impl InvalidUrl {
+    /// Check that `url` can be crawled: it is `http` or `https`, and has a host. The host is
+    /// checked anyway, although `http` and `https` URLs cannot be parsed without one.
+    fn check(url: &Url) -> Result<(), Self> {
+        let (code, error) = if !SUPPORTED_SCHEMES.contains(&url.scheme()) {
+            (
+                "unsupported_scheme",
+                format!(
+                    "Unsupported scheme {:?} of {}, expected one of {}",
+                    url.scheme(),
+                    url,
+                    SUPPORTED_SCHEMES.join(", ")
+                ),
+            )
+        } else if url.host_str().is_none_or(str::is_empty) {
+            ("missing_host", format!("URL {} has no host", url))
+        } else {
+            return Ok(());
+        };
+
+        Err(Self {
+            error,
+            code: code.to_string(),
+            supported_schemes: SUPPORTED_SCHEMES.iter().map(|s| s.to_string()).collect(),
+        })
+    }
+}
+
+/// Summary of the last finished crawl of each domain.
+type SummariesDb = Arc<Mutex<HashMap<Url, CrawlSummary>>>;
 
 /// GET query options for list request.
 #[derive(Debug, Deserialize)]
 struct ListOptions {
     domain: Url,
+    /// Comma-separated scope prefixes of the crawl to act on, e.g. `/blog/,/docs/`, an empty one
+    /// being the crawl of the whole domain. Every crawl of the domain if not given, the oldest
+    /// one when a single crawl is needed.
+    scope: Option<String>,
+    /// Only used by the list request: answer with a `ListResult` instead of the bare URLs.
+    #[serde(default)]
+    progress: bool,
+    /// Only used by the list request: list each URL with its count and what its last fetch found.
+    #[serde(default)]
+    detail: bool,
+    /// Only used by the list request: list a page of `limit` URLs after the first `offset` ones, in
+    /// a `ListResult`.
+    offset: Option<usize>,
+    limit: Option<usize>,
+    /// Only used by the list request: list the URLs whose path starts with this, e.g. `/blog/`.
+    prefix: Option<String>,
+    /// Only used by the list request: list the URLs with at least this number of occurences.
+    #[serde(default)]
+    min_count: usize,
+    /// Only used by the list request: `count` to list the URLs with the most occurences first.
+    #[serde(default)]
+    sort: UrlSort,
+}
+
+impl ListOptions {
+    fn scope(&self) -> Option<Vec<String>> {
+        self.scope.as_ref().map(|scope| {
+            scope
+                .split(',')
+                .filter(|prefix| !prefix.is_empty())
+                .map(String::from)
+                .collect()
+        })
+    }
 }
 
 /// GET query options for count request.
@@ -29,10 +155,184 @@ struct CountOptions {
     url: Url,
 }
 
-/// Used to parse JSON body of the POST /domains request
+/// Used to parse JSON body of the POST /domains/urls/batch-count request.
 #[derive(Debug, Deserialize)]
-struct Domain {
+struct BatchCountRequest {
+    urls: Vec<Url>,
+}
+
+/// Format of the export request, JSON by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// GET query options for the export request.
+#[derive(Debug, Deserialize)]
+struct ExportOptions {
+    domain: Url,
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// GET query options for the search request: the words `q` the pages must all contain, and the
+/// maximum number of pages returned.
+#[derive(Debug, Deserialize)]
+struct SearchOptions {
     domain: Url,
+    q: String,
+    limit: Option<usize>,
+}
+
+/// Format of the graph request, JSON by default: `dot` for Graphviz, `graphml` for Gephi.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum GraphFormat {
+    #[default]
+    Json,
+    Dot,
+    Graphml,
+}
+
+/// GET query options for the graph request.
+#[derive(Debug, Deserialize)]
+struct GraphOptions {
+    domain: Url,
+    #[serde(default)]
+    format: GraphFormat,
+}
+
+/// GET query options for the dump request, every domain being dumped if none is given.
+#[derive(Debug, Deserialize)]
+struct DumpOptions {
+    domain: Option<Url>,
+}
+
+/// GET query options for the lineage request. With `not_seen_since`, only the URLs no crawl
+/// session started since then found are listed, e.g. the ones that disappeared since last month.
+#[derive(Debug, Deserialize)]
+struct LineageOptions {
+    domain: Url,
+    not_seen_since: Option<DateTime<Utc>>,
+}
+
+/// GET query options for the assets request. With `kind`, only the assets of this kind are listed,
+/// e.g. `image`.
+#[derive(Debug, Deserialize)]
+struct AssetsOptions {
+    domain: Url,
+    kind: Option<AssetKind>,
+}
+
+/// GET query options for the audit log request.
+#[derive(Debug, Deserialize)]
+struct AuditOptions {
+    domain: Option<Url>,
+}
+
+/// GET query of the summaries request: every pair is a tag the crawls must have,
+/// e.g. `?team=seo&ticket=WEB-123`.
+type TagFilter = HashMap<String, String>;
+
+/// Used to parse JSON body of the POST /domains request.
+/// Crawl options are given next to the domain, e.g. `{"domain": "<url>", "max_depth": 3,
+/// "max_pages": 500, "max_concurrency": 4, "include": ["/docs/*"], "downloader": {"user_agent":
+/// "examplebot/1.0"}}`, the ones not given being the defaults of the settings. See `CrawlerConfig`
+/// for all of them. They are checked by `Crawler::new`.
+#[derive(Debug, Deserialize)]
+struct CrawlRequest {
+    domain: Url,
+    #[serde(flatten)]
+    config: CrawlerConfig,
+}
+
+/// Used to parse JSON body of the POST /schedules request: a domain, when to crawl it, either a
+/// cron expression or an interval in seconds, and crawl options as for POST /domains, e.g.
+/// `{"domain": "<url>", "cron": "0 3 * * *", "max_pages": 1000}`.
+#[derive(Debug, Deserialize)]
+struct ScheduleRequest {
+    domain: Url,
+    cron: Option<String>,
+    interval_secs: Option<u64>,
+    #[serde(flatten)]
+    config: CrawlerConfig,
+}
+
+/// GET query options for the schedules list request.
+#[derive(Debug, Deserialize)]
+struct ScheduleOptions {
+    domain: Option<Url>,
+}
+
+/// Used to parse JSON body of the POST /urls/check request, e.g. `{"url": "<url>"}`.
+/// The URL is checked against the scope of `domain`, its own one by default, and the crawl options
+/// not given are the defaults of the settings, as for a crawl.
+#[derive(Debug, Deserialize)]
+struct UrlCheckRequest {
+    url: Url,
+    domain: Option<Url>,
+    #[serde(flatten)]
+    config: CrawlerConfig,
+}
+
+/// GET query options for the purge request.
+#[derive(Debug, Deserialize)]
+struct PurgeOptions {
+    domain: Url,
+    /// Cancel the running crawls of the domain and wait for them to stop, instead of refusing.
+    #[serde(default)]
+    cancel: bool,
+}
+
+/// How long the purge request waits for the crawls it cancelled to stop.
+const PURGE_CANCEL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Result returned for the DELETE /domains/data request.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeResult {
+    domain: Url,
+    /// The jobs of the crawls cancelled before purging, the oldest first.
+    cancelled: Vec<JobId>,
+}
+
+/// Result returned for the DELETE /domains request.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CancelResult {
+    domain: Url,
+    /// Whether a crawl was running and got cancelled.
+    running: bool,
+    /// The job of the cancelled crawl, the oldest one if several were.
+    job: Option<JobId>,
+    /// The jobs of all the cancelled crawls.
+    jobs: Vec<JobId>,
+}
+
+/// Result returned for the list GET request when asked for the progress of the crawl or for a page.
+/// While `crawl_in_progress`, `urls` is partial and `progress` holds the live counters of the crawl.
+/// The URLs are `UrlDetail`s when asked for the details.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListResult<T = Url> {
+    urls: Vec<T>,
+    /// Number of URLs of the domain, over all pages.
+    total: usize,
+    /// The `offset` of the next page, none if this one is the last.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    next_offset: Option<usize>,
+    crawl_in_progress: bool,
+    progress: Option<ProgressCounters>,
+}
+
+/// An URL in the list GET request result when asked for the details: its number of occurences,
+/// and what its last fetch found, if it was fetched.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UrlDetail {
+    url: Url,
+    count: usize,
+    #[serde(flatten)]
+    fetch: Option<FetchStatus>,
 }
 
 /// Result returned for the count GET request.
@@ -42,19 +342,290 @@ pub struct CountResult {
     count: usize,
 }
 
-/// Create the webserver and start serving the routes.
-pub(crate) async fn server(db: Db) {
-    let spawned_crawlers = CrawlersDb::default();
-    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+/// The count of an URL in the batch count POST request result, `None` with an `error` if its
+/// domain has not been crawled.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchCount {
+    url: Url,
+    count: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A row of the export GET request result.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedUrl {
+    url: Url,
+    count: usize,
+}
+
+/// A third-party host linked to from a crawled domain, in the third-party GET request result.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ThirdPartyDomain {
+    domain: String,
+    links: usize,
+}
 
-    let routes = filters::crawl(
-        shutdown_tx.clone(),
-        db.clone(),
-        Arc::clone(&spawned_crawlers),
-    )
-    .or(filters::list(db.clone()))
-    .or(filters::count(db));
+/// A resource the pages of a crawled domain refer to, e.g. an image, in the assets GET request
+/// result.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DomainAsset {
+    url: Url,
+    kind: AssetKind,
+    count: usize,
+}
+
+/// A crawl session of a domain, in the lineage GET request result.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CrawlSession {
+    id: u64,
+    started_at: DateTime<Utc>,
+    /// How the session treated `robots.txt`, if it was recorded.
+    robots_mode: Option<RobotsMode>,
+}
+
+/// An URL with the crawl sessions that found it first and last.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UrlLineage {
+    url: Url,
+    #[serde(flatten)]
+    lineage: Lineage,
+}
+
+/// Result returned for the lineage GET request.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LineageResult {
+    sessions: Vec<CrawlSession>,
+    urls: Vec<UrlLineage>,
+}
+
+/// A broken link of a crawled domain, with some of the pages linking to it, in the broken GET
+/// request result.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BrokenLink {
+    url: Url,
+    #[serde(flatten)]
+    status: FetchStatus,
+    linked_from: Vec<Url>,
+}
+
+/// A fetched page of a crawled domain whose body has issues, in the issues GET request result.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PageIssues {
+    url: Url,
+    content_type: Option<String>,
+    issues: Vec<ContentIssue>,
+}
+
+/// URLs of a crawled domain serving the same content, in the duplicates GET request result.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    content_hash: String,
+    urls: Vec<Url>,
+}
+
+/// Used to parse JSON body of the POST /normalize request.
+#[derive(Debug, Deserialize)]
+struct NormalizeRequest {
+    urls: Vec<Url>,
+    #[serde(default)]
+    config: NormalizeConfig,
+}
+
+/// A URL together with its canonical form.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NormalizedUrl {
+    url: Url,
+    normalized: Url,
+}
+
+/// Result returned for the normalize POST request.
+/// `unique` holds the deduplicated canonical URLs, in order of first appearance.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NormalizeResult {
+    urls: Vec<NormalizedUrl>,
+    unique: Vec<Url>,
+}
+
+/// Result returned for the readiness GET request. A degraded storage still serves requests.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Readiness {
+    /// `ready` or `degraded`.
+    status: String,
+    storage: StorageHealth,
+}
+
+/// Result returned for the status GET request.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Status {
+    uptime_secs: u64,
+    /// Number of queued or running crawls.
+    active_crawlers: usize,
+    #[serde(flatten)]
+    totals: DbTotals,
+    /// Usage of the memory budget, when the database is kept in memory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    memory: Option<MemoryUsage>,
+}
+
+/// Used to parse JSON body of the POST /debug/robots-check request.
+#[derive(Debug, Deserialize)]
+struct RobotsCheckRequest {
+    domain: Url,
+    urls: Vec<Url>,
+}
+
+/// Whether a URL is allowed by `robots.txt`, in the robots-check POST request result.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RobotsCheck {
+    url: Url,
+    #[serde(flatten)]
+    decision: RobotsDecision,
+}
+
+/// Runtime the crawls run on, apart from the one serving the API, so that big crawls cannot
+/// starve request handling. Shut down without waiting for its tasks when dropped, as a runtime
+/// cannot be dropped from within another one.
+struct CrawlRuntime(Option<Runtime>);
+
+impl CrawlRuntime {
+    fn new(worker_threads: Option<usize>) -> Self {
+        let mut builder = runtime::Builder::new_multi_thread();
+        builder.thread_name("crawler").enable_all();
+        if let Some(worker_threads) = worker_threads {
+            builder.worker_threads(worker_threads);
+        }
+
+        Self(Some(
+            builder.build().expect("Could not start the crawl runtime"),
+        ))
+    }
+
+    fn handle(&self) -> runtime::Handle {
+        self.0.as_ref().unwrap().handle().clone()
+    }
+}
+
+impl Drop for CrawlRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Number of crawls running on the crawl runtime, waited for on shutdown.
+#[derive(Debug, Clone, Default)]
+struct CrawlTasks(Arc<(AtomicUsize, Notify)>);
+
+impl CrawlTasks {
+    /// Wait until no crawl is running.
+    async fn wait(&self) {
+        let (running, done) = &*self.0;
+        // A crawl ending before `notified` is awaited leaves a permit, so it is not missed.
+        while running.load(Ordering::SeqCst) > 0 {
+            done.notified().await;
+        }
+    }
+}
+
+/// A crawl counted in `CrawlTasks` until its task is dropped, whether it ended or panicked.
+struct RunningCrawl(CrawlTasks);
+
+impl RunningCrawl {
+    fn new(tasks: CrawlTasks) -> Self {
+        tasks.0 .0.fetch_add(1, Ordering::SeqCst);
+        Self(tasks)
+    }
+}
+
+impl Drop for RunningCrawl {
+    fn drop(&mut self) {
+        let (running, done) = &*self.0 .0;
+        running.fetch_sub(1, Ordering::SeqCst);
+        done.notify_one();
+    }
+}
+
+/// Where crawls are spawned: on the crawl runtime, with a child token of the shutdown one, so
+/// that in-flight downloads are dropped on shutdown. The artifacts of the crawls are kept in
+/// `artifacts`, and the running ones are counted in `tasks` to be waited for on shutdown.
+#[derive(Debug, Clone)]
+struct CrawlSpawner {
+    runtime: runtime::Handle,
+    shutdown: CancellationToken,
+    artifacts: Artifacts,
+    tasks: CrawlTasks,
+}
+
+impl CrawlSpawner {
+    /// Spawn `crawl` on the crawl runtime, counted as running until it ends.
+    fn spawn(&self, crawl: impl Future<Output = ()> + Send + 'static) {
+        let running = RunningCrawl::new(self.tasks.clone());
+        self.runtime.spawn(async move {
+            let _running = running;
+            crawl.await;
+        });
+    }
+}
 
+/// A server serving the routes in the background, started with `spawn_server`.
+#[derive(Debug)]
+pub struct ServerHandle {
+    addr: SocketAddr,
+    https: bool,
+    shutdown: CancellationToken,
+    task: JoinHandle<()>,
+}
+
+impl ServerHandle {
+    /// The address the server listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The URL of `path` on the server, e.g. `/domains`.
+    pub fn url(&self, path: &str) -> Url {
+        let scheme = if self.https { "https" } else { "http" };
+        Url::parse(&format!("{}://{}", scheme, self.addr))
+            .and_then(|base| base.join(path))
+            .expect("Invalid path")
+    }
+
+    /// Shut the server down as on a signal, cancelling the running crawls, and wait for it to
+    /// stop.
+    pub async fn stop(self) {
+        self.shutdown.cancel();
+        let _ = self.task.await;
+    }
+}
+
+/// Start serving the routes in the background, as set in `settings`, until the returned handle
+/// stops the server. With port `0`, the server listens on a free port picked by the system, e.g.
+/// to run black-box tests of the API against a mock site. Unlike `server`, signals are not handled
+/// and systemd is not notified. Must be called from a tokio runtime.
+pub fn spawn_server(db: Db, settings: Settings) -> anyhow::Result<ServerHandle> {
+    let shutdown = CancellationToken::new();
+    let https = settings.tls.is_some();
+    let (addr, server) = bind(db, settings, shutdown.clone())?;
+    info!("Listening on {}", addr);
+
+    Ok(ServerHandle {
+        addr,
+        https,
+        shutdown,
+        task: tokio::spawn(server),
+    })
+}
+
+/// Create the webserver and start serving the routes, as set in `settings`, until a shutdown
+/// signal.
+pub async fn server(db: Db, settings: Settings) {
+    // Cancelled on shutdown.
+    let shutdown = CancellationToken::new();
+    let (addr, server) = bind(db, settings, shutdown.clone()).expect("Could not bind the listener");
+
+    let shutdown_signal = shutdown.clone();
     tokio::spawn(async move {
         let mut sigterm = tokio::signal::unix::signal(SignalKind::terminate()).unwrap();
         let mut sigquit = tokio::signal::unix::signal(SignalKind::quit()).unwrap();
@@ -62,7 +633,7 @@ pub(crate) async fn server(db: Db) {
 
         let send_kill = move || {
             info!("Received shutdown signal. Sending shutdown command.");
-            shutdown_tx.send(()).unwrap();
+            shutdown_signal.cancel();
         };
         tokio::select! {
             _ = sigterm.recv() => send_kill(),
@@ -71,9 +642,464 @@ pub(crate) async fn server(db: Db) {
         }
     });
 
-    let (_addr, server) = warp::serve(routes).bind_with_graceful_shutdown(([0, 0, 0, 0], 3030), async move {
-        shutdown_rx.recv().await.ok();
+    systemd::spawn_watchdog(shutdown.clone());
+    tokio::spawn(async move {
+        shutdown.cancelled().await;
+        systemd::notify_stopping();
     });
 
+    // The listener is bound and the storage was opened by the caller.
+    info!("Listening on {}", addr);
+    systemd::notify_ready();
+
     server.await
 }
+
+/// Bind the listener of the webserver serving the routes, as set in `settings`, over HTTPS with
+/// `settings.tls`. The returned future serves them until `shutdown` is cancelled, then waits up to
+/// `SHUTDOWN_TIMEOUT` for the cancelled crawls to store their summary and call their
+/// `callback_url`. The ones still running after that are dropped.
+fn bind(
+    db: Db,
+    settings: Settings,
+    shutdown: CancellationToken,
+) -> anyhow::Result<(SocketAddr, BoxFuture<'static, ()>)> {
+    let started = Instant::now();
+    let crawl_runtime = CrawlRuntime::new(settings.crawl_threads);
+    let jobs = Jobs::default();
+    let summaries = SummariesDb::default();
+    let audit = AuditLog::default();
+    let artifacts = Artifacts::new(settings.artifacts_dir.as_deref(), Utc::now());
+    let schedules = Schedules::default();
+    let spawner = CrawlSpawner {
+        runtime: crawl_runtime.handle(),
+        shutdown: shutdown.clone(),
+        artifacts: artifacts.clone(),
+        tasks: CrawlTasks::default(),
+    };
+    let tasks = spawner.tasks.clone();
+    let scheduler = scheduler::Scheduler {
+        schedules: schedules.clone(),
+        spawner: spawner.clone(),
+        db: db.clone(),
+        jobs: jobs.clone(),
+        summaries: Arc::clone(&summaries),
+        audit: audit.clone(),
+    };
+
+    let limiter = settings.rate_limit.map(rate_limit::RateLimiter::new);
+    let defaults = Arc::new(CrawlerConfig {
+        owned_domains: settings.owned_domains,
+        domain_overrides: settings.domain_overrides,
+        ..settings.crawl
+    });
+    // Boxed in groups, as a single chain of all the routes makes a type so deep that handling a
+    // request overflows the stack of a runtime thread in debug builds.
+    let crawls = filters::crawl(
+        spawner.clone(),
+        db.clone(),
+        jobs.clone(),
+        Arc::clone(&summaries),
+        audit.clone(),
+        settings.body_limits.crawl,
+        Arc::clone(&defaults),
+    )
+    .or(filters::recrawl(
+        spawner.clone(),
+        db.clone(),
+        jobs.clone(),
+        Arc::clone(&summaries),
+        audit.clone(),
+        settings.body_limits.crawl,
+        Arc::clone(&defaults),
+    ))
+    .or(filters::check(
+        spawner,
+        db.clone(),
+        settings.body_limits.crawl,
+        Arc::clone(&defaults),
+    ))
+    .or(filters::cancel(jobs.clone(), audit.clone()))
+    .or(filters::purge(
+        db.clone(),
+        jobs.clone(),
+        Arc::clone(&summaries),
+        audit.clone(),
+    ))
+    .or(filters::pause(jobs.clone(), audit.clone()))
+    .or(filters::resume(jobs.clone(), audit.clone()))
+    .or(filters::list(db.clone(), jobs.clone()))
+    .or(filters::progress(jobs.clone()))
+    .or(filters::job(jobs.clone()))
+    .or(filters::artifacts(jobs.clone(), artifacts.clone()))
+    .or(filters::artifact(jobs.clone(), artifacts))
+    .or(filters::crawlers(jobs.clone()))
+    .or(filters::create_schedule(
+        schedules.clone(),
+        audit.clone(),
+        settings.body_limits.crawl,
+        defaults,
+    ))
+    .or(filters::schedules(schedules.clone()))
+    .or(filters::delete_schedule(schedules, audit.clone()))
+    .boxed();
+    let reports = filters::summary(Arc::clone(&summaries))
+        .or(filters::summaries(summaries))
+        .or(filters::audit(audit))
+        .or(filters::third_party(db.clone()))
+        .or(filters::assets(db.clone()))
+        .or(filters::lineage(db.clone()))
+        .or(filters::diff(db.clone()))
+        .or(filters::graph(db.clone()))
+        .or(filters::search(db.clone()))
+        .or(filters::broken(db.clone()))
+        .or(filters::issues(db.clone()))
+        .or(filters::duplicates(db.clone()))
+        .or(filters::export(db.clone()))
+        .or(filters::dump(db.clone()))
+        .or(filters::import(
+            db.clone(),
+            settings.body_limits.import,
+            settings.body_limits.import_decompressed,
+        ))
+        .or(filters::robots_check(
+            db.clone(),
+            settings.body_limits.robots_check,
+        ))
+        .boxed();
+    // Probes are not rate limited.
+    let probes = filters::healthz()
+        .or(filters::readyz(db.clone()))
+        .or(filters::metrics(db.clone(), jobs.clone()))
+        .boxed();
+    let operations = filters::status(db.clone(), jobs, started)
+        .or(filters::batch_count(
+            db.clone(),
+            settings.body_limits.batch_count,
+        ))
+        .or(filters::count(db))
+        .or(filters::normalize(settings.body_limits.normalize))
+        .or(openapi::spec())
+        .or(openapi::docs())
+        .boxed();
+    let routes = probes
+        .or(rate_limit::rate_limit(limiter).and(crawls.or(reports).or(operations)))
+        .recover(rate_limit::too_many_requests);
+
+    let signal = {
+        let shutdown = shutdown.clone();
+        move || {
+            let shutdown = shutdown.clone();
+            async move { shutdown.cancelled().await }
+        }
+    };
+    let address = SocketAddr::new(settings.address, settings.port);
+    let (addr, server) = match &settings.tls {
+        Some(tls) => {
+            let (cert, key) = tls::read(tls)?;
+            tls::check_bind(address)?;
+            let (addr, server) = warp::serve(routes)
+                .tls()
+                .cert(cert)
+                .key(key)
+                .bind_with_graceful_shutdown(address, signal());
+            let server = match tls.redirect_http_port {
+                Some(port) => {
+                    let (redirect_addr, redirect) = warp::serve(tls::redirect(addr.port()))
+                        .try_bind_with_graceful_shutdown((address.ip(), port), signal())?;
+                    info!("Redirecting HTTP from {} to HTTPS", redirect_addr);
+                    futures::future::join(server, redirect).map(drop).boxed()
+                }
+                None => server.boxed(),
+            };
+            (addr, server)
+        }
+        None => {
+            let (addr, server) =
+                warp::serve(routes).try_bind_with_graceful_shutdown(address, signal())?;
+            (addr, server.boxed())
+        }
+    };
+
+    Ok((
+        addr,
+        async move {
+            tokio::spawn(scheduler.run(shutdown));
+            server.await;
+            if tokio::time::timeout(SHUTDOWN_TIMEOUT, tasks.wait())
+                .await
+                .is_err()
+            {
+                warn!(
+                    "Dropping the crawls still running after {:?}",
+                    SHUTDOWN_TIMEOUT
+                );
+            }
+            // Kept until the crawls stopped, as they run on it.
+            drop(crawl_runtime);
+        }
+        .boxed(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::Duration,
+    };
+
+    use mockito::mock;
+    use url::Url;
+
+    use super::spawn_server;
+    use crate::{
+        db::Db,
+        settings::{Settings, Tls},
+    };
+
+    async fn json<T: serde::de::DeserializeOwned>(request: reqwest::RequestBuilder) -> T {
+        let body = request.send().await.unwrap().bytes().await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_spawn_server() {
+        let _page = mock("GET", "/embedded/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/embedded/about">About</a>"#)
+            .create();
+        let _m = mock(
+            "GET",
+            mockito::Matcher::Regex("^/(robots.txt|embedded/about)$".to_string()),
+        )
+        .with_status(200)
+        .with_header("content-type", "text/plain")
+        .create();
+        let site = Url::parse(&mockito::server_url()).unwrap();
+
+        let db = Db::default();
+        let server = spawn_server(
+            db.clone(),
+            Settings {
+                address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                port: 0,
+                ..Settings::default()
+            },
+        )
+        .unwrap();
+        assert_ne!(server.addr().port(), 0);
+
+        let client = reqwest::Client::new();
+        let job: serde_json::Value = json(
+            client.post(server.url("/domains")).body(
+                serde_json::json!({
+                    "domain": site,
+                    "scope": ["/embedded/"],
+                    "ignore_sitemaps": true,
+                    "downloader": {"egress": {"allow_private_domain": true}},
+                })
+                .to_string(),
+            ),
+        )
+        .await;
+
+        let job_url = server.url(&format!("/jobs/{}", job["id"]));
+        let mut state = serde_json::Value::Null;
+        for _ in 0..100 {
+            let job: serde_json::Value = json(client.get(job_url.clone())).await;
+            state = job["state"].clone();
+            if state != "queued" && state != "running" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(state, "finished");
+
+        let mut urls: Vec<Url> =
+            json(client.get(server.url(&format!("/domains?domain={}", site)))).await;
+        urls.sort();
+        assert_eq!(
+            urls,
+            [
+                site.join("/embedded/").unwrap(),
+                site.join("/embedded/about").unwrap()
+            ]
+        );
+        assert_eq!(db.unique_urls_for_domain(&site).unwrap().len(), 2);
+
+        let url = server.url("/healthz");
+        server.stop().await;
+        assert!(client.get(url).send().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stop_mid_crawl() {
+        let _page = mock("GET", "/stopped/")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body(r#"<a href="/stopped/1">1</a><a href="/stopped/2">2</a>"#)
+            .create();
+        // The callback is only received once retried, after the crawl runtime would have stopped.
+        let _unavailable = mock("POST", "/stopped-done")
+            .with_status(503)
+            .expect(1)
+            .create();
+        let callback = mock("POST", "/stopped-done")
+            .match_body(mockito::Matcher::Regex(
+                r#""state":"cancelled""#.to_string(),
+            ))
+            .expect(1)
+            .create();
+        let site = Url::parse(&mockito::server_url()).unwrap();
+
+        let server = spawn_server(
+            Db::default(),
+            Settings {
+                address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                port: 0,
+                ..Settings::default()
+            },
+        )
+        .unwrap();
+        let client = reqwest::Client::new();
+        // Waits a minute between requests, so it is still running when the server stops.
+        let job: serde_json::Value = json(
+            client.post(server.url("/domains")).body(
+                serde_json::json!({
+                    "domain": site.join("/stopped/").unwrap(),
+                    "scope": ["/stopped/"],
+                    "ignore_sitemaps": true,
+                    "delay_ms": 60_000,
+                    "callback_url": site.join("/stopped-done").unwrap(),
+                    "downloader": {"egress": {"allow_private_domain": true}},
+                })
+                .to_string(),
+            ),
+        )
+        .await;
+        let job_url = server.url(&format!("/jobs/{}", job["id"]));
+        for _ in 0..100 {
+            let job: serde_json::Value = json(client.get(job_url.clone())).await;
+            if job["state"] == "running" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        // The cancelled crawl reaches its end before the server is done stopping.
+        server.stop().await;
+        callback.assert();
+    }
+
+    #[tokio::test]
+    async fn test_openapi() {
+        let server = spawn_server(
+            Db::default(),
+            Settings {
+                address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                port: 0,
+                ..Settings::default()
+            },
+        )
+        .unwrap();
+        let client = reqwest::Client::new();
+
+        let document: serde_json::Value = json(client.get(server.url("/openapi.json"))).await;
+        assert_eq!(document, super::openapi::document());
+        let docs = client.get(server.url("/docs")).send().await.unwrap();
+        assert!(docs.status().is_success());
+        assert!(docs.text().await.unwrap().contains("/openapi.json"));
+
+        // Every documented operation is routed: warp answers the others with an empty 404 or a
+        // 405.
+        for (path, item) in document["paths"].as_object().unwrap() {
+            for (method, operation) in item.as_object().unwrap() {
+                let mut url = server.url(&path.replace("{id}", "1").replace("{name}", "x"));
+                for param in operation["parameters"].as_array().unwrap() {
+                    if param["in"] == "query" && param["required"] == true {
+                        let value = match param["schema"]["format"].as_str() {
+                            Some("uri") => "https://example.com",
+                            _ => "x",
+                        };
+                        url.query_pairs_mut()
+                            .append_pair(param["name"].as_str().unwrap(), value);
+                    }
+                }
+                let method = method.to_uppercase().parse().unwrap();
+                let response = client.request(method, url).send().await.unwrap();
+                let status = response.status();
+                let body = response.bytes().await.unwrap();
+                assert!(
+                    status != 405 && (status != 404 || !body.is_empty()),
+                    "{} {} answered {}",
+                    operation["summary"],
+                    path,
+                    status
+                );
+            }
+        }
+
+        server.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_spawn_server_tls() {
+        let tls = Tls {
+            cert_path: "src/server/testdata/cert.pem".into(),
+            key_path: "src/server/testdata/key.pem".into(),
+            redirect_http_port: None,
+        };
+        let server = spawn_server(
+            Db::default(),
+            Settings {
+                address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                port: 0,
+                tls: Some(tls.clone()),
+                ..Settings::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(server.url("/healthz").scheme(), "https");
+
+        // The certificate is self-signed.
+        let client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+        let response = client.get(server.url("/healthz")).send().await.unwrap();
+        assert!(response.status().is_success());
+        let mut plain = server.url("/healthz");
+        plain.set_scheme("http").unwrap();
+        assert!(client.get(plain).send().await.is_err());
+        server.stop().await;
+
+        // A port in use is an error rather than a panic.
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        assert!(spawn_server(
+            Db::default(),
+            Settings {
+                address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                port: taken.local_addr().unwrap().port(),
+                tls: Some(tls.clone()),
+                ..Settings::default()
+            },
+        )
+        .is_err());
+
+        let invalid = Tls {
+            key_path: tls.cert_path.clone(),
+            ..tls
+        };
+        assert!(spawn_server(
+            Db::default(),
+            Settings {
+                port: 0,
+                tls: Some(invalid),
+                ..Settings::default()
+            },
+        )
+        .is_err());
+    }
+}
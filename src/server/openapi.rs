@@ -0,0 +1,821 @@
+use serde_json::{json, Map, Value};
+use warp::Filter;
+
+/// Where a parameter of an operation is given.
+#[derive(Debug, Clone, Copy)]
+enum In {
+    Query,
+    Path,
+}
+
+/// A parameter of an operation, of type `string` with the given format, or of another JSON type.
+#[derive(Debug)]
+struct Param {
+    name: &'static str,
+    location: In,
+    required: bool,
+    /// `uri`, `date-time`, `integer` or `boolean`, a plain string if empty.
+    format: &'static str,
+    description: &'static str,
+}
+
+const fn query(
+    name: &'static str,
+    required: bool,
+    format: &'static str,
+    description: &'static str,
+) -> Param {
+    Param {
+        name,
+        location: In::Query,
+        required,
+        format,
+        description,
+    }
+}
+
+const fn path(name: &'static str, format: &'static str, description: &'static str) -> Param {
+    Param {
+        name,
+        location: In::Path,
+        required: true,
+        format,
+        description,
+    }
+}
+
+const DOMAIN: Param = query(
+    "domain",
+    true,
+    "uri",
+    "The crawled domain, e.g. https://example.com",
+);
+const SCOPE: Param = query(
+    "scope",
+    false,
+    "",
+    "Comma-separated scope prefixes of the crawl to act on, every crawl of the domain if not given",
+);
+const JOB_ID: Param = path(
+    "id",
+    "integer",
+    "Job of the crawl, as returned by POST /domains",
+);
+
+/// A request or response body: its media type and the name of its schema in the components, any
+/// JSON value or bytes if empty.
+#[derive(Debug)]
+struct Content(&'static str, &'static str);
+
+const JSON: Content = Content("application/json", "");
+
+/// An operation of the API. Every route of `bind` has one, for the document to describe the
+/// whole API.
+#[derive(Debug)]
+struct Operation {
+    method: &'static str,
+    path: &'static str,
+    tag: &'static str,
+    summary: &'static str,
+    params: &'static [Param],
+    body: Option<Content>,
+    /// Status of the successful response, `202 Accepted` ones being answered with
+    /// `409 Conflict` and the same content when the work is already under way.
+    status: u16,
+    response: Content,
+}
+
+const OPERATIONS: &[Operation] = &[
+    Operation {
+        method: "post",
+        path: "/domains",
+        tag: "crawls",
+        summary: "Start a crawl of a domain, or return the job of the one already running with \
+                  the same scope, its path being the Location header. A dry run or a probe \
+                  returns its report with 200 OK instead",
+        params: &[],
+        body: Some(Content("application/json", "CrawlRequest")),
+        status: 202,
+        response: Content("application/json", "JobStatus"),
+    },
+    Operation {
+        method: "get",
+        path: "/domains",
+        tag: "crawls",
+        summary: "List the URLs found for a domain, bare or in a ListResult with progress=true \
+                  or a page",
+        params: &[
+            DOMAIN,
+            SCOPE,
+            query("progress", false, "boolean", "Answer with a ListResult"),
+            query(
+                "detail",
+                false,
+                "boolean",
+                "List each URL with its count and last fetch",
+            ),
+            query("offset", false, "integer", "Number of URLs to skip"),
+            query(
+                "limit",
+                false,
+                "integer",
+                "Number of URLs of the page, at most 10000",
+            ),
+            query(
+                "prefix",
+                false,
+                "",
+                "Only the URLs whose path starts with it, e.g. /blog/",
+            ),
+            query(
+                "min_count",
+                false,
+                "integer",
+                "Only the URLs found at least this often",
+            ),
+            query("sort", false, "", "url, or count for the most found first"),
+        ],
+        body: None,
+        status: 200,
+        response: Content("application/json", "ListResult"),
+    },
+    Operation {
+        method: "delete",
+        path: "/domains",
+        tag: "crawls",
+        summary: "Cancel the running crawls of a domain",
+        params: &[DOMAIN, SCOPE],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "post",
+        path: "/domains/recrawl",
+        tag: "crawls",
+        summary: "Crawl an already crawled domain again, with a warm start",
+        params: &[],
+        body: Some(Content("application/json", "CrawlRequest")),
+        status: 202,
+        response: Content("application/json", "JobStatus"),
+    },
+    Operation {
+        method: "post",
+        path: "/domains/pause",
+        tag: "crawls",
+        summary: "Pause the running crawls of a domain",
+        params: &[DOMAIN, SCOPE],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "post",
+        path: "/domains/resume",
+        tag: "crawls",
+        summary: "Resume the paused crawls of a domain",
+        params: &[DOMAIN, SCOPE],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/progress",
+        tag: "crawls",
+        summary: "Stream the events of the running crawl of a domain as Server-Sent Events",
+        params: &[DOMAIN, SCOPE],
+        body: None,
+        status: 200,
+        response: Content("text/event-stream", ""),
+    },
+    Operation {
+        method: "delete",
+        path: "/domains/data",
+        tag: "crawls",
+        summary: "Delete everything stored for a domain",
+        params: &[
+            DOMAIN,
+            query(
+                "cancel",
+                false,
+                "boolean",
+                "Cancel its running crawls instead of refusing",
+            ),
+        ],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/jobs/{id}",
+        tag: "crawls",
+        summary: "Return the status of a crawl job",
+        params: &[JOB_ID],
+        body: None,
+        status: 200,
+        response: Content("application/json", "JobStatus"),
+    },
+    Operation {
+        method: "get",
+        path: "/jobs/{id}/artifacts",
+        tag: "crawls",
+        summary: "List the files kept for a crawl job",
+        params: &[JOB_ID],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/jobs/{id}/artifacts/{name}",
+        tag: "crawls",
+        summary: "Download a file kept for a crawl job, a single byte range being supported",
+        params: &[JOB_ID, path("name", "", "Name of the artifact")],
+        body: None,
+        status: 200,
+        response: Content("application/octet-stream", ""),
+    },
+    Operation {
+        method: "get",
+        path: "/crawlers",
+        tag: "crawls",
+        summary: "Return the status of every queued or running crawl",
+        params: &[],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "post",
+        path: "/urls/check",
+        tag: "crawls",
+        summary: "Tell whether a crawl would fetch an URL, and why not",
+        params: &[],
+        body: Some(JSON),
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "post",
+        path: "/schedules",
+        tag: "schedules",
+        summary: "Crawl a domain on a cron expression or every interval_secs, with the options of \
+                  POST /domains",
+        params: &[],
+        body: Some(JSON),
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/schedules",
+        tag: "schedules",
+        summary: "List the schedules, of a domain if given",
+        params: &[query(
+            "domain",
+            false,
+            "uri",
+            "Only the schedules of this domain",
+        )],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "delete",
+        path: "/schedules/{id}",
+        tag: "schedules",
+        summary: "Delete a schedule",
+        params: &[path("id", "", "Schedule to delete")],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/urls",
+        tag: "urls",
+        summary: "Count the occurences of an URL",
+        params: &[query("url", true, "uri", "The URL to count")],
+        body: None,
+        status: 200,
+        response: Content("application/json", "CountResult"),
+    },
+    Operation {
+        method: "post",
+        path: "/domains/urls/batch-count",
+        tag: "urls",
+        summary: "Count the occurences of up to 10000 URLs, in the same order",
+        params: &[],
+        body: Some(Content("application/json", "BatchCountRequest")),
+        status: 200,
+        response: Content("application/json", "BatchCountResult"),
+    },
+    Operation {
+        method: "post",
+        path: "/normalize",
+        tag: "urls",
+        summary: "Return the canonical form of URLs",
+        params: &[],
+        body: Some(JSON),
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "post",
+        path: "/debug/robots-check",
+        tag: "urls",
+        summary: "Tell whether the robots.txt of a domain allows URLs",
+        params: &[],
+        body: Some(JSON),
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/diff",
+        tag: "reports",
+        summary: "Report the URLs the last crawl session found new, removed or changed",
+        params: &[DOMAIN],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/graph",
+        tag: "reports",
+        summary: "Return the pages of a domain and the links between them",
+        params: &[DOMAIN, query("format", false, "", "json, dot or graphml")],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/search",
+        tag: "reports",
+        summary: "Search the indexed text of the pages of a domain",
+        params: &[
+            DOMAIN,
+            query("q", true, "", "Words the pages must all contain"),
+            query("limit", false, "integer", "Number of pages, at most 100"),
+        ],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/third-party",
+        tag: "reports",
+        summary: "Count the links of a domain to other hosts",
+        params: &[DOMAIN],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/assets",
+        tag: "reports",
+        summary: "List the assets of the pages of a domain",
+        params: &[
+            DOMAIN,
+            query(
+                "kind",
+                false,
+                "",
+                "Only the assets of this kind, e.g. image",
+            ),
+        ],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/lineage",
+        tag: "reports",
+        summary: "List the URLs of a domain with the sessions that found them",
+        params: &[
+            DOMAIN,
+            query(
+                "not_seen_since",
+                false,
+                "date-time",
+                "Only the URLs no session started since then found",
+            ),
+        ],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/broken",
+        tag: "reports",
+        summary: "List the broken links of a domain and the pages linking to them",
+        params: &[DOMAIN],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/issues",
+        tag: "reports",
+        summary: "List the content issues found on the pages of a domain",
+        params: &[DOMAIN],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/duplicates",
+        tag: "reports",
+        summary: "List the groups of pages of a domain serving the same content",
+        params: &[DOMAIN],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/export",
+        tag: "reports",
+        summary: "Export the URLs of a domain with their counts, streamed",
+        params: &[DOMAIN, query("format", false, "", "json or csv")],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/summary",
+        tag: "reports",
+        summary: "Return the summary of the last finished crawl of a domain",
+        params: &[DOMAIN],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/domains/summaries",
+        tag: "reports",
+        summary: "Return the last summary of every domain having all the tags in query",
+        params: &[],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/admin/audit",
+        tag: "reports",
+        summary: "Return the audit log, oldest entry first",
+        params: &[query(
+            "domain",
+            false,
+            "uri",
+            "Only the entries of this domain",
+        )],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/dump",
+        tag: "storage",
+        summary: "Dump a domain, or every domain, to be imported later",
+        params: &[query("domain", false, "uri", "The domain to dump")],
+        body: None,
+        status: 200,
+        response: Content("application/zstd", ""),
+    },
+    Operation {
+        method: "post",
+        path: "/dump",
+        tag: "storage",
+        summary: "Load a dump into the database",
+        params: &[],
+        body: Some(Content("application/zstd", "")),
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/status",
+        tag: "operations",
+        summary: "Report the uptime, the running crawls and how much the database holds",
+        params: &[],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/healthz",
+        tag: "operations",
+        summary: "Tell that the server is up",
+        params: &[],
+        body: None,
+        status: 200,
+        response: Content("text/plain", ""),
+    },
+    Operation {
+        method: "get",
+        path: "/readyz",
+        tag: "operations",
+        summary: "Tell whether the database can be used",
+        params: &[],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/metrics",
+        tag: "operations",
+        summary: "Expose metrics in the Prometheus text format",
+        params: &[],
+        body: None,
+        status: 200,
+        response: Content("text/plain", ""),
+    },
+    Operation {
+        method: "get",
+        path: "/openapi.json",
+        tag: "operations",
+        summary: "Return this document",
+        params: &[],
+        body: None,
+        status: 200,
+        response: JSON,
+    },
+    Operation {
+        method: "get",
+        path: "/docs",
+        tag: "operations",
+        summary: "Explore the API with Swagger UI",
+        params: &[],
+        body: None,
+        status: 200,
+        response: Content("text/html", ""),
+    },
+];
+
+impl Param {
+    fn to_json(&self) -> Value {
+        let schema = match self.format {
+            "integer" | "boolean" => json!({ "type": self.format }),
+            "" => json!({ "type": "string" }),
+            format => json!({ "type": "string", "format": format }),
+        };
+
+        json!({
+            "name": self.name,
+            "in": match self.location {
+                In::Query => "query",
+                In::Path => "path",
+            },
+            "required": self.required,
+            "description": self.description,
+            "schema": schema,
+        })
+    }
+}
+
+impl Content {
+    fn to_json(&self) -> Value {
+        let Content(media_type, schema) = self;
+        let schema = match *schema {
+            "" if media_type.ends_with("json") => json!({}),
+            "" => json!({ "type": "string", "format": "binary" }),
+            name => json!({ "$ref": format!("#/components/schemas/{}", name) }),
+        };
+
+        json!({ (*media_type): { "schema": schema } })
+    }
+}
+
+impl Operation {
+    fn to_json(&self) -> Value {
+        let mut operation = json!({
+            "tags": [self.tag],
+            "summary": self.summary,
+            "parameters": self.params.iter().map(Param::to_json).collect::<Vec<_>>(),
+            "responses": {
+                "default": {
+                    "description": "An error",
+                    "content": Content("application/json", "Error").to_json(),
+                },
+            },
+        });
+        let responses = &mut operation["responses"];
+        responses[self.status.to_string()] = json!({
+            "description": if self.status == 202 { "Accepted" } else { "OK" },
+            "content": self.response.to_json(),
+        });
+        if self.status == 202 {
+            responses["409"] = json!({
+                "description": "Already under way",
+                "content": self.response.to_json(),
+            });
+        }
+        if let Some(body) = &self.body {
+            operation["requestBody"] = json!({ "required": true, "content": body.to_json() });
+        }
+
+        operation
+    }
+}
+
+/// Schemas of the main requests and responses, the other ones being any JSON.
+fn schemas() -> Value {
+    json!({
+        "Error": {
+            "type": "object",
+            "required": ["error"],
+            "properties": { "error": { "type": "string" } },
+        },
+        "CrawlRequest": {
+            "type": "object",
+            "description": "A domain and the options of its crawl, the ones not given being the \
+                            defaults of the settings. See CrawlerConfig for all of them.",
+            "required": ["domain"],
+            "properties": {
+                "domain": { "type": "string", "format": "uri" },
+                "scope": { "type": "array", "items": { "type": "string" } },
+                "max_depth": { "type": "integer", "minimum": 0 },
+                "max_pages": { "type": "integer", "minimum": 0 },
+                "max_concurrency": { "type": "integer", "minimum": 1 },
+                "delay_ms": { "type": "integer", "minimum": 0 },
+                "include": { "type": "array", "items": { "type": "string" } },
+                "exclude": { "type": "array", "items": { "type": "string" } },
+                "dry_run": { "type": "boolean" },
+                "probe": { "type": "boolean" },
+                "callback_url": { "type": "string", "format": "uri" },
+                "tags": { "type": "object", "additionalProperties": { "type": "string" } },
+            },
+            "additionalProperties": true,
+        },
+        "JobStatus": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "domain": { "type": "string", "format": "uri" },
+                "scope": { "type": "array", "items": { "type": "string" } },
+                "state": {
+                    "type": "string",
+                    "enum": ["queued", "running", "paused", "finished", "cancelled"],
+                },
+                "found": { "type": "integer" },
+                "fetched": { "type": "integer" },
+                "errors": { "type": "integer" },
+                "outcome": { "type": "object", "nullable": true },
+                "created_at": { "type": "string", "format": "date-time" },
+                "started_at": { "type": "string", "format": "date-time", "nullable": true },
+                "finished_at": { "type": "string", "format": "date-time", "nullable": true },
+                "duration_ms": { "type": "integer", "nullable": true },
+            },
+        },
+        "ListResult": {
+            "type": "object",
+            "properties": {
+                "urls": { "type": "array", "items": { "type": "string", "format": "uri" } },
+                "total": { "type": "integer" },
+                "next_offset": { "type": "integer" },
+                "crawl_in_progress": { "type": "boolean" },
+                "progress": { "type": "object", "nullable": true },
+            },
+        },
+        "CountResult": {
+            "type": "object",
+            "properties": {
+                "url": { "type": "string", "format": "uri" },
+                "count": { "type": "integer" },
+            },
+        },
+        "BatchCountRequest": {
+            "type": "object",
+            "required": ["urls"],
+            "properties": {
+                "urls": { "type": "array", "items": { "type": "string", "format": "uri" } },
+            },
+        },
+        "BatchCountResult": {
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": {
+                    "url": { "type": "string", "format": "uri" },
+                    "count": { "type": "integer", "nullable": true },
+                    "error": { "type": "string" },
+                },
+            },
+        },
+    })
+}
+
+/// The OpenAPI 3 document of the API.
+pub(super) fn document() -> Value {
+    let mut paths = Map::new();
+    for operation in OPERATIONS {
+        let item = paths
+            .entry(operation.path)
+            .or_insert_with(|| Value::Object(Map::new()));
+        item[operation.method] = operation.to_json();
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "web-crawler-server",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Crawl domains and query the URLs found.",
+        },
+        "paths": paths,
+        "components": { "schemas": schemas() },
+    })
+}
+
+/// Swagger UI exploring `/openapi.json`, its scripts and styles being loaded from a CDN.
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>web-crawler-server API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    };
+  </script>
+</body>
+</html>
+"##;
+
+/// GET /openapi.json
+pub(super) fn spec() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let document = document();
+    warp::path!("openapi.json")
+        .and(warp::get())
+        .map(move || warp::reply::json(&document))
+}
+
+/// GET /docs
+pub(super) fn docs() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("docs")
+        .and(warp::get())
+        .map(|| warp::reply::html(SWAGGER_UI))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{document, OPERATIONS};
+
+    #[test]
+    fn test_document() {
+        let document = document();
+        assert_eq!(document["openapi"], "3.0.3");
+
+        let crawl = &document["paths"]["/domains"]["post"];
+        assert_eq!(
+            crawl["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/CrawlRequest"
+        );
+        assert!(crawl["responses"]["202"].is_object());
+        assert!(crawl["responses"]["409"].is_object());
+        let count = &document["paths"]["/domains/urls"]["get"];
+        assert_eq!(count["parameters"][0]["name"], "url");
+        assert_eq!(count["parameters"][0]["required"], true);
+
+        // Every schema referenced is defined, and every path parameter is in its path.
+        let text = document.to_string();
+        for reference in text.split("#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(
+                document["components"]["schemas"].get(name).is_some(),
+                "{}",
+                name
+            );
+        }
+        for operation in OPERATIONS {
+            for param in operation.params {
+                if let super::In::Path = param.location {
+                    assert!(operation.path.contains(&format!("{{{}}}", param.name)));
+                }
+            }
+        }
+    }
+}
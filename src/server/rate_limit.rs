@@ -0,0 +1,244 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use warp::{http::StatusCode, reject::Reject, Filter, Rejection, Reply};
+
+use crate::settings::RateLimit;
+
+/// How often the buckets of idle clients are forgotten.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Rejection of a request over the rate limit of its client.
+#[derive(Debug)]
+pub(super) struct RateLimited {
+    retry_after: Duration,
+}
+
+impl Reject for RateLimited {}
+
+/// Requests a client can still make: refilled at `RateLimit::requests_per_minute`, up to
+/// `RateLimit::burst`.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Clients {
+    buckets: HashMap<String, Bucket>,
+    // When the full buckets were last dropped.
+    swept: Instant,
+}
+
+/// Token buckets of the clients of the API, by configured `X-Api-Key` header or else by remote
+/// IP address.
+#[derive(Debug, Clone)]
+pub(super) struct RateLimiter {
+    limit: RateLimit,
+    clients: Arc<Mutex<Clients>>,
+}
+
+impl RateLimiter {
+    pub(super) fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            clients: Arc::new(Mutex::new(Clients {
+                buckets: HashMap::new(),
+                swept: Instant::now(),
+            })),
+        }
+    }
+
+    /// The bucket a request is counted in: its `api_key` if it is one of `RateLimit::api_keys`,
+    /// else its `remote` address, so that a client cannot get a fresh bucket by making up keys.
+    fn client(&self, api_key: Option<String>, remote: Option<SocketAddr>) -> String {
+        match (api_key, remote) {
+            (Some(key), _) if self.limit.api_keys.contains(&key) => format!("key:{}", key),
+            (_, Some(addr)) => addr.ip().to_string(),
+            (_, None) => String::new(),
+        }
+    }
+
+    /// Take a token of `client` at `now`, or tell how long to wait for one.
+    fn check(&self, client: String, now: Instant) -> Result<(), Duration> {
+        let burst = f64::from(self.limit.burst.max(1));
+        let per_sec = f64::from(self.limit.requests_per_minute) / 60.0;
+        let refill = |bucket: &mut Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * per_sec).min(burst);
+            bucket.updated = now;
+        };
+
+        let mut clients = self.clients.lock().unwrap();
+        // A full bucket is the same as a new one.
+        if now.saturating_duration_since(clients.swept) >= SWEEP_INTERVAL {
+            clients.buckets.retain(|_, bucket| {
+                refill(bucket);
+                bucket.tokens < burst
+            });
+            clients.swept = now;
+        }
+        let bucket = clients.buckets.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        refill(bucket);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+        }
+    }
+}
+
+/// Reject the requests of clients over their rate limit, if any.
+pub(super) fn rate_limit(
+    limiter: Option<RateLimiter>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("x-api-key")
+        .and(warp::addr::remote())
+        .and_then(move |api_key: Option<String>, remote: Option<SocketAddr>| {
+            let limiter = limiter.clone();
+            async move {
+                let limiter = match limiter {
+                    Some(limiter) => limiter,
+                    None => return Ok(()),
+                };
+                let client = limiter.client(api_key, remote);
+                limiter
+                    .check(client, Instant::now())
+                    .map_err(|retry_after| warp::reject::custom(RateLimited { retry_after }))
+            }
+        })
+        .untuple_one()
+}
+
+/// Answer a request over the rate limit with `429 Too Many Requests` and when to retry, in whole
+/// seconds. Other rejections are left to warp.
+pub(super) async fn too_many_requests(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    let retry_after = match rejection.find::<RateLimited>() {
+        Some(limited) => limited.retry_after.as_secs_f64().ceil().max(1.0) as u64,
+        None => return Err(rejection),
+    };
+
+    Ok(warp::reply::with_header(
+        warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({
+                "error": format!("Too many requests, retry in {}s", retry_after),
+            })),
+            StatusCode::TOO_MANY_REQUESTS,
+        ),
+        "retry-after",
+        retry_after.to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        time::{Duration, Instant},
+    };
+
+    use warp::{http::StatusCode, Filter};
+
+    use super::{rate_limit, too_many_requests, RateLimiter, SWEEP_INTERVAL};
+    use crate::settings::RateLimit;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_minute: 60,
+            burst: 2,
+            ..RateLimit::default()
+        });
+        let now = Instant::now();
+
+        assert!(limiter.check("a".to_string(), now).is_ok());
+        assert!(limiter.check("a".to_string(), now).is_ok());
+        assert_eq!(
+            limiter.check("a".to_string(), now),
+            Err(Duration::from_secs(1))
+        );
+        // Clients have their own bucket.
+        assert!(limiter.check("b".to_string(), now).is_ok());
+
+        // Refilled at a request per second.
+        let later = now + Duration::from_millis(1500);
+        assert!(limiter.check("a".to_string(), later).is_ok());
+        assert_eq!(
+            limiter.check("a".to_string(), later),
+            Err(Duration::from_millis(500))
+        );
+
+        // Only the full buckets are dropped by a sweep.
+        let sweep = now + SWEEP_INTERVAL;
+        assert!(limiter
+            .check("b".to_string(), sweep - Duration::from_millis(500))
+            .is_ok());
+        assert!(limiter.check("c".to_string(), sweep).is_ok());
+        let buckets = &limiter.clients.lock().unwrap().buckets;
+        let mut clients: Vec<_> = buckets.keys().collect();
+        clients.sort();
+        assert_eq!(clients, vec!["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_filter() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_minute: 1,
+            burst: 1,
+            api_keys: vec!["secret".to_string()],
+        });
+        let filter = rate_limit(Some(limiter))
+            .map(warp::reply)
+            .recover(too_many_requests);
+        let client: SocketAddr = "10.0.0.1:1234".parse().unwrap();
+
+        let response = warp::test::request()
+            .remote_addr(client)
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // From another port of the same address.
+        let response = warp::test::request()
+            .remote_addr("10.0.0.1:4321".parse().unwrap())
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "60");
+
+        // A configured key is limited apart from the address it comes from.
+        let response = warp::test::request()
+            .remote_addr(client)
+            .header("x-api-key", "secret")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Other keys are limited by address.
+        let response = warp::test::request()
+            .remote_addr(client)
+            .header("x-api-key", "made-up")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let filter = rate_limit(None).map(warp::reply);
+        for _ in 0..3 {
+            let response = warp::test::request()
+                .remote_addr(client)
+                .reply(&filter)
+                .await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+    }
+}
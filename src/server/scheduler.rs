@@ -0,0 +1,158 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use super::{handlers, CrawlSpawner, SummariesDb};
+use crate::{audit::AuditLog, crawler::Crawler, db::Db, jobs::Jobs, schedules::Schedules};
+
+/// How often due schedules are looked for.
+const TICK: Duration = Duration::from_secs(1);
+
+/// Starts the crawls of the registered schedules when they are due, as POST /domains would.
+#[derive(Debug, Clone)]
+pub(super) struct Scheduler {
+    pub(super) schedules: Schedules,
+    pub(super) spawner: CrawlSpawner,
+    pub(super) db: Db,
+    pub(super) jobs: Jobs,
+    pub(super) summaries: SummariesDb,
+    pub(super) audit: AuditLog,
+}
+
+impl Scheduler {
+    /// Start the due crawls every `TICK`, until `shutdown` is cancelled.
+    pub(super) async fn run(self, shutdown: CancellationToken) {
+        let mut ticks = tokio::time::interval(TICK);
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = ticks.tick() => self.start_due(Utc::now()),
+            }
+        }
+    }
+
+    /// Start the crawls due at `now`. A crawl is not started while the domain is still being
+    /// crawled, whatever the scope, by a previous run or otherwise.
+    pub(super) fn start_due(&self, now: DateTime<Utc>) {
+        for run in self.schedules.due(now) {
+            if let Some((job, _)) = self.jobs.active(&run.domain, None) {
+                warn!(
+                    "Skipping the scheduled crawl of {}: job {} is still in progress",
+                    run.domain, job
+                );
+                self.schedules.skipped(run.id);
+                continue;
+            }
+
+            let crawler = match Crawler::new(run.domain.clone(), run.config) {
+                Ok(crawler) => crawler,
+                Err(e) => {
+                    warn!(
+                        "Could not start the scheduled crawl of {}: {}",
+                        run.domain, e
+                    );
+                    self.schedules.skipped(run.id);
+                    continue;
+                }
+            };
+            match handlers::spawn_crawl(
+                crawler,
+                self.spawner.clone(),
+                self.db.clone(),
+                self.jobs.clone(),
+                self.summaries.clone(),
+                self.audit.clone(),
+                Some(format!("schedule:{}", run.id)),
+            ) {
+                Ok(status) => {
+                    info!("Started job {} of schedule {}", status.id, run.id);
+                    self.schedules.started(run.id, status.id, now);
+                }
+                Err(_) => self.schedules.skipped(run.id),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+    use tokio::runtime::Handle;
+    use tokio_util::sync::CancellationToken;
+    use url::Url;
+
+    use super::Scheduler;
+    use crate::{
+        artifacts::Artifacts,
+        audit::{AuditAction, AuditLog},
+        crawler::{CrawlControl, CrawlerConfig},
+        db::Db,
+        jobs::Jobs,
+        schedules::{Cadence, Schedules},
+        server::{CrawlSpawner, CrawlTasks, SummariesDb},
+        summary::CrawlOutcome,
+    };
+
+    #[tokio::test]
+    async fn test_start_due() {
+        let domain = Url::parse("https://example.com").unwrap();
+        let now = Utc::now();
+        // The crawls started stop right away.
+        let shutdown = CancellationToken::new();
+        shutdown.cancel();
+        let scheduler = Scheduler {
+            schedules: Schedules::default(),
+            spawner: CrawlSpawner {
+                runtime: Handle::current(),
+                shutdown,
+                artifacts: Artifacts::default(),
+                tasks: CrawlTasks::default(),
+            },
+            db: Db::default(),
+            jobs: Jobs::default(),
+            summaries: SummariesDb::default(),
+            audit: AuditLog::default(),
+        };
+        let schedule = scheduler.schedules.add(
+            domain.clone(),
+            CrawlerConfig::default(),
+            Cadence::new(None, Some(60)).unwrap(),
+            now,
+        );
+
+        // Another part of the domain is still being crawled.
+        let running = scheduler
+            .jobs
+            .create(
+                domain.clone(),
+                vec!["/docs/".to_string()],
+                CancellationToken::new(),
+                Arc::default(),
+                CrawlControl::default(),
+            )
+            .unwrap();
+        scheduler.start_due(now + Duration::seconds(61));
+        let status = &scheduler.schedules.list(None)[0];
+        assert_eq!(status.runs, 0);
+        assert_eq!(status.skipped_runs, 1);
+        assert_eq!(scheduler.jobs.active_count(), 1);
+
+        scheduler.jobs.finish(running, CrawlOutcome::Completed);
+        scheduler.start_due(now + Duration::seconds(122));
+        let status = &scheduler.schedules.list(None)[0];
+        assert_eq!(status.runs, 1);
+        assert_eq!(status.skipped_runs, 1);
+        let job = status.last_job.unwrap();
+        assert_ne!(job, running);
+        assert_eq!(scheduler.jobs.status(job).unwrap().domain, domain);
+
+        let entries = scheduler.audit.entries(Some(&domain));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].actor, Some(format!("schedule:{}", schedule.id)));
+        assert_eq!(entries[0].action, AuditAction::CrawlStarted { domain });
+    }
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// Send `state` to systemd. Nothing is sent unless the server runs as a `Type=notify` service,
+/// i.e. `NOTIFY_SOCKET` is set.
+fn notify(state: NotifyState) {
+    if let Err(e) = sd_notify::notify(false, &[state]) {
+        warn!("Could not notify systemd: {}", e);
+    }
+}
+
+/// Tell systemd the server is ready to take requests.
+pub(super) fn notify_ready() {
+    notify(NotifyState::Ready);
+}
+
+/// Tell systemd the server is shutting down.
+pub(super) fn notify_stopping() {
+    notify(NotifyState::Stopping);
+}
+
+/// Pet the watchdog until `shutdown`, if systemd asks for it with `WatchdogSec=`. The pets come
+/// from the runtime serving the requests, so a wedged server stops sending them and gets
+/// restarted.
+pub(super) fn spawn_watchdog(shutdown: CancellationToken) {
+    let mut usec = 0;
+    if !sd_notify::watchdog_enabled(false, &mut usec) {
+        return;
+    }
+
+    // Pet twice per period, so that a late tick does not trigger a restart.
+    let period = Duration::from_micros(usec) / 2;
+    info!("Petting the systemd watchdog every {:?}", period);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    debug!("Petting the systemd watchdog");
+                    notify(NotifyState::Watchdog);
+                }
+                _ = shutdown.cancelled() => break,
+            }
+        }
+    });
+}
@@ -0,0 +1,412 @@
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{crawler::CrawlerConfig, overrides::DomainOverrides};
+
+/// Settings of the application, read from a TOML file and overridden by `CRAWLER_*` environment
+/// variables. Everything is optional, e.g.:
+///
+/// ```toml
+/// port = 8080
+/// log_level = "debug"
+///
+/// [body_limits]
+/// crawl = 8192
+///
+/// [rate_limit]
+/// requests_per_minute = 120
+///
+/// [tls]
+/// cert_path = "/etc/crawler/cert.pem"
+/// key_path = "/etc/crawler/key.pem"
+/// redirect_http_port = 80
+///
+/// [crawl]
+/// max_concurrency = 8
+/// delay_ms = 250
+///
+/// [domain_overrides."partner.example.com"]
+/// delay_ms = 2000
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Address the API listens on, `CRAWLER_ADDRESS`.
+    pub address: IpAddr,
+    /// Port the API listens on, `CRAWLER_PORT`.
+    pub port: u16,
+    /// Most verbose level logged, e.g. `info` or `debug`, `CRAWLER_LOG_LEVEL`.
+    pub log_level: String,
+    /// Maximum size of request bodies.
+    pub body_limits: BodyLimits,
+    /// Number of threads running the crawls, apart from the ones serving the API, one per core
+    /// if not given, `CRAWLER_CRAWL_THREADS`.
+    pub crawl_threads: Option<usize>,
+    /// Keep writes in memory while the database on disk fails, instead of losing them,
+    /// `CRAWLER_STORAGE_OVERFLOW`.
+    pub storage_overflow: bool,
+    /// Most URLs the database kept in memory holds over all domains, the domains visited the
+    /// least recently being forgotten beyond it. Unbounded if not given, and unused with a
+    /// database on disk, `CRAWLER_MEMORY_MAX_URLS`.
+    pub memory_max_urls: Option<usize>,
+    /// Domains owned by whoever runs the crawler, with their subdomains, whose `robots.txt` crawls
+    /// may ignore, e.g. staging sites, `CRAWLER_OWNED_DOMAINS` as a comma-separated list.
+    pub owned_domains: Vec<String>,
+    /// Directory keeping the files produced by crawls, e.g. their summary and an export of their
+    /// URLs, to be downloaded from `/jobs/<id>/artifacts`. Nothing is kept if not given,
+    /// `CRAWLER_ARTIFACTS_DIR`.
+    pub artifacts_dir: Option<PathBuf>,
+    /// Requests each client of the API can make, by configured `X-Api-Key` header or else by IP
+    /// address. Unlimited if not given, `CRAWLER_RATE_LIMIT_PER_MINUTE` setting
+    /// `requests_per_minute`.
+    pub rate_limit: Option<RateLimit>,
+    /// Serve the API over HTTPS instead of HTTP, on the same address and port. Plain HTTP if not
+    /// given, `CRAWLER_TLS_CERT` and `CRAWLER_TLS_KEY` setting both paths.
+    pub tls: Option<Tls>,
+    /// Crawl options used when a request does not give them.
+    pub crawl: CrawlerConfig,
+    /// Politeness settings enforced on the crawls of some domains and their subdomains, whatever
+    /// the options of the crawl, by domain.
+    pub domain_overrides: BTreeMap<String, DomainOverrides>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 3030,
+            log_level: "info".to_string(),
+            body_limits: BodyLimits::default(),
+            crawl_threads: None,
+            storage_overflow: false,
+            memory_max_urls: None,
+            owned_domains: Vec::new(),
+            artifacts_dir: None,
+            rate_limit: None,
+            tls: None,
+            crawl: CrawlerConfig::default(),
+            domain_overrides: BTreeMap::new(),
+        }
+    }
+}
+
+/// Maximum size of request bodies, in bytes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BodyLimits {
+    /// POST /domains, `CRAWLER_BODY_LIMIT_CRAWL`.
+    pub crawl: u64,
+    /// POST /normalize, `CRAWLER_BODY_LIMIT_NORMALIZE`.
+    pub normalize: u64,
+    /// POST /debug/robots-check, `CRAWLER_BODY_LIMIT_ROBOTS_CHECK`.
+    pub robots_check: u64,
+    /// POST /domains/urls/batch-count, `CRAWLER_BODY_LIMIT_BATCH_COUNT`.
+    pub batch_count: u64,
+    /// POST /dump, `CRAWLER_BODY_LIMIT_IMPORT`.
+    pub import: u64,
+    /// The dump of POST /dump once decompressed, `CRAWLER_BODY_LIMIT_IMPORT_DECOMPRESSED`.
+    pub import_decompressed: u64,
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        Self {
+            crawl: 4 * 1024,
+            normalize: 64 * 1024,
+            robots_check: 64 * 1024,
+            batch_count: 1024 * 1024,
+            import: 64 * 1024 * 1024,
+            import_decompressed: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// Requests a client of the API can make before being answered with `429 Too Many Requests`.
+/// The health, readiness and metrics endpoints are not limited.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimit {
+    /// Requests a client can make per minute, on average.
+    pub requests_per_minute: u32,
+    /// Requests a client can make at once, after being idle.
+    pub burst: u32,
+    /// `X-Api-Key` values limited on their own, whatever address they come from. Requests with
+    /// any other key, or none, are limited by IP address.
+    pub api_keys: Vec<String>,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: 60,
+            burst: 10,
+            api_keys: Vec::new(),
+        }
+    }
+}
+
+/// Certificate and private key the API is served with over HTTPS.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tls {
+    /// PEM file of the certificate chain, the server certificate first.
+    pub cert_path: PathBuf,
+    /// PEM file of the private key of the certificate, in PKCS#8 or RSA format.
+    pub key_path: PathBuf,
+    /// Port on the same address redirecting plain HTTP requests to HTTPS, if any,
+    /// `CRAWLER_TLS_REDIRECT_PORT`.
+    #[serde(default)]
+    pub redirect_http_port: Option<u16>,
+}
+
+impl Settings {
+    /// Read the settings from the TOML file at `path`, if any, then from the environment.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let toml = match path {
+            Some(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("Could not read settings from {:?}", path))?,
+            None => String::new(),
+        };
+
+        Self::parse(&toml, |name| std::env::var(name).ok())
+    }
+
+    /// Parse the `toml` settings, overridden by the variables `env` finds.
+    fn parse(toml: &str, env: impl Fn(&str) -> Option<String>) -> anyhow::Result<Self> {
+        let mut settings: Settings = toml::from_str(toml)?;
+
+        if let Some(address) = env("CRAWLER_ADDRESS") {
+            settings.address = address.parse().context("Invalid CRAWLER_ADDRESS")?;
+        }
+        if let Some(port) = env("CRAWLER_PORT") {
+            settings.port = port.parse().context("Invalid CRAWLER_PORT")?;
+        }
+        if let Some(log_level) = env("CRAWLER_LOG_LEVEL") {
+            settings.log_level = log_level;
+        }
+        if let Some(threads) = env("CRAWLER_CRAWL_THREADS") {
+            settings.crawl_threads =
+                Some(threads.parse().context("Invalid CRAWLER_CRAWL_THREADS")?);
+        }
+        if settings.crawl_threads == Some(0) {
+            anyhow::bail!("crawl_threads must be at least 1");
+        }
+        if let Some(overflow) = env("CRAWLER_STORAGE_OVERFLOW") {
+            settings.storage_overflow = overflow
+                .parse()
+                .context("Invalid CRAWLER_STORAGE_OVERFLOW")?;
+        }
+        if let Some(max_urls) = env("CRAWLER_MEMORY_MAX_URLS") {
+            settings.memory_max_urls = Some(
+                max_urls
+                    .parse()
+                    .context("Invalid CRAWLER_MEMORY_MAX_URLS")?,
+            );
+        }
+        if settings.memory_max_urls == Some(0) {
+            anyhow::bail!("memory_max_urls must be at least 1");
+        }
+        if let Some(domains) = env("CRAWLER_OWNED_DOMAINS") {
+            settings.owned_domains = domains
+                .split(',')
+                .map(str::trim)
+                .filter(|domain| !domain.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        if let Some(rate) = env("CRAWLER_RATE_LIMIT_PER_MINUTE") {
+            settings
+                .rate_limit
+                .get_or_insert_with(RateLimit::default)
+                .requests_per_minute = rate
+                .parse()
+                .context("Invalid CRAWLER_RATE_LIMIT_PER_MINUTE")?;
+        }
+        if let Some(rate_limit) = &settings.rate_limit {
+            if rate_limit.requests_per_minute == 0 || rate_limit.burst == 0 {
+                anyhow::bail!("rate_limit must allow at least 1 request");
+            }
+        }
+        match (env("CRAWLER_TLS_CERT"), env("CRAWLER_TLS_KEY")) {
+            (Some(cert_path), Some(key_path)) => {
+                let redirect_http_port = settings.tls.and_then(|tls| tls.redirect_http_port);
+                settings.tls = Some(Tls {
+                    cert_path: cert_path.into(),
+                    key_path: key_path.into(),
+                    redirect_http_port,
+                });
+            }
+            (None, None) => {}
+            _ => anyhow::bail!("CRAWLER_TLS_CERT and CRAWLER_TLS_KEY must be set together"),
+        }
+        if let Some(port) = env("CRAWLER_TLS_REDIRECT_PORT") {
+            match &mut settings.tls {
+                Some(tls) => {
+                    tls.redirect_http_port =
+                        Some(port.parse().context("Invalid CRAWLER_TLS_REDIRECT_PORT")?)
+                }
+                None => anyhow::bail!("CRAWLER_TLS_REDIRECT_PORT needs TLS to be set"),
+            }
+        }
+        if let Some(tls) = &settings.tls {
+            if tls.redirect_http_port == Some(settings.port) {
+                anyhow::bail!("redirect_http_port must differ from the port of the API");
+            }
+        }
+        if let Some(dir) = env("CRAWLER_ARTIFACTS_DIR") {
+            settings.artifacts_dir = Some(dir.into());
+        }
+        let limits = [
+            ("CRAWLER_BODY_LIMIT_CRAWL", &mut settings.body_limits.crawl),
+            (
+                "CRAWLER_BODY_LIMIT_NORMALIZE",
+                &mut settings.body_limits.normalize,
+            ),
+            (
+                "CRAWLER_BODY_LIMIT_ROBOTS_CHECK",
+                &mut settings.body_limits.robots_check,
+            ),
+            (
+                "CRAWLER_BODY_LIMIT_BATCH_COUNT",
+                &mut settings.body_limits.batch_count,
+            ),
+            (
+                "CRAWLER_BODY_LIMIT_IMPORT",
+                &mut settings.body_limits.import,
+            ),
+            (
+                "CRAWLER_BODY_LIMIT_IMPORT_DECOMPRESSED",
+                &mut settings.body_limits.import_decompressed,
+            ),
+        ];
+        for (name, limit) in limits {
+            if let Some(value) = env(name) {
+                *limit = value.parse().with_context(|| format!("Invalid {}", name))?;
+            }
+        }
+
+        settings.log_level()?;
+
+        Ok(settings)
+    }
+
+    /// The parsed `log_level`.
+    pub fn log_level(&self) -> anyhow::Result<tracing::Level> {
+        self.log_level
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid log level {:?}", self.log_level))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::{RateLimit, Settings, Tls};
+
+    #[test]
+    fn test_parse() {
+        let toml = r#"
+port = 8080
+log_level = "debug"
+
+[body_limits]
+crawl = 8192
+
+[crawl]
+max_concurrency = 8
+
+[domain_overrides."partner.example.com"]
+delay_ms = 2000
+user_agent = "gentlebot"
+"#;
+        let settings = Settings::parse(toml, |_| None).unwrap();
+
+        assert_eq!(settings.address, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        assert_eq!(settings.port, 8080);
+        assert_eq!(settings.log_level().unwrap(), tracing::Level::DEBUG);
+        assert_eq!(settings.body_limits.crawl, 8192);
+        assert_eq!(settings.body_limits.normalize, 64 * 1024);
+        assert_eq!(settings.crawl.max_concurrency, Some(8));
+        let overrides = &settings.domain_overrides["partner.example.com"];
+        assert_eq!(overrides.delay_ms, Some(2000));
+        assert_eq!(overrides.user_agent.as_deref(), Some("gentlebot"));
+
+        let settings = Settings::parse(toml, |name| match name {
+            "CRAWLER_ADDRESS" => Some("127.0.0.1".to_string()),
+            "CRAWLER_PORT" => Some("9090".to_string()),
+            "CRAWLER_BODY_LIMIT_NORMALIZE" => Some("1024".to_string()),
+            "CRAWLER_STORAGE_OVERFLOW" => Some("true".to_string()),
+            "CRAWLER_MEMORY_MAX_URLS" => Some("100000".to_string()),
+            "CRAWLER_CRAWL_THREADS" => Some("2".to_string()),
+            "CRAWLER_OWNED_DOMAINS" => Some("staging.example.com, example.org".to_string()),
+            "CRAWLER_ARTIFACTS_DIR" => Some("/var/lib/crawler/artifacts".to_string()),
+            "CRAWLER_RATE_LIMIT_PER_MINUTE" => Some("120".to_string()),
+            "CRAWLER_TLS_CERT" => Some("/etc/crawler/cert.pem".to_string()),
+            "CRAWLER_TLS_KEY" => Some("/etc/crawler/key.pem".to_string()),
+            "CRAWLER_TLS_REDIRECT_PORT" => Some("8080".to_string()),
+            _ => None,
+        })
+        .unwrap();
+
+        assert_eq!(settings.address, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(settings.port, 9090);
+        assert_eq!(settings.body_limits.normalize, 1024);
+        assert!(settings.storage_overflow);
+        assert_eq!(
+            settings.rate_limit,
+            Some(RateLimit {
+                requests_per_minute: 120,
+                burst: 10,
+                api_keys: Vec::new(),
+            })
+        );
+        assert_eq!(
+            settings.tls,
+            Some(Tls {
+                cert_path: "/etc/crawler/cert.pem".into(),
+                key_path: "/etc/crawler/key.pem".into(),
+                redirect_http_port: Some(8080),
+            })
+        );
+        assert_eq!(settings.memory_max_urls, Some(100_000));
+        assert_eq!(settings.crawl_threads, Some(2));
+        assert_eq!(
+            settings.owned_domains,
+            vec!["staging.example.com", "example.org"]
+        );
+        assert_eq!(
+            settings.artifacts_dir.as_deref(),
+            Some(std::path::Path::new("/var/lib/crawler/artifacts"))
+        );
+
+        assert!(Settings::parse("port = \"http\"", |_| None).is_err());
+        assert!(Settings::parse("log_level = \"loud\"", |_| None).is_err());
+        assert!(Settings::parse("crawl_threads = 0", |_| None).is_err());
+        assert!(Settings::parse("[rate_limit]\nburst = 0", |_| None).is_err());
+        assert!(Settings::parse("[tls]\ncert_path = \"cert.pem\"", |_| None).is_err());
+        assert!(Settings::parse(
+            "port = 80\n[tls]\ncert_path = \"c\"\nkey_path = \"k\"\nredirect_http_port = 80",
+            |_| None
+        )
+        .is_err());
+        assert!(Settings::parse("", |name| match name {
+            "CRAWLER_TLS_CERT" => Some("cert.pem".to_string()),
+            _ => None,
+        })
+        .is_err());
+        assert!(
+            Settings::parse("[domain_overrides.\"example.com\"]\ndelay = 1", |_| None).is_err()
+        );
+        assert!(Settings::parse("", |name| match name {
+            "CRAWLER_PORT" => Some("99999".to_string()),
+            _ => None,
+        })
+        .is_err());
+    }
+}
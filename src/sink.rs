@@ -0,0 +1,224 @@
+use std::{
+    fmt::Debug,
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{error, warn};
+use url::Url;
+
+use crate::{
+    crawler::{CrawlEvent, CrawlEventKind},
+    downloader::Downloader,
+};
+
+/// Maximum number of events emitted at once, e.g. in a single request.
+const MAX_BATCH: usize = 100;
+
+/// Where the events of a crawl are emitted as they happen, one JSON object per line, e.g.
+/// `{"type": "http", "url": "https://example.com/crawl-events"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    /// Appended to the file at `path`, created if needed.
+    File { path: PathBuf },
+    /// Written to the standard output.
+    Stdout,
+    /// Sent in the body of POST requests to `url`, as `application/x-ndjson`. The requests go
+    /// through the egress rules and the proxy of the crawl.
+    Http { url: Url },
+}
+
+impl SinkConfig {
+    /// Whether the sink writes on the machine the crawl runs on, which only the command line may
+    /// ask for.
+    pub(crate) fn is_local(&self) -> bool {
+        !matches!(self, SinkConfig::Http { .. })
+    }
+
+    /// Open the sink, its requests being sent by `downloader`.
+    pub(crate) fn open(&self, downloader: &Downloader) -> anyhow::Result<Box<dyn Sink>> {
+        Ok(match self {
+            SinkConfig::File { path } => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| anyhow::anyhow!("Could not open sink {:?}: {}", path, e))?;
+                Box::new(NdjsonSink(BufWriter::new(file)))
+            }
+            SinkConfig::Stdout => Box::new(NdjsonSink(io::stdout())),
+            SinkConfig::Http { url } => Box::new(HttpSink {
+                downloader: downloader.clone(),
+                url: url.clone(),
+            }),
+        })
+    }
+}
+
+/// Receives the events of a crawl, in order, up to the `Finished` one. Implement it to send them
+/// elsewhere, e.g. to a message queue, and add it with `Crawler::add_sink`.
+pub trait Sink: Send + Debug {
+    /// Emit `events`, the ones that happened since the last call. A failure is logged, and the
+    /// next events are emitted anyway.
+    fn emit<'a>(&'a mut self, events: &'a [CrawlEvent]) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+/// Writes the events as NDJSON, flushed after each batch.
+#[derive(Debug)]
+struct NdjsonSink<W>(W);
+
+impl<W: Write + Send + Debug> Sink for NdjsonSink<W> {
+    fn emit<'a>(&'a mut self, events: &'a [CrawlEvent]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            self.0.write_all(&to_ndjson(events)?)?;
+            self.0.flush()?;
+            Ok(())
+        })
+    }
+}
+
+/// POSTs the events as NDJSON, a request for each batch.
+#[derive(Debug)]
+struct HttpSink {
+    downloader: Downloader,
+    url: Url,
+}
+
+impl Sink for HttpSink {
+    fn emit<'a>(&'a mut self, events: &'a [CrawlEvent]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            self.downloader
+                .post(&self.url, "application/x-ndjson", to_ndjson(events)?)
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+fn to_ndjson(events: &[CrawlEvent]) -> serde_json::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    for event in events {
+        serde_json::to_writer(&mut body, event)?;
+        body.push(b'\n');
+    }
+
+    Ok(body)
+}
+
+/// Emit the `events` of a crawl to `sinks` until it finishes, in batches of the events that
+/// happened while the previous batch was emitted.
+pub(crate) async fn forward(
+    mut events: broadcast::Receiver<CrawlEvent>,
+    mut sinks: Vec<Box<dyn Sink>>,
+) {
+    let mut finished = false;
+    while !finished {
+        let mut batch = Vec::new();
+        loop {
+            let event = if batch.is_empty() {
+                events.recv().await
+            } else {
+                match events.try_recv() {
+                    Ok(event) => Ok(event),
+                    Err(_) => break,
+                }
+            };
+            match event {
+                Ok(event) => {
+                    finished = matches!(event.kind, CrawlEventKind::Finished { .. });
+                    batch.push(event);
+                    if finished || batch.len() == MAX_BATCH {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("The sinks missed {} events of the crawl", missed);
+                }
+                Err(RecvError::Closed) => {
+                    finished = true;
+                    break;
+                }
+            }
+        }
+
+        for sink in &mut sinks {
+            if let Err(e) = sink.emit(&batch).await {
+                error!("Could not emit {} events to {:?}: {}", batch.len(), sink, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use mockito::mock;
+    use url::Url;
+
+    use super::{forward, SinkConfig};
+    use crate::{
+        crawler::CrawlProgress,
+        downloader::{Downloader, DownloaderConfig},
+        egress::EgressConfig,
+        summary::CrawlOutcome,
+    };
+
+    #[tokio::test]
+    async fn test_forward() {
+        let path = std::env::temp_dir().join(format!("crawler-sink-{}.ndjson", std::process::id()));
+        let m = mock("POST", "/events")
+            .match_header("content-type", "application/x-ndjson")
+            .match_body(mockito::Matcher::Regex(r#""event":"finished""#.to_string()))
+            .expect(1)
+            .create();
+        let server = Url::parse(&mockito::server_url()).unwrap();
+        let downloader = Downloader::new(&DownloaderConfig {
+            egress: EgressConfig {
+                allow_private: true,
+                ..EgressConfig::default()
+            },
+            ..DownloaderConfig::default()
+        })
+        .unwrap();
+        let sinks = vec![
+            SinkConfig::File { path: path.clone() }
+                .open(&downloader)
+                .unwrap(),
+            SinkConfig::Http {
+                url: server.join("/events").unwrap(),
+            }
+            .open(&downloader)
+            .unwrap(),
+        ];
+
+        let progress = Arc::new(CrawlProgress::default());
+        let forwarder = tokio::spawn(forward(progress.subscribe(), sinks));
+        progress.found(&server);
+        progress.finished(&CrawlOutcome::Completed);
+        forwarder.await.unwrap();
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["event"], "found");
+        assert_eq!(lines[0]["found"], 1);
+        assert_eq!(lines[1]["event"], "finished");
+        m.assert();
+
+        assert!(SinkConfig::File {
+            path: "/nonexistent/events.ndjson".into()
+        }
+        .open(&downloader)
+        .is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::{HashSet, VecDeque};
+
+use tracing::{info, warn};
+use url::Url;
+
+use crate::downloader::Downloader;
+
+/// Most sitemap files fetched for a crawl, index files included, so that a cycle of sitemap
+/// indexes or a huge site cannot keep the crawl from starting.
+const MAX_SITEMAPS: usize = 50;
+
+/// Content of a sitemap file.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Sitemap {
+    /// A `<urlset>`: the pages of the site.
+    Urls(Vec<String>),
+    /// A `<sitemapindex>`: other sitemap files.
+    Index(Vec<String>),
+}
+
+impl Sitemap {
+    /// Parse the XML of a sitemap or sitemap index file.
+    pub(crate) fn parse(xml: &str) -> anyhow::Result<Self> {
+        let document = roxmltree::Document::parse(xml)?;
+        let root = document.root_element();
+
+        let entry = match root.tag_name().name() {
+            "urlset" => "url",
+            "sitemapindex" => "sitemap",
+            other => anyhow::bail!("Unknown sitemap root element <{}>", other),
+        };
+        let locations = root
+            .children()
+            .filter(|node| node.has_tag_name(entry))
+            .filter_map(|node| node.children().find(|child| child.has_tag_name("loc")))
+            .filter_map(|loc| loc.text())
+            .map(|loc| loc.trim().to_string())
+            .collect();
+
+        Ok(match entry {
+            "url" => Sitemap::Urls(locations),
+            _ => Sitemap::Index(locations),
+        })
+    }
+}
+
+/// Fetch the sitemaps of `domain`: the ones listed in `robots.txt` and `/sitemap.xml`, following
+/// sitemap indexes. Returns the page URLs they list.
+pub(crate) async fn discover(downloader: &Downloader, domain: &Url, listed: &[String]) -> Vec<Url> {
+    let mut queue: VecDeque<Url> = listed
+        .iter()
+        .filter_map(|sitemap| domain.join(sitemap).ok())
+        .collect();
+    queue.push_back(domain.join("/sitemap.xml").unwrap());
+
+    let mut fetched = HashSet::new();
+    let mut urls = Vec::new();
+    while let Some(sitemap) = queue.pop_front() {
+        if fetched.len() >= MAX_SITEMAPS {
+            warn!("Giving up on sitemaps after {} files", MAX_SITEMAPS);
+            break;
+        }
+        if !fetched.insert(sitemap.clone()) {
+            continue;
+        }
+
+        let xml = match downloader.download(&sitemap).await {
+            Ok(xml) => xml,
+            Err(e) => {
+                info!("No sitemap at {}: {}", sitemap, e);
+                continue;
+            }
+        };
+        match Sitemap::parse(&xml) {
+            Ok(Sitemap::Urls(locations)) => {
+                urls.extend(locations.iter().filter_map(|loc| domain.join(loc).ok()))
+            }
+            Ok(Sitemap::Index(locations)) => {
+                queue.extend(locations.iter().filter_map(|loc| domain.join(loc).ok()))
+            }
+            Err(e) => warn!("Invalid sitemap {}: {}", sitemap, e),
+        }
+    }
+
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::mock;
+    use url::Url;
+
+    use super::{discover, Sitemap};
+    use crate::{
+        downloader::{Downloader, DownloaderConfig},
+        egress::EgressConfig,
+    };
+
+    #[test]
+    fn test_parse() {
+        let urlset = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+    <url><loc>https://example.com/</loc><priority>1.0</priority></url>
+    <url>
+        <loc> https://example.com/foo </loc>
+    </url>
+</urlset>"#;
+        assert_eq!(
+            Sitemap::parse(urlset).unwrap(),
+            Sitemap::Urls(vec![
+                "https://example.com/".to_string(),
+                "https://example.com/foo".to_string()
+            ])
+        );
+
+        let index = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+    <sitemap><loc>https://example.com/posts.xml</loc></sitemap>
+</sitemapindex>"#;
+        assert_eq!(
+            Sitemap::parse(index).unwrap(),
+            Sitemap::Index(vec!["https://example.com/posts.xml".to_string()])
+        );
+
+        assert!(Sitemap::parse("<html></html>").is_err());
+        assert!(Sitemap::parse("not xml").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_discover() {
+        let _m = mock("GET", "/sitemap.xml")
+            .with_status(200)
+            .with_body(
+                r#"<sitemapindex>
+                    <sitemap><loc>/pages.xml</loc></sitemap>
+                    <sitemap><loc>/sitemap.xml</loc></sitemap>
+                </sitemapindex>"#,
+            )
+            .create();
+        let _m = mock("GET", "/pages.xml")
+            .with_status(200)
+            .with_body("<urlset><url><loc>/foo</loc></url></urlset>")
+            .create();
+        let _m = mock("GET", "/listed.xml")
+            .with_status(200)
+            .with_body("<urlset><url><loc>/bar</loc></url></urlset>")
+            .create();
+
+        let domain = Url::parse(&mockito::server_url()).unwrap();
+        let downloader = Downloader::new(&DownloaderConfig {
+            egress: EgressConfig {
+                allow_private_domain: true,
+                ..EgressConfig::default()
+            },
+            ..DownloaderConfig::default()
+        })
+        .unwrap()
+        .with_crawled_host(domain.host_str().unwrap());
+        let urls = discover(&downloader, &domain, &["/listed.xml".to_string()]).await;
+
+        assert_eq!(
+            urls,
+            vec![domain.join("/bar").unwrap(), domain.join("/foo").unwrap()]
+        );
+    }
+}
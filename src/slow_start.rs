@@ -0,0 +1,226 @@
+use std::{sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use tracing::info;
+
+/// Mean latency under which downloads are never too slow, whatever the first window's, so that
+/// jitter on a fast host does not hold the ramp-up back.
+const MIN_SLOW_LATENCY: Duration = Duration::from_millis(50);
+
+/// Ramp-up of the number of downloads a crawl makes at once, e.g. `{"initial": 2, "window": 20}`,
+/// instead of starting with `max_concurrency` downloads against a host that may not take them.
+/// After each window of downloads, the concurrency doubles, up to `max_concurrency` if set, unless
+/// too many of them failed or they got too slow, which halves it instead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SlowStart {
+    /// Number of downloads at once the crawl starts with.
+    pub initial: usize,
+    /// Number of finished downloads after which the concurrency is adjusted.
+    pub window: usize,
+    /// Highest fraction of downloads of a window that can fail for the concurrency to grow, between
+    /// 0 and 1. Only network errors, `429 Too Many Requests` and `5xx` statuses count.
+    pub max_error_rate: f64,
+    /// Highest ratio between the mean latency of a window and the one of the first window for the
+    /// concurrency to grow.
+    pub max_latency_factor: f64,
+}
+
+impl SlowStart {
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        if !(0.0..=1.0).contains(&self.max_error_rate) {
+            anyhow::bail!("The max_error_rate of slow_start must be between 0 and 1");
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for SlowStart {
+    fn default() -> Self {
+        Self {
+            initial: 1,
+            window: 10,
+            max_error_rate: 0.1,
+            max_latency_factor: 2.0,
+        }
+    }
+}
+
+/// Adjusts the permits of the semaphore limiting the downloads of a crawl, as set by `SlowStart`.
+/// Owned by the crawler, which records every finished download.
+#[derive(Debug)]
+pub(crate) struct Ramp {
+    config: SlowStart,
+    permits: Arc<Semaphore>,
+    max: Option<usize>,
+    // Current number of downloads at once.
+    limit: usize,
+    // Highest `limit` so far.
+    peak: usize,
+    // Permits still to be taken back after the limit went down, as they are released.
+    debt: usize,
+    // The current window.
+    downloads: usize,
+    failures: usize,
+    latencies: Vec<Duration>,
+    // Mean latency of the first window with a successful download.
+    baseline: Option<Duration>,
+}
+
+impl Ramp {
+    /// Start with `initial` downloads at once, growing up to `max` if set.
+    pub(crate) fn new(config: SlowStart, max: Option<usize>) -> Self {
+        let limit = config.initial.min(max.unwrap_or(usize::MAX)).max(1);
+
+        Self {
+            permits: Arc::new(Semaphore::new(limit)),
+            config,
+            max,
+            limit,
+            peak: limit,
+            debt: 0,
+            downloads: 0,
+            failures: 0,
+            latencies: Vec::new(),
+            baseline: None,
+        }
+    }
+
+    /// The semaphore whose permits are adjusted, to be acquired before each download.
+    pub(crate) fn permits(&self) -> Arc<Semaphore> {
+        Arc::clone(&self.permits)
+    }
+
+    /// Highest number of downloads at once reached so far.
+    pub(crate) fn peak(&self) -> usize {
+        self.peak
+    }
+
+    /// Record a finished download, that took `latency` if it succeeded.
+    pub(crate) fn record(&mut self, failed: bool, latency: Option<Duration>) {
+        self.take_back();
+
+        self.downloads += 1;
+        self.failures += failed as usize;
+        self.latencies.extend(latency);
+        if self.downloads < self.config.window.max(1) {
+            return;
+        }
+
+        let error_rate = self.failures as f64 / self.downloads as f64;
+        let mean = (!self.latencies.is_empty())
+            .then(|| self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32);
+        let slow = match (mean, self.baseline) {
+            (Some(mean), Some(baseline)) => {
+                mean > MIN_SLOW_LATENCY
+                    && mean.as_secs_f64() > baseline.as_secs_f64() * self.config.max_latency_factor
+            }
+            _ => false,
+        };
+        if self.baseline.is_none() {
+            self.baseline = mean;
+        }
+        self.downloads = 0;
+        self.failures = 0;
+        self.latencies.clear();
+
+        let limit = if error_rate > self.config.max_error_rate || slow {
+            (self.limit / 2).max(1)
+        } else {
+            self.limit
+                .saturating_mul(2)
+                .min(self.max.unwrap_or(usize::MAX))
+        };
+        if limit != self.limit {
+            info!(
+                "Concurrency from {} to {} (error rate {:.3}, mean latency {:?})",
+                self.limit, limit, error_rate, mean
+            );
+            self.set_limit(limit);
+        }
+    }
+
+    fn set_limit(&mut self, limit: usize) {
+        if limit > self.limit {
+            let mut added = limit - self.limit;
+            let forgiven = added.min(self.debt);
+            self.debt -= forgiven;
+            added -= forgiven;
+            self.permits.add_permits(added);
+        } else {
+            self.debt += self.limit - limit;
+        }
+        self.limit = limit;
+        self.peak = self.peak.max(limit);
+        self.take_back();
+    }
+
+    /// Take back the permits owed after the limit went down, among the available ones.
+    fn take_back(&mut self) {
+        while self.debt > 0 {
+            match self.permits.try_acquire() {
+                Ok(permit) => {
+                    permit.forget();
+                    self.debt -= 1;
+                }
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Ramp, SlowStart};
+
+    #[test]
+    fn test_ramp() {
+        let config = SlowStart {
+            initial: 1,
+            window: 2,
+            ..SlowStart::default()
+        };
+        let mut ramp = Ramp::new(config, Some(6));
+        let permits = ramp.permits();
+        let fast = Some(Duration::from_millis(10));
+        let slow = Some(Duration::from_millis(100));
+        assert_eq!(permits.available_permits(), 1);
+
+        ramp.record(false, fast);
+        assert_eq!(permits.available_permits(), 1);
+        ramp.record(false, fast);
+        assert_eq!(permits.available_permits(), 2);
+        ramp.record(false, fast);
+        ramp.record(false, fast);
+        assert_eq!(permits.available_permits(), 4);
+        // Capped by `max_concurrency`.
+        ramp.record(false, fast);
+        ramp.record(false, fast);
+        assert_eq!(permits.available_permits(), 6);
+
+        // Failures halve the concurrency, permits in use being taken back once released.
+        let held = permits.clone().try_acquire_many_owned(6).unwrap();
+        ramp.record(true, None);
+        ramp.record(false, fast);
+        assert_eq!(permits.available_permits(), 0);
+        drop(held);
+        ramp.record(false, fast);
+        assert_eq!(permits.available_permits(), 3);
+
+        // And so do downloads getting slower than in the first window.
+        ramp.record(false, slow);
+        assert_eq!(permits.available_permits(), 1);
+        assert_eq!(ramp.peak(), 6);
+
+        assert_eq!(
+            Ramp::new(SlowStart::default(), None)
+                .permits()
+                .available_permits(),
+            1
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::crawler::{CrawlStats, CrawlerConfig};
+
+/// Version of the `CrawlSummary` format. Bump it on incompatible changes.
+pub const SUMMARY_VERSION: u32 = 1;
+
+/// How a crawl ended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum CrawlOutcome {
+    /// The frontier was exhausted.
+    Completed,
+    /// The crawl was stopped before the frontier was exhausted.
+    Cancelled,
+    /// The crawl ran to the end, but crossed one of its `fail_on` thresholds.
+    Failed { reason: String },
+}
+
+/// Machine-readable document describing a finished crawl: the options it ran with,
+/// how long it took and what it found.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrawlSummary {
+    pub version: u32,
+    pub domain: Url,
+    pub options: CrawlerConfig,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub duration_ms: i64,
+    pub outcome: CrawlOutcome,
+    pub stats: CrawlStats,
+}
+
+impl CrawlSummary {
+    pub(crate) fn new(
+        domain: Url,
+        options: CrawlerConfig,
+        started_at: DateTime<Utc>,
+        outcome: CrawlOutcome,
+        stats: CrawlStats,
+    ) -> Self {
+        let finished_at = Utc::now();
+
+        Self {
+            version: SUMMARY_VERSION,
+            domain,
+            options,
+            started_at,
+            finished_at,
+            duration_ms: (finished_at - started_at).num_milliseconds(),
+            outcome,
+            stats,
+        }
+    }
+}
@@ -1,56 +1,340 @@
-use crate::{downloader::Downloader, parser::Parser};
-use tokio::sync::{broadcast, mpsc};
+use crate::{
+    db::FetchStatus,
+    downloader::{DownloadError, Downloader, Page, Validators},
+    normalize::NormalizeConfig,
+    parser::{AssetKind, Parser},
+    throttle::Throttle,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use scraper::Selector;
+use tokio::sync::{broadcast, mpsc, OwnedSemaphorePermit};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 use url::Url;
 
+/// Messages sent by a task to its crawler.
+#[derive(Debug)]
+pub(crate) enum TaskEvent {
+    /// The download is starting, after the URL waited in the queue for the given time.
+    Started(Duration),
+    /// An URL found, its depth: the number of links followed from the seed, and the page it was
+    /// found on, `None` for the seeds.
+    Found(Url, usize, Option<Url>),
+    /// The URL was downloaded, as described by the status.
+    Fetched(Url, FetchStatus),
+    /// The page did not change since its last fetch, as the server answered in the given time.
+    Unchanged(Duration),
+    /// Session identifiers were stripped from the given number of URLs found on the page.
+    SessionIdsStripped(usize),
+    /// The number of links of the downloaded page left out by `exclude_selectors`.
+    Excluded(usize),
+    /// The number of links of the downloaded page not followed as `nofollow`.
+    Nofollow(usize),
+    /// The links of the downloaded page not followed as it has more than `max_links_per_page`.
+    OverLinkLimit(Vec<Url>),
+    /// The assets the downloaded page refers to, found as `extract_assets` is set.
+    Assets(Vec<(AssetKind, Url)>),
+    /// The text of the downloaded page, extracted as `index_text` is set.
+    Text(Url, String),
+    /// The download of the URL failed.
+    Failed(Url, DownloadError),
+}
+
 /// Task representing one URL to download and parse.
 #[derive(Debug)]
 pub(crate) struct Task {
     pub(crate) downloader: Downloader,
     pub(crate) domain: Url,
+    // Rules applied to every found URL before it is sent to the crawler.
+    pub(crate) normalize: NormalizeConfig,
+    // Page sections whose links are ignored.
+    pub(crate) exclude: Arc<Vec<Selector>>,
+    // Follow `nofollow` links and index `noindex` pages anyway.
+    pub(crate) ignore_nofollow: bool,
+    pub(crate) ignore_noindex: bool,
+    // Extract the assets of the page too.
+    pub(crate) extract_assets: bool,
+    // Extract the text of HTML pages to index it.
+    pub(crate) index_text: bool,
+    // Maximum number of links of the page followed, if set.
+    pub(crate) max_links: Option<usize>,
     pub(crate) url: Url,
-    // Channel where the task can send found URLs to.
-    pub(crate) tx: mpsc::UnboundedSender<Url>,
-    // Channel use to receive shutdown notifications.
-    pub(crate) notify_shutdown: broadcast::Receiver<()>,
+    // Sent back to the server for the page to only be downloaded if it changed since its last
+    // fetch.
+    pub(crate) validators: Validators,
+    // Number of links followed from the seed to reach `url`.
+    pub(crate) depth: usize,
+    // When the URL was scheduled by the crawler.
+    pub(crate) enqueued_at: Instant,
+    // Taken by the crawler from the ones limiting the number of concurrent downloads of the crawl,
+    // if set. Held until the page is downloaded and parsed.
+    pub(crate) _permit: Option<OwnedSemaphorePermit>,
+    // Spaces out the downloads of the crawl, if set.
+    pub(crate) throttle: Option<Arc<Throttle>>,
+    // Channel where the task can send found URLs and failures to.
+    pub(crate) tx: mpsc::Sender<TaskEvent>,
+    // Cancelled on shutdown. Drops the in-flight download instead of waiting for it.
+    pub(crate) cancel: CancellationToken,
     // Dropped when task is done. Will notify crawler so it can gracefully shutdown.
     pub(crate) _shutdown_complete: broadcast::Sender<()>,
 }
 
 impl Task {
     pub(crate) async fn run(&mut self) {
+        if let Some(throttle) = &self.throttle {
+            tokio::select! {
+                _ = throttle.wait() => {}
+                _ = self.cancel.cancelled() => {
+                    info!("Shutting down");
+                    return;
+                }
+            }
+        }
+        self.send(TaskEvent::Started(self.enqueued_at.elapsed()))
+            .await;
+
+        let started = Instant::now();
         tokio::select! {
-            response = self.downloader.download(&self.url) => {
+            response = self.download() => {
+                let elapsed = started.elapsed();
                 match response {
-                    Ok(response) => {
-                        for url in Parser::new(&response).extract_urls() {
-                            if let Some(url) = build_absolute_url(&self.domain, url) {
-                                match self.tx.send(url) {
-                                    Ok(_) => {}
-                                    Err(_) => {
-                                        info!("Failed to send. Receiver has probably shut down");
-                                    }
-                                }
-                            }
+                    Ok(page) if page.is_unchanged() => {
+                        info!("Url {} is unchanged", self.url);
+                        self.send(TaskEvent::Unchanged(elapsed)).await;
+                    }
+                    Ok(page) if page.skipped => {
+                        info!("Skipped the body of {}, of type {:?}", self.url, page.content_type);
+                        let status = page_status(&page, &Links::default(), elapsed);
+                        self.send(TaskEvent::Fetched(self.url.clone(), status)).await;
+                    }
+                    Ok(page) => {
+                        let text = self.index_text && page.is_html();
+                        let mut links = extract_links(&self.domain, &self.normalize, &self.exclude, self.extract_assets, text, &page.body);
+                        links.ignore_hints(self.ignore_nofollow, self.ignore_noindex);
+                        links.cap(self.max_links);
+                        let status = page_status(&page, &links, elapsed);
+                        // The body is not needed anymore: free it before waiting for room in the
+                        // channel, as many tasks may be waiting at once.
+                        drop(page);
+                        self.send(TaskEvent::Fetched(self.url.clone(), status)).await;
+                        if let Some(text) = links.indexable_text() {
+                            self.send(TaskEvent::Text(self.url.clone(), text)).await;
+                        }
+                        if links.session_ids_stripped > 0 {
+                            self.send(TaskEvent::SessionIdsStripped(links.session_ids_stripped)).await;
+                        }
+                        if !links.excluded.is_empty() {
+                            self.send(TaskEvent::Excluded(links.excluded.len())).await;
+                        }
+                        if !links.nofollow.is_empty() {
+                            self.send(TaskEvent::Nofollow(links.nofollow.len())).await;
+                        }
+                        if !links.over_limit.is_empty() {
+                            self.send(TaskEvent::OverLinkLimit(links.over_limit)).await;
+                        }
+                        if !links.assets.is_empty() {
+                            self.send(TaskEvent::Assets(links.assets)).await;
+                        }
+                        for url in links.urls {
+                            self.send(TaskEvent::Found(url, self.depth + 1, Some(self.url.clone()))).await;
                         }
                     },
-                    Err(_) => error!("Failed to download url: {}", self.url),
+                    Err(e) => {
+                        error!("Failed to download url {}: {}", self.url, e);
+                        self.send(TaskEvent::Failed(self.url.clone(), e)).await;
+                    }
                 }
             }
-            _ = self.notify_shutdown.recv() => {
+            _ = self.cancel.cancelled() => {
                 info!("Shutting down");
             }
         }
     }
+
+    /// Download the page, waiting for the circuit breaker of the host to close if it is open.
+    async fn download(&self) -> Result<Page, DownloadError> {
+        loop {
+            match self
+                .downloader
+                .revalidate(&self.url, &self.validators)
+                .await
+            {
+                Err(DownloadError::CircuitOpen { retry_in }) => {
+                    warn!("Host of {} is down, retrying in {:?}", self.url, retry_in);
+                    tokio::time::sleep(retry_in).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Send `event` to the crawler, waiting for room in the channel unless the crawl is cancelled.
+    async fn send(&self, event: TaskEvent) {
+        let result = tokio::select! {
+            result = self.tx.send(event) => result,
+            _ = self.cancel.cancelled() => return,
+        };
+        match result {
+            Ok(_) => {}
+            Err(_) => {
+                info!("Failed to send. Receiver has probably shut down");
+            }
+        }
+    }
+}
+
+/// Links found on a page.
+#[derive(Debug, Default)]
+pub(crate) struct Links {
+    /// Absolute, normalized URLs.
+    pub(crate) urls: Vec<Url>,
+    /// Number of URLs session identifiers were stripped from.
+    pub(crate) session_ids_stripped: usize,
+    /// Absolute, normalized URLs linked to from the `exclude`d sections.
+    pub(crate) excluded: Vec<Url>,
+    /// Absolute, normalized URLs of the links not to follow, with `rel="nofollow"` or all of them
+    /// if the page has a `nofollow` robots meta tag.
+    pub(crate) nofollow: Vec<Url>,
+    /// Absolute, normalized URLs of the links past the limit of links per page, if capped.
+    pub(crate) over_limit: Vec<Url>,
+    /// Absolute URLs of the resources the page refers to, e.g. images, if they were extracted.
+    pub(crate) assets: Vec<(AssetKind, Url)>,
+    /// The page has a `noindex` robots meta tag.
+    pub(crate) noindex: bool,
+    /// The `<title>` of the page.
+    pub(crate) title: Option<String>,
+    /// The visible text of the page, if it was extracted.
+    pub(crate) text: Option<String>,
+}
+
+impl Links {
+    /// Follow the `nofollow` links, and index the page whatever its robots meta tags say, as set.
+    pub(crate) fn ignore_hints(&mut self, nofollow: bool, noindex: bool) {
+        if nofollow {
+            self.urls.append(&mut self.nofollow);
+        }
+        if noindex {
+            self.noindex = false;
+        }
+    }
+
+    /// The text of the page to index, if it was extracted and the page may be indexed.
+    pub(crate) fn indexable_text(&mut self) -> Option<String> {
+        self.text.take().filter(|_| !self.noindex)
+    }
+
+    /// Only follow the first `max` links, if set.
+    pub(crate) fn cap(&mut self, max: Option<usize>) {
+        if let Some(max) = max {
+            if self.urls.len() > max {
+                self.over_limit = self.urls.split_off(max);
+            }
+        }
+    }
+}
+
+/// What is recorded about a downloaded `page` with the given `links`, that took `duration` to
+/// download. Only the size and type of a `noindex` page are, and only the type of a page whose
+/// body was skipped.
+pub(crate) fn page_status(page: &Page, links: &Links, duration: Duration) -> FetchStatus {
+    let status = FetchStatus {
+        content_type: page.content_type.clone(),
+        bytes: Some(page.body.len()),
+        duration_ms: Some(duration.as_millis() as u64),
+        issues: page.issues.clone(),
+        etag: page.validators.etag.clone(),
+        last_modified: page.validators.last_modified.clone(),
+        ..FetchStatus::ok(page.status)
+    };
+    if page.skipped {
+        return FetchStatus {
+            bytes: None,
+            body_skipped: true,
+            ..status
+        };
+    }
+    if links.noindex {
+        return FetchStatus {
+            noindex: true,
+            ..status
+        };
+    }
+
+    FetchStatus {
+        title: links.title.clone(),
+        content_hash: page.content_hash.clone(),
+        ..status
+    }
+}
+
+/// Parse `html` and return the absolute, normalized URLs it links to, the ones of the `exclude`d
+/// sections and the `nofollow` ones apart, the ones of its assets if `assets` is set, and its text
+/// if `text` is.
+pub(crate) fn extract_links(
+    domain: &Url,
+    normalize: &NormalizeConfig,
+    exclude: &[Selector],
+    assets: bool,
+    text: bool,
+    html: &str,
+) -> Links {
+    let parser = Parser::new(html);
+    let robots = parser.meta_robots();
+    let mut links = Links {
+        title: parser.title(),
+        text: text.then(|| parser.text()),
+        noindex: robots.noindex,
+        ..Links::default()
+    };
+    // Relative links are resolved against the `<base>` of the page if it has one.
+    let base = parser
+        .base_href()
+        .and_then(|href| build_absolute_url(domain, href))
+        .filter(|base| matches!(base.scheme(), "http" | "https"));
+    let base = base.as_ref().unwrap_or(domain);
+    for (url, link) in parser
+        .links(exclude)
+        .filter_map(|link| Some((build_absolute_url(base, link.href)?, link)))
+    {
+        if link.excluded {
+            links.excluded.push(normalize.normalize(&url));
+            continue;
+        }
+        let (url, stripped) = normalize.rewrite(&url);
+        links.session_ids_stripped += stripped as usize;
+        if robots.nofollow || link.is_nofollow() {
+            links.nofollow.push(url);
+        } else {
+            links.urls.push(url);
+        }
+    }
+    if assets {
+        links.assets = parser
+            .assets()
+            .into_iter()
+            .filter_map(|(kind, href)| Some((kind, build_absolute_url(base, href)?)))
+            .filter(|(_, url)| matches!(url.scheme(), "http" | "https"))
+            .map(|(kind, mut url)| {
+                url.set_fragment(None);
+                (kind, url)
+            })
+            .collect();
+    }
+
+    links
 }
 
-/// Combine the `domain` URL that we are crawling with a relative path to build
-/// an absolute url.
-fn build_absolute_url(domain: &Url, url: &str) -> Option<Url> {
-    let url = match url::Url::parse(&url) {
+/// Combine the `base` URL, the domain that we are crawling or the `<base>` of the page, with a
+/// relative path to build an absolute url.
+fn build_absolute_url(base: &Url, url: &str) -> Option<Url> {
+    let url = match url::Url::parse(url) {
         Ok(url) => url,
         Err(e) => match e {
-            url::ParseError::RelativeUrlWithoutBase => domain.join(&url).unwrap(),
+            url::ParseError::RelativeUrlWithoutBase => base.join(url).unwrap(),
             _ => {
                 warn!("Unknown url: {}", url);
                 return None;
@@ -0,0 +1,90 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+/// The longest interval between two requests that `delay_ms` or `max_rps` may ask for.
+pub(crate) const MAX_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Spaces out the requests of a crawl, so that they start at least `interval` apart.
+/// Shared by all the tasks of a crawler.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    interval: Duration,
+    // When the next request is allowed to start.
+    next: Mutex<Instant>,
+}
+
+impl Throttle {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// The interval matching a `delay_ms` between requests or a maximum of `max_rps` requests per
+    /// second, whichever is slower, up to `MAX_INTERVAL`. `None` if neither is set.
+    pub(crate) fn interval(delay_ms: Option<u64>, max_rps: Option<f64>) -> Option<Duration> {
+        let delay = delay_ms.map(|delay_ms| Duration::from_millis(delay_ms).min(MAX_INTERVAL));
+        let rate = max_rps
+            .filter(|rps| rps.is_finite() && *rps > 0.0)
+            .map(|rps| Duration::from_secs_f64((1.0 / rps).min(MAX_INTERVAL.as_secs_f64())));
+
+        delay.max(rate)
+    }
+
+    /// Wait until the next request slot.
+    pub(crate) async fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(Instant::now());
+            // Intervals are at most `MAX_INTERVAL`, so only a clock at the end of its range overflows.
+            *next = slot.checked_add(self.interval).unwrap_or(slot);
+            slot
+        };
+
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{Throttle, MAX_INTERVAL};
+
+    #[test]
+    fn test_interval() {
+        assert_eq!(Throttle::interval(None, None), None);
+        assert_eq!(
+            Throttle::interval(Some(100), None),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            Throttle::interval(Some(100), Some(2.0)),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            Throttle::interval(Some(100), Some(20.0)),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(Throttle::interval(Some(u64::MAX), None), Some(MAX_INTERVAL));
+        assert_eq!(Throttle::interval(None, Some(1e-300)), Some(MAX_INTERVAL));
+        assert_eq!(Throttle::interval(None, Some(f64::NAN)), None);
+    }
+
+    #[tokio::test]
+    async fn test_wait() {
+        let throttle = Throttle::new(Duration::from_millis(20));
+        let start = Instant::now();
+
+        throttle.wait().await;
+        assert!(start.elapsed() < Duration::from_millis(20));
+
+        throttle.wait().await;
+        throttle.wait().await;
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}